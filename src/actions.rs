@@ -0,0 +1,103 @@
+//! what is done with the files checked by their verdict: --release, --on-fail, --post-hook and --delete-after-verify
+use std::path::PathBuf;
+use std::sync::Arc;
+use log::debug;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use crate::command::{self, ExtCommand};
+use crate::events::{Event, Events};
+use crate::options::Options;
+
+/// what a command spawned after a verdict is for
+#[derive(Clone,Copy,PartialEq)]
+pub(crate) enum Action {Release,OnFail,PostHook}
+
+impl Action
+{
+    fn describe(&self) -> &'static str
+    {
+        match self {
+            Action::Release => "releasing",
+            Action::OnFail => "running on-fail for",
+            Action::PostHook => "running post-hook for"
+        }
+    }
+}
+
+/// the commands run for the files checked, in the background
+pub(crate) struct Actions
+{
+    /// the release commands, run in order
+    pub release:Vec<Arc<ExtCommand>>,
+    pub on_fail:Option<Arc<ExtCommand>>,
+    pub post_hook:Option<Arc<ExtCommand>>,
+    /// release the files that failed as well
+    pub release_on_fail:bool,
+    pub delete_after_verify:bool,
+    retries:u32,
+    /// the commands running at once at most (shared by the jobs of the daemon)
+    pub slots:Arc<Semaphore>,
+    /// the commands spawned, giving the number of files release/on-fail and post-hook failed for
+    pub running:JoinSet<(u64,u64)>
+}
+
+impl Actions
+{
+    pub fn new(options:&Options) -> Actions
+    {
+        let command = |what,cmd:&Option<String>|command::configured(what,cmd.as_deref(),&options.commands);
+        Actions{
+            release:options.release.iter().filter_map(|cmd|command::configured("release",Some(cmd),&options.commands)).collect(),
+            on_fail:command("on-fail",&options.on_fail),
+            post_hook:command("post-hook",&options.post_hook),
+            release_on_fail:options.release_on_fail,delete_after_verify:options.delete_after_verify,
+            retries:options.release_retries,
+            slots:Arc::new(Semaphore::new(options.release_procs.max(1))),
+            running:JoinSet::new()
+        }
+    }
+    /// the release commands to run in order
+    pub fn release_chain(&self) -> Vec<(Action,Arc<ExtCommand>)>
+    {
+        self.release.iter().map(|release|(Action::Release,release.clone())).collect()
+    }
+    /// run the commands for the paths one after the other once there's a slot, the release chain stops at the first failing
+    pub fn spawn(&mut self,commands:Vec<(Action,Arc<ExtCommand>)>,paths:Vec<PathBuf>,env:command::Env,events:&Events)
+    {
+        let slots = self.slots.clone();
+        let retries = self.retries;
+        let events = events.clone();
+        events.emit(||Event::CommandsQueued{paths:paths.clone()});
+        self.running.spawn(async move {
+            let _permit = slots.acquire_owned().await;
+            events.emit(||Event::CommandsStarted{paths:paths.clone()});
+            let (mut failed,mut hook_failed) = (0,0);
+            let (mut release_started,mut release_failed) = (None,false);
+            for (action,cmd) in commands {
+                if action == Action::Release {
+                    // the rest of the release chain is skipped once a command failed
+                    if release_failed {continue}
+                    release_started.get_or_insert_with(Instant::now);
+                }
+                if !cmd.run_retrying(action.describe(),&paths,&env,retries).await {
+                    match action {
+                        Action::PostHook => hook_failed = paths.len() as u64,
+                        Action::Release => {release_failed = true; failed = paths.len() as u64}
+                        _ => failed = paths.len() as u64
+                    }
+                }
+            }
+            if let Some(started) = release_started {
+                let files = match paths.as_slice() {
+                    [path] => format!("'{}'",path.to_string_lossy()),
+                    paths => format!("{} files",paths.len())
+                };
+                debug!("releasing {files} took {:.1?}",started.elapsed());
+                events.emit(||Event::Released{paths:paths.clone(),ok:!release_failed});
+            }
+            events.emit(||Event::CommandsDone{paths});
+            (failed,hook_failed)
+        });
+    }
+}
@@ -0,0 +1,84 @@
+//! --adaptive: tuning how many files are checked at once for the most throughput, by hill climbing
+//!
+//! The throughput of all checks is measured over a window, then one file more (or less) is allowed.
+//! When the throughput drops the direction changes, so the limit climbs to where it's best and stays around there.
+use std::time::Duration;
+use log::{debug, info};
+use tokio::time::Instant;
+use crate::MEGABYTE;
+
+/// throughput changes smaller than that are noise, and don't change the direction
+const TOLERANCE:f64 = 0.05;
+/// with --adaptive-freeze, the limit stays at the best one seen after the direction changed that often
+const FREEZE_REVERSALS:u32 = 3;
+
+pub(crate) struct Adaptive
+{
+    min:usize,max:usize,
+    /// how many files may be checked at once now
+    pub limit:usize,
+    window:Duration,
+    /// when the current window started, and the bytes read by all checks by then
+    started:(Instant,u64),
+    /// the throughput of the last window judged, and if the limit is grown (or shrunk)
+    last:Option<f64>,growing:bool,
+    /// the limit with the best throughput so far, and that throughput (in M/s)
+    best:(usize,f64),
+    /// how often the direction changed, and if the limit was frozen at the best one
+    reversals:u32,freeze:bool,frozen:bool,
+    /// the lowest and highest limit used
+    range:(usize,usize)
+}
+
+impl Adaptive
+{
+    /// start at min files at once
+    pub fn new(min:usize,max:usize,window:Duration,freeze:bool) -> Adaptive
+    {
+        let min = min.max(1);
+        let max = max.max(min);
+        Adaptive{
+            min,max,limit:min,window,started:(Instant::now(),0),last:None,growing:true,best:(min,0.0),
+            reversals:0,freeze,frozen:false,range:(min,min)
+        }
+    }
+    /// when the current window ends, None once frozen
+    pub fn window_end(&self) -> Option<Instant> {(!self.frozen).then_some(self.started.0+self.window)}
+    /// judge the window ending now by the bytes read by all checks so far, and change the limit
+    ///
+    /// Windows with fewer files running than allowed (as there weren't more, or --max-size was the limit) say nothing about it.
+    pub fn adjust(&mut self,now:Instant,read:u64,running:usize)
+    {
+        let (started,before) = std::mem::replace(&mut self.started,(now,read));
+        if self.frozen {return}
+        let rate = read.saturating_sub(before) as f64/MEGABYTE as f64/(now-started).as_secs_f64();
+        if running < self.limit {
+            debug!("{running} files were checked at once at {rate:.1}M/s, fewer than the {} allowed, not adjusting",self.limit);
+            return
+        }
+        if rate > self.best.1 {self.best = (self.limit,rate);}
+        if self.last.is_some_and(|last|rate < last*(1.0-TOLERANCE)) {
+            self.growing = !self.growing;
+            self.reversals += 1;
+        }
+        self.last = Some(rate);
+        if self.freeze && self.reversals >= FREEZE_REVERSALS {
+            info!("staying at {} files at once, the best throughput seen ({:.1}M/s)",self.best.0,self.best.1);
+            self.frozen = true;
+            self.set(self.best.0);
+            return
+        }
+        // at the floor or the ceiling the only way is back
+        if (self.growing && self.limit == self.max) || (!self.growing && self.limit == self.min) {self.growing = !self.growing;}
+        let limit = if self.growing {self.limit+1} else {self.limit-1};
+        debug!("{} files at once read {rate:.1}M/s (best so far {} at {:.1}M/s), allowing {limit} now",self.limit,self.best.0,self.best.1);
+        self.set(limit.clamp(self.min,self.max));
+    }
+    fn set(&mut self,limit:usize)
+    {
+        self.limit = limit;
+        self.range = (self.range.0.min(limit),self.range.1.max(limit));
+    }
+    /// the lowest and highest limit used, and the last one
+    pub fn used(&self) -> (usize,usize,usize) {(self.range.0,self.range.1,self.limit)}
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+#[cfg(unix)]
+use log::{debug, warn};
+
+/// open the file for reading without updating its access time (O_NOATIME) where permitted
+///
+/// Only the owner of a file (or root) may, others open it the usual way.
+#[cfg(target_os="linux")]
+pub async fn open(path:&Path) -> std::io::Result<tokio::fs::File>
+{
+    match tokio::fs::OpenOptions::new().read(true).custom_flags(libc::O_NOATIME).open(path).await {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => {
+            debug!("'{}' is opened without O_NOATIME (not permitted)",path.to_string_lossy());
+            tokio::fs::File::open(path).await
+        }
+        Ok(file) => {
+            debug!("'{}' is opened with O_NOATIME",path.to_string_lossy());
+            Ok(file)
+        }
+        Err(e) => Err(e)
+    }
+}
+
+#[cfg(not(target_os="linux"))]
+pub async fn open(path:&Path) -> std::io::Result<tokio::fs::File>
+{
+    tokio::fs::File::open(path).await
+}
+
+/// the access time of the file (seconds and nanoseconds), to restore it after reading it with --restore-atime
+#[cfg(unix)]
+pub fn accessed(path:&Path) -> Option<(i64,i64)>
+{
+    use std::os::unix::fs::MetadataExt;
+    path.metadata().ok().map(|metadata|(metadata.atime(),metadata.atime_nsec()))
+}
+
+#[cfg(not(unix))]
+pub fn accessed(_path:&Path) -> Option<(i64,i64)>
+{
+    None
+}
+
+/// sets the access time of the file back to what it was before when dropped, if reading changed it
+///
+/// The modification time is left alone (reading doesn't change it).
+pub struct Restore<'p>
+{
+    pub path:&'p Path,
+    pub before:(i64,i64)
+}
+
+impl Drop for Restore<'_>
+{
+    fn drop(&mut self) {restore(self.path,self.before)}
+}
+
+#[cfg(unix)]
+fn restore(path:&Path,before:(i64,i64))
+{
+    use std::os::unix::ffi::OsStrExt;
+    if accessed(path).is_none_or(|now|now == before) {
+        debug!("the access time of '{}' was kept",path.to_string_lossy());
+        return
+    }
+    let Ok(name) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {return};
+    let times = [
+        libc::timespec{tv_sec:before.0 as libc::time_t,tv_nsec:before.1 as _},
+        libc::timespec{tv_sec:0,tv_nsec:libc::UTIME_OMIT}
+    ];
+    if unsafe {libc::utimensat(libc::AT_FDCWD,name.as_ptr(),times.as_ptr(),0)} < 0 {
+        warn!("failed to restore the access time of '{}': {}",path.to_string_lossy(),std::io::Error::last_os_error());
+    } else {
+        debug!("the access time of '{}' was restored",path.to_string_lossy());
+    }
+}
+
+#[cfg(not(unix))]
+fn restore(_path:&Path,_before:(i64,i64)) {}
+
+#[cfg(all(test,unix))]
+mod tests
+{
+    use std::time::{Duration, SystemTime};
+    use tokio::io::AsyncReadExt;
+    use super::*;
+
+    /// a file last accessed long ago, so reading it updates the access time (also with relatime)
+    fn old_file(dir:&tempfile::TempDir) -> std::path::PathBuf
+    {
+        let path = dir.path().join("file");
+        std::fs::write(&path,"hello\n").unwrap();
+        let old = SystemTime::UNIX_EPOCH+Duration::from_secs(946684800);
+        std::fs::File::options().write(true).open(&path).unwrap().set_times(std::fs::FileTimes::new().set_accessed(old)).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn reading_keeps_the_access_time()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = old_file(&dir);
+        let before = accessed(&path).unwrap();
+        assert_eq!(before.0,946684800);
+        {
+            let _restore = Restore{path:&path,before};
+            let mut content = String::new();
+            open(&path).await.unwrap().read_to_string(&mut content).await.unwrap();
+            // without O_NOATIME (or where it's not permitted)
+            std::fs::read(&path).unwrap();
+        }
+        assert_eq!(accessed(&path),Some(before));
+    }
+}
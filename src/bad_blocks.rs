@@ -0,0 +1,125 @@
+//! --probe-bad-blocks: where the unreadable part of a file is, found by reading small blocks after the offset reading failed at
+use std::path::Path;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+
+/// the size of each probe, and so how exact the offsets found are
+const BLOCK:u64 = 4096;
+/// at most this many probes per file
+const MAX_PROBES:u32 = 64;
+/// and for at most this long (a probe of a bad block can take long itself, it's not waited for after that)
+const MAX_TIME:Duration = Duration::from_secs(30);
+/// the first probes read block by block, as reading usually fails right at the bad block (or shortly after with readahead)
+const LINEAR_PROBES:u32 = 16;
+
+struct Prober
+{
+    file:std::fs::File,
+    probes:u32,
+    started:Instant
+}
+
+impl Prober
+{
+    /// if the block at offset can be read, None once out of probes or time
+    fn readable(&mut self,offset:u64) -> Option<bool>
+    {
+        if self.probes >= MAX_PROBES || self.started.elapsed() >= MAX_TIME {return None}
+        self.probes += 1;
+        let mut block = [0;BLOCK as usize];
+        Some(read_at(&self.file,&mut block,offset).is_ok())
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file:&std::fs::File,buffer:&mut [u8],offset:u64) -> std::io::Result<usize>
+{
+    std::os::unix::fs::FileExt::read_at(file,buffer,offset)
+}
+#[cfg(windows)]
+fn read_at(file:&std::fs::File,buffer:&mut [u8],offset:u64) -> std::io::Result<usize>
+{
+    std::os::windows::fs::FileExt::seek_read(file,buffer,offset)
+}
+
+/// the first unreadable offset at or after offset and the length found unreadable from there, None if all probed was readable
+///
+/// Both ends are found by probing further each time (after the first blocks), then halving the gap to the last block on the other side.
+/// If the probes run out, it's what was found unreadable so far (the extent may start earlier and be longer).
+fn probe(path:&Path,offset:u64,size:u64) -> std::io::Result<Option<(u64,u64,u32)>>
+{
+    let mut prober = Prober{file:std::fs::File::open(path)?,probes:0,started:Instant::now()};
+    let (mut good,mut bad,mut step) = (None,offset-offset%BLOCK,BLOCK);
+    loop {
+        if bad >= size {return Ok(None)}
+        match prober.readable(bad) {
+            Some(true) => {
+                good = Some(bad);
+                bad += step;
+                if prober.probes >= LINEAR_PROBES {step *= 2;}
+            }
+            Some(false) => break,
+            None => return Ok(None)
+        }
+    }
+    if let Some(mut good) = good {
+        while bad-good > BLOCK {
+            let middle = good+(bad-good)/BLOCK/2*BLOCK;
+            match prober.readable(middle) {
+                Some(true) => good = middle,
+                Some(false) => bad = middle,
+                None => break
+            }
+        }
+    }
+    // the last unreadable block, and the first readable one after it (or the end)
+    let (mut last,mut end,mut step) = (bad,bad+BLOCK,BLOCK);
+    while end < size {
+        match prober.readable(end) {
+            Some(false) => {last = end;end += step;step *= 2;}
+            Some(true) => break,
+            None => {end = last+BLOCK;break}
+        }
+    }
+    let mut end = end.min(size);
+    while end-last > BLOCK {
+        let middle = last+(end-last)/BLOCK/2*BLOCK;
+        match prober.readable(middle) {
+            Some(false) => last = middle,
+            Some(true) => end = middle,
+            None => break
+        }
+    }
+    Ok(Some((bad,(last+BLOCK).min(size)-bad,prober.probes)))
+}
+
+/// the first unreadable offset of the local file (of size) at or after offset, where reading it failed, and the length unreadable from there
+pub(crate) async fn locate(path:&Path,offset:u64,size:u64) -> Option<(u64,u64)>
+{
+    let probed = {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move||probe(&path,offset,size))
+    };
+    match tokio::time::timeout(MAX_TIME+Duration::from_secs(1),probed).await {
+        Ok(Ok(Ok(Some((first,length,probes))))) => {
+            info!("'{}' is unreadable from offset {first} for {length} bytes (found with {probes} probes)",path.to_string_lossy());
+            Some((first,length))
+        }
+        Ok(Ok(Ok(None))) => {
+            info!("probing '{}' after offset {offset} found no unreadable block",path.to_string_lossy());
+            None
+        }
+        Ok(Ok(Err(e))) => {
+            warn!("failed probing '{}' for bad blocks: {e}",path.to_string_lossy());
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("probing '{}' for bad blocks failed: {e}",path.to_string_lossy());
+            None
+        }
+        Err(_) => {
+            warn!("probing '{}' for bad blocks didn't finish within {}, giving up on it",path.to_string_lossy(),humantime::format_duration(MAX_TIME));
+            None
+        }
+    }
+}
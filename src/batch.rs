@@ -0,0 +1,60 @@
+//! --release-batch: the files verified are released together, once enough are queued or the first one waited long enough
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// the files queued for release
+pub(crate) struct Batch
+{
+    /// files released at once at most
+    pub size:usize,
+    /// how long the first file queued waits at most
+    pub wait:Option<Duration>,
+    files:Vec<PathBuf>,
+    started:Option<Instant>
+}
+
+impl Batch
+{
+    pub fn new(size:usize,wait:Option<Duration>) -> Batch
+    {
+        Batch{size:size.max(1),wait,files:vec![],started:None}
+    }
+    /// queue the file, returns if the batch is full
+    pub fn push(&mut self,path:PathBuf) -> bool
+    {
+        self.files.push(path);
+        self.started.get_or_insert_with(Instant::now);
+        self.files.len() >= self.size
+    }
+    /// the files queued, the next batch starts empty
+    pub fn take(&mut self) -> Vec<PathBuf>
+    {
+        self.started = None;
+        std::mem::take(&mut self.files)
+    }
+    /// when the batch is to be released, as its first file waited long enough
+    pub fn deadline(&self) -> Option<Instant>
+    {
+        self.started.zip(self.wait).map(|(started,wait)|started+wait)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn batches_are_released_when_full_or_waited()
+    {
+        let mut batch = Batch::new(2,Some(Duration::from_secs(60)));
+        assert!(batch.deadline().is_none());
+        assert!(!batch.push("a".into()));
+        assert!(batch.deadline().is_some_and(|deadline|deadline > Instant::now()));
+        assert!(batch.push("b".into()));
+        assert_eq!(batch.take(),[PathBuf::from("a"),PathBuf::from("b")]);
+        assert!(batch.deadline().is_none() && batch.take().is_empty());
+        assert!(Batch::new(3,None).deadline().is_none());
+    }
+}
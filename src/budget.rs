@@ -0,0 +1,81 @@
+//! --daemon: the --max-size of the daemon, shared by the jobs checked at once (each by a reader of its own)
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// the bytes of the files being checked by all jobs
+pub(crate) struct Budget
+{
+    max:u64,
+    used:AtomicU64,
+    freed:Notify
+}
+
+impl Budget
+{
+    pub fn new(max:u64) -> Arc<Budget>
+    {
+        Arc::new(Budget{max,used:AtomicU64::new(0),freed:Notify::new()})
+    }
+}
+
+/// the part of the budget a reader uses, given back when dropped (so a job stopped with checks running doesn't keep it)
+pub(crate) struct Share
+{
+    budget:Arc<Budget>,
+    taken:u64
+}
+
+impl Share
+{
+    pub fn new(budget:Arc<Budget>) -> Share {Share{budget,taken:0}}
+    pub fn max(&self) -> u64 {self.budget.max}
+    /// the bytes used by the checks of the other readers
+    pub fn others(&self) -> u64 {self.budget.used.load(Ordering::Relaxed).saturating_sub(self.taken)}
+    pub fn take(&mut self,size:u64)
+    {
+        self.taken += size;
+        self.budget.used.fetch_add(size,Ordering::Relaxed);
+    }
+    pub fn give(&mut self,size:u64)
+    {
+        self.taken -= size;
+        self.budget.used.fetch_sub(size,Ordering::Relaxed);
+        self.budget.freed.notify_waiters();
+    }
+    /// wait until another reader gives back some of the budget, or at most for timeout
+    pub async fn freed(&self,timeout:Duration)
+    {
+        let _ = tokio::time::timeout(timeout,self.budget.freed.notified()).await;
+    }
+}
+
+impl Drop for Share
+{
+    fn drop(&mut self)
+    {
+        if self.taken > 0 {self.give(self.taken);}
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn shares_are_given_back_when_dropped()
+    {
+        let budget = Budget::new(100);
+        let (mut a,mut b) = (Share::new(budget.clone()),Share::new(budget.clone()));
+        a.take(30);
+        b.take(50);
+        assert_eq!((a.others(),b.others()),(50,30));
+        a.give(10);
+        assert_eq!(b.others(),20);
+        drop(a);
+        assert_eq!(b.others(),0);
+        assert_eq!(budget.used.load(Ordering::Relaxed),50);
+    }
+}
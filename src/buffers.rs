@@ -0,0 +1,58 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+/// read buffers reused by the checks, instead of allocating (and zeroing) one for each file
+///
+/// Buffers are taken when a file is started and given back when it's done, so there are never more
+/// than files being checked at the same time. Of those given back at most keep are kept for later.
+pub(crate) struct BufferPool
+{
+    size:usize,
+    keep:usize,
+    idle:Mutex<Vec<Vec<u8>>>
+}
+
+/// a buffer of the pool, given back when dropped
+pub(crate) struct Buffer
+{
+    buffer:Vec<u8>,
+    pool:Arc<BufferPool>
+}
+
+impl BufferPool
+{
+    /// buffers of size bytes, keeping at most keep of them
+    pub fn new(size:usize,keep:usize) -> Arc<BufferPool>
+    {
+        Arc::new(BufferPool{size:size.max(1),keep,idle:Mutex::new(vec![])})
+    }
+    /// the size of the buffers (--tar-stream reads with one of its own)
+    #[cfg(feature="tar")]
+    pub fn size(&self) -> usize {self.size}
+    /// a buffer kept before, or a new one
+    pub fn take(self:&Arc<Self>) -> Buffer
+    {
+        let idle = self.idle.lock().expect("buffer pool lock").pop();
+        Buffer{buffer:idle.unwrap_or_else(||vec![0;self.size]),pool:self.clone()}
+    }
+}
+
+impl Deref for Buffer
+{
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {&self.buffer}
+}
+
+impl DerefMut for Buffer
+{
+    fn deref_mut(&mut self) -> &mut [u8] {&mut self.buffer}
+}
+
+impl Drop for Buffer
+{
+    fn drop(&mut self)
+    {
+        let mut idle = self.pool.idle.lock().expect("buffer pool lock");
+        if idle.len() < self.pool.keep {idle.push(std::mem::take(&mut self.buffer));}
+    }
+}
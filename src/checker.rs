@@ -0,0 +1,543 @@
+//! the library interface to the checking
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use crate::error::Error;
+use crate::events::{Event, Events};
+use crate::options::Options;
+use crate::retry::RetryPolicy;
+use crate::{Digest, Digests, Entry, Reader, Source, Status};
+
+/// the digest files are checked with
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+#[non_exhaustive]
+pub enum Algorithm
+{
+    #[default]
+    Md5,
+    Sha256
+}
+
+/// the verdict for a file (or checksum file)
+#[derive(Clone,Debug,PartialEq)]
+#[non_exhaustive]
+pub struct CheckResult
+{
+    /// as listed, "archive.zip//member" for archive members
+    pub path:PathBuf,
+    pub status:Status,
+    /// the digest it was checked against, if it was read
+    pub expected:Option<String>,
+    /// the digest read
+    pub computed:Option<String>,
+    /// its size when it was checked
+    pub size:Option<u64>,
+    /// how long staging, opening and reading it took (None for files listed again, that were read for another listing)
+    pub duration:Option<Duration>,
+    /// the file was modified after its checksum file
+    pub newer_than_manifest:bool,
+    /// the file has other hard links, and the digest read for one of them was used
+    pub hardlink:bool,
+    /// the target of a symlink reported instead of checked
+    pub target:Option<PathBuf>,
+    /// the algorithm of the digests, like "md5"
+    pub algorithm:Option<&'static str>,
+    /// the offset reading failed at
+    pub failed_at:Option<u64>,
+    /// the first offset found unreadable with --probe-bad-blocks, and the length unreadable from there
+    pub bad_extent:Option<(u64,u64)>
+}
+
+impl CheckResult
+{
+    pub(crate) fn new(path:PathBuf,status:Status) -> CheckResult
+    {
+        CheckResult{path,status,expected:None,computed:None,size:None,duration:None,newer_than_manifest:false,hardlink:false,target:None,algorithm:None,failed_at:None,bad_extent:None}
+    }
+}
+
+/// configures a [`Checker`]
+pub struct CheckerBuilder
+{
+    options:Options,
+    events:Events,
+    release_unverified:bool,
+    /// the commands are checked to be runnable by build
+    validate:bool,
+    digests:Digests,
+    source:Option<Arc<dyn Source>>
+}
+
+impl Default for CheckerBuilder
+{
+    fn default() -> Self {Self::new()}
+}
+
+impl CheckerBuilder
+{
+    pub fn new() -> CheckerBuilder
+    {
+        CheckerBuilder::with(Options::default(),Digests::default(),None,Events::default())
+    }
+    /// the command line tool sets up the reader with its own options (and checks the commands itself)
+    pub(crate) fn with(options:Options,digests:Digests,source:Option<Arc<dyn Source>>,events:Events) -> CheckerBuilder
+    {
+        CheckerBuilder{options,events,release_unverified:false,validate:true,digests,source}
+    }
+    pub(crate) fn unvalidated(mut self) -> Self
+    {
+        self.validate = false;
+        self
+    }
+    /// the most bytes of files being checked at a time (default 1 TiB)
+    ///
+    /// A file bigger than that fails with [`Status::Error`] (oversize), unless [`allow_oversize`](Self::allow_oversize).
+    pub fn budget(mut self,bytes:u64) -> Self
+    {
+        self.options.max_size = bytes;
+        self
+    }
+    /// check files bigger than the budget alone, instead of failing them
+    pub fn allow_oversize(mut self,allow:bool) -> Self
+    {
+        self.options.allow_oversize = allow;
+        self
+    }
+    /// the digest of the files, md5 and sha256 are built in (and both known, the length of a digest tells which it is)
+    pub fn algorithm(self,algorithm:Algorithm) -> Self
+    {
+        match algorithm {Algorithm::Md5|Algorithm::Sha256 => self}
+    }
+    /// add a digest algorithm made by factory, files are checked by the one with the length of their expected digest
+    ///
+    /// It replaces the one with the same name or length known so far (like md5).
+    pub fn register_digest(mut self,factory:impl Fn()->Box<dyn Digest>+Send+Sync+'static) -> Self
+    {
+        self.digests.register(factory);
+        self
+    }
+    /// which errors opening a file are retried how often (by default TimedOut and Interrupted, forever)
+    pub fn open_retries(mut self,policy:RetryPolicy) -> Self
+    {
+        self.options.open_policy = policy;
+        self
+    }
+    /// command run before a file is opened, it's skipped if the command fails
+    ///
+    /// Commands get the path appended, or replacing an argument `{}`, and run with the same environment
+    /// variables as with the command line tool.
+    pub fn pre_hook(mut self,cmd:&str) -> Self
+    {
+        self.options.pre_hook = Some(cmd.into());
+        self
+    }
+    /// command to recall a file from tape before it's opened
+    pub fn stage(mut self,cmd:&str) -> Self
+    {
+        self.options.stage = Some(cmd.into());
+        self
+    }
+    /// command run after a file was checked, whatever the result
+    pub fn post_hook(mut self,cmd:&str) -> Self
+    {
+        self.options.post_hook = Some(cmd.into());
+        self
+    }
+    /// command run for files that verified OK, given more than once the commands run in order until one fails
+    pub fn release(mut self,cmd:&str) -> Self
+    {
+        self.options.release.push(cmd.into());
+        self
+    }
+    /// also release files that failed verification
+    pub fn release_on_fail(mut self,release:bool) -> Self
+    {
+        self.options.release_on_fail = release;
+        self
+    }
+    /// command run for files that failed verification or could not be checked
+    pub fn on_fail(mut self,cmd:&str) -> Self
+    {
+        self.options.on_fail = Some(cmd.into());
+        self
+    }
+    /// once stopped, release the files that were staged or being checked, but not verified
+    ///
+    /// Files that verified before are released either way.
+    pub fn release_unverified(mut self,release:bool) -> Self
+    {
+        self.release_unverified = release;
+        self
+    }
+    /// run the commands via the shell, with the path(s) as positional parameters
+    pub fn command_shell(mut self,shell:bool) -> Self
+    {
+        self.options.commands.shell = shell;
+        self
+    }
+    /// kill commands that run longer than this
+    pub fn command_timeout(mut self,timeout:Option<Duration>) -> Self
+    {
+        self.options.commands.timeout = timeout;
+        self
+    }
+    /// read the files from this source instead of the local file system
+    ///
+    /// The paths submitted are given to it as they are. The open retries are up to the source, and symlinks,
+    /// special files, hard links and zip members are only looked at for local sources.
+    pub fn source(mut self,source:impl Source+'static) -> Self
+    {
+        self.source = Some(Arc::new(source));
+        self
+    }
+    /// send what happens while checking to the channel, like files admitted, bytes read and releases
+    ///
+    /// Events are only made while the receiver is there, they are sent as they happen (the channel is
+    /// unbounded, so a receiver not keeping up takes memory, but doesn't hold up the checks).
+    pub fn events(mut self,sender:mpsc::UnboundedSender<Event>) -> Self
+    {
+        self.events = Events::new(sender);
+        self
+    }
+    /// fails with [`Error::InvalidCommand`] if a command can't be run
+    pub fn build(self) -> Result<Checker,Error>
+    {
+        let (canceller,receiver) = Canceller::new();
+        let reader = Reader::new(&self.options,receiver,self.events,self.digests,self.source);
+        if self.validate {
+            for (what,cmd) in reader.commands() {
+                cmd.validate().map_err(|reason|Error::InvalidCommand{what,reason})?;
+            }
+        }
+        Ok(Checker{reader,canceller,release_unverified:self.release_unverified})
+    }
+}
+
+/// checks files against their expected digests, reading as many at a time as the budget allows
+///
+/// Files are checked in the background while more are submitted. Their results are taken with [`next`](Checker::next)
+/// (or as [`Stream`](Checker::into_stream)) as they're done, or all at once by [`finish`](Checker::finish).
+/// ```no_run
+/// # async fn example() -> Result<(),md5check::Error> {
+/// let mut checker = md5check::CheckerBuilder::new().budget(10<<30).stage("dmget").build()?;
+/// checker.submit("/archive/run1.dat","b026324c6904b2a9cb4b88d6d61c81d1").await?;
+/// for result in checker.finish().await? {
+///     println!("{} {}",result.path.display(),result.status);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Checker
+{
+    reader:Reader,
+    /// the reader stops when told so by this
+    canceller:Canceller,
+    release_unverified:bool
+}
+
+impl Checker
+{
+    /// the reader to check with by the command line tool
+    pub(crate) fn into_parts(self) -> (Reader,Canceller)
+    {
+        (self.reader,self.canceller)
+    }
+    /// check the file against the expected digest (hex, of an algorithm registered)
+    ///
+    /// Waits while the budget is taken by the files being checked. Those finishing meanwhile are
+    /// collected for [`results`](Checker::results).
+    pub async fn submit(&mut self,path:impl Into<PathBuf>,expected:&str) -> Result<(),Error>
+    {
+        self.submit_all([(path,expected)]).await
+    }
+    /// check the files against their expected digests, like [`submit`](Checker::submit)
+    ///
+    /// A path given more than once with digests of different algorithms is read once, computing all of them.
+    /// Fails with [`Error::InvalidDigest`] before any is checked if one of the digests is not valid.
+    pub async fn submit_all<P:Into<PathBuf>,S:AsRef<str>>(&mut self,files:impl IntoIterator<Item=(P,S)>) -> Result<(),Error>
+    {
+        let mut entries = vec![];
+        for (path,expected) in files {
+            let (path,expected) = (path.into(),expected.as_ref());
+            if !self.reader.check.digests.known_len(expected.len()) || !expected.chars().all(|c|c.is_ascii_hexdigit()) {
+                return Err(Error::InvalidDigest{path,digest:expected.into()});
+            }
+            entries.push(Entry{
+                path,expected:expected.to_ascii_lowercase(),manifest:Path::new("").into(),
+                size:0,mtime:None,id:None,recorded:None,member:None,along:vec![]
+            });
+        }
+        for entry in &entries {self.reader.events.emit(||Event::Listed{path:entry.path.clone()});}
+        self.reader.add_all(entries).await
+    }
+    /// a handle to stop the checking from elsewhere
+    pub fn canceller(&self) -> Canceller
+    {
+        self.canceller.clone()
+    }
+    /// the files submitted that were not checked as it was stopped (not started, or cancelled)
+    pub fn not_attempted(&self) -> Vec<PathBuf>
+    {
+        self.reader.not_attempted().into_iter().map(|entry|entry.path).collect()
+    }
+    /// the results of the files finished so far, that were not taken yet
+    pub fn results(&mut self) -> Vec<CheckResult>
+    {
+        self.reader.results.drain(..).collect()
+    }
+    /// the next result, None once all files submitted are done (and their commands finished)
+    ///
+    /// Awaiting this is what drives the checks: only then are files found done, making room in the budget
+    /// for the ones waiting and running their commands. So a caller that stops taking results (and
+    /// submitting) holds up the checks, instead of results piling up.
+    pub async fn next(&mut self) -> Option<Result<CheckResult,Error>>
+    {
+        match self.reader.next_result().await {
+            Ok(Some(result)) => Some(Ok(result)),
+            Ok(None) => {
+                if self.release_unverified && self.reader.interrupted() {self.reader.release_unverified();}
+                self.reader.join_releases().await.err().map(Err)
+            }
+            Err(e) => Some(Err(e))
+        }
+    }
+    /// the results as a stream, see [`next`](Checker::next)
+    pub fn into_stream(self) -> impl futures_util::Stream<Item=Result<CheckResult,Error>>
+    {
+        futures_util::stream::unfold(self,|mut checker|async move {
+            checker.next().await.map(|result|(result,checker))
+        })
+    }
+    /// wait for all files submitted (and their commands), returns the results not taken yet
+    pub async fn finish(mut self) -> Result<Vec<CheckResult>,Error>
+    {
+        let mut results = self.results();
+        while let Some(result) = self.next().await {results.push(result?);}
+        Ok(results)
+    }
+}
+
+/// stops a [`Checker`] (or the command line tool) from elsewhere, like interrupts do
+///
+/// Once stopped no more files are started, and files submitted are not checked (see [`Checker::not_attempted`]).
+/// Results for the files done meanwhile are still given, and their commands run.
+#[derive(Clone)]
+pub struct Canceller(Arc<watch::Sender<u32>>);
+
+impl Canceller
+{
+    pub(crate) fn new() -> (Canceller,watch::Receiver<u32>)
+    {
+        let (sender,receiver) = watch::channel(0);
+        (Canceller(Arc::new(sender)),receiver)
+    }
+    /// don't start checking more files, the running checks are finished (like the first Ctrl-C)
+    pub fn stop(&self)
+    {
+        self.0.send_modify(|level|*level = (*level).max(1));
+    }
+    /// cancel the running checks as well (like the second Ctrl-C)
+    ///
+    /// They stop reading right away (killing their stage command), their size is given back to the budget,
+    /// and they're reported as [`Status::Cancelled`], without running their commands.
+    pub fn cancel(&self)
+    {
+        self.0.send_modify(|level|*level = (*level).max(2));
+    }
+    /// if it was stopped (or cancelled)
+    pub fn stopped(&self) -> bool
+    {
+        *self.0.borrow() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{DigestValue, SourceFuture, SourceMetadata};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+    use tokio::sync::Notify;
+
+    /// the sum of the bytes, as a second algorithm of 8 hex digits
+    struct Sum32(u32);
+
+    impl Digest for Sum32
+    {
+        fn update(&mut self,bytes:&[u8]) -> std::io::Result<()>
+        {
+            self.0 = bytes.iter().fold(self.0,|sum,b|sum.wrapping_add(*b as u32));
+            Ok(())
+        }
+        fn finalize(self:Box<Self>) -> std::io::Result<DigestValue> {Ok(DigestValue(self.0.to_be_bytes().to_vec()))}
+        fn name(&self) -> &'static str {"sum32"}
+        fn hex_len(&self) -> usize {8}
+    }
+
+    /// an algorithm of 16 hex digits that panics reading
+    struct Panicking;
+
+    impl Digest for Panicking
+    {
+        fn update(&mut self,_:&[u8]) -> std::io::Result<()> {panic!("no digest today")}
+        fn finalize(self:Box<Self>) -> std::io::Result<DigestValue> {Ok(DigestValue(vec![0;8]))}
+        fn name(&self) -> &'static str {"panicking"}
+        fn hex_len(&self) -> usize {16}
+    }
+
+    const HELLO_MD5:&str = "b1946ac92492d2347c6235b4d2611184";
+    const HELLO_SUM:&str = "0000021e";
+
+    /// a checker with md5 and sum32, and the events it sends
+    fn checker() -> (Checker,mpsc::UnboundedReceiver<Event>)
+    {
+        let (sender,receiver) = mpsc::unbounded_channel();
+        let checker = CheckerBuilder::new().register_digest(||Box::new(Sum32(0))).events(sender).build().unwrap();
+        (checker,receiver)
+    }
+    fn hello(dir:&tempfile::TempDir) -> PathBuf
+    {
+        let path = dir.path().join("hello");
+        std::fs::write(&path,"hello\n").unwrap();
+        path
+    }
+    fn admitted(events:&mut mpsc::UnboundedReceiver<Event>) -> usize
+    {
+        std::iter::from_fn(||events.try_recv().ok()).filter(|event|matches!(event,Event::Admitted{..})).count()
+    }
+    fn verdicts(results:&[CheckResult]) -> Vec<(Status,Option<&'static str>)>
+    {
+        let mut verdicts:Vec<_> = results.iter().map(|result|(result.status,result.algorithm)).collect();
+        verdicts.sort_by_key(|(_,algorithm)|*algorithm);
+        verdicts
+    }
+
+    #[tokio::test]
+    async fn several_algorithms_are_read_once()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hello(&dir);
+        let (mut checker,mut events) = checker();
+        checker.submit_all([(&path,HELLO_MD5),(&path,HELLO_SUM)]).await.unwrap();
+        let results = checker.finish().await.unwrap();
+        assert_eq!(verdicts(&results),[(Status::Ok,Some("md5")),(Status::Ok,Some("sum32"))]);
+        assert_eq!(admitted(&mut events),1);
+    }
+
+    #[tokio::test]
+    async fn verdicts_of_several_algorithms_differ()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hello(&dir);
+        let (mut checker,_events) = checker();
+        checker.submit_all([(&path,HELLO_MD5),(&path,"00000000")]).await.unwrap();
+        let results = checker.finish().await.unwrap();
+        assert_eq!(verdicts(&results),[(Status::Ok,Some("md5")),(Status::Fail,Some("sum32"))]);
+    }
+
+    #[tokio::test]
+    async fn panics_are_errors_of_their_file()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hello(&dir);
+        let other = dir.path().join("other");
+        std::fs::write(&other,"hello\n").unwrap();
+        let mut checker = CheckerBuilder::new().register_digest(||Box::new(Panicking)).build().unwrap();
+        checker.submit(&path,"0000000000000000").await.unwrap();
+        checker.submit(&other,HELLO_MD5).await.unwrap();
+        let mut results = checker.finish().await.unwrap();
+        results.sort_by(|a,b|a.path.cmp(&b.path));
+        let statuses:Vec<_> = results.iter().map(|result|(result.path.file_name().unwrap().to_str().unwrap(),result.status)).collect();
+        assert_eq!(statuses,[("hello",Status::Error(Some("panic"))),("other",Status::Ok)]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cancelling_stops_the_running_checks()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hello(&dir);
+        let other = dir.path().join("other");
+        std::fs::write(&other,"hello\n").unwrap();
+        // one file at a time, staged for long
+        let mut checker = CheckerBuilder::new().budget(6).stage("sleep 30").command_shell(true).build().unwrap();
+        let started = std::time::Instant::now();
+        checker.submit(&path,HELLO_MD5).await.unwrap();
+        let canceller = checker.canceller();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            canceller.cancel();
+        });
+        // waits for the budget until cancelled
+        checker.submit(&other,HELLO_MD5).await.unwrap();
+        // the one cancelled wasn't checked either
+        let mut not_attempted = checker.not_attempted();
+        not_attempted.sort();
+        assert_eq!(not_attempted,[path.clone(),other]);
+        let results = checker.finish().await.unwrap();
+        assert_eq!(results.iter().map(|result|(&result.path,result.status)).collect::<Vec<_>>(),[(&path,Status::Cancelled)]);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    /// gives the first half of its content, then stalls the read (telling so)
+    struct Stalling(&'static [u8],Arc<Notify>);
+
+    impl Source for Stalling
+    {
+        fn metadata<'a>(&'a self,_path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+        {
+            Box::pin(async {Ok(SourceMetadata{size:self.0.len() as u64,modified:None})})
+        }
+        fn open<'a>(&'a self,_path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+        {
+            let half = &self.0[offset as usize..self.0.len()/2];
+            Box::pin(async move {Ok(Box::new(half.chain(Stall(self.1.clone()))) as Box<dyn AsyncRead+Unpin+Send>)})
+        }
+    }
+
+    struct Stall(Arc<Notify>);
+
+    impl AsyncRead for Stall
+    {
+        fn poll_read(self:Pin<&mut Self>,_:&mut Context<'_>,_:&mut ReadBuf<'_>) -> Poll<std::io::Result<()>>
+        {
+            self.0.notify_one();
+            Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_read_gives_back_its_budget()
+    {
+        let stalled = Arc::new(Notify::new());
+        let mut checker = CheckerBuilder::new().budget(100).source(Stalling(b"hello world\n",stalled.clone())).build().unwrap();
+        checker.submit("slow",HELLO_MD5).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(10),stalled.notified()).await.expect("the read didn't start");
+        assert_eq!(checker.reader.cur_size,12);
+        checker.canceller().cancel();
+        let mut results = vec![];
+        while let Some(result) = checker.next().await {results.push(result.unwrap());}
+        assert_eq!(results.iter().map(|result|(result.path.as_path(),result.status)).collect::<Vec<_>>(),[(Path::new("slow"),Status::Cancelled)]);
+        assert_eq!(checker.not_attempted(),[PathBuf::from("slow")]);
+        assert_eq!((checker.reader.cur_size,checker.reader.used()),(0,0));
+        assert!(checker.reader.in_flight.is_empty() && checker.reader.progress.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cached_digests_are_kept_per_algorithm()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hello(&dir);
+        let (mut checker,mut events) = checker();
+        // submitted on their own, the second one can't be read along, but must not use the md5 digest
+        checker.submit(&path,HELLO_MD5).await.unwrap();
+        checker.submit(&path,HELLO_SUM).await.unwrap();
+        checker.submit(&path,HELLO_MD5).await.unwrap();
+        let results = checker.finish().await.unwrap();
+        assert_eq!(verdicts(&results),[(Status::Ok,Some("md5")),(Status::Ok,Some("md5")),(Status::Ok,Some("sum32"))]);
+        assert_eq!(admitted(&mut events),2);
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use log::warn;
+use crate::{parse_line, Digests};
+
+/// the extension of the sidecar files with the chunk digests of a file
+pub static EXTENSION:&str = "chunks";
+
+/// md5 digests of fixed size chunks of a file, to tell where a file that failed is damaged
+///
+/// They're read from a sidecar file next to the file ("file.chunks"):
+/// a line `chunk-size <bytes>` followed by lines `<md5>  <chunk index>` (chunks are counted from 0,
+/// chunk n starts at byte n*chunk size and all but the last one are chunk size long).
+pub struct Chunks
+{
+    size:u64,
+    expected:Vec<Option<String>>,
+    context:md5::Context,
+    /// bytes in the current chunk so far
+    filled:u64,
+    index:usize,
+    /// chunks that didn't match
+    mismatched:Vec<usize>,
+}
+
+/// the result of checking the chunks of a file
+pub struct Checked
+{
+    pub size:u64,
+    pub count:usize,
+    /// the indices of the chunks that didn't match
+    pub mismatched:Vec<usize>
+}
+
+fn sidecar(path:&Path) -> PathBuf
+{
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(EXTENSION);
+    sidecar.into()
+}
+
+impl Chunks
+{
+    /// load the chunk digests for the file, if there are any
+    ///
+    /// A sidecar file that can't be parsed is ignored (with a warning).
+    pub async fn load(path:&Path) -> Option<Chunks>
+    {
+        let sidecar = sidecar(path);
+        let content = tokio::fs::read_to_string(&sidecar).await.ok()?;
+        match Self::parse(&content) {
+            Ok(chunks) => Some(chunks),
+            Err(e) => {
+                warn!("ignoring '{}': {e}",sidecar.to_string_lossy());
+                None
+            }
+        }
+    }
+    fn parse(content:&str) -> Result<Chunks,String>
+    {
+        let mut lines = content.lines().enumerate().filter(|(_,l)|!l.trim().is_empty());
+        let size = lines.next()
+            .and_then(|(_,line)|line.trim().strip_prefix("chunk-size"))
+            .and_then(|size|size.trim().parse::<u64>().ok())
+            .filter(|size|*size > 0)
+            .ok_or("the first line has to be \"chunk-size <bytes>\"")?;
+        let mut expected = vec![];
+        // chunk digests are md5
+        let digests = Digests::default();
+        for (nr,line) in lines {
+            let Some((digest,index)) = parse_line(line,&digests).map_err(|e|format!("line {}: {e}",nr+1))? else {continue};
+            let index:usize = index.parse().map_err(|e|format!("line {}: not a chunk index: {e}",nr+1))?;
+            if expected.len() <= index {expected.resize(index+1,None);}
+            expected[index] = Some(digest.to_lowercase());
+        }
+        Ok(Chunks{size,expected,context:md5::Context::new(),filled:0,index:0,mismatched:vec![]})
+    }
+    /// feed the next bytes of the file
+    pub fn consume(&mut self,mut data:&[u8])
+    {
+        while !data.is_empty() {
+            let take = (self.size-self.filled).min(data.len() as u64) as usize;
+            self.context.consume(&data[..take]);
+            self.filled += take as u64;
+            data = &data[take..];
+            if self.filled == self.size {self.finish_chunk();}
+        }
+    }
+    fn finish_chunk(&mut self)
+    {
+        let context = std::mem::replace(&mut self.context,md5::Context::new());
+        let computed = format!("{:x}",context.compute());
+        // chunks not listed can't mismatch
+        if matches!(self.expected.get(self.index),Some(Some(expected)) if *expected != computed) {
+            self.mismatched.push(self.index);
+        }
+        self.index += 1;
+        self.filled = 0;
+    }
+    /// compare the last chunk, chunks listed beyond the end of the file count as mismatched
+    pub fn finish(mut self) -> Checked
+    {
+        if self.filled > 0 {self.finish_chunk();}
+        let count = self.index;
+        self.mismatched.extend((count..self.expected.len()).filter(|i|self.expected[*i].is_some()));
+        Checked{size:self.size,count,mismatched:self.mismatched}
+    }
+}
@@ -0,0 +1,1828 @@
+//! the command line tool, on top of the checking in the crate root
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+#[cfg(feature="tar")]
+use std::thread;
+use std::time::{Duration, SystemTime};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueHint::FilePath};
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncBufReadExt};
+use crate::command::{self, ExtCommand};
+use crate::error::Error;
+use crate::events::Events;
+use crate::journal::Journal;
+use crate::retry::{RetryPolicy, Retryable};
+use crate::{adaptive, compare, config, extra, failed_script, lock, manifest_list, merge, path_compat, priority, sample, signature, status, tcman};
+use crate::options::{Accounting, Duplicates, HardLinks, Options, Order, Symlinks};
+#[cfg(feature="s3")]
+use crate::s3;
+#[cfg(feature="sftp")]
+use crate::sftp;
+#[cfg(feature="tar")]
+use crate::tar_stream;
+#[cfg(feature="sqlite")]
+use crate::ref_db;
+#[cfg(feature="tar")]
+use crate::manifest_path;
+use crate::watcher::Watcher;
+#[cfg(all(unix,feature="daemon"))]
+use crate::daemon;
+use crate::{group_algorithms, manifest_base, manifest_counts, signalled, path_bytes, Canceller, CheckResult, CheckerBuilder, DigestValue, Digests, Entry, Source, Event, File, Footer, Reader, Status, GIGABYTE, MEGABYTE};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, disable_help_subcommand = true)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    subcommand:Option<Subcommand>,
+    /// file(s) containing the md5 checksums, or directories with one of a conventional name (md5sum, MD5SUMS ...)
+    #[arg(value_hint = FilePath, default_value="md5sum")]
+    pub(crate) file:Vec<PathBuf>,
+    /// also check the checksum files listed in this file, one per line ("-" for stdin)
+    #[arg(long,value_hint = FilePath)]
+    pub(crate) manifest_list:Option<PathBuf>,
+    /// like --manifest-list, with the paths separated by NUL
+    #[arg(long,value_hint = FilePath,conflicts_with="manifest_list")]
+    pub(crate) manifest_list0:Option<PathBuf>,
+    /// check the entries of the files listed in this file (one per line) before the others; it's read again on SIGHUP or the ctl request reprioritize
+    #[arg(long,value_hint = FilePath,conflicts_with="tar_stream")]
+    pub(crate) priority_file:Option<PathBuf>,
+    /// like --priority-file, with the paths separated by NUL
+    #[arg(long,value_hint = FilePath,conflicts_with_all=["priority_file","tar_stream"])]
+    pub(crate) priority_file0:Option<PathBuf>,
+    /// keep running, and also check the checksum files showing up in this directory (and below) until stopped by a signal
+    #[arg(long,value_hint = clap::ValueHint::DirPath,conflicts_with_all=["tar_stream","sample","shuffle","prescan","dry_run","stream_digest"])]
+    pub(crate) watch:Option<PathBuf>,
+    /// with --watch, the names of the checksum files ("*" and "?" match any characters), the conventional ones by default
+    #[arg(long,value_name="PATTERN",requires="watch")]
+    pub(crate) watch_name:Vec<String>,
+    /// with --watch, check a checksum file once it wasn't changed for this long
+    #[arg(long,value_parser=humantime::parse_duration,default_value="30s",requires="watch")]
+    pub(crate) watch_stable:Duration,
+    /// keep running, checking the checksum files of the jobs submitted on --socket (see the ctl subcommand)
+    #[arg(long,requires="socket",conflicts_with_all=[
+        "file","manifest_list","manifest_list0","watch","tar_stream","sample","shuffle","prescan","dry_run","stream_digest","check_extra","confirm"
+    ])]
+    pub(crate) daemon:bool,
+    /// the Unix socket --daemon listens on, only the user running it can connect
+    #[arg(long,value_hint = FilePath,requires="daemon")]
+    pub(crate) socket:Option<PathBuf>,
+    /// jobs --daemon checks at once, sharing its --max-size
+    #[arg(long,default_value_t=4,value_parser=clap::value_parser!(u32).range(1..),requires="daemon")]
+    pub(crate) concurrent_jobs:u32,
+    /// check the files listed in the checksum files below this directory and below --base-dir-b, and compare the two copies
+    #[arg(long,value_hint = clap::ValueHint::DirPath,requires="base_dir_b",conflicts_with_all=[
+        "watch","daemon","tar_stream","sample","shuffle","prescan","dry_run","stream_digest","check_extra","confirm","source_url"
+    ])]
+    pub(crate) base_dir_a:Option<PathBuf>,
+    /// the directory with the other copies for --base-dir-a
+    #[arg(long,value_hint = clap::ValueHint::DirPath,requires="base_dir_a")]
+    pub(crate) base_dir_b:Option<PathBuf>,
+    /// check the files given (and the files below the directories given) against the digests this SQLite database has for their absolute path, instead of checksum files
+    #[arg(long,value_hint = FilePath,conflicts_with_all=[
+        "manifest_list","manifest_list0","watch","daemon","base_dir_a","tar_stream","dry_run","check_extra","confirm","source_url"
+    ])]
+    pub(crate) ref_db:Option<PathBuf>,
+    /// the table of --ref-db with the digests
+    #[arg(long,default_value="digests",requires="ref_db")]
+    pub(crate) ref_db_table:String,
+    /// the column of the --ref-db table with the paths
+    #[arg(long,default_value="path",requires="ref_db")]
+    pub(crate) ref_db_path_column:String,
+    /// the column of the --ref-db table with the digests (hex)
+    #[arg(long,default_value="md5",requires="ref_db")]
+    pub(crate) ref_db_digest_column:String,
+    /// look up the files below the local directory by the path below the one of --ref-db instead (e.g. "/mnt/tape7=/archive")
+    #[arg(long,value_name="LOCAL=LISTED",value_parser=parse_remap,requires="ref_db")]
+    pub(crate) ref_db_remap:Option<(PathBuf,String)>,
+    /// don't check the totals declared by the footers of the checksum files ("# files: <entries>" and "# bytes: <size of the files>")
+    #[arg(long)]
+    pub(crate) ignore_footer:bool,
+    /// show the number of files OK, FAIL and ERROR for each checksum file at the end
+    #[arg(long)]
+    pub(crate) summary_per_manifest:bool,
+    /// show the files, bytes, FAIL and ERROR and the slowest throughput for each directory at this depth (1 by default) at the end, those with failures first
+    #[arg(long,value_name="DEPTH",num_args=0..=1,require_equals=true,default_missing_value="1")]
+    pub(crate) aggregate_by_dir:Option<usize>,
+    /// maximum size of files active at the same time (in GBytes)
+    #[arg(short,long,default_value_t=1024)]
+    pub(crate) max_size:u64,
+    /// tune how many files are checked at once for the most throughput (within --max-size)
+    #[arg(long)]
+    pub(crate) adaptive:bool,
+    /// the fewest files checked at once with --adaptive (where it starts)
+    #[arg(long,default_value_t=1,requires="adaptive")]
+    pub(crate) adaptive_min_files:usize,
+    /// the most files checked at once with --adaptive
+    #[arg(long,default_value_t=32,requires="adaptive")]
+    pub(crate) adaptive_max_files:usize,
+    /// how long the throughput is measured before allowing one file more or less
+    #[arg(long,value_parser=humantime::parse_duration,default_value="30s",requires="adaptive")]
+    pub(crate) adaptive_window:Duration,
+    /// stay at the best number of files found once it settled (changed direction 3 times)
+    #[arg(long,requires="adaptive")]
+    pub(crate) adaptive_freeze:bool,
+    /// set the access time of files back after reading them, where they can't be read without changing it (O_NOATIME is used where permitted)
+    #[arg(long)]
+    pub(crate) restore_atime:bool,
+    /// how much of a file is read at once (e.g. "4M")
+    #[arg(long,value_parser=parse_size,default_value="1M")]
+    pub(crate) buffer_size:u64,
+    /// when a file's size is credited back to max-size: when it's done, or progressively as it's read
+    #[arg(long,value_enum,default_value_t=Accounting::WholeFile)]
+    pub(crate) accounting:Accounting,
+    /// check files bigger than max-size alone, instead of failing them
+    #[arg(long)]
+    pub(crate) allow_oversize:bool,
+    ///release command, given more than once the commands run in order until one fails
+    #[arg(long)]
+    pub(crate) release:Vec<String>,
+    /// delete files that verified OK
+    #[arg(long,conflicts_with="release")]
+    pub(crate) delete_after_verify:bool,
+    /// also run the release command for files that failed verification
+    #[arg(long)]
+    pub(crate) release_on_fail:bool,
+    /// run the commands for files with several hard links once (for the path read), or for each path listed
+    #[arg(long,value_enum,default_value_t=HardLinks::Once)]
+    pub(crate) release_hardlinks:HardLinks,
+    /// command to run for files that failed verification (or could not be read)
+    #[arg(long)]
+    pub(crate) on_fail:Option<String>,
+    /// errors to retry opening files on, as errno names, numbers or std::io::ErrorKind names
+    #[arg(long,value_delimiter=',',default_values=["TimedOut","Interrupted"])]
+    pub(crate) retry_errno:Vec<Retryable>,
+    /// give up opening a file after this many retries (default: never)
+    #[arg(long)]
+    pub(crate) open_retries:Option<u32>,
+    /// read errors to resume reading from where it failed on (after reopening the file)
+    #[cfg_attr(unix,arg(long,value_delimiter=',',default_values=["EIO","TimedOut","Interrupted"]))]
+    #[cfg_attr(not(unix),arg(long,value_delimiter=',',default_values=["TimedOut","Interrupted"]))]
+    pub(crate) resume_errno:Vec<Retryable>,
+    /// give up reading a file after resuming it this many times
+    #[arg(long,default_value_t=3)]
+    pub(crate) resume_attempts:u32,
+    /// after a read error, look for where the file is unreadable by reading a few small blocks after it (at most 64, for 30s)
+    #[arg(long)]
+    pub(crate) probe_bad_blocks:bool,
+    /// how long to wait for the file system of the files to come back when it's gone (ESTALE, ENODEV, ENOTCONN), 0s to not wait
+    #[arg(long,value_parser=humantime::parse_duration,default_value="10m")]
+    pub(crate) mount_wait:Duration,
+    /// command to run just before a file is checked, if it fails the file is skipped
+    #[arg(long)]
+    pub(crate) pre_hook:Option<String>,
+    /// command to run right after a file was checked (whatever the result)
+    #[arg(long)]
+    pub(crate) post_hook:Option<String>,
+    /// command to run (and wait for) before reading each file (e.g. to recall it from tape)
+    #[arg(long)]
+    pub(crate) stage:Option<String>,
+    /// stage this many files ahead while earlier ones are being checked (needs --stage)
+    #[arg(long,default_value_t=0)]
+    pub(crate) lookahead:usize,
+    /// maximum size of files staged ahead but not being checked yet (in GBytes)
+    #[arg(long,default_value_t=1024)]
+    pub(crate) lookahead_size:u64,
+    /// when the run is aborted, release the files that were staged or being checked but not verified
+    #[arg(long,requires="release")]
+    pub(crate) release_unverified:bool,
+    /// command to query if files are online (exit status 0), or see --offline-match
+    #[arg(long)]
+    pub(crate) online_check:Option<String>,
+    /// query this many files with one call of the online-check command
+    #[arg(long,default_value_t=1)]
+    pub(crate) online_check_batch:usize,
+    /// the online-check command prints one line per file, those containing this text are offline
+    #[arg(long)]
+    pub(crate) offline_match:Option<String>,
+    /// don't check files that are offline (so they are not recalled)
+    #[arg(long)]
+    pub(crate) skip_offline:bool,
+    /// command to run once at the end of the run (also if it's aborted), gets the totals as environment
+    #[arg(long)]
+    pub(crate) finally:Option<String>,
+    /// exit with an error if the finally command failed
+    #[arg(long)]
+    pub(crate) finally_fatal:bool,
+    /// terminate release/on-fail/stage/hook/online-check commands running longer than this (e.g. "10m")
+    #[arg(long,value_parser=humantime::parse_duration)]
+    pub(crate) command_timeout:Option<Duration>,
+    /// working directory for release/on-fail/stage/online-check commands
+    #[arg(long,value_hint = clap::ValueHint::DirPath)]
+    pub(crate) command_cwd:Option<PathBuf>,
+    /// print a status line every so often (e.g. "10m")
+    #[arg(long,value_parser=humantime::parse_duration)]
+    pub(crate) status_interval:Option<Duration>,
+    /// log how far a file is (at info level) once it's being checked for that long, and every that long after ("0s" for never)
+    #[arg(long,value_parser=humantime::parse_duration,default_value="30m")]
+    pub(crate) heartbeat:Duration,
+    /// stat all entries before checking, so the status line can show the percentage of bytes done and the time left
+    #[arg(long,conflicts_with="tar_stream")]
+    pub(crate) prescan:bool,
+    /// the order the entries are checked in, physical looks where the files are on disk in a pre-scan
+    #[arg(long,value_enum,default_value_t=Order::Listed,conflicts_with_all=["shuffle","stream_digest","tar_stream"])]
+    pub(crate) order:Order,
+    /// record checked files here, files verified OK according to it are not checked again
+    #[arg(long,value_hint = FilePath)]
+    pub(crate) journal:Option<PathBuf>,
+    /// check all files again, ignoring what's recorded in the journal
+    #[arg(long,requires="journal")]
+    pub(crate) rescan:bool,
+    /// check files that could not be checked again at the end of the run, up to this many times
+    #[arg(long,default_value_t=0)]
+    pub(crate) retry_failed:u32,
+    /// with --retry-failed also check files that failed verification again
+    #[arg(long,requires="retry_failed")]
+    pub(crate) retry_mismatch:bool,
+    /// check files that changed while being read again at the end of the run, after they didn't change for this long (e.g. "5m")
+    #[arg(long,value_parser=humantime::parse_duration)]
+    pub(crate) requeue_changed:Option<Duration>,
+    /// only check files modified after this time (RFC3339, e.g. "2024-01-31T12:00:00Z") or duration ago (e.g. "30d")
+    #[arg(long,value_parser=parse_time)]
+    pub(crate) newer_than:Option<SystemTime>,
+    /// only check files modified before this time or duration ago
+    #[arg(long,value_parser=parse_time)]
+    pub(crate) older_than:Option<SystemTime>,
+    /// only check files of at least this size (e.g. "10G", units are binary: K, M, G, T, P)
+    #[arg(long,value_parser=parse_size)]
+    pub(crate) min_file_size:Option<u64>,
+    /// only check files of at most this size
+    #[arg(long,value_parser=parse_size)]
+    pub(crate) max_file_size:Option<u64>,
+    /// check only the entries of shard i of n ("i/n", 1 to n), by the hash of their path as listed
+    #[arg(long,value_parser=parse_shard,conflicts_with="tar_stream")]
+    pub(crate) shard:Option<(u64,u64)>,
+    /// skip the first n entries of all checksum files (not counting those skipped by --newer-than/--older-than)
+    #[arg(long,default_value_t=0,conflicts_with="tar_stream")]
+    pub(crate) skip:u64,
+    /// check at most n entries (after --skip)
+    #[arg(long,conflicts_with="tar_stream")]
+    pub(crate) limit:Option<u64>,
+    /// what to do with entries that are symlinks
+    #[arg(long,value_enum,default_value_t=Symlinks::Follow)]
+    pub(crate) symlinks:Symlinks,
+    /// also check entries outside the directory of their checksum file (like "../x" or absolute paths)
+    #[arg(long)]
+    pub(crate) no_confine:bool,
+    /// resolve symlinks to make sure entries don't point outside the directory of their checksum file
+    #[arg(long,conflicts_with="no_confine")]
+    pub(crate) confine_strict:bool,
+    /// read checksum files written on Windows: backslashes are separators, drive prefixes ("C:") are stripped
+    #[arg(long)]
+    pub(crate) path_compat:bool,
+    /// with --path-compat map the drive to this directory instead of stripping it (e.g. "D=/archive/d")
+    #[arg(long,value_parser=parse_drive_map,requires="path_compat")]
+    pub(crate) drive_map:Vec<(char,PathBuf)>,
+    /// with --path-compat look for files that don't exist under the other Unicode normalization of their name (NFC/NFD, as on macOS)
+    #[arg(long,requires="path_compat")]
+    pub(crate) path_compat_unicode:bool,
+    /// read the files listed from here instead of the directory of their checksum file: s3://bucket/prefix,
+    /// sftp://[user@]host[:port]/dir or http(s)://host/dir
+    #[arg(long,visible_alias="base-url",value_name="URL",value_parser=parse_source_url)]
+    pub(crate) source_url:Option<String>,
+    /// the endpoint of S3 compatible storage other than AWS (e.g. https://rgw.example.org)
+    #[arg(long,value_name="URL",requires="source_url")]
+    pub(crate) s3_endpoint:Option<String>,
+    /// the key file for sftp:// besides the keys of the ssh agent
+    #[arg(long,value_name="FILE",requires="source_url")]
+    pub(crate) ssh_key:Option<PathBuf>,
+    /// files looked up and read at once over the SFTP session for sftp://
+    #[arg(long,default_value_t=10,value_parser=clap::value_parser!(u32).range(1..))]
+    pub(crate) ssh_channels:u32,
+    /// HTTP requests to make at once, for http(s):// URLs
+    #[arg(long,default_value_t=4,value_parser=clap::value_parser!(u32).range(1..))]
+    pub(crate) http_requests:u32,
+    /// don't verify TLS certificates of https:// URLs
+    #[arg(long)]
+    pub(crate) insecure:bool,
+    /// also read entries that are no regular files (e.g. block devices)
+    #[arg(long)]
+    pub(crate) allow_special:bool,
+    /// what to do with files listed more than once (also under different paths)
+    #[arg(long,value_enum,default_value_t=Duplicates::Coalesce)]
+    pub(crate) duplicates:Duplicates,
+    /// also check checksum files listed in checksum files (usually that's the checksum file listing itself)
+    #[arg(long)]
+    pub(crate) check_self:bool,
+    /// don't start new files after this time ("HH:MM") or duration (e.g. "8h")
+    #[arg(long,value_parser=parse_deadline)]
+    pub(crate) deadline:Option<Deadline>,
+    /// cancel the running checks when the deadline is reached, instead of letting them finish
+    #[arg(long,requires="deadline")]
+    pub(crate) deadline_cancel:bool,
+    /// don't start new files once the files started add up to this many bytes (e.g. "500G")
+    #[arg(long,value_parser=parse_size)]
+    pub(crate) max_bytes:Option<u64>,
+    /// don't start new files once more than this many files failed verification
+    #[arg(long)]
+    pub(crate) max_failures:Option<u64>,
+    /// don't start new files once more than this many files could not be checked
+    #[arg(long)]
+    pub(crate) max_errors:Option<u64>,
+    /// don't start new files once more than this percentage of the files checked failed or could not be checked (e.g. "5%")
+    #[arg(long,value_parser=parse_percent)]
+    pub(crate) max_failure_rate:Option<f64>,
+    /// how many files have to be checked before max-failure-rate applies
+    #[arg(long,default_value_t=100,requires="max_failure_rate")]
+    pub(crate) failure_rate_min_files:u64,
+    /// write the entries that were not checked (e.g. because of the deadline) to this checksum file
+    #[arg(long,value_hint = FilePath)]
+    pub(crate) remaining:Option<PathBuf>,
+    /// abort the run at the first file that could not be checked
+    #[arg(long)]
+    pub(crate) fail_fast:bool,
+    /// don't check at startup that the configured commands exist
+    #[arg(long)]
+    pub(crate) no_validate_commands:bool,
+    /// if another run holds the lock of a checksum file or the journal, wait this long for it (instead of exiting)
+    #[arg(long,value_parser=humantime::parse_duration)]
+    pub(crate) wait_lock:Option<Duration>,
+    /// print results as "STATUS<tab>path" terminated by NUL (with the path as is, not converted to UTF-8)
+    #[arg(long)]
+    pub(crate) print0:bool,
+    /// write the paths of the files that failed or could not be checked to this file, NUL separated
+    #[arg(long,value_hint = FilePath)]
+    pub(crate) failed_files0:Option<PathBuf>,
+    /// write a shell script to this file that stages the files that failed or could not be checked (with --stage) and checks them again
+    #[arg(long,value_hint = FilePath)]
+    pub(crate) failed_script:Option<PathBuf>,
+    /// write an extended checksum file "<checksum file>.tcman" next to each checksum file, listing the files verified OK with their size and modification time
+    #[arg(long,conflicts_with_all=["ref_db","tar_stream"])]
+    pub(crate) write_tcman:bool,
+    /// compare the chunk digests from sidecar files ("file.chunks") to locate the damage in files that failed
+    #[arg(long)]
+    pub(crate) chunks:bool,
+    /// compute the md5 digest of all files concatenated in the order listed (checking them one at a time), and compare it with the one given as --stream-digest=<md5>
+    #[arg(long,num_args=0..=1,require_equals=true,default_missing_value="",conflicts_with="tar_stream")]
+    pub(crate) stream_digest:Option<String>,
+    /// check only a random sample of the entries, a percentage (e.g. "2%") or a number of entries
+    #[arg(long,value_parser=parse_sample,conflicts_with_all=["stream_digest","tar_stream"])]
+    pub(crate) sample:Option<sample::Size>,
+    /// seed for --sample to choose the same sample again (it's shown in the summary)
+    #[arg(long,requires="sample")]
+    pub(crate) seed:Option<u64>,
+    /// with --sample choose files with a probability proportional to their size
+    #[arg(long,requires="sample")]
+    pub(crate) sample_by_bytes:bool,
+    /// check the entries in random order, --shuffle=<seed> for the order of an earlier run (its seed is shown)
+    #[arg(long,num_args=0..=1,require_equals=true,conflicts_with_all=["stream_digest","tar_stream"])]
+    pub(crate) shuffle:Option<Option<u64>>,
+    /// only trust checksum files with a signature "file.minisig" verifying with this minisign public key (file, or the key itself)
+    #[arg(long,value_name="PUBKEY")]
+    pub(crate) verify_signature:Option<String>,
+    /// only trust checksum files with a signature "file.asc" that gpg verifies as made by the key of this fingerprint (or a minisign signature with --verify-signature)
+    #[arg(long,value_name="FINGERPRINT")]
+    pub(crate) verify_gpg:Vec<String>,
+    /// the GnuPG home directory with the keyring for --verify-gpg, instead of the default one
+    #[arg(long,value_hint = clap::ValueHint::DirPath,requires="verify_gpg")]
+    pub(crate) gpg_home:Option<PathBuf>,
+    /// after checking, report the files below this directory that are not listed in any checksum file as EXTRA
+    #[arg(long,value_hint = clap::ValueHint::DirPath,conflicts_with="tar_stream")]
+    pub(crate) check_extra:Option<PathBuf>,
+    /// also report sidecar files (".chunks", ".minisig", ".asc") of listed files and checksum files as EXTRA
+    #[arg(long,requires="check_extra")]
+    pub(crate) extra_all:bool,
+    /// write the paths of the extra files to this file, one per line
+    #[arg(long,requires="check_extra",value_hint = FilePath)]
+    pub(crate) extra_files:Option<PathBuf>,
+    /// fail the run if there are extra files
+    #[arg(long,requires="check_extra")]
+    pub(crate) extra_fatal:bool,
+    /// read the default options from this config file (TOML) instead of /etc/tape-check.toml and ~/.config/tape-check.toml
+    #[arg(long,value_hint = FilePath)]
+    pub(crate) config:Option<PathBuf>,
+    /// print the effective options (from the command line, config files and defaults) as config file
+    #[arg(long)]
+    pub(crate) dump_config:bool,
+    /// which implementation computes md5
+    #[arg(long,value_enum,default_value_t=DigestBackend::Pure)]
+    pub(crate) digest_backend:DigestBackend,
+    /// print how fast each digest backend built in computes md5 (in memory) and check they agree, instead of checking
+    #[arg(long)]
+    pub(crate) bench:bool,
+    /// with --release or --delete-after-verify show what would be done and ask for confirmation before starting
+    #[arg(long)]
+    pub(crate) confirm:bool,
+    /// don't ask with --confirm (needed without a terminal)
+    #[arg(long,requires="confirm")]
+    pub(crate) yes:bool,
+    /// only show what would be done, don't read any files or run any commands
+    #[arg(long)]
+    pub(crate) dry_run:bool,
+    /// check the members of a tar archive read sequentially from this file/device ("-" for stdin) instead of files on disk
+    #[arg(long,value_hint = FilePath,conflicts_with="dry_run")]
+    pub(crate) tar_stream:Option<PathBuf>,
+    /// don't start checking new files while this command fails (e.g. during a backup window)
+    #[arg(long)]
+    pub(crate) pause_when:Option<String>,
+    /// how long the result of the pause-when command is valid (and how often it's rerun when paused)
+    #[arg(long,value_parser=humantime::parse_duration,default_value="1m")]
+    pub(crate) pause_check_interval:Duration,
+    /// run all commands via "sh -c", the path(s) are given as "$1"... ("$@")
+    #[arg(long)]
+    pub(crate) release_shell:bool,
+    /// release up to this many files with one call of the release command
+    #[arg(long,default_value_t=1)]
+    pub(crate) release_batch:usize,
+    /// release a non-full batch after it waited this long (e.g. "5m")
+    #[arg(long,value_parser=humantime::parse_duration)]
+    pub(crate) release_batch_wait:Option<Duration>,
+    /// maximum number of release/on-fail commands running at the same time
+    #[arg(long,default_value_t=2)]
+    pub(crate) max_release_procs:usize,
+    /// retry failed release/on-fail commands this many times (with increasing delay)
+    #[arg(long,default_value_t=0)]
+    pub(crate) release_retries:u32,
+    /// exit with an error if any release/on-fail command failed
+    #[arg(long)]
+    pub(crate) release_failures_fatal:bool,
+    #[command(flatten)]
+    pub(crate) verbose: clap_verbosity_flag::Verbosity,
+}
+
+/// which implementation computes md5
+#[derive(Clone,Copy,PartialEq,clap::ValueEnum)]
+pub(crate) enum DigestBackend
+{
+    /// the md5 crate (in Rust)
+    Pure,
+    /// OpenSSL's libcrypto (needs the "openssl" feature)
+    Openssl
+}
+
+impl DigestBackend
+{
+    fn name(self) -> &'static str
+    {
+        match self {DigestBackend::Pure => "pure",DigestBackend::Openssl => "openssl"}
+    }
+    fn built(self) -> bool
+    {
+        self == DigestBackend::Pure || cfg!(feature="openssl")
+    }
+    /// the digest algorithms, computed by this backend, failing if it can't compute md5
+    fn digests(self) -> Result<Digests,Error>
+    {
+        let digests = match self {
+            #[cfg(feature="openssl")]
+            DigestBackend::Openssl => {
+                let mut digests = Digests::default();
+                digests.register(||Box::new(crate::OpensslMd5::new()));
+                digests
+            }
+            _ => Digests::default()
+        };
+        // rather now than for every file
+        let md5 = digests.by_name("md5").expect("md5 is always known");
+        md5().finalize().map_err(|source|Error::DigestBackend{backend:self.name(),source})?;
+        Ok(digests)
+    }
+}
+
+/// when to stop starting new files
+#[derive(Clone,Copy)]
+pub(crate) enum Deadline
+{
+    After(Duration),
+    /// the next time it's that time of day (local time)
+    At(chrono::NaiveTime)
+}
+
+fn parse_deadline(s:&str) -> Result<Deadline,String>
+{
+    if let Ok(time) = chrono::NaiveTime::parse_from_str(s,"%H:%M") {return Ok(Deadline::At(time));}
+    humantime::parse_duration(s).map(Deadline::After).map_err(|e|format!("neither HH:MM nor a duration: {e}"))
+}
+
+impl Deadline
+{
+    /// how long from now until the deadline
+    pub(crate) fn left(&self) -> Duration
+    {
+        match self {
+            Deadline::After(duration) => *duration,
+            Deadline::At(time) => {
+                let mut left = *time - chrono::Local::now().time();
+                if left <= chrono::TimeDelta::zero() {left += chrono::TimeDelta::days(1);}
+                left.to_std().unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// parse a size in bytes, with an optional binary unit ("10G", "1.5TiB", "500MB" are all powers of 1024)
+fn parse_size(s:&str) -> Result<u64,String>
+{
+    let number = s.trim_end_matches(|c:char|c.is_ascii_alphabetic());
+    let unit = &s[number.len()..];
+    let exponent = match unit.trim_end_matches(['B','b']).trim_end_matches('i') {
+        "" => 0,
+        "k"|"K" => 1,
+        "m"|"M" => 2,
+        "g"|"G" => 3,
+        "t"|"T" => 4,
+        "p"|"P" => 5,
+        _ => return Err(format!("unknown unit '{unit}'"))
+    };
+    let number:f64 = number.trim().parse().map_err(|e|format!("not a size: {e}"))?;
+    if number < 0.0 {return Err("can't be negative".into());}
+    Ok((number*1024f64.powi(exponent)) as u64)
+}
+
+/// parse a point in time, as RFC3339 timestamp or as duration before now
+fn parse_time(s:&str) -> Result<SystemTime,String>
+{
+    if let Ok(time) = humantime::parse_rfc3339_weak(s) {return Ok(time);}
+    let ago = humantime::parse_duration(s).map_err(|e|format!("neither a timestamp nor a duration: {e}"))?;
+    SystemTime::now().checked_sub(ago).ok_or_else(||"too long ago".into())
+}
+
+/// parse a percentage ("5%" or "5") as fraction
+fn parse_percent(s:&str) -> Result<f64,String>
+{
+    let percent:f64 = s.trim_end_matches('%').parse().map_err(|e|format!("not a percentage: {e}"))?;
+    if !(0.0..=100.0).contains(&percent) {return Err("has to be between 0 and 100%".into());}
+    Ok(percent/100.0)
+}
+
+fn parse_shard(s:&str) -> Result<(u64,u64),String>
+{
+    let (i,n) = s.split_once('/').ok_or("has to be i/n")?;
+    let (i,n):(u64,u64) = (i.parse().map_err(|e|format!("{i}: {e}"))?,n.parse().map_err(|e|format!("{n}: {e}"))?);
+    if i == 0 || i > n {return Err(format!("the shard has to be between 1 and {n}"));}
+    Ok((i,n))
+}
+
+fn parse_drive_map(s:&str) -> Result<(char,PathBuf),String>
+{
+    let (drive,dir) = s.split_once('=').ok_or("has to be drive=directory")?;
+    let drive = drive.trim_end_matches(':');
+    match drive.chars().collect::<Vec<_>>().as_slice() {
+        [letter] if letter.is_ascii_alphabetic() => Ok((letter.to_ascii_uppercase(),dir.into())),
+        _ => Err(format!("'{drive}' is no drive letter"))
+    }
+}
+
+fn parse_remap(s:&str) -> Result<(PathBuf,String),String>
+{
+    let (local,listed) = s.split_once('=').ok_or("has to be local=listed")?;
+    Ok((local.into(),listed.into()))
+}
+
+fn parse_source_url(s:&str) -> Result<String,String>
+{
+    match s.split_once("://") {
+        Some(("s3",rest)) => match rest.split_once('/').map_or(rest,|(bucket,_)|bucket) {
+            "" => Err("has no bucket".into()),
+            _ => Ok(s.into())
+        },
+        Some(("sftp",rest)) => match rest.split_once('/') {
+            Some(("",_)) => Err("has no host".into()),
+            Some(_) => Ok(s.into()),
+            None => Err("has no directory".into())
+        },
+        Some(("http"|"https",_)) => Ok(s.into()),
+        _ => Err("only s3://bucket/prefix, sftp://host/dir and http(s)://host/dir are supported".into())
+    }
+}
+
+fn parse_sample(s:&str) -> Result<sample::Size,String>
+{
+    if s.ends_with('%') {return parse_percent(s).map(sample::Size::Fraction);}
+    s.parse().map(sample::Size::Count).map_err(|e|format!("neither a percentage nor a number of entries: {e}"))
+}
+
+/// what to do instead of checking
+#[derive(clap::Subcommand)]
+enum Subcommand
+{
+    /// print the completion script for the shell
+    Completions{shell:clap_complete::Shell},
+    /// compare the files of two directory trees by their digests, paired by their path below them
+    ///
+    /// Prints MATCH, DIFFER, ONLY-IN-A or ONLY-IN-B (or ERROR) for each path, the options given before apply
+    /// (like --max-size, --symlinks, --min-file-size, --newer-than, --stage).
+    Compare{
+        #[arg(value_hint = clap::ValueHint::DirPath)]
+        a:PathBuf,
+        #[arg(value_hint = clap::ValueHint::DirPath)]
+        b:PathBuf,
+        /// leave out files and directories with a name matching the pattern ("*" and "?" match any characters), can be given more than once
+        #[arg(long,value_name="PATTERN")]
+        ignore:Vec<String>
+    },
+    /// compare two checksum files by the digests they list for the same paths, without reading any files
+    ///
+    /// Prints DIFFER, ONLY-IN-A or ONLY-IN-B (or ERROR for digests of different kinds) for each path that doesn't match.
+    /// --path-compat and --print0 given before apply.
+    Diff{
+        #[arg(value_hint = FilePath)]
+        a:PathBuf,
+        #[arg(value_hint = FilePath)]
+        b:PathBuf,
+        /// leave out this prefix of the paths listed in A (e.g. "/old/archive" when B lists them relative)
+        #[arg(long,value_name="PREFIX")]
+        strip_a:Option<PathBuf>,
+        /// leave out this prefix of the paths listed in B
+        #[arg(long,value_name="PREFIX")]
+        strip_b:Option<PathBuf>,
+        /// print "VERDICT<tab>path<tab>digest in A<tab>digest in B" for each path
+        #[arg(long)]
+        tsv:bool
+    },
+    /// combine checksum files into one, sorted by path and with the paths relative to a common root
+    ///
+    /// Entries listed more than once are written once, paths listed with different digests are conflicts.
+    /// --path-compat and --verify-signature given before apply.
+    Merge{
+        /// checksum files, or directories with one of a conventional name
+        #[arg(value_hint = FilePath,required=true)]
+        manifests:Vec<PathBuf>,
+        /// the checksum file to write ("-" for stdout)
+        #[arg(long,short,value_hint = FilePath)]
+        output:PathBuf,
+        /// the directory the paths written are relative to, the one of the output by default (files not below it are written with absolute paths)
+        #[arg(long,value_hint = clap::ValueHint::DirPath)]
+        root:Option<PathBuf>,
+        /// write all digests of paths listed with different ones instead of failing
+        #[arg(long)]
+        keep_conflicts:bool
+    },
+    /// send a request to md5check running with --daemon and print its response (a JSON line)
+    Ctl{
+        /// the socket the daemon listens on
+        #[arg(long,value_hint = FilePath)]
+        socket:PathBuf,
+        #[command(subcommand)]
+        request:CtlRequest
+    }
+}
+
+/// the requests of the ctl subcommand
+#[derive(clap::Subcommand)]
+pub(crate) enum CtlRequest
+{
+    /// check the checksum files (or directories with one), the job id is printed
+    Submit{
+        /// the most size of files being checked at a time for the job (in GBytes), at most that of the daemon
+        #[arg(long)]
+        max_size:Option<u64>,
+        #[arg(required=true,value_hint = FilePath)]
+        manifests:Vec<PathBuf>
+    },
+    /// the state and counts of the job, or of all jobs
+    Status{job:Option<u64>},
+    /// don't check more files of the job, cancelling the running ones
+    Cancel{job:u64},
+    /// the results of the job, from the one given on (those before are dropped), by default those not dropped yet
+    Results{
+        job:u64,
+        #[arg(long)]
+        since:Option<u64>
+    },
+    /// read the --priority-file of the daemon again
+    Reprioritize
+}
+
+impl Cli
+{
+    /// parse the command line, with the options of the environment and config files it doesn't give
+    ///
+    /// Also returns the options taken from those.
+    fn parse_with_config() -> Result<(Cli,ArgMatches,config::Config),Error>
+    {
+        Cli::parse_with_config_from(std::env::args_os().collect(),std::env::vars_os())
+    }
+    /// parse argv with the configuration of the files and the environment variables given
+    fn parse_with_config_from(argv:Vec<OsString>,vars:impl IntoIterator<Item=(OsString,OsString)>) -> Result<(Cli,ArgMatches,config::Config),Error>
+    {
+        let command = Cli::command();
+        let matches = command.clone().get_matches_from(&argv);
+        // the options of the config files apply to comparing too
+        if matches.subcommand().is_some_and(|(name,_)|!["compare","diff","merge"].contains(&name)) {
+            return Ok((Cli::from_arg_matches(&matches).unwrap_or_else(|e|e.exit()),matches,config::Config::new()));
+        }
+        let files = match matches.get_one::<PathBuf>("config") {
+            Some(file) => vec![file.clone()],
+            None => config::default_files()
+        };
+        let mut config = config::load(&files,&command)?;
+        config::load_env(&mut config,&command,vars)?;
+        config.retain(|id,_|matches.value_source(id) != Some(clap::parser::ValueSource::CommandLine));
+        let (options,positional) = config::args(&config,&command)?;
+        if options.is_empty() && positional.is_empty() {
+            return Ok((Cli::from_arg_matches(&matches).unwrap_or_else(|e|e.exit()),matches,config));
+        }
+        // the command line after the config, the checksum files given there override those of the config
+        let mut merged:Vec<_> = argv.iter().take(1).cloned().chain(options).chain(argv.iter().skip(1).cloned()).collect();
+        if !positional.is_empty() && matches.subcommand().is_none() {
+            merged.push("--".into());
+            merged.extend(positional);
+        }
+        let matches = command.get_matches_from(merged);
+        Ok((Cli::from_arg_matches(&matches).unwrap_or_else(|e|e.exit()),matches,config))
+    }
+    /// what verifies the signatures of the checksum files, if they are to be verified
+    fn verifier(&self) -> Result<Option<signature::Verifier>,Error>
+    {
+        if self.verify_signature.is_none() && self.verify_gpg.is_empty() {return Ok(None)}
+        signature::Verifier::new(self.verify_signature.as_deref(),&self.verify_gpg,self.gpg_home.as_deref(),self.command_timeout).map(Some)
+    }
+    /// the given command with the configured command options, if there is one
+    pub(crate) fn ext_command(&self,what:&str,cmd:&Option<String>) -> Option<Arc<ExtCommand>>
+    {
+        command::configured(what,cmd.as_deref(),&self.command_options())
+    }
+    fn command_options(&self) -> command::Options
+    {
+        command::Options{shell:self.release_shell,timeout:self.command_timeout,cwd:self.command_cwd.clone()}
+    }
+    /// where the files are read from with --source-url (None for local files and URLs, which are read over HTTP anyway)
+    #[cfg_attr(not(all(feature="s3",feature="sftp")),allow(unused_variables))]
+    fn source(&self,events:Events) -> Option<Arc<dyn Source>>
+    {
+        let url = self.source_url.as_ref()?;
+        let policy = RetryPolicy{retryable:self.retry_errno.clone(),max_retries:self.open_retries};
+        match url.split_once("://").map(|(scheme,_)|scheme) {
+            #[cfg(feature="sftp")]
+            Some("sftp") => Some(Arc::new(sftp::Sftp::new(self.ssh_key.clone(),self.ssh_channels,policy,events))),
+            #[cfg(feature="s3")]
+            Some("s3") => Some(Arc::new(s3::S3::new(self.s3_endpoint.clone(),policy,events))),
+            _ => None
+        }
+    }
+    /// the option and the cargo feature it needs, if md5check was built without it
+    fn without_feature(&self) -> Option<(&'static str,&'static str)>
+    {
+        let scheme = self.source_url.as_deref().and_then(|url|url.split_once("://")).map(|(scheme,_)|scheme);
+        let needed = [
+            (scheme == Some("s3"),"--source-url s3://","s3",cfg!(feature="s3")),
+            (scheme == Some("sftp"),"--source-url sftp://","sftp",cfg!(feature="sftp")),
+            (matches!(scheme,Some("http"|"https")),"--source-url http(s)://","http",cfg!(feature="http")),
+            (self.tar_stream.is_some(),"--tar-stream","tar",cfg!(feature="tar")),
+            (self.verify_signature.is_some(),"--verify-signature","minisign",cfg!(feature="minisign")),
+            (self.ref_db.is_some(),"--ref-db","sqlite",cfg!(feature="sqlite")),
+            (self.daemon,"--daemon","daemon",cfg!(all(unix,feature="daemon"))),
+            (!self.digest_backend.built(),"--digest-backend openssl","openssl",cfg!(feature="openssl")),
+            (matches!(self.subcommand,Some(Subcommand::Ctl{..})),"ctl","daemon",cfg!(all(unix,feature="daemon")))
+        ];
+        needed.into_iter().find(|(used,_,_,built)|*used && !built).map(|(_,option,feature,_)|(option,feature))
+    }
+}
+
+/// the options of the reader are those of the command line, with its defaults (not those of the library)
+impl From<&Cli> for Options
+{
+    fn from(args:&Cli) -> Options
+    {
+        Options{
+            max_size:args.max_size*GIGABYTE,allow_oversize:args.allow_oversize,accounting:args.accounting,buffer_size:args.buffer_size,
+            adaptive:args.adaptive.then_some((args.adaptive_min_files,args.adaptive_max_files,args.adaptive_window,args.adaptive_freeze)),
+            pre_hook:args.pre_hook.clone(),stage:args.stage.clone(),post_hook:args.post_hook.clone(),release:args.release.clone(),
+            on_fail:args.on_fail.clone(),online_check:args.online_check.clone(),pause_when:args.pause_when.clone(),
+            commands:args.command_options(),
+            online_check_batch:args.online_check_batch,offline_match:args.offline_match.clone(),skip_offline:args.skip_offline,
+            pause_check_interval:args.pause_check_interval,
+            release_on_fail:args.release_on_fail,release_retries:args.release_retries,
+            release_batch:args.release_batch,release_batch_wait:args.release_batch_wait,release_procs:args.max_release_procs,
+            release_hardlinks:args.release_hardlinks,delete_after_verify:args.delete_after_verify,fail_fast:args.fail_fast,
+            lookahead:args.lookahead,lookahead_size:args.lookahead_size*GIGABYTE,
+            open_policy:RetryPolicy{retryable:args.retry_errno.clone(),max_retries:args.open_retries},
+            resume_policy:RetryPolicy{retryable:args.resume_errno.clone(),max_retries:Some(args.resume_attempts)},
+            chunks:args.chunks,stream_digest:args.stream_digest.is_some(),restore_atime:args.restore_atime,
+            mount_wait:args.mount_wait,probe_bad_blocks:args.probe_bad_blocks,
+            prescan:args.prescan,order:args.order,footers:!args.ignore_footer,symlinks:args.symlinks,allow_special:args.allow_special,
+            confine:(!args.no_confine).then_some(args.confine_strict),source_url:args.source_url.clone(),
+            path_compat:args.path_compat.then(||path_compat::PathCompat{drives:args.drive_map.clone(),unicode:args.path_compat_unicode}),
+            #[cfg(feature="http")]
+            insecure:args.insecure,
+            #[cfg(feature="http")]
+            http_requests:args.http_requests,
+            newer_than:args.newer_than,older_than:args.older_than,file_sizes:(args.min_file_size,args.max_file_size),
+            window:(args.skip > 0 || args.limit.is_some()).then_some((args.skip,args.limit)),shard:args.shard,print0:args.print0,
+            heartbeat:args.heartbeat,deadline:args.deadline.map(|deadline|deadline.left()),deadline_cancel:args.deadline_cancel,
+            max_bytes:args.max_bytes,max_failures:args.max_failures,max_errors:args.max_errors,
+            max_failure_rate:args.max_failure_rate.map(|rate|(rate,args.failure_rate_min_files)),
+            duplicates:args.duplicates,mark_hardlinks:args.verbose.log_level_filter() > log::LevelFilter::Error,
+            sample:args.sample.map(|size|sample::Sampling{
+                size,seed:args.seed.unwrap_or_else(sample::random_seed),by_bytes:args.sample_by_bytes
+            }),
+            shuffle:args.shuffle.map(|seed|seed.unwrap_or_else(sample::random_seed)),
+            // --watch shows the numbers of each checksum file once it's done
+            per_manifest:args.summary_per_manifest || args.watch.is_some(),write_tcman:args.write_tcman,per_dir:args.aggregate_by_dir,
+            known:args.check_extra.is_some(),
+            retry_failed:args.retry_failed,retry_mismatch:args.retry_mismatch,requeue_changed:args.requeue_changed
+        }
+    }
+}
+
+/// what a run would do, used by --dry-run
+struct Plan
+{
+    entries:Vec<Entry>,
+    /// what would be done for the files
+    commands:Vec<String>,
+    problems:Vec<String>,
+    /// entries of files listed before (by another path or checksum file), that would not be read again
+    duplicates:Vec<(PathBuf,PathBuf)>
+}
+
+impl Plan
+{
+    /// parse all manifests and stat all files listed, without reading any of them
+    async fn new(files:&[PathBuf],reader:&Reader) -> Plan
+    {
+        let mut plan = Plan{entries:vec![],commands:vec![],problems:vec![],duplicates:vec![]};
+        let mut seen = HashMap::<_,PathBuf>::new();
+        for (what,cmd) in reader.commands() {
+            match cmd.validate() {
+                Ok(()) => plan.commands.push(format!("{what} command: {cmd} (running {})",
+                    cmd.resolve().unwrap_or(Path::new("")).to_string_lossy())),
+                Err(e) => plan.problems.push(format!("{what} command {e}"))
+            }
+        }
+        if reader.actions.delete_after_verify {
+            plan.commands.push("files that verify OK would be deleted".into());
+        }
+        for md5filepath in files {
+            let content = match tokio::fs::read_to_string(md5filepath).await {
+                Ok(content) => content,
+                Err(e) => {
+                    plan.problems.push(format!("failed to read '{}': {e}",md5filepath.to_string_lossy()));
+                    continue
+                }
+            };
+            if let Some(signatures) = &reader.signatures {
+                if let Err(reason) = signatures.verify(md5filepath,content.as_bytes()).await {
+                    plan.problems.push(format!("'{}' would be rejected: {reason}",md5filepath.to_string_lossy()));
+                    continue
+                }
+            }
+            let md5base = manifest_base(md5filepath);
+            let manifest:Arc<Path> = md5filepath.as_path().into();
+            let mut format = tcman::Format::default();
+            for (nr,line) in content.lines().enumerate() {
+                let (md5,recorded,filename) = match format.parse(nr as u64+1,line,&reader.check.digests) {
+                    Ok(Some(parsed)) => parsed,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        plan.problems.push(format!("{}:{}: {e}",md5filepath.to_string_lossy(),nr+1));
+                        continue
+                    }
+                };
+                let (path,member) = reader.manifest_member(md5base,filename);
+                let mut entry = Entry{path,expected:md5.into(),manifest:manifest.clone(),size:0,mtime:None,id:None,recorded,member,along:vec![]};
+                if !reader.confined(md5base,entry.file()) {
+                    plan.problems.push(format!("'{}' is outside the directory of '{}'",entry.path.to_string_lossy(),md5filepath.to_string_lossy()));
+                    continue
+                }
+                entry.path = entry.listed_path();
+                let path = &entry.path;
+                match reader.check.source.metadata(entry.file()).await {
+                    Ok(meta) if reader.filters.by_time(meta.modified) || reader.filters.by_size(meta.size) => {},
+                    Ok(meta) if meta.size > reader.max_size && !reader.allow_oversize => plan.problems.push(
+                        format!("'{}' is bigger than the maximum allowed buffer size {}G",path.to_string_lossy(),reader.max_size/GIGABYTE)
+                    ),
+                    Ok(meta) => {
+                        entry.size = meta.size;
+                        // like Reader::add, files listed again are read once (with --duplicates coalesce)
+                        if let Some(id) = reader.plan_id(&entry) {
+                            if let Some(first) = seen.get(&id) {
+                                plan.duplicates.push((entry.path,first.clone()));
+                                continue
+                            }
+                            seen.insert(id,entry.path.clone());
+                        }
+                        plan.entries.push(entry);
+                    }
+                    Err(e) => plan.problems.push(format!("'{}': {e}",path.to_string_lossy()))
+                }
+            }
+        }
+        plan
+    }
+}
+
+impl std::fmt::Display for Plan
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for cmd in &self.commands {
+            writeln!(f,"{cmd}")?;
+        }
+        for entry in &self.entries {
+            writeln!(f,"{} {} bytes",entry.path.to_string_lossy(),entry.size)?;
+        }
+        for (path,first) in &self.duplicates {
+            writeln!(f,"{} is the same file as {}, not read again",path.to_string_lossy(),first.to_string_lossy())?;
+        }
+        for problem in &self.problems {
+            writeln!(f,"problem: {problem}")?;
+        }
+        writeln!(f,"{}",self.totals())
+    }
+}
+
+impl Plan
+{
+    fn totals(&self) -> String
+    {
+        format!("{} files, {} bytes total, {} listed again, {} problems",
+            self.entries.len(),self.entries.iter().map(|e|e.size).sum::<u64>(),self.duplicates.len(),self.problems.len())
+    }
+    /// the commands and totals, for --confirm
+    fn outline(&self) -> String
+    {
+        self.commands.iter().map(|cmd|format!("{cmd}\n")).collect::<String>()+&self.totals()
+    }
+}
+
+/// show the plan for the run and ask to go on, without a terminal only with --yes
+async fn confirm(files:&[PathBuf],reader:&Reader,yes:bool) -> Result<(),Error>
+{
+    use std::io::IsTerminal;
+    if yes {return Ok(());}
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::NotConfirmed("there's no terminal to confirm the release/delete, use --yes"));
+    }
+    let plan = Plan::new(files,reader).await;
+    eprint!("{}\ntype \"yes\" to start: ",plan.outline());
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).map_err(|source|Error::Io{action:"read",path:"stdin".into(),source})?;
+    if answer.trim() == "yes" {Ok(())} else {Err(Error::NotConfirmed("not confirmed"))}
+}
+
+/// count SIGINT/SIGTERM: the first one stops adding files, the second cancels the running checks, the third exits
+async fn watch_signals(canceller:Canceller) -> std::io::Result<()>
+{
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut signals = 0;
+    loop {
+        #[cfg(unix)]
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await?;
+        signals += 1;
+        match signals {
+            1 => {
+                eprintln!("interrupted, waiting for the running checks to finish (interrupt again to cancel them)");
+                canceller.stop();
+            }
+            2 => {
+                eprintln!("interrupted again, cancelling the running checks (interrupt again to exit immediately)");
+                canceller.cancel();
+            }
+            _ => std::process::exit(INTERRUPTED.into())
+        }
+    }
+}
+
+/// exit status of an interrupted run
+pub(crate) static INTERRUPTED:u8 = 130;
+/// exit status of a run stopped by the deadline, without failures
+static INCOMPLETE:u8 = 75;
+
+/// marks results of files modified after their checksum file
+static NEWER_THAN_MANIFEST:&str = "NEWER-THAN-MANIFEST";
+/// marks results of hard links using the digest read for another link, in verbose output
+static HARDLINK_CACHED:&str = "HARDLINK-CACHED";
+
+/// the output of the command line tool
+impl Reader
+{
+    /// print a result: "path STATUS" (with the symlink target if there is one), or "STATUS<tab>path<NUL>" with print0
+    fn print(&self,result:&CheckResult)
+    {
+        #[cfg(all(unix,feature="daemon"))]
+        if let Some(job) = &self.job {job.push(result);}
+        let mut status = match result.newer_than_manifest {
+            true => format!("{} {NEWER_THAN_MANIFEST}",result.status),
+            false => result.status.to_string()
+        };
+        if result.hardlink && self.mark_hardlinks {status = format!("{status} {HARDLINK_CACHED}");}
+        // the verdicts of a file listed with digests of several algorithms may differ
+        if let Some(algorithm) = result.algorithm.filter(|_|self.digest_lens.len() > 1) {status = format!("{status} ({algorithm})");}
+        if let Some(offset) = result.failed_at {status = format!("{status} at offset {offset}");}
+        if let Some((first,length)) = result.bad_extent {status = format!("{status}, unreadable from {first} for {length} bytes");}
+        let path = &result.path;
+        let mut stdout = std::io::stdout().lock();
+        let written = if self.print0 {
+            write!(stdout,"{status}\t").and_then(|_|stdout.write_all(&path_bytes(path))).and_then(|_|stdout.write_all(b"\0"))
+        } else {
+            match &result.target {
+                Some(target) => writeln!(stdout,"{} {status} -> {}",path.to_string_lossy(),target.to_string_lossy()),
+                None => writeln!(stdout,"{} {status}",path.to_string_lossy())
+            }
+        };
+        if let Err(e) = written {debug!("failed to print result: {e}");}
+    }
+    /// print the number of files OK, FAIL and ERROR for each checksum file
+    fn print_per_manifest(&self)
+    {
+        let Some(per_manifest) = &self.per_manifest else {return};
+        let mut manifests:Vec<_> = per_manifest.iter().collect();
+        manifests.sort_by_key(|(manifest,_)|manifest.to_path_buf());
+        for (manifest,files) in manifests {
+            let (ok,fail,error) = manifest_counts(files);
+            eprintln!("{}: {ok} OK, {fail} FAIL, {error} ERROR",manifest.to_string_lossy());
+        }
+    }
+    fn print_per_dir(&self)
+    {
+        if let Some(per_dir) = &self.per_dir {eprint!("{per_dir}");}
+    }
+    /// print the results not taken yet
+    pub(crate) fn print_results(&mut self)
+    {
+        for result in std::mem::take(&mut self.results) {self.print(&result);}
+    }
+    /// add the entries, printing the results of files done meanwhile
+    pub(crate) async fn add_printing(&mut self,entries:Vec<Entry>) -> Result<(),Error>
+    {
+        let added = self.add_all(entries).await;
+        self.print_results();
+        added
+    }
+    /// add the entries in batches of online_check_batch, those of --priority-file first
+    ///
+    /// The order is looked at again before each batch, as the priority file may be read again meanwhile.
+    pub(crate) async fn add_batches(&mut self,entries:Vec<Entry>) -> Result<(),Error>
+    {
+        // the entries of a file with digests of several algorithms stay in the same batch, to be read once
+        let (mut entries,mut new) = (VecDeque::from(group_algorithms(entries)),true);
+        while !entries.is_empty() {
+            if let Some(priorities) = &mut self.priorities {priorities.order(&mut entries,new);}
+            new = false;
+            let batch = entries.drain(..self.online.batch.clamp(1,entries.len())).flat_map(|mut entry|{
+                let along = std::mem::take(&mut entry.along);
+                std::iter::once(entry).chain(along)
+            }).collect();
+            self.add_printing(batch).await?;
+        }
+        Ok(())
+    }
+    /// wait for all files being checked printing their results, then for their commands
+    async fn drain(&mut self) -> Result<(),Error>
+    {
+        self.print_results();
+        while let Some(result) = self.next_result().await? {self.print(&result);}
+        self.join().await
+    }
+}
+
+/// run the command line tool
+pub async fn main() -> ExitCode
+{
+    match run().await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_code()
+        }
+    }
+}
+
+/// the data hashed by --bench, a buffer of pseudo random bytes hashed that many times
+static BENCH_BUFFER:usize = 1<<20;
+static BENCH_ROUNDS:usize = 1024;
+
+/// print how fast md5 is computed by each backend built in, failing if they don't compute the same digests
+fn bench() -> Result<ExitCode,Error>
+{
+    let mut state = 0x2545f4914f6cdd1d_u64;
+    let buffer:Vec<u8> = (0..BENCH_BUFFER).map(|_|{
+        state ^= state<<13;state ^= state>>7;state ^= state<<17;
+        state as u8
+    }).collect();
+    let mut computed = vec![];
+    for backend in [DigestBackend::Pure,DigestBackend::Openssl].into_iter().filter(|backend|backend.built()) {
+        let factory = backend.digests()?.by_name("md5").cloned().expect("md5 is always known");
+        let failed = |source|Error::DigestBackend{backend:backend.name(),source};
+        // around the lengths where the padding needs another block
+        let mut digests:Vec<DigestValue> = [0,1,55,56,63,64,65,1000].into_iter().map(|len|{
+            let mut digest = factory();
+            digest.update(&buffer[..len])?;
+            digest.finalize()
+        }).collect::<Result<_,_>>().map_err(failed)?;
+        let start = std::time::Instant::now();
+        let mut digest = factory();
+        for _ in 0..BENCH_ROUNDS {digest.update(&buffer).map_err(failed)?;}
+        digests.push(digest.finalize().map_err(failed)?);
+        let rate = (BENCH_ROUNDS*BENCH_BUFFER) as f64/MEGABYTE as f64/start.elapsed().as_secs_f64();
+        println!("md5 {}: {rate:.1}M/s",backend.name());
+        computed.push(digests);
+    }
+    if computed.windows(2).any(|pair|pair[0] != pair[1]) {return Err(Error::BackendsDiffer);}
+    Ok(ExitCode::SUCCESS)
+}
+
+/// the completion script for the shell
+fn completions(shell:clap_complete::Shell) -> Vec<u8>
+{
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    let mut script = vec![];
+    clap_complete::generate(shell,&mut command,name,&mut script);
+    script
+}
+
+async fn run() -> Result<ExitCode,Error>
+{
+    let (args,matches,config) = Cli::parse_with_config()?;
+    if let Some(Subcommand::Completions{shell}) = args.subcommand {
+        std::io::stdout().write_all(&completions(shell)).map_err(|source|Error::Io{action:"write",path:"stdout".into(),source})?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    #[cfg(all(unix,feature="daemon"))]
+    if let Some(Subcommand::Ctl{socket,request}) = &args.subcommand {
+        return daemon::ctl(socket,request).await;
+    }
+    if args.dump_config {
+        print!("{}",config::dump(&Cli::command(),&matches,&config));
+        return Ok(ExitCode::SUCCESS);
+    }
+    env_logger::Builder::new()
+        .filter_level(args.verbose.log_level_filter())
+        .init();
+
+    if let Some((option,feature)) = args.without_feature() {
+        return Err(Error::WithoutFeature{option,feature});
+    }
+    if args.bench {return bench();}
+    if let Some(cwd) = args.command_cwd.as_ref().filter(|cwd|!cwd.is_dir()) {
+        return Err(Error::CommandCwd(cwd.clone()));
+    }
+    // the notices and the status line are made from the events of the checks
+    let (sender,notices) = tokio::sync::mpsc::unbounded_channel();
+    let events = Events::new(sender);
+    // the commands are checked below, unless --no-validate-commands
+    let (mut reader,canceller) = CheckerBuilder::with(Options::from(&args),args.digest_backend.digests()?,args.source(events.clone()),events.clone())
+        .unvalidated().build()?.into_parts();
+    let status = args.status_interval.map(|interval|{
+        (interval,status::StatusLine::new(reader.accounting == Accounting::Progressive,reader.max_size))
+    });
+    let (done,shown) = tokio::sync::oneshot::channel();
+    let shown = tokio::spawn(status::show(notices,status,shown));
+    if let Some(Subcommand::Compare{a,b,ignore}) = &args.subcommand {
+        tokio::spawn(async {
+            if let Err(e) = watch_signals(canceller).await {error!("failed to handle signals: {e}");}
+        });
+        return compare::compare(a,b,ignore,&mut reader).await;
+    }
+    reader.signatures = args.verifier()?;
+    if let Some(Subcommand::Diff{a,b,strip_a,strip_b,tsv}) = &args.subcommand {
+        let format = compare::DiffFormat{tsv:*tsv,print0:args.print0};
+        return compare::diff(a,b,(strip_a.as_deref(),strip_b.as_deref()),format,&mut reader).await;
+    }
+    if let Some(Subcommand::Merge{manifests,output,root,keep_conflicts}) = &args.subcommand {
+        let manifests:Vec<PathBuf> = manifests.iter()
+            .map(|file|if file.is_dir() {manifest_list::discover(file)} else {Ok(file.clone())})
+            .collect::<Result<_,_>>()?;
+        return merge::merge(&manifests,output,root.as_deref(),*keep_conflicts,&mut reader).await;
+    }
+
+    // only given on the command line, the checksum file by default isn't checked with a list
+    let list = args.manifest_list.as_deref().map(|list|(list,b'\n')).or(args.manifest_list0.as_deref().map(|list|(list,b'\0')));
+    let default_file = matches.value_source("file") == Some(clap::parser::ValueSource::DefaultValue);
+    let mut files = match (list.is_some() || args.watch.is_some() || args.daemon) && default_file || args.ref_db.is_some() {
+        true => vec![],
+        // a directory stands for the checksum file in it
+        false => args.file.iter()
+            .map(|file|if file.is_dir() {manifest_list::discover(file)} else {Ok(file.clone())})
+            .collect::<Result<_,_>>()?
+    };
+    let mut manifests = manifest_list::Manifests::open(files.clone(),list.map(|(list,_)|list),list.map_or(b'\n',|(_,separator)|separator)).await?;
+    if let (Some(a),Some(b)) = (&args.base_dir_a,&args.base_dir_b) {
+        let files = manifests.collect().await?;
+        tokio::spawn(async {
+            if let Err(e) = watch_signals(canceller).await {error!("failed to handle signals: {e}");}
+        });
+        return compare::compare_copies(&files,a,b,&mut reader).await;
+    }
+    let confirm_run = args.confirm && (!reader.actions.release.is_empty() || reader.actions.delete_after_verify);
+    if args.dry_run || args.tar_stream.is_some() || confirm_run {
+        files = manifests.collect().await?;
+        manifests = manifest_list::Manifests::open(files.clone(),None,b'\n').await?;
+    }
+    if let Some(dir) = &args.watch {
+        std::fs::read_dir(dir).map_err(|source|Error::Io{action:"watch",path:dir.clone(),source})?;
+        let names = if args.watch_name.is_empty() {manifest_list::conventional()} else {args.watch_name.clone()};
+        manifests.watch(Watcher::new(dir,names,args.watch_stable));
+    }
+
+    if args.dry_run {
+        let plan = Plan::new(&files,&reader).await;
+        print!("{plan}");
+        return if plan.problems.is_empty() {Ok(ExitCode::SUCCESS)} else {
+            Err(Error::Problems(plan.problems.len()))
+        }
+    }
+
+    if confirm_run {confirm(&files,&reader,args.yes).await?;}
+    let finally = args.ext_command("finally",&args.finally);
+    if !args.no_validate_commands {
+        // rather fail now than hours later when the first file is done
+        let finally = finally.as_deref().map(|cmd|("finally",cmd));
+        for (what,cmd) in reader.commands().into_iter().chain(finally) {
+            cmd.validate().map_err(|reason|Error::InvalidCommand{what,reason})?;
+        }
+    }
+    // don't check the same checksum files (or write the same journal) as another run
+    // the checksum files of a list are not locked one by one, but the list is
+    let mut lock_files:Vec<PathBuf> = files.iter().map(|f|lock::manifest_lock(f)).collect::<Result<_,_>>()?;
+    lock_files.extend(list.map(|(list,_)|list).filter(|list|*list != Path::new("-")).map(lock::manifest_lock).transpose()?);
+    lock_files.extend(args.watch.as_deref().map(lock::manifest_lock).transpose()?);
+    lock_files.extend(args.journal.as_deref().map(lock::journal_lock));
+    // in the same order everywhere, so waiting runs can't deadlock
+    lock_files.sort();
+    lock_files.dedup();
+    // held until the end of the run
+    let mut locks = vec![];
+    for lock_file in lock_files {
+        locks.push(lock::Lock::acquire(&lock_file,args.wait_lock).await?);
+    }
+    if let Some(journal) = &args.journal {
+        let journal = Journal::open(journal,args.rescan)
+            .map_err(|source|Error::Io{action:"open journal",path:journal.clone(),source})?;
+        reader.journal = Some(journal);
+    }
+    let priority_file = args.priority_file.as_deref().map(|file|(file,b'\n')).or(args.priority_file0.as_deref().map(|file|(file,b'\0')));
+    if let Some((file,separator)) = priority_file {
+        // watching and jobs have no end to collect all entries to, only those of a checksum file are put first then
+        let collect = args.watch.is_none() && !args.daemon;
+        let priorities = priority::Priorities::open(file,separator,collect)
+            .map_err(|source|Error::Io{action:"read priority file",path:file.into(),source})?;
+        #[cfg(unix)]
+        {
+            let reload = priorities.reloader();
+            tokio::spawn(async {
+                if let Err(e) = priority::reload_on_hangup(reload).await {error!("failed to handle SIGHUP: {e}");}
+            });
+        }
+        reader.priorities = Some(priorities);
+    }
+    tokio::spawn(async {
+        if let Err(e) = watch_signals(canceller).await {error!("failed to handle signals: {e}");}
+    });
+    #[cfg(feature="sqlite")]
+    let mut ref_db = match &args.ref_db {
+        Some(db) => Some(ref_db::RefDb::open(db,&args.ref_db_table,&args.ref_db_path_column,&args.ref_db_digest_column)?),
+        None => None
+    };
+    let start = std::time::Instant::now();
+    let result = match (&args.tar_stream,args.socket.as_deref().filter(|_|args.daemon)) {
+        #[cfg(feature="tar")]
+        (Some(source),_) => check_tar_stream(source,&files,&mut reader).await,
+        #[cfg(all(unix,feature="daemon"))]
+        (_,Some(socket)) => {
+            // each job is checked by a reader of its own, with the options of the daemon
+            let new_reader = || {
+                let (mut job,canceller) = CheckerBuilder::with(Options::from(&args),args.digest_backend.digests()?,None,events.clone())
+                    .unvalidated().build()?.into_parts();
+                job.signatures = args.verifier()?;
+                if let Some(journal) = &args.journal {
+                    job.journal = Some(Journal::open(journal,args.rescan).map_err(|source|Error::Io{action:"open journal",path:journal.clone(),source})?);
+                }
+                Ok((job,canceller))
+            };
+            daemon::serve(socket,&mut reader,&new_reader,args.check_self,args.concurrent_jobs as usize).await
+        }
+        #[cfg(feature="sqlite")]
+        _ if ref_db.is_some() => {
+            // without files given those in the current directory are checked
+            let given = if default_file {vec![PathBuf::from(".")]} else {args.file.clone()};
+            let db = ref_db.as_mut().expect("--ref-db was opened");
+            ref_db::check(&given,db,args.ref_db_remap.as_ref(),&mut reader).await
+        }
+        // without the features --tar-stream and --daemon were rejected already
+        _ => check(&mut manifests,&mut reader,args.check_self).await
+    };
+    conclude(&args,&matches,&mut reader,result,finally,start,(done,shown)).await
+}
+
+/// the end of a run after checking: print the results left and the summary, write what's asked for and run finally
+///
+/// Failing to write something is returned after the summary was printed and finally ran (the first error, the others are logged).
+async fn conclude(args:&Cli,matches:&ArgMatches,reader:&mut Reader,result:Result<(),Error>,finally:Option<Arc<ExtCommand>>,
+    start:std::time::Instant,notices:(tokio::sync::oneshot::Sender<()>,tokio::task::JoinHandle<()>)) -> Result<ExitCode,Error>
+{
+    let (done,shown) = notices;
+    reader.print_results();
+    // what's written at the end doesn't keep the summary and finally from coming if it fails
+    let mut errors = vec![];
+    reader.summary.mount_losses = reader.check.mount.losses();
+    reader.summary.concurrency = reader.adaptive.as_ref().map(adaptive::Adaptive::used);
+    let interrupted = reader.interrupted();
+    if let Some(priorities) = reader.priorities.take() {
+        reader.summary.expedited = priorities.expedited();
+        // stopping --watch or --daemon is how they end, the entries of the checksum files so far are known then
+        if !interrupted || !priorities.collect {
+            let unlisted = priorities.unlisted();
+            for path in &unlisted {
+                warn!("'{}' of the priority file is not listed in any checksum file",path.to_string_lossy());
+                reader.report(path,Status::NotListed,None);
+            }
+            reader.summary.priority_unlisted = unlisted.len() as u64;
+            reader.print_results();
+        }
+    }
+    if let (Some(root),Some(known),Ok(()),false) = (&args.check_extra,reader.known.take(),&result,interrupted) {
+        let (walked,all) = (root.clone(),args.extra_all);
+        let walk = tokio::task::spawn_blocking(move||extra::walk(&walked,&known,all)).await.map_err(Error::from).and_then(|walk|{
+            walk.map_err(|source|Error::Io{action:"look for extra files in",path:root.clone(),source})
+        });
+        match walk {
+            Ok(extra) => {
+                for path in &extra {reader.report(path,Status::Extra,None);}
+                reader.print_results();
+                reader.summary.extra = extra.len() as u64;
+                if let Some(extra_files) = &args.extra_files {
+                    errors.extend(write_paths(extra_files,&extra,b"\n").err()
+                        .map(|source|Error::Io{action:"write extra files to",path:extra_files.clone(),source}));
+                }
+            }
+            Err(e) => errors.push(e)
+        }
+    }
+    let not_attempted = reader.not_attempted();
+    let incomplete = (reader.deadline_passed() || reader.budget_spent()) && !not_attempted.is_empty();
+    reader.summary.not_attempted = not_attempted.len() as u64;
+    reader.summary.deadline_reached = reader.deadline_passed();
+    if let Some(max_bytes) = reader.max_bytes {
+        let remaining = reader.remaining_bytes(&not_attempted).await;
+        reader.summary.budget = Some((max_bytes,reader.admitted_bytes,remaining));
+    }
+    reader.summary.aborted = reader.threshold_exceeded();
+    let aborted = reader.summary.aborted.is_some();
+    if let Some(remaining) = &args.remaining {
+        errors.extend(write_remaining(remaining,&not_attempted).err()
+            .map(|source|Error::Io{action:"write remaining entries to",path:remaining.clone(),source}));
+    }
+    if let Some(failed_files0) = &args.failed_files0 {
+        let paths:Vec<PathBuf> = reader.failed_files.iter().map(|(path,_)|path.clone()).collect();
+        errors.extend(write_paths(failed_files0,&paths,b"\0").err()
+            .map(|source|Error::Io{action:"write failed files to",path:failed_files0.clone(),source}));
+    }
+    if let Some(script) = &args.failed_script {
+        let options = config::given(&Cli::command(),matches,&failed_script::NOT_REPEATED);
+        errors.extend(failed_script::write(script,&reader.failed_files,reader.check.stage.as_deref(),&options).err()
+            .map(|source|Error::Io{action:"write failed script",path:script.clone(),source}));
+    }
+    for (manifest,verified) in reader.verified.take().unwrap_or_default() {
+        // an extended checksum file given is not extended again
+        if manifest.extension().is_some_and(|extension|extension == tcman::EXTENSION) {continue}
+        let path = tcman::path_for(&manifest);
+        info!("writing {} files verified to '{}'",verified.len(),path.to_string_lossy());
+        errors.extend(tcman::write(&path,verified).err().map(|source|Error::Io{action:"write extended checksum file",path,source}));
+    }
+    if result.is_err() || interrupted || aborted || incomplete {
+        if args.release_unverified {reader.release_unverified();}
+        // don't leave outstanding releases behind
+        errors.extend(reader.join_releases().await.err());
+    }
+    // the notices so far are told before the summary
+    let _ = done.send(());
+    let _ = shown.await;
+    reader.summary.interrupted = interrupted;
+    reader.print_per_manifest();
+    reader.print_per_dir();
+    eprintln!("{}",reader.summary);
+    match &reader.stream_digest.result {
+        Some(Ok(digest)) => eprintln!("stream digest: {digest}"),
+        Some(Err(missing)) => eprintln!("no stream digest, {missing} files were not read completely"),
+        None => {}
+    }
+    let mut finally_failed = false;
+    if let Some(finally) = finally {
+        let abnormal = result.is_err() || !errors.is_empty() || interrupted || aborted || incomplete;
+        finally_failed = !finally.run("running finally for",&[],&reader.summary.env(start.elapsed(),abnormal)).await;
+    }
+    result?;
+    let mut errors = errors.into_iter();
+    if let Some(first) = errors.next() {
+        for e in errors {error!("{e}");}
+        return Err(first);
+    }
+    if interrupted {return Ok(ExitCode::from(INTERRUPTED));}
+    if let Some(reason) = reader.summary.aborted.take() {return Err(Error::ThresholdExceeded(reason));}
+    if reader.summary.fail + reader.summary.error + reader.summary.changed > 0 {
+        return Err(Error::Verification{failed:reader.summary.fail,errors:reader.summary.error+reader.summary.changed});
+    }
+    if args.extra_fatal && reader.summary.extra > 0 {
+        return Err(Error::ExtraFiles(reader.summary.extra));
+    }
+    if reader.summary.unreadable_manifests > 0 {
+        return Err(Error::UnreadableManifests(reader.summary.unreadable_manifests));
+    }
+    if reader.summary.rejected > 0 {
+        return Err(Error::SignatureRejected(reader.summary.rejected));
+    }
+    if reader.summary.confinement > 0 {
+        return Err(Error::Confinement(reader.summary.confinement));
+    }
+    if reader.summary.invalid_lines > 0 {
+        return Err(Error::InvalidLines(reader.summary.invalid_lines));
+    }
+    if reader.summary.footer_mismatches > 0 {
+        return Err(Error::FooterMismatch(reader.summary.footer_mismatches));
+    }
+    if reader.summary.noref > 0 {
+        return Err(Error::NoRef(reader.summary.noref));
+    }
+    if let Some(expected) = args.stream_digest.as_deref().filter(|e|!e.is_empty()) {
+        match reader.stream_digest.result.take() {
+            Some(Ok(digest)) if digest.eq_ignore_ascii_case(expected) => {}
+            Some(Ok(digest)) => return Err(Error::StreamDigest{expected:expected.into(),computed:digest}),
+            Some(Err(missing)) => return Err(Error::StreamIncomplete(missing)),
+            None => return Err(Error::StreamIncomplete(reader.stream_digest.missing()))
+        }
+    }
+    if args.release_failures_fatal && reader.summary.release_failures > 0 {
+        return Err(Error::ReleaseFailed{files:reader.summary.release_failures});
+    }
+    if args.finally_fatal && finally_failed {
+        return Err(Error::FinallyFailed);
+    }
+    if incomplete {return Ok(ExitCode::from(INCOMPLETE));}
+    Ok(ExitCode::SUCCESS)
+}
+
+/// write the paths to a file, each followed by the separator
+fn write_paths(path:&Path,paths:&[PathBuf],separator:&[u8]) -> std::io::Result<()>
+{
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for path in paths {
+        file.write_all(&path_bytes(path))?;
+        file.write_all(separator)?;
+    }
+    file.flush()
+}
+
+/// write the entries as checksum file (with absolute paths), so they can be checked in another run
+fn write_remaining(path:&Path,entries:&[Entry]) -> std::io::Result<()>
+{
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for entry in entries {
+        writeln!(file,"{}  {}",entry.expected,entry.listed_path().to_string_lossy())?;
+    }
+    file.flush()
+}
+
+pub(crate) async fn check(files:&mut manifest_list::Manifests,reader:&mut Reader,check_self:bool) -> Result<(),Error>
+{
+    // the checksum files themselves, so entries listing them can be skipped (those of the list as they're read)
+    let mut manifests:HashSet<PathBuf> = if check_self {HashSet::new()} else {
+        files.given().iter().filter_map(|f|std::fs::canonicalize(f).ok()).collect()
+    };
+    // the files listed in several checksum files with different algorithms are read once, if they're added together
+    let collect = collects(reader) || files.several() && !files.watching();
+    let mut all = vec![];
+    // with --watch, the checksum files whose entries were added since all were done
+    let mut watched:Vec<PathBuf> = vec![];
+    // after the deadline the rest is still read, to know what was not attempted
+    loop
+    {
+        let md5filepath = match files.next().await? {
+            Some(md5filepath) => md5filepath,
+            None if files.watching() && !reader.stopping() => {
+                // nothing to check now, so the checksum files so far are done
+                reader.drain().await?;
+                for manifest in watched.drain(..) {
+                    let (ok,fail,error) = reader.manifest_done(&manifest);
+                    eprintln!("{}: {ok} OK, {fail} FAIL, {error} ERROR",manifest.to_string_lossy());
+                }
+                // checksum files appearing later are checked against the files as they are then
+                reader.forget_digests();
+                tokio::select! {
+                    _ = files.changed() => {},
+                    _ = signalled(&mut reader.signals) => reader.on_signal()
+                }
+                continue
+            }
+            None => break
+        };
+        let md5filepath = &md5filepath;
+        if reader.interrupted() {break;}
+        if files.watching() {
+            if reader.journal.as_ref().is_some_and(|journal|journal.manifest_done(md5filepath)) {
+                info!("'{}' was done before according to the journal",md5filepath.to_string_lossy());
+                continue
+            }
+            watched.push(md5filepath.clone());
+        }
+        if !check_self {manifests.extend(std::fs::canonicalize(md5filepath).ok());}
+        if let Some(known) = &mut reader.known {known.extend(std::path::absolute(md5filepath).ok());}
+        let mut md5file = match File::open(md5filepath,&reader.check.open_policy,&reader.events).await {
+            Ok(md5file) => md5file,
+            Err(source) => {
+                let e = Error::Io{action:"open",path:md5filepath.clone(),source};
+                if reader.fail_fast {return Err(e);}
+                error!("{e}");
+                reader.summary.unreadable_manifests += 1;
+                continue
+            }
+        };
+        let md5base = manifest_base(md5filepath);
+        let manifest:Arc<Path> = md5filepath.as_path().into();
+
+        // to be verified the content has to be read completely, and only what was verified is parsed
+        let source:Box<dyn AsyncRead+Unpin+Send> = if reader.signatures.is_some() {
+            let mut content = vec![];
+            md5file.read_to_end(&mut content).await
+                .map_err(|source|Error::Io{action:"read",path:md5filepath.clone(),source})?;
+            if !reader.trusted(md5filepath,&content).await {continue}
+            Box::new(std::io::Cursor::new(content))
+        } else {Box::new(md5file)};
+        let mut lines= tokio::io::BufReader::new(source).lines();
+        let mut entries = vec![];
+        let (mut nr,mut listed,mut footer,mut format) = (0,0,Footer::default(),tcman::Format::default());
+        while let Some(line) = lines.next_line().await.transpose()
+        {
+            if reader.interrupted() {break;}
+            nr += 1;
+            match line {
+                Ok(line) => {
+                    if footer.parse(&line) {continue}
+                    let (md5,recorded,filename) = match format.parse(nr,&line,&reader.check.digests) {
+                        Ok(Some(parsed)) => parsed,
+                        Ok(None) => continue,
+                        Err(kind) => {
+                            reader.invalid_line(md5filepath,nr,kind);
+                            continue
+                        }
+                    };
+                    listed += 1;
+                    let (path,member) = reader.manifest_member(md5base,filename);
+                    if let Some(known) = &mut reader.known {
+                        known.extend(std::path::absolute(member.as_ref().map_or(&path,|(archive,_)|archive)).ok());
+                    }
+                    if !reader.in_shard(filename) {continue}
+                    if !reader.in_window(&path) {continue}
+                    reader.stream_digest.listed += 1;
+                    reader.events.emit(||Event::Listed{path:path.clone()});
+                    if !reader.confined(md5base,member.as_ref().map_or(&path,|(archive,_)|archive)) {
+                        error!("'{}' listed in '{}' is outside its directory, not checking it",path.to_string_lossy(),md5filepath.to_string_lossy());
+                        reader.report(&path,Status::Confinement,None);
+                        reader.summary.confinement += 1;
+                        continue
+                    }
+                    if !manifests.is_empty() && std::fs::canonicalize(&path).is_ok_and(|p|manifests.contains(&p)) {
+                        reader.report(&path,Status::SelfReference,None);
+                        reader.summary.self_references += 1;
+                        continue
+                    }
+                    debug!("adding '{}' with reference '{}'",path.to_string_lossy(),md5);
+                    entries.push(Entry{path,expected:md5.into(),manifest:manifest.clone(),size:0,mtime:None,id:None,recorded,member,along:vec![]});
+                },
+                Err(source) => return Err(Error::Io{action:"read",path:md5filepath.clone(),source})
+            }
+        }
+        // the checksum file is read completely before its files are checked, so a truncated one is noticed first
+        if !reader.interrupted() {reader.check_footer(&manifest,footer,listed);}
+        if collect {all.append(&mut entries)} else {reader.add_batches(entries).await?}
+    }
+    check_collected(all,reader).await
+}
+
+/// for a sample, to shuffle or pre-scan them all entries are collected first
+pub(crate) fn collects(reader:&Reader) -> bool
+{
+    reader.sample.is_some() || reader.shuffle.is_some() || reader.prescan || reader.priorities.as_ref().is_some_and(|p|p.collect)
+}
+
+/// check the entries collected (those not collected were added already), then those to check again
+pub(crate) async fn check_collected(mut all:Vec<Entry>,reader:&mut Reader) -> Result<(),Error>
+{
+    // the sample is taken from the files of the sizes wanted
+    if reader.sample.is_some() && reader.filters.file_sizes != (None,None) {
+        let mut wanted = Vec::with_capacity(all.len());
+        for entry in all {
+            match entry.file().metadata() {
+                Ok(meta) if reader.filters.by_size(meta.len()) => reader.skip_size(&entry.path,meta.len()),
+                _ => wanted.push(entry)
+            }
+        }
+        all = wanted;
+    }
+    if let Some(sampling) = &reader.sample {
+        let weights:Vec<u64> = match sampling.by_bytes {
+            true => all.iter().map(|e|e.file().metadata().map(|m|m.len()).unwrap_or(0)).collect(),
+            false => vec![0;all.len()]
+        };
+        let chosen = sampling.choose(&weights);
+        reader.summary.sample = Some((chosen.len() as u64,all.len() as u64,sampling.to_string()));
+        let mut listed:Vec<Option<Entry>> = all.into_iter().map(Some).collect();
+        all = chosen.into_iter().filter_map(|i|listed[i].take()).collect();
+    }
+    if let Some(seed) = reader.shuffle {
+        eprintln!("checking {} entries in random order (--shuffle={seed})",all.len());
+        sample::shuffle(&mut all,seed);
+    }
+    if reader.prescan && !reader.prescan_entries(&mut all).await {all.clear()}
+    reader.add_batches(all).await?;
+    reader.drain().await?;
+    // files checked again below would be in the wrong order
+    reader.finish_stream();
+    while !reader.changed.is_empty() && !reader.stopping() {
+        let changed = std::mem::take(&mut reader.changed);
+        reader.forget_failed(&changed);
+        eprintln!("checking {} files that changed while being read again",changed.len());
+        for entry in changed {
+            reader.wait_stable(entry.file()).await;
+            reader.add_printing(vec![entry]).await?;
+        }
+        reader.drain().await?;
+    }
+    // those that weren't checked again
+    reader.summary.changed += reader.changed.len() as u64;
+    for pass in 1..=reader.retry_pass.passes {
+        if reader.stopping() || reader.retry_pass.is_empty() {break;}
+        let failed = reader.take_failed();
+        reader.forget_failed(&failed);
+        eprintln!("retry pass {pass}/{}: checking {} files again",reader.retry_pass.passes,failed.len());
+        reader.add_batches(failed).await?;
+        reader.drain().await?;
+    }
+    Ok(())
+}
+
+/// check the members of a tar stream against the entries of the checksum files, matched by the path listed
+///
+/// The stream is read once front to back, the size limit and commands don't apply.
+#[cfg(feature="tar")]
+async fn check_tar_stream(source:&Path,files:&[PathBuf],reader:&mut Reader) -> Result<(),Error>
+{
+    let mut listed:HashMap<PathBuf,Vec<String>> = HashMap::new();
+    for md5filepath in files {
+        let content = tokio::fs::read_to_string(md5filepath).await
+            .map_err(|source|Error::Io{action:"read",path:md5filepath.clone(),source})?;
+        if !reader.trusted(md5filepath,content.as_bytes()).await {continue}
+        let mut format = tcman::Format::default();
+        for (nr,line) in content.lines().enumerate() {
+            let (md5,filename) = match format.parse(nr as u64+1,line,&reader.check.digests) {
+                Ok(Some((md5,_,filename))) => (md5,filename),
+                Ok(None) => continue,
+                Err(kind) => {
+                    reader.invalid_line(md5filepath,nr as u64+1,kind);
+                    continue
+                }
+            };
+            let filename = match &reader.path_compat {
+                Some(compat) => compat.translate(filename),
+                None => filename.into()
+            };
+            listed.entry(tar_stream::key(&manifest_path(Path::new(""),&filename))).or_default().push(md5.into());
+        }
+    }
+    let (sender,mut members) = tokio::sync::mpsc::channel(16);
+    let stream = source.to_path_buf();
+    // not spawn_blocking, the runtime would wait for a read from stdin that never returns when exiting
+    // tar members are compared by md5
+    let md5 = reader.check.digests.by_name("md5").cloned().expect("md5 is known");
+    let buffer_size = reader.check.buffers.size();
+    thread::spawn(move||tar_stream::digest_members(&stream,md5,buffer_size,sender));
+    loop {
+        let member = tokio::select! {
+            member = members.recv() => member,
+            _ = signalled(&mut reader.signals) => {reader.on_signal();None}
+        };
+        let Some(member) = member else {break};
+        let (path,size,digest) = member.map_err(|e|Error::Io{action:"read tar stream",path:source.to_path_buf(),source:e})?;
+        let Some(expected) = listed.remove(&path) else {
+            reader.report(&path,Status::NotListed,None);
+            reader.summary.not_listed += 1;
+            continue
+        };
+        match digest {
+            Ok(digest) => for expected in expected {
+                let ok = digest == expected;
+                if ok {reader.summary.ok += 1} else {reader.summary.fail += 1}
+                reader.summary.bytes += size;
+                reader.report(&path,if ok {Status::Ok} else {Status::Fail},None);
+            }
+            Err(e) => {
+                reader.summary.error += expected.len() as u64;
+                for _ in expected {reader.report(&path,Status::Error(None),None);}
+                error!("failed reading '{}' from the tar stream: {e}",path.to_string_lossy());
+            }
+        }
+        reader.print_results();
+        if reader.stopping() {break}
+    }
+    // the rest of the stream wasn't read
+    if reader.stopping() {return Ok(());}
+    let mut missing:Vec<(PathBuf,Vec<String>)> = listed.into_iter().collect();
+    missing.sort();
+    for (path,expected) in missing {
+        error!("'{}' is not in the tar stream",path.to_string_lossy());
+        reader.summary.error += expected.len() as u64;
+        for _ in expected {reader.report(&path,Status::Error(Some("not-in-stream")),None);}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn files_listed_with_several_algorithms_are_read_once()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("file"),"hello\n").unwrap();
+        let (md5sum,sha256sums) = (dir.path().join("md5sum"),dir.path().join("SHA256SUMS"));
+        std::fs::write(&md5sum,"b1946ac92492d2347c6235b4d2611184  file\n").unwrap();
+        // not the digest of the file
+        std::fs::write(&sha256sums,format!("{}  file\n","0".repeat(64))).unwrap();
+        let (sender,mut events) = tokio::sync::mpsc::unbounded_channel();
+        let options = Options{per_manifest:true,..Options::default()};
+        let (mut reader,_canceller) = CheckerBuilder::with(options,Digests::default(),None,Events::new(sender)).build().unwrap().into_parts();
+        let mut files = manifest_list::Manifests::open(vec![md5sum.clone(),sha256sums.clone()],None,b'\n').await.unwrap();
+        check(&mut files,&mut reader,false).await.unwrap();
+        let admitted:Vec<u64> = std::iter::from_fn(||events.try_recv().ok())
+            .filter_map(|event|match event {Event::Admitted{size,..} => Some(size),_ => None}).collect();
+        assert_eq!((admitted,reader.admitted_bytes),(vec![6],6));
+        let per_manifest = reader.per_manifest.as_ref().unwrap();
+        let status = |manifest:&Path|per_manifest[manifest].values().copied().collect::<Vec<_>>();
+        assert_eq!((status(&md5sum),status(&sha256sums)),(vec![Status::Ok],vec![Status::Fail]));
+        assert_eq!((reader.summary.ok,reader.summary.fail),(1,1));
+    }
+
+    #[tokio::test]
+    async fn only_the_valid_lines_are_checked()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("file"),"hello\n").unwrap();
+        let md5sum = dir.path().join("md5sum");
+        std::fs::write(&md5sum,"\n0123456789\nb1946ac92492d2347c6235b4d2611184  file\n").unwrap();
+        let (sender,mut events) = tokio::sync::mpsc::unbounded_channel();
+        let (mut reader,_canceller) = CheckerBuilder::with(Options::default(),Digests::default(),None,Events::new(sender)).build().unwrap().into_parts();
+        let mut files = manifest_list::Manifests::open(vec![md5sum.clone()],None,b'\n').await.unwrap();
+        check(&mut files,&mut reader,false).await.unwrap();
+        let (mut listed,mut invalid) = (vec![],vec![]);
+        for event in std::iter::from_fn(||events.try_recv().ok()) {
+            match event {
+                Event::Listed{path} => listed.push(path),
+                Event::InvalidLine{manifest,line,reason} => invalid.push(Error::ManifestParse{file:manifest,line,kind:reason}.to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(listed,[dir.path().join("file")]);
+        assert_eq!(invalid.len(),1);
+        assert!(invalid[0].starts_with(&format!("{}:2: ",md5sum.display())),"{invalid:?}");
+        assert_eq!((reader.summary.ok,reader.summary.invalid_lines),(1,1));
+    }
+
+    #[tokio::test]
+    async fn finally_runs_when_writing_the_results_fails()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("finally-ran");
+        let unwritable = dir.path().join("missing").join("failed");
+        let finally = format!("touch '{}'",marker.display());
+        let argv = ["md5check","--release-shell","--failed-files0",unwritable.to_str().unwrap(),"--finally",&finally];
+        let matches = Cli::command().try_get_matches_from(argv).unwrap();
+        let args = Cli::from_arg_matches(&matches).unwrap();
+        let (sender,_events) = tokio::sync::mpsc::unbounded_channel();
+        let (mut reader,_canceller) = CheckerBuilder::with(Options::from(&args),Digests::default(),None,Events::new(sender)).build().unwrap().into_parts();
+        let (done,notices) = tokio::sync::oneshot::channel();
+        let shown = tokio::spawn(async move {let _ = notices.await;});
+        let finally = args.ext_command("finally",&args.finally);
+        let result = conclude(&args,&matches,&mut reader,Ok(()),finally,std::time::Instant::now(),(done,shown)).await;
+        assert!(matches!(result,Err(Error::Io{action:"write failed files to",..})),"{result:?}");
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn the_command_line_overrides_the_environment_and_the_config()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("tape-check.toml");
+        std::fs::write(&file,"max-size = 5\nstage = \"from-config\"\nrelease-batch = 3\nrelease = [\"a\",\"b\"]\n").unwrap();
+        let vars = [("TAPE_CHECK_MAX_SIZE","7"),("TAPE_CHECK_STAGE","from-env")].map(|(var,value)|(var.into(),value.into()));
+        let argv = ["md5check","--config",file.to_str().unwrap(),"--stage","from-cli"].map(OsString::from);
+        let (args,_,config) = Cli::parse_with_config_from(argv.to_vec(),vars).unwrap();
+        assert_eq!((args.max_size,args.stage.as_deref(),args.release_batch),(7,Some("from-cli"),3));
+        assert_eq!(args.release,["a","b"]);
+        assert!(matches!(&config["max_size"].0,config::Origin::Env(var) if var == "TAPE_CHECK_MAX_SIZE"));
+        assert!(!config.contains_key("stage"));
+    }
+
+    #[test]
+    fn completions_know_the_options()
+    {
+        for shell in [clap_complete::Shell::Bash,clap_complete::Shell::Zsh,clap_complete::Shell::Fish] {
+            let script = String::from_utf8(completions(shell)).unwrap();
+            for option in ["max-size","stage","release","status-interval","completions"] {
+                assert!(script.contains(option),"no {option} in the {shell} completions");
+            }
+        }
+    }
+}
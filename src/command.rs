@@ -0,0 +1,410 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+
+/// additional environment variables for a command
+pub type Env = Vec<(&'static str,OsString)>;
+
+static MAX_RETRY_DELAY:Duration = Duration::new(60,0);
+#[cfg(not(windows))]
+static SHELL:&str = "sh";
+#[cfg(not(windows))]
+static SHELL_FLAG:&str = "-c";
+#[cfg(windows)]
+static SHELL:&str = "cmd";
+#[cfg(windows)]
+static SHELL_FLAG:&str = "/C";
+
+/// how long a command gets to exit after SIGTERM before it is killed
+#[cfg(unix)]
+static KILL_GRACE:Duration = Duration::new(10,0);
+
+/// how external commands are run
+#[derive(Clone,Default)]
+pub struct Options
+{
+    /// run via `sh -c`
+    pub shell:bool,
+    /// terminate commands running longer than this
+    pub timeout:Option<Duration>,
+    /// working directory for commands (paths given to them are made absolute then)
+    pub cwd:Option<PathBuf>
+}
+
+/// an external command (release, on-fail ...) as given on the command line
+///
+/// Without shell the command is split at whitespace, and the path(s) replace an argument "{}"
+/// or are appended if there is none.
+/// With shell the command is run via `sh -c` and gets the path(s) as positional parameters.
+/// Paths are never interpolated into the command string.
+/// On Windows `cmd /C` is used as shell, and the path(s) are appended to the command (quoted).
+///
+/// The program is resolved once when the command is created: if it contains a path separator
+/// it is taken relative to the current directory, otherwise it is searched in PATH.
+pub struct ExtCommand
+{
+    line:String,
+    program:Option<PathBuf>,
+    options:Options
+}
+
+/// the command (what it's for, like "release"), unless it's not given or blank; logs what it runs
+pub(crate) fn configured(what:&str,cmd:Option<&str>,options:&Options) -> Option<Arc<ExtCommand>>
+{
+    let cmd = ExtCommand::new(cmd.filter(|cmd|!cmd.trim().is_empty())?,options.clone());
+    match cmd.resolve() {
+        Some(program) => info!("{what} command '{cmd}' runs {}",program.to_string_lossy()),
+        None => warn!("{what} command '{cmd}' was not found")
+    }
+    Some(Arc::new(cmd))
+}
+
+impl ExtCommand
+{
+    pub fn new(line:&str,options:Options) -> ExtCommand
+    {
+        let program = Self::find_program(if options.shell {SHELL} else {line.split_whitespace().next().unwrap_or_default()});
+        ExtCommand{line:line.into(),program,options}
+    }
+    fn find_program(program:&str) -> Option<PathBuf>
+    {
+        let program = Path::new(program);
+        if program.components().count() > 1 {
+            let program = std::path::absolute(program).ok()?;
+            return is_executable(&program).then_some(program);
+        }
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path).flat_map(|dir|candidates(&dir.join(program))).find(|p|is_executable(p))
+    }
+    /// the program that will be run, if it was found
+    pub fn resolve(&self) -> Option<&Path>
+    {
+        self.program.as_deref()
+    }
+    /// check that the program was found, and the placeholder `{}` is a separate argument
+    pub fn validate(&self) -> Result<(),String>
+    {
+        if self.program.is_none() {
+            let program = if self.options.shell {SHELL} else {self.line.split_whitespace().next().unwrap_or_default()};
+            return Err(format!("{self}: '{program}' was not found"));
+        }
+        if !self.options.shell && self.line.split_whitespace().any(|arg|arg != "{}" && arg.contains("{}")) {
+            return Err(format!("{self}: the path placeholder {{}} has to be an argument of its own"));
+        }
+        Ok(())
+    }
+    fn command(&self,paths:&[PathBuf],env:&Env) -> Command
+    {
+        let mut cmd = match &self.options.cwd {
+            Some(cwd) => {
+                let paths:Vec<PathBuf> = paths.iter().map(|p|std::path::absolute(p).unwrap_or_else(|_|p.clone())).collect();
+                let mut cmd = self.argv(&paths);
+                cmd.current_dir(cwd);
+                cmd
+            }
+            None => self.argv(paths)
+        };
+        cmd.envs(env.iter().map(|(k,v)|(k,v)));
+        cmd
+    }
+    fn argv(&self,paths:&[PathBuf]) -> Command
+    {
+        if self.options.shell {
+            let mut cmd = Command::new(self.program.as_deref().unwrap_or(Path::new(SHELL)));
+            #[cfg(not(windows))]
+            cmd.arg("-c").arg(&self.line).arg(env!("CARGO_PKG_NAME")).args(paths);
+            // cmd has no positional parameters, and does its own parsing of the command line
+            #[cfg(windows)]
+            cmd.arg("/C").raw_arg(&self.line).args(paths);
+            cmd
+        } else {
+            let argv:Vec<&str> = self.line.split_whitespace().collect();
+            let (program,params) = argv.split_first().expect("commands should not be empty");
+            // if it wasn't found, let spawning fail with a proper error
+            let program = self.program.as_deref().unwrap_or(Path::new(program));
+            let mut args:Vec<&OsStr> = Vec::new();
+            let mut substituted = false;
+            for &param in params {
+                if param == "{}" {
+                    args.extend(paths.iter().map(|p|p.as_os_str()));
+                    substituted = true;
+                } else {
+                    args.push(param.as_ref());
+                }
+            }
+            if !substituted {
+                args.extend(paths.iter().map(|p|p.as_os_str()));
+            }
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            cmd
+        }
+    }
+    /// the command run for the path as line of a shell script (without the environment variables it gets)
+    pub fn script_line(&self,path:&Path) -> Vec<u8>
+    {
+        let path = path.as_os_str();
+        let args:Vec<&OsStr> = if self.options.shell {
+            // scripts are for a POSIX shell, also on Windows
+            vec!["sh".as_ref(),"-c".as_ref(),self.line.as_ref(),env!("CARGO_PKG_NAME").as_ref(),path]
+        } else {
+            let mut args:Vec<&OsStr> = self.line.split_whitespace().map(|arg|if arg == "{}" {path} else {arg.as_ref()}).collect();
+            if !self.line.split_whitespace().any(|arg|arg == "{}") {args.push(path);}
+            args
+        };
+        let mut line = args.into_iter().map(shell_quote).collect::<Vec<_>>().join(&b' ');
+        if let Some(cwd) = &self.options.cwd {
+            line = [b"(cd ".as_slice(),&shell_quote(cwd.as_os_str())," && ".as_bytes(),&line,b")"].concat();
+        }
+        line
+    }
+    /// run for paths and capture its output
+    ///
+    /// If it runs longer than the timeout it is terminated, and an error of kind TimedOut is returned.
+    pub async fn output(&self,what:&str,paths:&[PathBuf],env:&Env) -> std::io::Result<Output>
+    {
+        self.capture(what,paths,env,None).await
+    }
+    /// like [`output`](ExtCommand::output), with the input given on stdin
+    pub async fn output_with_input(&self,what:&str,paths:&[PathBuf],env:&Env,input:&[u8]) -> std::io::Result<Output>
+    {
+        self.capture(what,paths,env,Some(input)).await
+    }
+    async fn capture(&self,what:&str,paths:&[PathBuf],env:&Env,input:Option<&[u8]>) -> std::io::Result<Output>
+    {
+        let mut cmd = self.command(paths,env);
+        debug!("{what} {} with {:?}",describe(paths),cmd.as_std());
+        // make sure the child doesn't outlive us if we're cancelled or exit
+        // and give it its own process group, so terminate() and the guard get its children as well
+        #[cfg(unix)]
+        cmd.process_group(0);
+        let mut guard = Group(cmd.kill_on_drop(true)
+            .stdin(if input.is_some() {Stdio::piped()} else {Stdio::null()}).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn()?);
+        let child = &mut guard.0;
+        let (stdin_pipe, mut stdout_pipe, mut stderr_pipe) = (child.stdin.take(), child.stdout.take(), child.stderr.take());
+        let (mut stdout, mut stderr) = (vec![],vec![]);
+        let finished = async {
+            // closed once written, a command not reading all of it is up to its exit status
+            let write_stdin = async {
+                match (stdin_pipe,input) {
+                    (Some(mut pipe),Some(input)) => match pipe.write_all(input).await {
+                        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+                        written => written
+                    },
+                    _ => Ok(())
+                }
+            };
+            let read_stdout = async {
+                match stdout_pipe.as_mut() {Some(pipe) => pipe.read_to_end(&mut stdout).await,None => Ok(0)}
+            };
+            let read_stderr = async {
+                match stderr_pipe.as_mut() {Some(pipe) => pipe.read_to_end(&mut stderr).await,None => Ok(0)}
+            };
+            let (status,written,out,err) = tokio::join!(child.wait(),write_stdin,read_stdout,read_stderr);
+            written?;out?;err?;
+            status
+        };
+        let status = match self.options.timeout {
+            None => finished.await?,
+            Some(timeout) => match tokio::time::timeout(timeout,finished).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    warn!("{self} for {} did not finish within {timeout:?}, terminating it",describe(paths));
+                    terminate(child).await;
+                    return Err(std::io::Error::new(ErrorKind::TimedOut,format!("timed out after {timeout:?}")));
+                }
+            }
+        };
+        Ok(Output{status,stdout,stderr})
+    }
+    /// run for paths, returns false if it could not be run, timed out or exited with an error
+    pub async fn run(&self,what:&str,paths:&[PathBuf],env:&Env) -> bool
+    {
+        let output = self.output(what,paths,env).await;
+        match output {
+            Ok(output) if output.status.success() => true,
+            Ok(output) => {
+                warn!(r#"running {self} for {} failed ({}): {}"#,
+                    describe(paths), output.status,
+                    String::from_utf8_lossy(&output.stderr).trim());
+                false
+            }
+            Err(e) => {
+                error!(r#"failed running {self} for {}: {e}"#, describe(paths));
+                false
+            }
+        }
+    }
+    /// run for paths, on failure retry up to retries times, waiting twice as long each time
+    pub async fn run_retrying(&self,what:&str,paths:&[PathBuf],env:&Env,retries:u32) -> bool
+    {
+        let mut delay = Duration::from_secs(1);
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                debug!("retrying '{self}' for {} in {delay:?} ({attempt}/{retries})",describe(paths));
+                tokio::time::sleep(delay).await;
+                delay = (delay*2).min(MAX_RETRY_DELAY);
+            }
+            if self.run(what,paths,env).await {return true;}
+        }
+        false
+    }
+}
+
+impl Display for ExtCommand
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.options.shell {write!(f,"{SHELL} {SHELL_FLAG} '{}'",self.line)} else {f.write_str(&self.line)}
+    }
+}
+
+/// the argument in single quotes for sh (unless it's made of characters that are safe without)
+pub fn shell_quote(arg:&OsStr) -> Vec<u8>
+{
+    let arg = arg.as_encoded_bytes();
+    if !arg.is_empty() && arg.iter().all(|c|c.is_ascii_alphanumeric() || b"-_./=:,+@%".contains(c)) {return arg.to_vec();}
+    let mut quoted = vec![b'\''];
+    for &c in arg {
+        if c == b'\'' {quoted.extend_from_slice(b"'\\''")} else {quoted.push(c)}
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+pub fn describe(paths:&[PathBuf]) -> String
+{
+    match paths {
+        [] => "the run".into(),
+        [path] => format!("'{}'",path.to_string_lossy()),
+        _ => format!("batch of {} files",paths.len())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path:&Path) -> bool
+{
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m|m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+#[cfg(not(unix))]
+fn is_executable(path:&Path) -> bool
+{
+    path.is_file()
+}
+
+/// the paths a program could be found as
+#[cfg(not(windows))]
+fn candidates(program:&Path) -> Vec<PathBuf>
+{
+    vec![program.to_path_buf()]
+}
+/// the paths a program could be found as (with the extensions from PATHEXT if it has none)
+#[cfg(windows)]
+fn candidates(program:&Path) -> Vec<PathBuf>
+{
+    if program.extension().is_some() {return vec![program.to_path_buf()];}
+    let pathext = std::env::var("PATHEXT").unwrap_or(".COM;.EXE;.BAT;.CMD".into());
+    pathext.split(';').filter(|ext|!ext.is_empty())
+        .map(|ext|program.with_extension(ext.trim_start_matches('.'))).collect()
+}
+
+/// a running child, whose process group is killed if it's dropped before the child was waited for (as its future was cancelled)
+///
+/// Dropping the child only kills the child itself, not what it started, like the commands of `sh -c`.
+struct Group(Child);
+
+impl Drop for Group
+{
+    fn drop(&mut self)
+    {
+        #[cfg(unix)]
+        if let Some(pid) = self.0.id() {
+            // SAFETY: kill has no memory safety requirements. The id is only there while the child is not reaped,
+            // so the group can't have been taken by other processes yet.
+            unsafe {libc::kill(-(pid as libc::pid_t),libc::SIGKILL);}
+        }
+    }
+}
+
+/// send SIGTERM to the child's process group, and kill the group if the child doesn't exit within KILL_GRACE
+///
+/// Once the child has exited (and is reaped) its group is not signalled anymore, as its id could be reused then.
+/// So the members of the group that don't exit on SIGTERM are only killed with the child.
+async fn terminate(child:&mut Child)
+{
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: kill has no memory safety requirements, the group belongs to our child as it's not reaped yet
+        unsafe {libc::kill(-(pid as libc::pid_t),libc::SIGTERM);}
+        if tokio::time::timeout(KILL_GRACE,child.wait()).await.is_ok() {return;}
+        warn!("child {pid} did not exit after SIGTERM, killing it");
+        // SAFETY: as above, waiting timed out, so the child is still not reaped
+        unsafe {libc::kill(-(pid as libc::pid_t),libc::SIGKILL);}
+    }
+    if let Err(e) = child.kill().await {
+        error!("failed to kill child: {e}");
+    }
+}
+
+#[cfg(all(test,target_os="linux"))]
+mod tests
+{
+    use super::*;
+
+    /// the command in the background of a shell, whose pid is written to a file
+    fn backgrounded(dir:&Path,timeout:Option<Duration>) -> ExtCommand
+    {
+        let line = format!("sleep 30 & echo $! > '{}'; wait",dir.join("pid").display());
+        ExtCommand::new(&line,Options{shell:true,timeout,cwd:None})
+    }
+    async fn background_pid(dir:&Path) -> libc::pid_t
+    {
+        loop {
+            if let Some(pid) = std::fs::read_to_string(dir.join("pid")).ok().and_then(|pid|pid.trim().parse().ok()) {return pid;}
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+    /// if the process is gone (or a zombie, waiting for init to reap it)
+    async fn gone(pid:libc::pid_t) -> bool
+    {
+        for _ in 0..500 {
+            match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+                Err(_) => return true,
+                Ok(stat) if stat.rsplit(") ").next().is_some_and(|state|state.starts_with('Z')) => return true,
+                Ok(_) => tokio::time::sleep(Duration::from_millis(10)).await
+            }
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn cancelling_kills_the_group()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let (cmd,env) = (backgrounded(dir.path(),None),Env::new());
+        let running = cmd.output("testing",&[],&env);
+        let pid = tokio::select! {
+            _ = running => panic!("the command finished"),
+            pid = background_pid(dir.path()) => pid
+        };
+        assert!(gone(pid).await,"the background command {pid} survived cancelling");
+    }
+
+    #[tokio::test]
+    async fn timeouts_terminate_the_group()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let cmd = backgrounded(dir.path(),Some(Duration::from_millis(500)));
+        let result = cmd.output("testing",&[],&Env::new()).await;
+        assert_eq!(result.unwrap_err().kind(),ErrorKind::TimedOut);
+        assert!(gone(background_pid(dir.path()).await).await);
+    }
+}
@@ -0,0 +1,471 @@
+//! the compare subcommand: two directory trees compared by the digests of their files, without checksum files
+//! and --base-dir-a/--base-dir-b: the files of checksum files compared in two places, and with their checksums
+//! and the diff subcommand: two checksum files compared by the digests they list, without reading any files
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use log::{debug, error, warn};
+use tokio::task::JoinSet;
+use crate::options::Symlinks;
+use crate::error::Error;
+use crate::source::SourceMetadata;
+use crate::watcher::glob;
+use crate::{check_file, manifest_path, signalled, CheckError, Checked, Entry, Reader};
+
+/// what's found at a path below one of the trees
+enum Node
+{
+    File(SourceMetadata),
+    /// a symlink not followed, with its target
+    Symlink(PathBuf),
+    /// a symlink to follow pointing nowhere
+    Dangling(PathBuf),
+    /// not a regular file (FIFO, device ...)
+    Special
+}
+
+/// the verdict for a path
+#[derive(Clone,Copy,PartialEq)]
+enum Verdict
+{
+    Match,
+    Differ,
+    OnlyInA,
+    OnlyInB,
+    Error
+}
+
+impl std::fmt::Display for Verdict
+{
+    fn fmt(&self,f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(match self {
+            Verdict::Match => "MATCH",Verdict::Differ => "DIFFER",Verdict::OnlyInA => "ONLY-IN-A",
+            Verdict::OnlyInB => "ONLY-IN-B",Verdict::Error => "ERROR"
+        })
+    }
+}
+
+/// the files below root by their path relative to it, without those with a name matching one of the patterns
+///
+/// Symlinks are followed (also to directories, each directory is walked once) with `Symlinks::Follow`,
+/// reported with their target with `Report` and left out with `Skip`.
+fn walk(root:&Path,ignore:&[String],symlinks:Symlinks) -> std::io::Result<BTreeMap<PathBuf,Node>>
+{
+    let mut found = BTreeMap::new();
+    let mut walked = HashSet::new();
+    let mut dirs = vec![PathBuf::new()];
+    // the root itself has to be readable
+    let mut first = true;
+    while let Some(dir) = dirs.pop() {
+        if let Ok(canonical) = root.join(&dir).canonicalize() {
+            if !walked.insert(canonical) {continue}
+        }
+        let entries = match std::fs::read_dir(root.join(&dir)) {
+            Ok(entries) => entries,
+            Err(e) if first => return Err(e),
+            Err(e) => {
+                warn!("failed to read directory '{}': {e}",root.join(&dir).to_string_lossy());
+                continue
+            }
+        };
+        first = false;
+        for entry in entries.flatten() {
+            if ignore.iter().any(|pattern|glob(pattern,&entry.file_name().to_string_lossy())) {continue}
+            let (path,relative) = (entry.path(),dir.join(entry.file_name()));
+            let Ok(kind) = entry.file_type() else {continue};
+            if kind.is_symlink() {
+                let target = std::fs::read_link(&path).unwrap_or_default();
+                match (symlinks,path.metadata()) {
+                    (Symlinks::Skip,_) => continue,
+                    (Symlinks::Report,_) => {found.insert(relative,Node::Symlink(target));}
+                    (Symlinks::Follow,Ok(metadata)) if metadata.is_dir() => dirs.push(relative),
+                    (Symlinks::Follow,Ok(metadata)) if metadata.is_file() => {found.insert(relative,Node::File(SourceMetadata::from(&metadata)));}
+                    (Symlinks::Follow,Ok(_)) => {found.insert(relative,Node::Special);}
+                    (Symlinks::Follow,Err(_)) => {found.insert(relative,Node::Dangling(target));}
+                }
+            } else if kind.is_dir() {
+                dirs.push(relative);
+            } else if kind.is_file() {
+                if let Ok(metadata) = entry.metadata() {found.insert(relative,Node::File(SourceMetadata::from(&metadata)));}
+            } else {
+                found.insert(relative,Node::Special);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// counts of the verdicts
+#[derive(Default)]
+struct Summary
+{
+    matching:u64,differ:u64,only_a:u64,only_b:u64,error:u64,
+    /// not regular files on both sides, and those skipped by the filters
+    not_files:u64,filtered:u64,
+    /// pairs not compared as it was interrupted
+    not_attempted:u64,
+    bytes:u64,
+    /// with --base-dir-a/-b, the files OK, FAIL and ERROR on side A and B, and the pairs of copies read that differ
+    copies:[[u64;3];2],copies_differ:u64
+}
+
+impl std::fmt::Display for Summary
+{
+    fn fmt(&self,f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let compared = self.matching+self.differ+self.error;
+        write!(f,"{compared} files compared: {} MATCH, {} DIFFER, {} ERROR ({:.1}G read)",self.matching,self.differ,self.error,
+            self.bytes as f64/crate::GIGABYTE as f64)?;
+        write!(f,"\n{} files only in A, {} only in B",self.only_a,self.only_b)?;
+        if self.not_files > 0 {write!(f,"\n{} paths are no files on both sides",self.not_files)?;}
+        if self.filtered > 0 {write!(f,"\n{} files were skipped by their size or modification time",self.filtered)?;}
+        if self.not_attempted > 0 {write!(f,"\n{} files were not compared",self.not_attempted)?;}
+        Ok(())
+    }
+}
+
+/// the summary of comparing copies of the files of checksum files
+struct Copies<'s>(&'s Summary);
+
+impl std::fmt::Display for Copies<'_>
+{
+    fn fmt(&self,f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let Summary{copies:[a,b],copies_differ,matching,bytes,not_attempted,filtered,..} = self.0;
+        write!(f,"{} pairs of copies read: {matching} MATCH, {copies_differ} DIFFER ({:.1}G read)",matching+copies_differ,
+            *bytes as f64/crate::GIGABYTE as f64)?;
+        write!(f,"\nA: {} OK, {} FAIL, {} ERROR",a[0],a[1],a[2])?;
+        write!(f,"\nB: {} OK, {} FAIL, {} ERROR",b[0],b[1],b[2])?;
+        if *filtered > 0 {write!(f,"\n{filtered} files were skipped by their size or modification time")?;}
+        if *not_attempted > 0 {write!(f,"\n{not_attempted} files were not compared")?;}
+        Ok(())
+    }
+}
+
+/// the digest of a side of a pair, or why it couldn't be read
+type Digest = Result<String,String>;
+
+/// the files of both trees being read, as much at a time as max-size allows
+struct Comparison<'r>
+{
+    reader:&'r mut Reader,
+    readers:JoinSet<(usize,bool,u64,Result<Checked,CheckError>)>,
+    cur_size:u64,
+    /// the paths of the pairs (and the listed digest with --base-dir-a/-b), the digests (or why not) of their sides read so far
+    pairs:Vec<(PathBuf,Option<String>)>,
+    digests:HashMap<usize,(Option<Digest>,Option<Digest>)>,
+    /// the pairs with a verdict
+    decided:HashSet<usize>,
+    summary:Summary
+}
+
+impl Comparison<'_>
+{
+    fn print(&mut self,path:&Path,verdict:Verdict)
+    {
+        println!("{} {verdict}",path.to_string_lossy());
+        match verdict {
+            Verdict::Match => self.summary.matching += 1,
+            Verdict::Differ => self.summary.differ += 1,
+            Verdict::OnlyInA => self.summary.only_a += 1,
+            Verdict::OnlyInB => self.summary.only_b += 1,
+            Verdict::Error => self.summary.error += 1
+        }
+    }
+    /// print if the copies are OK (match the listed digest), and if they match each other when both could be read
+    fn print_copies(&mut self,path:&Path,expected:&str,a:Option<Digest>,b:Option<Digest>)
+    {
+        let mut line = path.to_string_lossy().into_owned();
+        for (side,digest) in [a.as_ref(),b.as_ref()].into_iter().enumerate() {
+            let (status,counted) = match digest {
+                Some(Ok(digest)) if digest.eq_ignore_ascii_case(expected) => ("OK",0),
+                Some(Ok(_)) => ("FAIL",1),
+                _ => ("ERROR",2)
+            };
+            self.summary.copies[side][counted] += 1;
+            line += &format!(" {}:{status}",if side == 0 {'A'} else {'B'});
+        }
+        if let (Some(Ok(a)),Some(Ok(b))) = (a,b) {
+            let verdict = if a == b {Verdict::Match} else {Verdict::Differ};
+            if verdict == Verdict::Match {self.summary.matching += 1} else {self.summary.copies_differ += 1}
+            line += &format!(" {verdict}");
+        }
+        println!("{line}");
+    }
+    /// read the side (b or a) of the pair, waiting for others to finish until it fits
+    async fn add(&mut self,pair:usize,b:bool,path:PathBuf,metadata:SourceMetadata)
+    {
+        // the other side failed already
+        if self.decided.contains(&pair) {return}
+        let size = metadata.size;
+        if size > self.reader.max_size && !self.reader.allow_oversize {
+            let e = Error::Oversize{path:path.clone(),size,budget:self.reader.max_size};
+            self.finish(pair,b,Err(e.to_string()));
+            return;
+        }
+        while (self.cur_size + size > self.reader.max_size || size > self.reader.max_size) && !self.readers.is_empty() {
+            if self.reader.interrupted() {break}
+            self.next().await;
+        }
+        if self.reader.interrupted() {return}
+        self.cur_size += size;
+        // the digest is picked by the length of the expected one, md5 as there's none
+        let expected = self.pairs[pair].1.clone().unwrap_or_else(||"0".repeat(32));
+        let entry = Entry{path,expected,manifest:Path::new("").into(),size,mtime:metadata.modified,id:None,recorded:None,member:None,along:vec![]};
+        let options = self.reader.check.clone();
+        self.readers.spawn(async move {
+            let result = check_file(&entry,options,None,Arc::new(AtomicU64::new(0))).await;
+            (pair,b,size,result)
+        });
+    }
+    /// wait for the next side to be read
+    async fn next(&mut self) -> bool
+    {
+        let joined = loop {
+            tokio::select! {
+                joined = self.readers.join_next() => break joined,
+                _ = signalled(&mut self.reader.signals) => if *self.reader.signals.borrow() > 1 {self.readers.abort_all()}
+            }
+        };
+        match joined {
+            None => false,
+            Some(Ok((pair,b,size,result))) => {
+                self.cur_size -= size;
+                let result = match result {
+                    Ok(checked) => {
+                        self.summary.bytes += size;
+                        Ok(checked.digest)
+                    }
+                    Err(e) => Err(e.to_string())
+                };
+                self.finish(pair,b,result);
+                true
+            }
+            Some(Err(e)) => {
+                // reads are cancelled by the second interrupt
+                if !e.is_cancelled() {error!("internal error: {e}");}
+                true
+            }
+        }
+    }
+    /// the side of the pair is done, with the verdict once both are (or the first fails when comparing trees)
+    fn finish(&mut self,pair:usize,b:bool,result:Digest)
+    {
+        // the other side failed, its result doesn't matter anymore
+        if self.decided.contains(&pair) {return}
+        let (path,expected) = self.pairs[pair].clone();
+        if let Err(e) = &result {error!("failed comparing '{}': {e}",path.to_string_lossy());}
+        let sides = self.digests.entry(pair).or_default();
+        if b {sides.1 = Some(result)} else {sides.0 = Some(result)}
+        let done = match sides {
+            (Some(_),Some(_)) => true,
+            // copies are both checked against the listed digest
+            (Some(Err(_)),_)|(_,Some(Err(_))) => expected.is_none(),
+            _ => false
+        };
+        if !done {return}
+        let (a,b) = self.digests.remove(&pair).unwrap_or_default();
+        self.decided.insert(pair);
+        if let Some(expected) = expected {return self.print_copies(&path,&expected,a,b);}
+        let verdict = match (a,b) {
+            (Some(Ok(a)),Some(Ok(b))) => if a == b {Verdict::Match} else {Verdict::Differ},
+            _ => Verdict::Error
+        };
+        self.print(&path,verdict);
+    }
+}
+
+/// compare the trees, printing "path VERDICT" for each path below one of them
+///
+/// Files of different size differ without being read, both sides of a pair are read at the same time if max-size allows.
+pub(crate) async fn compare(a:&Path,b:&Path,ignore:&[String],reader:&mut Reader) -> Result<ExitCode,Error>
+{
+    let (walked_a,walked_b) = (
+        walk(a,ignore,reader.symlinks).map_err(|source|Error::Io{action:"read directory",path:a.into(),source})?,
+        walk(b,ignore,reader.symlinks).map_err(|source|Error::Io{action:"read directory",path:b.into(),source})?
+    );
+    let mut comparison = Comparison{
+        reader,readers:JoinSet::new(),cur_size:0,pairs:vec![],digests:HashMap::new(),decided:HashSet::new(),summary:Summary::default()
+    };
+    let filtered = |reader:&Reader,node:&Node|match node {
+        Node::File(metadata) => reader.filters.by_time(metadata.modified) || reader.filters.by_size(metadata.size),
+        _ => false
+    };
+    let mut paths:Vec<&PathBuf> = walked_a.keys().chain(walked_b.keys().filter(|path|!walked_a.contains_key(*path))).collect();
+    paths.sort();
+    for path in paths {
+        match (walked_a.get(path),walked_b.get(path)) {
+            (Some(node),None) | (None,Some(node)) if filtered(comparison.reader,node) => comparison.summary.filtered += 1,
+            (Some(_),None) => comparison.print(path,Verdict::OnlyInA),
+            (None,Some(_)) => comparison.print(path,Verdict::OnlyInB),
+            (Some(node),Some(_)) if filtered(comparison.reader,node) => comparison.summary.filtered += 1,
+            (Some(Node::File(in_a)),Some(Node::File(in_b))) => {
+                if comparison.reader.interrupted() {comparison.summary.not_attempted += 1;continue}
+                if in_a.size != in_b.size {
+                    debug!("'{}' differs in size: {} and {}",path.to_string_lossy(),in_a.size,in_b.size);
+                    comparison.print(path,Verdict::Differ);
+                    continue
+                }
+                let pair = comparison.pairs.len();
+                comparison.pairs.push((path.clone(),None));
+                comparison.add(pair,false,a.join(path),*in_a).await;
+                comparison.add(pair,true,b.join(path),*in_b).await;
+            }
+            (Some(Node::Symlink(in_a)),Some(Node::Symlink(in_b))) => {
+                comparison.print(path,if in_a == in_b {Verdict::Match} else {Verdict::Differ})
+            }
+            (Some(Node::Special),Some(Node::Special)) => comparison.summary.not_files += 1,
+            (Some(Node::Dangling(target)),Some(_)) | (Some(_),Some(Node::Dangling(target))) => {
+                error!("failed comparing '{}': dangling symlink to '{}'",path.to_string_lossy(),target.to_string_lossy());
+                comparison.print(path,Verdict::Error)
+            }
+            // a file on one side and a symlink or special file on the other
+            (Some(_),Some(_)) => comparison.print(path,Verdict::Differ),
+            (None,None) => unreachable!("the path is from one of the trees")
+        }
+    }
+    while comparison.next().await {}
+    // the pairs not read (or cancelled) as it was interrupted
+    comparison.summary.not_attempted += (comparison.pairs.len()-comparison.decided.len()) as u64;
+    let Comparison{summary,reader,..} = comparison;
+    eprintln!("{summary}");
+    if reader.interrupted() {return Ok(ExitCode::from(crate::cli::INTERRUPTED));}
+    if summary.differ + summary.only_a + summary.only_b + summary.error > 0 {
+        return Err(Error::TreesDiffer{differ:summary.differ,only:summary.only_a+summary.only_b,errors:summary.error});
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// compare the copies below a and b of the files listed in the checksum files, printing "path A:STATUS B:STATUS VERDICT"
+///
+/// The status of a copy is OK if it matches the listed digest, the verdict is if the copies match each other
+/// (left out if one couldn't be read). Both copies of a file are read at the same time if max-size allows.
+pub(crate) async fn compare_copies(files:&[PathBuf],a:&Path,b:&Path,reader:&mut Reader) -> Result<ExitCode,Error>
+{
+    let mut comparison = Comparison{
+        reader,readers:JoinSet::new(),cur_size:0,pairs:vec![],digests:HashMap::new(),decided:HashSet::new(),summary:Summary::default()
+    };
+    for manifest in files {
+        for (digest,filename) in comparison.reader.entries(manifest).await? {
+            let path = manifest_path(Path::new(""),&filename);
+            let copies = [manifest_path(a,&filename),manifest_path(b,&filename)];
+            if copies.iter().zip([a,b]).any(|(copy,base)|!comparison.reader.confined(base,copy)) {
+                error!("'{}' listed in '{}' is outside the directories, not comparing it",path.to_string_lossy(),manifest.to_string_lossy());
+                comparison.reader.summary.confinement += 1;
+                continue
+            }
+            let metadata = copies.each_ref().map(|copy|match copy.metadata() {
+                Ok(metadata) if metadata.is_file() => Ok(SourceMetadata::from(&metadata)),
+                Ok(_) => Err("not a regular file".to_string()),
+                Err(e) => Err(e.to_string())
+            });
+            // filtered by the copy in A, like a file in A is checked
+            if let Ok(in_a) = &metadata[0] {
+                if comparison.reader.filters.by_time(in_a.modified) || comparison.reader.filters.by_size(in_a.size) {
+                    comparison.summary.filtered += 1;
+                    continue
+                }
+            }
+            if comparison.reader.interrupted() {comparison.summary.not_attempted += 1;continue}
+            let pair = comparison.pairs.len();
+            comparison.pairs.push((path,Some(digest)));
+            for (side,(copy,metadata)) in copies.into_iter().zip(metadata).enumerate() {
+                match metadata {
+                    Ok(metadata) => comparison.add(pair,side == 1,copy,metadata).await,
+                    Err(e) => comparison.finish(pair,side == 1,Err(format!("'{}': {e}",copy.to_string_lossy())))
+                }
+            }
+        }
+    }
+    while comparison.next().await {}
+    comparison.summary.not_attempted += (comparison.pairs.len()-comparison.decided.len()) as u64;
+    let Comparison{summary,reader,..} = comparison;
+    eprintln!("{}",Copies(&summary));
+    if reader.interrupted() {return Ok(ExitCode::from(crate::cli::INTERRUPTED));}
+    let [in_a,in_b] = summary.copies;
+    // copies that differ can't both match the listed digest
+    let (failed,errors) = (in_a[1]+in_b[1],in_a[2]+in_b[2]);
+    if failed + errors > 0 {return Err(Error::Verification{failed,errors});}
+    if reader.summary.rejected > 0 {return Err(Error::SignatureRejected(reader.summary.rejected));}
+    if reader.summary.confinement > 0 {return Err(Error::Confinement(reader.summary.confinement));}
+    if reader.summary.invalid_lines > 0 {return Err(Error::InvalidLines(reader.summary.invalid_lines));}
+    Ok(ExitCode::SUCCESS)
+}
+
+/// the entries of the checksum file by their path, with prefix stripped and path_compat applied
+///
+/// Entries listed again replace the earlier ones, entries not below prefix are kept as they are.
+async fn listed(manifest:&Path,prefix:Option<&Path>,reader:&mut Reader) -> Result<BTreeMap<PathBuf,String>,Error>
+{
+    let mut listed = BTreeMap::new();
+    for (digest,filename) in reader.entries(manifest).await? {
+        let path = manifest_path(Path::new(""),&filename);
+        let path = prefix.and_then(|prefix|path.strip_prefix(prefix).ok()).unwrap_or(&path);
+        // "./a" and "a//b" are the same as "a" and "a/b"
+        let path:PathBuf = path.components().filter(|c|*c != std::path::Component::CurDir).collect();
+        if listed.insert(path.clone(),digest.to_ascii_lowercase()).is_some() {
+            warn!("'{}' is listed more than once in '{}', the last entry counts",path.to_string_lossy(),manifest.to_string_lossy());
+        }
+    }
+    Ok(listed)
+}
+
+/// how the differences between two checksum files are printed
+pub(crate) struct DiffFormat
+{
+    /// "VERDICT<tab>path<tab>digest in A<tab>digest in B", instead of "path VERDICT"
+    pub tsv:bool,
+    /// terminated by NUL (with the path as is), "VERDICT<tab>path" unless tsv
+    pub print0:bool
+}
+
+/// compare the checksum files without reading any files, printing the paths listed with different digests or in one of them only
+pub(crate) async fn diff(a:&Path,b:&Path,strip:(Option<&Path>,Option<&Path>),format:DiffFormat,reader:&mut Reader) -> Result<ExitCode,Error>
+{
+    let (in_a,in_b) = (listed(a,strip.0,reader).await?,listed(b,strip.1,reader).await?);
+    let mut summary = Summary::default();
+    let mut out = std::io::stdout().lock();
+    let mut paths:Vec<&PathBuf> = in_a.keys().chain(in_b.keys().filter(|path|!in_a.contains_key(*path))).collect();
+    paths.sort();
+    for path in paths {
+        let (digest_a,digest_b) = (in_a.get(path),in_b.get(path));
+        let verdict = match (digest_a,digest_b) {
+            (Some(digest_a),Some(digest_b)) if digest_a == digest_b => {summary.matching += 1;continue}
+            (Some(digest_a),Some(digest_b)) if digest_a.len() != digest_b.len() => {
+                error!("'{}' is listed with digests of different kinds, they can't be compared",path.to_string_lossy());
+                Verdict::Error
+            }
+            (Some(_),Some(_)) => Verdict::Differ,
+            (Some(_),None) => Verdict::OnlyInA,
+            (None,Some(_)) => Verdict::OnlyInB,
+            (None,None) => unreachable!("the path is from one of the checksum files")
+        };
+        match verdict {
+            Verdict::Differ => summary.differ += 1,
+            Verdict::OnlyInA => summary.only_a += 1,
+            Verdict::OnlyInB => summary.only_b += 1,
+            _ => summary.error += 1
+        }
+        let line = match (format.tsv,format.print0) {
+            (true,_) => [format!("{verdict}\t").as_bytes(),&crate::path_bytes(path),
+                format!("\t{}\t{}",digest_a.map_or("",String::as_str),digest_b.map_or("",String::as_str)).as_bytes()].concat(),
+            (false,true) => [format!("{verdict}\t").as_bytes(),&crate::path_bytes(path)].concat(),
+            (false,false) => format!("{} {verdict}",path.to_string_lossy()).into_bytes()
+        };
+        out.write_all(&line).and_then(|_|out.write_all(if format.print0 {b"\0"} else {b"\n"}))
+            .map_err(|source|Error::Io{action:"write",path:"stdout".into(),source})?;
+    }
+    let listed = summary.matching+summary.differ+summary.error;
+    eprintln!("{listed} files in both: {} MATCH, {} DIFFER, {} ERROR\n{} files only in A, {} only in B",
+        summary.matching,summary.differ,summary.error,summary.only_a,summary.only_b);
+    if summary.differ + summary.only_a + summary.only_b + summary.error > 0 {
+        return Err(Error::ManifestsDiffer{differ:summary.differ,only:summary.only_a+summary.only_b,errors:summary.error});
+    }
+    if reader.summary.rejected > 0 {return Err(Error::SignatureRejected(reader.summary.rejected));}
+    if reader.summary.invalid_lines > 0 {return Err(Error::InvalidLines(reader.summary.invalid_lines));}
+    Ok(ExitCode::SUCCESS)
+}
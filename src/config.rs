@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use clap::parser::ValueSource;
+use toml::{Table, Value};
+use crate::error::Error;
+
+/// where the value of an option is from
+#[derive(Clone,Debug)]
+pub enum Origin
+{
+    File(PathBuf),
+    Env(String)
+}
+
+impl Display for Origin
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::File(file) => write!(f,"config file '{}'",file.to_string_lossy()),
+            Origin::Env(var) => write!(f,"environment variable {var}")
+        }
+    }
+}
+
+/// the value for each option set, and where it's from
+pub type Config = BTreeMap<String,(Origin,Value)>;
+
+/// environment variables for options are this followed by the option in upper case (TAPE_CHECK_MAX_SIZE)
+static ENV_PREFIX:&str = "TAPE_CHECK_";
+/// separates the values of options given more than once in environment variables
+static ENV_SEPARATOR:char = ',';
+
+/// options that can't be set in a config file
+static CLI_ONLY:[&str;4] = ["config","dump_config","help","version"];
+
+/// the config files used without --config (if they exist), later ones override earlier ones
+pub fn default_files() -> Vec<PathBuf>
+{
+    let name = "tape-check.toml";
+    let user = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(||std::env::home_dir().map(|home|home.join(".config")));
+    [Some(Path::new("/etc").join(name)),user.map(|dir|dir.join(name))].into_iter().flatten()
+        .filter(|file|file.is_file()).collect()
+}
+
+/// the option a key of a config file sets ("max-size" or "max_size" for --max-size)
+fn option<'c>(command:&'c Command,key:&str) -> Option<&'c Arg>
+{
+    let id = key.replace('-',"_");
+    command.get_arguments().find(|arg|arg.get_id() == id.as_str() && !CLI_ONLY.contains(&arg.get_id().as_str()))
+}
+
+/// read the config files and merge them, keys that are no option are an error
+pub fn load(files:&[PathBuf],command:&Command) -> Result<Config,Error>
+{
+    let mut config = Config::new();
+    for file in files {
+        let content = std::fs::read_to_string(file)
+            .map_err(|source|Error::Io{action:"read config file",path:file.clone(),source})?;
+        let origin = ||Origin::File(file.clone());
+        let table:Table = content.parse().map_err(|e:toml::de::Error|Error::Config{origin:origin(),reason:e.message().into()})?;
+        for (key,value) in table {
+            if option(command,&key).is_none() {
+                return Err(Error::Config{origin:origin(),reason:format!("'{key}' is no option")});
+            }
+            config.insert(key.replace('-',"_"),(origin(),value));
+        }
+    }
+    Ok(config)
+}
+
+/// the options set by the environment variables given, they override those of the config files
+pub fn load_env(config:&mut Config,command:&Command,vars:impl IntoIterator<Item=(OsString,OsString)>) -> Result<(),Error>
+{
+    for (var,value) in vars {
+        let Some(key) = var.to_str().and_then(|var|var.strip_prefix(ENV_PREFIX)).map(str::to_lowercase) else {continue};
+        let origin = Origin::Env(var.to_string_lossy().into_owned());
+        let Some(arg) = option(command,&key) else {
+            return Err(Error::Config{origin,reason:format!("'{key}' is no option")});
+        };
+        let Ok(value) = value.into_string() else {
+            return Err(Error::Config{origin,reason:"is not valid UTF-8".into()});
+        };
+        let value = match list(arg) {
+            true => Value::Array(value.split(ENV_SEPARATOR).map(|v|Value::String(v.into())).collect()),
+            false => Value::String(value)
+        };
+        config.insert(key,(origin,value));
+    }
+    Ok(())
+}
+
+/// if the option can be given more than once (or is the positional checksum files)
+fn list(arg:&Arg) -> bool
+{
+    matches!(arg.get_action(),ArgAction::Append) || arg.get_long().is_none()
+}
+
+/// a value as given on the command line
+fn scalar(key:&str,value:&Value) -> Result<String,String>
+{
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Datetime(d) => Ok(d.to_string()),
+        Value::Array(_)|Value::Table(_) => Err(format!("'{key}' can't be a nested list or table"))
+    }
+}
+
+/// the arguments for the options of the config (those given on the command line should be removed),
+/// and the positional arguments (checksum files)
+pub fn args(config:&Config,command:&Command) -> Result<(Vec<OsString>,Vec<OsString>),Error>
+{
+    let (mut options,mut positional) = (vec![],vec![]);
+    for (key,(origin,value)) in config {
+        let arg = option(command,key).expect("keys are checked when loading");
+        let invalid = |reason:String|Error::Config{origin:origin.clone(),reason};
+        let values = match value {
+            Value::Array(values) => values.iter().map(|v|scalar(key,v)).collect::<Result<Vec<_>,_>>().map_err(invalid)?,
+            value => vec![scalar(key,value).map_err(invalid)?]
+        };
+        let Some(long) = arg.get_long() else {
+            positional.extend(values.into_iter().map(OsString::from));
+            continue
+        };
+        for value in values {
+            match (arg.get_action(),value.as_str()) {
+                // flags are set by true, and counted ones (like verbose) by a number
+                (ArgAction::Count,_) => {
+                    let count:usize = value.parse().map_err(|_|invalid(format!("'{key}' has to be a number")))?;
+                    options.extend(std::iter::repeat_n(OsString::from(format!("--{long}")),count));
+                }
+                (_,"true") if !arg.get_action().takes_values() || arg.get_num_args().is_some_and(|n|n.min_values() == 0) =>
+                    options.push(format!("--{long}").into()),
+                (_,"false") if !arg.get_action().takes_values() => {}
+                (action,_) if !action.takes_values() => return Err(invalid(format!("'{key}' has to be true or false"))),
+                _ => options.push(format!("--{long}={value}").into())
+            }
+        }
+    }
+    Ok((options,positional))
+}
+
+/// the options given on the command line or by config files (but those of skip) as arguments, by their long name
+pub fn given(command:&Command,matches:&ArgMatches,skip:&[&str]) -> Vec<OsString>
+{
+    let mut given = vec![];
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(long) = arg.get_long() else {continue};
+        if CLI_ONLY.contains(&id) || skip.contains(&id) || matches!(matches.value_source(id),None|Some(ValueSource::DefaultValue)) {continue}
+        match arg.get_action() {
+            ArgAction::Count => given.extend(std::iter::repeat_n(OsString::from(format!("--{long}")),matches.get_count(id).into())),
+            action if !action.takes_values() => given.push(format!("--{long}").into()),
+            _ => match matches.get_raw(id) {
+                // an option with an optional value, given without one
+                Some(raw) if raw.len() == 0 => given.push(format!("--{long}").into()),
+                raw => for value in raw.into_iter().flatten() {
+                    let mut option = OsString::from(format!("--{long}="));
+                    option.push(value);
+                    given.push(option);
+                }
+            }
+        }
+    }
+    given
+}
+
+/// the effective options as TOML with where they're from, defaults are commented out
+pub fn dump(command:&Command,matches:&ArgMatches,config:&Config) -> String
+{
+    let mut dump = String::new();
+    for arg in command.get_arguments().filter(|arg|!CLI_ONLY.contains(&arg.get_id().as_str())) {
+        let id = arg.get_id().as_str();
+        let flag = !arg.get_action().takes_values();
+        let values:Vec<Value> = match arg.get_action() {
+            ArgAction::Count => vec![Value::Integer(matches.get_count(id).into())],
+            _ => match matches.get_raw(id) {
+                // an option with an optional value, given without one
+                Some(raw) if raw.len() == 0 => vec![Value::Boolean(true)],
+                Some(raw) => raw.map(|v|{
+                    let v = v.to_string_lossy();
+                    match (v.parse::<i64>(),v.parse::<bool>()) {
+                        (Ok(i),_) => Value::Integer(i),
+                        (_,Ok(b)) if flag => Value::Boolean(b),
+                        _ => Value::String(v.into_owned())
+                    }
+                }).collect(),
+                None => continue
+            }
+        };
+        let value = match values.len() {
+            1 if !list(arg) => values.into_iter().next().expect("one value"),
+            _ => Value::Array(values)
+        };
+        let line = Table::from_iter([(id.to_string(),value)]).to_string();
+        let line = line.trim_end();
+        match (config.get(id),matches.value_source(id)) {
+            (Some((origin,_)),_) => dump.push_str(&format!("{line}  # {origin}\n")),
+            (None,Some(ValueSource::DefaultValue)) => dump.push_str(&format!("# {line}\n")),
+            (None,_) => dump.push_str(&format!("{line}  # command line\n"))
+        }
+    }
+    dump
+}
@@ -0,0 +1,98 @@
+use std::path::{Component, Path, PathBuf};
+
+/// the absolute path with "." and ".." resolved by the names alone (without following symlinks)
+fn normalize(path:&Path) -> std::io::Result<PathBuf>
+{
+    // the empty path is the current directory
+    let path = if path.as_os_str().is_empty() {Path::new(".")} else {path};
+    let mut normalized = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {normalized.pop();}
+            component => normalized.push(component)
+        }
+    }
+    Ok(normalized)
+}
+
+/// the path with symlinks resolved as far as it exists, the missing rest appended as it is
+fn resolve(path:&Path) -> std::io::Result<PathBuf>
+{
+    let path = std::path::absolute(path)?;
+    let (mut existing,mut missing) = (path.as_path(),vec![]);
+    loop {
+        match std::fs::canonicalize(existing) {
+            Ok(resolved) => return Ok(missing.into_iter().rev().fold(resolved,|resolved,name|resolved.join(name))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let (Some(name),Some(parent)) = (existing.file_name(),existing.parent()) else {return Err(e)};
+                missing.push(name);
+                existing = parent;
+            }
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+/// if the path of a file listed in the checksum file in base is within base
+///
+/// If strict, symlinks are resolved as well, so they can't point outside either. Of a missing file the directories
+/// that exist are resolved (the file fails when it's opened), what can't be resolved at all is not confined.
+pub fn confined(base:&Path,path:&Path,strict:bool) -> bool
+{
+    let (Ok(base),Ok(normalized)) = (normalize(base),normalize(path)) else {return false};
+    if !normalized.starts_with(&base) {return false;}
+    if !strict {return true;}
+    match (resolve(&base),resolve(path)) {
+        (Ok(base),Ok(path)) => path.starts_with(base),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn escapes_by_name_are_rejected()
+    {
+        let base = Path::new("/archive/run1");
+        for strict in [false,true] {
+            assert!(!confined(base,&base.join("../../etc/passwd"),strict));
+            assert!(!confined(base,&base.join("a/../../x"),strict));
+            assert!(!confined(base,Path::new("/etc/passwd"),strict));
+            assert!(!confined(base,Path::new("/archive/run10/file"),strict));
+        }
+        assert!(confined(base,&base.join("a/../x"),false));
+        assert!(confined(base,Path::new("/archive/run1/./a/b"),false));
+        assert_eq!(normalize(Path::new("/a/b/../c/./d")).unwrap(),Path::new("/a/c/d"));
+    }
+
+    #[test]
+    fn missing_files_are_left_to_fail_opening()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("no/such/file");
+        assert!(confined(dir.path(),&missing,true));
+        assert!(std::fs::File::open(&missing).is_err());
+        assert!(!confined(dir.path(),&dir.path().join("no/../../file"),true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_pointing_outside_are_rejected_if_strict()
+    {
+        let (outside,dir) = (tempfile::tempdir().unwrap(),tempfile::tempdir().unwrap());
+        std::fs::write(outside.path().join("secret"),"").unwrap();
+        std::fs::write(dir.path().join("file"),"").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"),dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path(),dir.path().join("linked-dir")).unwrap();
+        std::os::unix::fs::symlink("file",dir.path().join("inside")).unwrap();
+        for escaping in ["link","linked-dir/secret","linked-dir/missing"].map(|name|dir.path().join(name)) {
+            assert!(confined(dir.path(),&escaping,false));
+            assert!(!confined(dir.path(),&escaping,true),"{escaping:?}");
+        }
+        assert!(confined(dir.path(),&dir.path().join("inside"),true));
+    }
+}
@@ -0,0 +1,501 @@
+//! --daemon: checking the checksum files of jobs submitted over a Unix socket, and the ctl client for it
+//!
+//! Up to --concurrent-jobs jobs are checked at once, each by a reader of its own sharing the --max-size of the daemon.
+//! The protocol is a JSON object per line each way, every request gets one response with "ok" true or false
+//! (and "error" then):
+//! - `{"op":"submit","manifests":["/abs/md5sum",...],"options":{"max_size":100}}` gives the "job" id
+//! - `{"op":"status","job":1}` gives the state and counts (OK, FAIL and ERROR) of the job, without "job" of all jobs (as "jobs")
+//! - `{"op":"cancel","job":1}` stops the job (a queued one doesn't start)
+//! - `{"op":"results","job":1,"since":0}` gives the "results" of the job from that one on, and the "next" to ask for;
+//!   those before "since" were fetched and are dropped (without it the results not dropped yet are given)
+//! - `{"op":"reprioritize"}` reads the --priority-file again before the next entries are added
+//!
+//! Jobs that ended are forgotten after an hour.
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use crate::budget::{Budget, Share};
+use crate::checker::Canceller;
+use crate::error::Error;
+use crate::cli::CtlRequest;
+use crate::priority::Priorities;
+use crate::{cli, manifest_list, signalled, CheckResult, Reader, Status, GIGABYTE};
+
+/// how long jobs are kept after they ended, for their status and results
+static KEPT:Duration = Duration::from_secs(3600);
+
+/// where a job is at
+#[derive(Clone,Copy,PartialEq)]
+enum State
+{
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+    /// the daemon was stopped while it ran
+    Interrupted,
+    Failed
+}
+
+impl State
+{
+    fn name(self) -> &'static str
+    {
+        match self {
+            State::Queued => "queued",State::Running => "running",State::Done => "done",
+            State::Cancelled => "cancelled",State::Interrupted => "interrupted",State::Failed => "failed"
+        }
+    }
+}
+
+struct Job
+{
+    manifests:Vec<PathBuf>,
+    /// the budget asked for, at most that of the daemon
+    max_size:Option<u64>,
+    state:State,
+    /// why it failed
+    error:Option<String>,
+    /// the results not fetched yet, and how many were dropped before them as they were
+    results:Vec<CheckResult>,dropped:usize,
+    /// the results OK, FAIL and ERROR
+    counts:(usize,usize,usize),
+    /// entries that were not checked as it was cancelled
+    not_attempted:usize,
+    /// to cancel it while it runs
+    canceller:Option<Canceller>,
+    /// when it ended, it's forgotten KEPT after
+    ended:Option<Instant>
+}
+
+impl Job
+{
+    fn new(manifests:Vec<PathBuf>,max_size:Option<u64>) -> Job
+    {
+        Job{
+            manifests,max_size,state:State::Queued,error:None,results:vec![],dropped:0,counts:(0,0,0),
+            not_attempted:0,canceller:None,ended:None
+        }
+    }
+    fn status(&self,id:u64) -> Value
+    {
+        let (ok,fail,error) = self.counts;
+        json!({
+            "job":id,"state":self.state.name(),"manifests":self.manifests,"error":self.error,
+            "results":self.dropped+self.results.len(),"not_attempted":self.not_attempted,
+            "counts":{"OK":ok,"FAIL":fail,"ERROR":error}
+        })
+    }
+    /// keep the result until it's fetched
+    fn push(&mut self,result:&CheckResult)
+    {
+        let (ok,fail,error) = &mut self.counts;
+        match result.status {
+            Status::Ok => *ok += 1,
+            Status::Fail => *fail += 1,
+            status if status.failed() => *error += 1,
+            _ => {}
+        }
+        self.results.push(result.clone());
+    }
+    /// the results from since on (by default those not dropped yet), dropping those before as they were fetched
+    fn fetch(&mut self,since:Option<usize>) -> Result<&[CheckResult],String>
+    {
+        let since = since.unwrap_or(self.dropped);
+        if since < self.dropped {return Err(format!("the results before {} were fetched and dropped",self.dropped));}
+        let fetched = (since-self.dropped).min(self.results.len());
+        self.results.drain(..fetched);
+        self.dropped += fetched;
+        Ok(&self.results)
+    }
+    fn end(&mut self,state:State,error:Option<String>)
+    {
+        (self.state,self.error) = (state,error);
+        self.canceller = None;
+        self.ended = Some(Instant::now());
+    }
+}
+
+/// the jobs submitted to the daemon, kept until KEPT after they ended
+#[derive(Default)]
+pub(crate) struct Jobs
+{
+    jobs:BTreeMap<u64,Job>,
+    last:u64,
+    /// to read the priority file again, with --priority-file
+    reprioritize:Option<Arc<AtomicU64>>
+}
+
+impl Jobs
+{
+    /// forget the jobs that ended longer than kept ago
+    fn expire(&mut self,kept:Duration)
+    {
+        self.jobs.retain(|_,job|job.ended.is_none_or(|ended|ended.elapsed() < kept));
+    }
+}
+
+/// the results of the job being run go to it (besides being printed)
+pub(crate) struct Running
+{
+    jobs:Arc<Mutex<Jobs>>,
+    id:u64
+}
+
+impl Running
+{
+    pub(crate) fn push(&self,result:&CheckResult)
+    {
+        if let Some(job) = self.jobs.lock().expect("the jobs are never poisoned").jobs.get_mut(&self.id) {job.push(result);}
+    }
+}
+
+/// the job id of a request
+fn job_id(request:&Value) -> Result<u64,String>
+{
+    request.get("job").and_then(Value::as_u64).ok_or_else(||"no job id given".to_string())
+}
+
+/// the checksum files of a submit request, directories stand for the checksum file in them
+fn submitted_manifests(request:&Value) -> Result<Vec<PathBuf>,String>
+{
+    let Some(Value::Array(manifests)) = request.get("manifests") else {return Err("no manifests given".into())};
+    if manifests.is_empty() {return Err("no manifests given".into());}
+    manifests.iter().map(|manifest|{
+        let manifest = PathBuf::from(manifest.as_str().ok_or("manifests have to be paths")?);
+        if !manifest.is_absolute() {return Err(format!("'{}' is not an absolute path",manifest.to_string_lossy()));}
+        if manifest.is_dir() {return manifest_list::discover(&manifest).map_err(|e|e.to_string());}
+        if !manifest.is_file() {return Err(format!("there is no checksum file '{}'",manifest.to_string_lossy()));}
+        Ok(manifest)
+    }).collect()
+}
+
+/// answer a request
+fn handle(request:&Value,jobs:&Mutex<Jobs>,queue:&mpsc::UnboundedSender<u64>) -> Result<Value,String>
+{
+    let mut jobs = jobs.lock().expect("the jobs are never poisoned");
+    jobs.expire(KEPT);
+    match request.get("op").and_then(Value::as_str) {
+        Some("submit") => {
+            let manifests = submitted_manifests(request)?;
+            let mut max_size = None;
+            if let Some(options) = request.get("options").and_then(Value::as_object) {
+                for (option,value) in options {
+                    match option.as_str() {
+                        "max_size" => max_size = Some(value.as_u64().ok_or("max_size has to be a number of GBytes")?*GIGABYTE),
+                        option => return Err(format!("unknown option '{option}'"))
+                    }
+                }
+            }
+            jobs.last += 1;
+            let id = jobs.last;
+            info!("job {id} submitted for {}",manifests.iter().map(|m|m.to_string_lossy()).collect::<Vec<_>>().join(", "));
+            jobs.jobs.insert(id,Job::new(manifests,max_size));
+            queue.send(id).map_err(|_|"the daemon is stopping".to_string())?;
+            Ok(json!({"ok":true,"job":id}))
+        }
+        Some("status") if request.get("job").is_none() => {
+            let all:Vec<Value> = jobs.jobs.iter().map(|(id,job)|job.status(*id)).collect();
+            Ok(json!({"ok":true,"jobs":all}))
+        }
+        Some("status") => {
+            let id = job_id(request)?;
+            let job = jobs.jobs.get(&id).ok_or(format!("there is no job {id}"))?;
+            let mut status = job.status(id);
+            status["ok"] = true.into();
+            Ok(status)
+        }
+        Some("cancel") => {
+            let id = job_id(request)?;
+            let job = jobs.jobs.get_mut(&id).ok_or(format!("there is no job {id}"))?;
+            match job.state {
+                State::Queued => job.end(State::Cancelled,None),
+                State::Running => if let Some(canceller) = &job.canceller {canceller.cancel()},
+                _ => {}
+            }
+            info!("job {id} cancelled");
+            Ok(json!({"ok":true,"job":id,"state":job.state.name()}))
+        }
+        Some("results") => {
+            let id = job_id(request)?;
+            let job = jobs.jobs.get_mut(&id).ok_or(format!("there is no job {id}"))?;
+            let since = request.get("since").and_then(Value::as_u64).map(|since|since as usize);
+            let results:Vec<Value> = job.fetch(since)?.iter().map(|result|json!({
+                "path":result.path,"status":result.status.to_string(),"expected":result.expected,
+                "computed":result.computed,"size":result.size,"failed_at":result.failed_at,
+                "bad_extent":result.bad_extent.map(|(offset,length)|json!({"offset":offset,"length":length}))
+            })).collect();
+            let next = (job.dropped+results.len()).max(since.unwrap_or(0));
+            Ok(json!({"ok":true,"job":id,"state":job.state.name(),"results":results,"next":next}))
+        }
+        Some("reprioritize") => {
+            let reprioritize = jobs.reprioritize.as_ref().ok_or("the daemon runs without --priority-file")?;
+            reprioritize.fetch_add(1,Ordering::Relaxed);
+            info!("reading the priority file again as requested");
+            Ok(json!({"ok":true}))
+        }
+        Some(op) => Err(format!("unknown op '{op}'")),
+        None => Err("no op given".into())
+    }
+}
+
+/// answer the requests of a client until it disconnects
+async fn connection(stream:UnixStream,jobs:Arc<Mutex<Jobs>>,queue:mpsc::UnboundedSender<u64>)
+{
+    let (read,mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {continue}
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle(&request,&jobs,&queue).unwrap_or_else(|error|json!({"ok":false,"error":error})),
+            Err(e) => json!({"ok":false,"error":format!("invalid request: {e}")})
+        };
+        if write.write_all(format!("{response}\n").as_bytes()).await.is_err() {break}
+    }
+}
+
+/// the socket, removed when the daemon ends
+struct Socket(PathBuf);
+
+impl Socket
+{
+    /// listen on the socket, which only the user can use; a stale one (nobody listening) is replaced
+    ///
+    /// It's bound in a directory only the user can enter and moved in place once it has its mode, so nobody else can connect meanwhile.
+    async fn bind(path:&Path) -> std::io::Result<(Socket,UnixListener)>
+    {
+        if path.exists() {
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse,"another daemon listens on it"));
+            }
+            info!("removing stale socket '{}'",path.to_string_lossy());
+            std::fs::remove_file(path)?;
+        }
+        let private = path.with_file_name(format!(".md5check-{}",std::process::id()));
+        std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+        let bound = private.join("socket");
+        let listener = UnixListener::bind(&bound).and_then(|listener|{
+            std::fs::set_permissions(&bound,std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&bound,path)?;
+            Ok(listener)
+        });
+        // the socket is left there only if moving it failed
+        let _ = std::fs::remove_file(&bound);
+        let _ = std::fs::remove_dir(&private);
+        Ok((Socket(path.into()),listener?))
+    }
+}
+
+impl Drop for Socket
+{
+    fn drop(&mut self)
+    {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// makes the reader of a job (with the options of the daemon), and what stops it
+pub(crate) type NewReader<'a> = dyn Fn() -> Result<(Reader,Canceller),Error> + 'a;
+
+/// a job being checked, giving its id, how the check ended and the reader of it
+type Checking = Pin<Box<dyn Future<Output=(u64,Result<(),Error>,Reader)>>>;
+
+/// check the jobs submitted on the socket, up to concurrent at once, until interrupted
+///
+/// Each job is checked like the checksum files given on the command line, by a reader of its own from new_reader.
+/// The jobs read the files the same way as the daemon and share its budget, the results of all are counted for its summary.
+pub(crate) async fn serve(socket:&Path,reader:&mut Reader,new_reader:&NewReader<'_>,check_self:bool,concurrent:usize) -> Result<(),Error>
+{
+    let (_socket,listener) = Socket::bind(socket).await.map_err(|source|Error::Io{action:"listen on",path:socket.into(),source})?;
+    eprintln!("waiting for jobs on '{}'",socket.to_string_lossy());
+    let reprioritize = reader.priorities.as_ref().map(|priorities|priorities.reloader());
+    let jobs = Arc::new(Mutex::new(Jobs{reprioritize,..Jobs::default()}));
+    let (queue,mut queued) = mpsc::unbounded_channel();
+    let server = tokio::spawn({
+        let jobs = jobs.clone();
+        async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream,_)) => {tokio::spawn(connection(stream,jobs.clone(),queue.clone()));}
+                    Err(e) => warn!("failed to accept a connection: {e}")
+                }
+            }
+        }
+    });
+    let budget = Budget::new(reader.max_size);
+    let mut running:FuturesUnordered<Checking> = FuturesUnordered::new();
+    // the running jobs finish when interrupted
+    while !(reader.interrupted() && running.is_empty()) {
+        let starting = !reader.interrupted() && running.len() < concurrent;
+        tokio::select! {
+            Some(id) = queued.recv(), if starting => {
+                if let Some(checking) = start(id,&jobs,reader,new_reader,&budget,check_self) {running.push(checking);}
+            }
+            Some((id,result,job)) = running.next() => end(id,result,job,&jobs,reader),
+            _ = signalled(&mut reader.signals) => reader.on_signal()
+        }
+    }
+    server.abort();
+    Ok(())
+}
+
+/// start checking the job, unless it was cancelled while queued
+fn start(id:u64,jobs:&Arc<Mutex<Jobs>>,reader:&Reader,new_reader:&NewReader,budget:&Arc<Budget>,check_self:bool) -> Option<Checking>
+{
+    let (manifests,max_size) = {
+        let mut jobs = jobs.lock().expect("the jobs are never poisoned");
+        jobs.jobs.get_mut(&id).filter(|job|job.state == State::Queued).map(|job|{
+            job.state = State::Running;
+            (job.manifests.clone(),job.max_size)
+        })?
+    };
+    info!("running job {id}");
+    let (mut job,canceller) = match new_reader() {
+        Ok(job) => job,
+        Err(e) => {
+            if let Some(job) = jobs.lock().expect("the jobs are never poisoned").jobs.get_mut(&id) {job.end(State::Failed,Some(e.to_string()));}
+            info!("job {id} failed");
+            return None
+        }
+    };
+    if let Some(job) = jobs.lock().expect("the jobs are never poisoned").jobs.get_mut(&id) {job.canceller = Some(canceller.clone());}
+    // the sources, buffers and release commands of the daemon
+    job.check = reader.check.clone();
+    job.actions.slots = reader.actions.slots.clone();
+    job.max_size = max_size.map_or(reader.max_size,|max_size|max_size.min(reader.max_size));
+    job.budget = Some(Share::new(budget.clone()));
+    job.priorities = reader.priorities.as_ref().map(Priorities::again);
+    job.job = Some(Running{jobs:jobs.clone(),id});
+    // interrupts stop the job, besides the daemon
+    let mut signals = reader.signals.clone();
+    let forward = tokio::spawn(async move {
+        while signals.changed().await.is_ok() {
+            if *signals.borrow() > 1 {canceller.cancel()} else {canceller.stop()}
+        }
+    });
+    Some(Box::pin(async move {
+        let result = match manifest_list::Manifests::open(manifests,None,b'\n').await {
+            Ok(mut files) => cli::check(&mut files,&mut job,check_self).await,
+            Err(e) => Err(e)
+        };
+        job.print_results();
+        forward.abort();
+        // the commands of the files checked aren't left behind
+        let released = job.join_releases().await;
+        (id,result.and(released),job)
+    }))
+}
+
+/// count the results of the job for the daemon, and keep how it ended
+fn end(id:u64,result:Result<(),Error>,job:Reader,jobs:&Mutex<Jobs>,reader:&mut Reader)
+{
+    let cancelled = job.interrupted();
+    let interrupted = reader.interrupted();
+    let not_attempted = job.not_attempted().len();
+    reader.absorb(job);
+    let mut jobs = jobs.lock().expect("the jobs are never poisoned");
+    let Some(job) = jobs.jobs.get_mut(&id) else {return};
+    job.not_attempted = not_attempted;
+    match result {
+        Err(e) => job.end(State::Failed,Some(e.to_string())),
+        Ok(()) if interrupted => job.end(State::Interrupted,None),
+        Ok(()) if cancelled => job.end(State::Cancelled,None),
+        Ok(()) => job.end(State::Done,None)
+    }
+    info!("job {id} {}",job.state.name());
+}
+
+/// send the request to the daemon listening on the socket and print its response, fails if it's not ok
+///
+/// The checksum files submitted are made absolute, as the daemon runs elsewhere.
+pub(crate) async fn ctl(socket:&Path,request:&CtlRequest) -> Result<ExitCode,Error>
+{
+    let request = match request {
+        CtlRequest::Submit{max_size,manifests} => {
+            let manifests:Vec<PathBuf> = manifests.iter().map(|manifest|std::path::absolute(manifest).unwrap_or(manifest.clone())).collect();
+            let options = match max_size {
+                Some(max_size) => json!({"max_size":max_size}),
+                None => json!({})
+            };
+            json!({"op":"submit","manifests":manifests,"options":options})
+        }
+        CtlRequest::Status{job:None} => json!({"op":"status"}),
+        CtlRequest::Status{job:Some(job)} => json!({"op":"status","job":job}),
+        CtlRequest::Cancel{job} => json!({"op":"cancel","job":job}),
+        CtlRequest::Results{job,since:None} => json!({"op":"results","job":job}),
+        CtlRequest::Results{job,since:Some(since)} => json!({"op":"results","job":job,"since":since}),
+        CtlRequest::Reprioritize => json!({"op":"reprioritize"})
+    };
+    let io = |action:&'static str|move|source|Error::Io{action,path:socket.into(),source};
+    let stream = UnixStream::connect(socket).await.map_err(io("connect to"))?;
+    let (read,mut write) = stream.into_split();
+    write.write_all(format!("{request}\n").as_bytes()).await.map_err(io("write to"))?;
+    let mut response = String::new();
+    BufReader::new(read).read_line(&mut response).await.map_err(io("read from"))?;
+    print!("{response}");
+    let ok = serde_json::from_str::<Value>(&response).ok().and_then(|response|response.get("ok")?.as_bool());
+    Ok(if ok == Some(true) {ExitCode::SUCCESS} else {ExitCode::FAILURE})
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn sockets_are_private()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("md5check.sock");
+        let (socket,_listener) = Socket::bind(&path).await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,0o600);
+        // nothing is left of where it was bound
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(),1);
+        UnixStream::connect(&path).await.unwrap();
+        assert_eq!(Socket::bind(&path).await.err().map(|e|e.kind()),Some(std::io::ErrorKind::AddrInUse));
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn fetched_results_are_dropped()
+    {
+        let jobs = Mutex::new(Jobs::default());
+        let (queue,_queued) = mpsc::unbounded_channel();
+        let manifest = tempfile::NamedTempFile::new().unwrap();
+        let id = handle(&json!({"op":"submit","manifests":[manifest.path()]}),&jobs,&queue).unwrap()["job"].as_u64().unwrap();
+        {
+            let job = jobs.lock().unwrap().jobs.get_mut(&id).map(|job|{
+                job.push(&CheckResult::new("a".into(),Status::Ok));
+                job.push(&CheckResult::new("b".into(),Status::Fail));
+            });
+            assert!(job.is_some());
+        }
+        let results = |since:Option<u64>|handle(&json!({"op":"results","job":id,"since":since}),&jobs,&queue);
+        assert_eq!(results(None).unwrap()["next"],2);
+        let fetched = results(Some(1)).unwrap();
+        assert_eq!(fetched["results"].as_array().map(Vec::len),Some(1));
+        assert_eq!(fetched["results"][0]["path"],"b");
+        assert!(results(Some(0)).is_err());
+        assert_eq!(results(Some(2)).unwrap()["results"].as_array().map(Vec::len),Some(0));
+        let status = handle(&json!({"op":"status","job":id}),&jobs,&queue).unwrap();
+        assert_eq!((status["results"].as_u64(),status["counts"]["FAIL"].as_u64()),(Some(2),Some(1)));
+        // until it ended it's kept
+        jobs.lock().unwrap().expire(Duration::ZERO);
+        handle(&json!({"op":"cancel","job":id}),&jobs,&queue).unwrap();
+        jobs.lock().unwrap().expire(Duration::ZERO);
+        assert!(handle(&json!({"op":"status","job":id}),&jobs,&queue).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! --deadline: no new files are started after it (and with --deadline-cancel the running checks are cancelled)
+use std::time::Duration;
+use log::info;
+use tokio::time::Instant;
+use crate::options::Options;
+
+/// the deadline of a run
+pub(crate) struct Deadline
+{
+    at:Option<Instant>,
+    /// cancel the running checks once it's reached
+    pub cancel:bool,
+    /// if reaching it was handled already
+    handled:bool
+}
+
+impl Deadline
+{
+    pub fn new(options:&Options) -> Deadline
+    {
+        let at = options.deadline.map(|left|{
+            info!("deadline is in {}",humantime::format_duration(Duration::from_secs(left.as_secs())));
+            Instant::now()+left
+        });
+        Deadline{at,cancel:options.deadline_cancel,handled:false}
+    }
+    pub fn passed(&self) -> bool {self.at.is_some_and(|at|at <= Instant::now())}
+    /// when it is if it wasn't handled yet
+    pub fn pending(&self) -> Option<Instant> {self.at.filter(|_|!self.handled)}
+    /// if it was reached by now (once)
+    pub fn reached(&mut self,now:Instant) -> bool
+    {
+        if self.pending().is_some_and(|at|at <= now) {
+            self.handled = true;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn it_is_reached_once()
+    {
+        let mut deadline = Deadline::new(&Options{deadline:Some(Duration::from_millis(50)),..Options::default()});
+        assert!(!deadline.passed() && !deadline.reached(Instant::now()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(deadline.passed() && deadline.reached(Instant::now()));
+        assert!(!deadline.reached(Instant::now()) && deadline.pending().is_none() && deadline.passed());
+        assert!(!Deadline::new(&Options::default()).passed());
+    }
+}
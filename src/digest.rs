@@ -0,0 +1,251 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// a digest algorithm being computed, see [`Digests`] for how it's used
+///
+/// Implementations that can fail (like those of a library) return the error, the file is reported as ERROR then.
+pub trait Digest:Send
+{
+    fn update(&mut self,bytes:&[u8]) -> std::io::Result<()>;
+    fn finalize(self:Box<Self>) -> std::io::Result<DigestValue>;
+    /// a short lower case name, like "md5"
+    fn name(&self) -> &'static str;
+    /// the number of hex digits of its digests
+    fn hex_len(&self) -> usize;
+}
+
+/// a computed digest
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct DigestValue(pub Vec<u8>);
+
+impl DigestValue
+{
+    /// parse the hex digits (upper or lower case)
+    pub fn from_hex(hex:&str) -> Option<DigestValue>
+    {
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {return None}
+        (0..hex.len()).step_by(2).map(|i|u8::from_str_radix(&hex[i..i+2],16).ok()).collect::<Option<_>>().map(DigestValue)
+    }
+    /// as lower case hex digits
+    pub fn to_hex(&self) -> String
+    {
+        self.to_string()
+    }
+    /// if it's the digest given as hex digits (upper or lower case)
+    pub fn matches(&self,hex:&str) -> bool
+    {
+        DigestValue::from_hex(hex).is_some_and(|other|other == *self)
+    }
+}
+
+impl Display for DigestValue
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|byte|write!(f,"{byte:02x}"))
+    }
+}
+
+/// md5, what checksum files are mostly made with
+pub struct Md5(md5::Context);
+
+impl Md5
+{
+    pub fn new() -> Md5 {Md5(md5::Context::new())}
+}
+
+impl Default for Md5
+{
+    fn default() -> Self {Self::new()}
+}
+
+impl Digest for Md5
+{
+    fn update(&mut self,bytes:&[u8]) -> std::io::Result<()> {self.0.consume(bytes);Ok(())}
+    fn finalize(self:Box<Self>) -> std::io::Result<DigestValue> {Ok(DigestValue(self.0.compute().0.to_vec()))}
+    fn name(&self) -> &'static str {"md5"}
+    fn hex_len(&self) -> usize {32}
+}
+
+/// sha256, as in the SHA256SUMS of sha256sum
+pub struct Sha256(sha2::Sha256);
+
+impl Sha256
+{
+    pub fn new() -> Sha256 {Sha256(sha2::Digest::new())}
+}
+
+impl Default for Sha256
+{
+    fn default() -> Self {Self::new()}
+}
+
+impl Digest for Sha256
+{
+    fn update(&mut self,bytes:&[u8]) -> std::io::Result<()> {sha2::Digest::update(&mut self.0,bytes);Ok(())}
+    fn finalize(self:Box<Self>) -> std::io::Result<DigestValue> {Ok(DigestValue(sha2::Digest::finalize(self.0).to_vec()))}
+    fn name(&self) -> &'static str {"sha256"}
+    fn hex_len(&self) -> usize {64}
+}
+
+/// md5 by OpenSSL's libcrypto, faster than [`Md5`] on some CPUs
+///
+/// If libcrypto can't set it up (like with a FIPS provider, that doesn't allow md5), computing it fails with that error.
+#[cfg(feature="openssl")]
+pub struct OpensslMd5(Result<openssl::hash::Hasher,openssl::error::ErrorStack>);
+
+#[cfg(feature="openssl")]
+impl OpensslMd5
+{
+    pub fn new() -> OpensslMd5 {OpensslMd5(openssl::hash::Hasher::new(openssl::hash::MessageDigest::md5()))}
+}
+
+#[cfg(feature="openssl")]
+impl Default for OpensslMd5
+{
+    fn default() -> Self {Self::new()}
+}
+
+#[cfg(feature="openssl")]
+impl Digest for OpensslMd5
+{
+    fn update(&mut self,bytes:&[u8]) -> std::io::Result<()>
+    {
+        let hasher = self.0.as_mut().map_err(|e|std::io::Error::other(e.clone()))?;
+        hasher.update(bytes).map_err(std::io::Error::other)
+    }
+    fn finalize(self:Box<Self>) -> std::io::Result<DigestValue>
+    {
+        let digest = self.0.and_then(|mut hasher|hasher.finish()).map_err(std::io::Error::other)?;
+        Ok(DigestValue(digest.to_vec()))
+    }
+    fn name(&self) -> &'static str {"md5"}
+    fn hex_len(&self) -> usize {32}
+}
+
+/// makes a new [`Digest`] to compute
+pub type DigestFactory = Arc<dyn Fn()->Box<dyn Digest>+Send+Sync>;
+
+/// the digest algorithms known, by name and by the number of hex digits of their digests
+///
+/// Which one a listed digest was made with is told by its length, so algorithms registered later
+/// take the place of earlier ones with the same length (or name).
+#[derive(Clone)]
+pub struct Digests(Vec<(&'static str,usize,DigestFactory)>);
+
+impl Default for Digests
+{
+    /// with md5 and sha256
+    fn default() -> Self
+    {
+        let mut digests = Digests(vec![]);
+        digests.register(||Box::new(Md5::new()));
+        digests.register(||Box::new(Sha256::new()));
+        digests
+    }
+}
+
+impl Digests
+{
+    pub fn register(&mut self,factory:impl Fn()->Box<dyn Digest>+Send+Sync+'static)
+    {
+        let digest = factory();
+        let (name,hex_len) = (digest.name(),digest.hex_len());
+        self.0.retain(|(known,len,_)|*known != name && *len != hex_len);
+        self.0.push((name,hex_len,Arc::new(factory)));
+    }
+    pub fn by_name(&self,name:&str) -> Option<&DigestFactory>
+    {
+        self.0.iter().find(|(known,_,_)|*known == name).map(|(_,_,factory)|factory)
+    }
+    /// the algorithm for digests of this many hex digits
+    pub fn by_hex_len(&self,hex_len:usize) -> Option<&DigestFactory>
+    {
+        self.0.iter().find(|(_,len,_)|*len == hex_len).map(|(_,_,factory)|factory)
+    }
+    /// if a digest of this many hex digits is known
+    pub fn known_len(&self,hex_len:usize) -> bool
+    {
+        self.by_hex_len(hex_len).is_some()
+    }
+    /// the name of the algorithm for digests of this many hex digits
+    pub fn name_of(&self,hex_len:usize) -> Option<&'static str>
+    {
+        self.0.iter().find(|(_,len,_)|*len == hex_len).map(|(name,_,_)|*name)
+    }
+    /// if more than one algorithm is known
+    pub fn several(&self) -> bool
+    {
+        self.0.len() > 1
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// the test suite of RFC 1321
+    const VECTORS:[(&str,&str);7] = [
+        ("","d41d8cd98f00b204e9800998ecf8427e"),
+        ("a","0cc175b9c0f1b6a831c399e269772661"),
+        ("abc","900150983cd24fb0d6963f7d28e17f72"),
+        ("message digest","f96b697d7cb7938d525a2f31aaf161d0"),
+        ("abcdefghijklmnopqrstuvwxyz","c3fcd3d76192e4007dfb496cca67e13b"),
+        ("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789","d174ab98d277d9f5a5611c2c9f419d9f"),
+        ("12345678901234567890123456789012345678901234567890123456789012345678901234567890","57edf4a22be3c955ac49da2e2107b67a")
+    ];
+
+    /// the digest of input, fed in pieces of size (to cross the block boundaries)
+    fn compute(mut digest:Box<dyn Digest>,input:&[u8],size:usize) -> String
+    {
+        for piece in input.chunks(size.max(1)) {digest.update(piece).unwrap();}
+        digest.finalize().unwrap().to_hex()
+    }
+
+    fn backends() -> Vec<DigestFactory>
+    {
+        vec![
+            Arc::new(||Box::new(Md5::new())),
+            #[cfg(feature="openssl")]
+            Arc::new(||Box::new(OpensslMd5::new()))
+        ]
+    }
+
+    #[test]
+    fn known_vectors()
+    {
+        for backend in backends() {
+            for (input,expected) in VECTORS {
+                for size in [1,3,64,1000] {assert_eq!(compute(backend(),input.as_bytes(),size),expected,"{input:?} in pieces of {size}");}
+            }
+        }
+    }
+
+    #[test]
+    fn backends_agree()
+    {
+        let input:Vec<u8> = (0..100_000u32).map(|i|(i*7919%251) as u8).collect();
+        let digests:Vec<String> = backends().iter().map(|backend|compute(backend(),&input,4099)).collect();
+        assert!(digests.windows(2).all(|pair|pair[0] == pair[1]),"{digests:?}");
+    }
+
+    #[test]
+    fn hex()
+    {
+        let digest = DigestValue::from_hex("D41D8cd98f00b204e9800998ecf8427e").unwrap();
+        assert_eq!(digest.to_hex(),"d41d8cd98f00b204e9800998ecf8427e");
+        assert!(digest.matches("d41d8cd98f00b204e9800998ecf8427E"));
+        assert!(DigestValue::from_hex("abc").is_none());
+        assert!(DigestValue::from_hex("zz").is_none());
+    }
+
+    #[test]
+    fn registered_by_length()
+    {
+        let digests = Digests::default();
+        assert_eq!((digests.name_of(32),digests.name_of(64)),(Some("md5"),Some("sha256")));
+        assert!(!digests.known_len(40) && digests.several());
+        let sha256 = digests.by_name("sha256").unwrap();
+        assert_eq!(compute(sha256(),b"abc",1),"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
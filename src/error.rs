@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use crate::{CheckError, GIGABYTE};
+
+/// why a run (or with --fail-fast the check of a file) failed
+#[derive(thiserror::Error,Debug)]
+pub enum Error
+{
+    /// a line of a checksum file that could not be parsed
+    #[error("{}:{line}: {kind}",.file.to_string_lossy())]
+    ManifestParse{file:PathBuf,line:u64,kind:&'static str},
+    /// reading or writing a file of the run itself (checksum file, journal ...)
+    #[error("failed to {action} '{}': {source}",.path.to_string_lossy())]
+    Io{action:&'static str,path:PathBuf,source:std::io::Error},
+    /// a file listed in a checksum file could not be checked
+    #[error("failed checking '{}': {source}",.path.to_string_lossy())]
+    Check{path:PathBuf,source:CheckError},
+    #[error("failed checking '{}': dangling symlink to '{}': {source}",.path.to_string_lossy(),.target.to_string_lossy())]
+    DanglingSymlink{path:PathBuf,target:PathBuf,source:std::io::Error},
+    /// a file whose size differs from the one listed in its extended checksum file
+    #[error("failed checking '{}': it's {size} bytes, but its extended checksum file lists {recorded}",.path.to_string_lossy())]
+    SizeMismatch{path:PathBuf,size:u64,recorded:u64},
+    /// a file bigger than max-size (without --allow-oversize)
+    #[error("failed checking '{}': it's {size} bytes, bigger than the maximum allowed buffer size {}G",.path.to_string_lossy(),.budget/GIGABYTE)]
+    Oversize{path:PathBuf,size:u64,budget:u64},
+    /// a file listed again, that could not be checked for the first listing
+    #[error("failed checking '{}': it's the same file as '{}' which could not be checked",.path.to_string_lossy(),.original.to_string_lossy())]
+    Duplicate{path:PathBuf,original:PathBuf},
+    #[error("{failed} files failed verification, {errors} could not be checked")]
+    Verification{failed:u64,errors:u64},
+    /// with --max-failures, --max-errors or --max-failure-rate
+    #[error("aborted as {0}")]
+    ThresholdExceeded(String),
+    #[error("the stream digest is {computed}, expected {expected}")]
+    StreamDigest{expected:String,computed:String},
+    #[error("the stream digest could not be verified, {0} files were not read completely")]
+    StreamIncomplete(u64),
+    /// with --extra-fatal
+    #[error("{0} files are not listed in any checksum file")]
+    ExtraFiles(u64),
+    #[error("{0} checksum files could not be opened")]
+    UnreadableManifests(u64),
+    /// with --verify-signature or --verify-gpg
+    #[error("{0} checksum files were rejected as their signature could not be verified")]
+    SignatureRejected(u64),
+    /// entries like "../x", without --no-confine
+    #[error("{0} entries are outside the directory of their checksum file")]
+    Confinement(u64),
+    /// found by the compare subcommand
+    #[error("the trees differ: {differ} files differ, {only} are only in one of them, {errors} could not be compared")]
+    TreesDiffer{differ:u64,only:u64,errors:u64},
+    /// found by the merge subcommand
+    #[error("{0} files are listed with different digests")]
+    Conflicts(u64),
+    /// found by the diff subcommand
+    #[error("the checksum files differ: {differ} digests differ, {only} files are only in one of them, {errors} could not be compared")]
+    ManifestsDiffer{differ:u64,only:u64,errors:u64},
+    #[error("{0} invalid lines in the checksum files")]
+    InvalidLines(u64),
+    #[error("{0} checksum files don't list what their footer declares")]
+    FooterMismatch(u64),
+    #[error("{0} files have no digest in the reference database")]
+    NoRef(u64),
+    #[error("reference database '{}': {reason}",.path.to_string_lossy())]
+    RefDb{path:PathBuf,reason:String},
+    /// with --release-failures-fatal
+    #[error("release/on-fail commands failed for {files} files")]
+    ReleaseFailed{files:u64},
+    /// with --finally-fatal
+    #[error("the finally command failed")]
+    FinallyFailed,
+    #[error("{what} command {reason}")]
+    InvalidCommand{what:&'static str,reason:String},
+    /// an option set in a config file or environment variable
+    #[error("invalid {origin}: {reason}")]
+    Config{origin:crate::config::Origin,reason:String},
+    #[error("invalid public key '{key}': {reason}")]
+    PublicKey{key:String,reason:String},
+    /// an option needs a cargo feature md5check was built without
+    #[error("{option} is not available, md5check was built without the \"{feature}\" feature")]
+    WithoutFeature{option:&'static str,feature:&'static str},
+    /// --confirm was not answered with yes
+    #[error("{0}")]
+    NotConfirmed(&'static str),
+    /// a directory given instead of a checksum file, without a single one of the conventional names in it
+    #[error("no checksum file to check in '{}': {reason}",.dir.to_string_lossy())]
+    Discovery{dir:PathBuf,reason:String},
+    /// a digest given to Checker::submit that is no md5 digest
+    #[error("'{digest}' given for '{}' is no md5 digest",.path.to_string_lossy())]
+    InvalidDigest{path:PathBuf,digest:String},
+    #[error("command working directory '{}' does not exist",.0.to_string_lossy())]
+    CommandCwd(PathBuf),
+    /// another run holds the lock
+    #[error("already running ({holder}), lock file '{}'",.lock.to_string_lossy())]
+    Locked{lock:PathBuf,holder:String},
+    /// problems found by --dry-run
+    #[error("found {0} problems")]
+    Problems(usize),
+    /// --bench: the digest backends computed different digests
+    #[error("the digest backends computed different digests")]
+    BackendsDiffer,
+    /// the digest backend can't compute md5 (like OpenSSL with a FIPS provider)
+    #[error("the {backend} digest backend can't compute md5: {source}")]
+    DigestBackend{backend:&'static str,source:std::io::Error},
+    /// a check or command task panicked
+    #[error("internal error: {0}")]
+    Task(#[from] tokio::task::JoinError)
+}
+
+impl Error
+{
+    /// the exit status for the error: 2 if the run could not start because of its configuration, 3 if a threshold stopped it, 1 otherwise
+    pub fn exit_code(&self) -> ExitCode
+    {
+        match self {
+            Error::InvalidCommand{..}|Error::CommandCwd(_)|Error::PublicKey{..}|Error::Config{..}|Error::NotConfirmed(_)|Error::Discovery{..}|Error::DigestBackend{..} => ExitCode::from(2),
+            Error::ThresholdExceeded(_) => ExitCode::from(3),
+            _ => ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::sync::mpsc::UnboundedSender;
+use crate::CheckResult;
+
+/// what happens while checking, see [`CheckerBuilder::events`](crate::CheckerBuilder::events)
+///
+/// Paths are as listed ("archive.zip//member" for archive members), except for the files opened and the
+/// paths commands run for.
+#[derive(Clone,Debug)]
+#[non_exhaustive]
+pub enum Event
+{
+    /// an entry was read from a checksum file (or submitted)
+    Listed{path:PathBuf},
+    /// the entry is being checked now, taking its size from the budget
+    Admitted{path:PathBuf,size:u64},
+    /// a file (or checksum file) is being opened
+    Opening{path:PathBuf},
+    /// opening failed with an error that is retried
+    OpenRetried{path:PathBuf,attempt:u32,error:ErrorKind},
+    /// the bytes of the entry read so far
+    Progress{path:PathBuf,bytes:u64},
+    /// the verdict for a file (or checksum file), as also given by [`Checker::next`](crate::Checker::next)
+    Completed(CheckResult),
+    /// the file is queued for a batch release
+    Batched{path:PathBuf},
+    /// the batch of files queued is to be released now
+    BatchFlushed{files:usize},
+    /// commands (release, on-fail or post-hook) for the files are waiting for a slot
+    CommandsQueued{paths:Vec<PathBuf>},
+    /// the commands for the files are running
+    CommandsStarted{paths:Vec<PathBuf>},
+    /// the release commands for the files ran
+    Released{paths:Vec<PathBuf>,ok:bool},
+    /// the commands for the files are done
+    CommandsDone{paths:Vec<PathBuf>},
+    /// the pre-scan found this many files and bytes to check
+    Prescanned{files:u64,bytes:u64},
+    /// the pre-scan stat'ed this many of the entries so far, of this many bytes (every few seconds)
+    PrescanProgress{entries:u64,of:u64,bytes:u64},
+    /// the pre-scan was interrupted after this many entries
+    PrescanInterrupted{entries:u64,of:u64},
+    /// the entries are checked in the order on disk, of the files this many were located
+    PhysicalOrder{located:usize,files:usize},
+    /// the deadline was reached, no new files are started (and the running checks are cancelled if cancelling)
+    DeadlineReached{cancelling:bool},
+    /// a threshold of failures was exceeded, for the reason given, no new files are started
+    ThresholdExceeded{reason:String},
+    /// the line (counted from 1) of the checksum file could not be parsed, for the reason given
+    InvalidLine{manifest:PathBuf,line:u64,reason:&'static str}
+}
+
+/// where events go, if anybody listens
+#[derive(Clone,Default)]
+pub(crate) struct Events(Option<UnboundedSender<Event>>);
+
+impl Events
+{
+    pub(crate) fn new(sender:UnboundedSender<Event>) -> Events {Events(Some(sender))}
+    /// send the event made by event, which is only called if somebody listens
+    pub(crate) fn emit(&self,event:impl FnOnce()->Event)
+    {
+        // a receiver that went away just doesn't listen anymore
+        if let Some(sender) = self.0.as_ref().filter(|sender|!sender.is_closed()) {
+            let _ = sender.send(event());
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use log::warn;
+
+/// sidecar files of a listed file (or checksum file), by the extension appended to its name
+static SIDECARS:[&str;4] = ["chunks","minisig","asc","lock"];
+
+/// if the file is the sidecar of a known one
+fn sidecar(path:&Path,known:&HashSet<PathBuf>) -> bool
+{
+    path.extension().and_then(|e|e.to_str()).is_some_and(|e|SIDECARS.contains(&e))
+        && known.contains(&path.with_extension(""))
+}
+
+/// the files below root that are not known (absolute paths), sorted
+///
+/// Symlinks are not followed, directories that can't be read are skipped with a warning.
+/// Unless all, sidecar files of known files are not reported either.
+pub fn walk(root:&Path,known:&HashSet<PathBuf>,all:bool) -> std::io::Result<Vec<PathBuf>>
+{
+    let mut extra = vec![];
+    let mut dirs = vec![std::path::absolute(root)?];
+    // the root itself has to be readable
+    let mut first = true;
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if first => return Err(e),
+            Err(e) => {
+                warn!("failed to read directory '{}': {e}",dir.to_string_lossy());
+                continue
+            }
+        };
+        first = false;
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("failed to read directory '{}': {e}",dir.to_string_lossy());
+                    break
+                }
+            };
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|t|t.is_dir()) {
+                dirs.push(path);
+            } else if !known.contains(&path) && (all || !sidecar(&path,known)) {
+                extra.push(path);
+            }
+        }
+    }
+    extra.sort();
+    Ok(extra)
+}
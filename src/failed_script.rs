@@ -0,0 +1,70 @@
+//! --failed-script: a shell script staging the files that failed again and checking them in a new run
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use log::warn;
+use crate::command::{shell_quote, ExtCommand};
+use crate::path_bytes;
+
+/// the options of a run not given to the run of the script, as they select other files or write elsewhere
+pub(crate) static NOT_REPEATED:[&str;52] = [
+    "manifest_list","manifest_list0","priority_file","priority_file0","watch","watch_name","watch_stable","daemon","socket","base_dir_a","base_dir_b",
+    "stage","lookahead","lookahead_size","journal","rescan","newer_than","older_than","min_file_size","max_file_size",
+    "shard","skip","limit","deadline","deadline_cancel","max_bytes","remaining","failed_files0","failed_script","stream_digest",
+    "sample","seed","sample_by_bytes","shuffle","verify_signature","verify_gpg","gpg_home","check_extra","extra_all","extra_files",
+    "extra_fatal","write_tcman","confirm","yes","dry_run","tar_stream","bench","ref_db",
+    "ref_db_table","ref_db_path_column","ref_db_digest_column","ref_db_remap"
+];
+
+/// write the script staging the failed files with the stage command (if there is one) and checking them again
+///
+/// The entries are written into the script (with absolute paths, sorted), and checked from a temporary checksum file
+/// with the options of this run. So the script is the same for the same failures.
+pub(crate) fn write(script:&Path,failed:&[(PathBuf,Option<String>)],stage:Option<&ExtCommand>,options:&[OsString]) -> std::io::Result<()>
+{
+    let mut entries:Vec<(PathBuf,&str)> = failed.iter().filter_map(|(path,expected)|{
+        let path = std::path::absolute(path).unwrap_or_else(|_|path.clone());
+        match expected {
+            // a checksum file can't list it
+            Some(_) if path_bytes(&path).contains(&b'\n') => {
+                warn!("'{}' has a newline in its name, it's not in the failed script",path.to_string_lossy());
+                None
+            }
+            Some(expected) => Some((path,expected.as_str())),
+            None => None
+        }
+    }).collect();
+    entries.sort();
+    entries.dedup();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(script)?);
+    writeln!(file,"#!/bin/bash")?;
+    writeln!(file,"# generated by md5check: stage and check again the {} files that failed or could not be checked",entries.len())?;
+    writeln!(file,"set -euo pipefail")?;
+    let cwd = std::env::current_dir()?;
+    file.write_all(&[b"cd ".as_slice(),&shell_quote(cwd.as_os_str()),b"\n\n"].concat())?;
+    if let Some(stage) = stage {
+        for (path,_) in &entries {
+            file.write_all(&stage.script_line(path))?;
+            file.write_all(b"\n")?;
+        }
+        file.write_all(b"\n")?;
+    }
+    writeln!(file,"manifest=\"$(mktemp)\"")?;
+    writeln!(file,"trap 'rm -f \"$manifest\"' EXIT")?;
+    writeln!(file,"cat > \"$manifest\" <<'MD5CHECK_ENTRIES'")?;
+    for (path,expected) in &entries {
+        file.write_all(&[expected.as_bytes(),b"  ",&path_bytes(path),b"\n"].concat())?;
+    }
+    writeln!(file,"MD5CHECK_ENTRIES")?;
+    let program = std::env::current_exe().map_or_else(|_|"md5check".into(),PathBuf::into_os_string);
+    let mut command = [shell_quote(&program)].into_iter().chain(options.iter().map(|option|shell_quote(option))).collect::<Vec<_>>();
+    command.push(b"-- \"$manifest\"\n".to_vec());
+    file.write_all(&command.join(&b' '))?;
+    file.flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(script,std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! which entries are checked by their modification time, size and position (--newer-than, --min-file-size, --skip ...)
+use std::path::Path;
+use std::time::SystemTime;
+use crate::options::Options;
+
+/// the filters of the entries given, applied as they're added
+pub(crate) struct Filters
+{
+    newer_than:Option<SystemTime>,older_than:Option<SystemTime>,
+    /// --min-file-size and --max-file-size
+    pub file_sizes:(Option<u64>,Option<u64>),
+    /// --skip and --limit, and how many entries were counted for them
+    window:Option<(u64,Option<u64>)>,position:u64
+}
+
+impl Filters
+{
+    pub fn new(options:&Options) -> Filters
+    {
+        Filters{newer_than:options.newer_than,older_than:options.older_than,file_sizes:options.file_sizes,window:options.window,position:0}
+    }
+    /// if the modification time is not within --newer-than and --older-than (files without one are checked)
+    pub fn by_time(&self,mtime:Option<SystemTime>) -> bool
+    {
+        let Some(mtime) = mtime else {return false};
+        self.newer_than.is_some_and(|newer_than|mtime <= newer_than) || self.older_than.is_some_and(|older_than|mtime >= older_than)
+    }
+    /// if the size is not within --min-file-size and --max-file-size
+    pub fn by_size(&self,size:u64) -> bool
+    {
+        let (min,max) = self.file_sizes;
+        min.is_some_and(|min|size < min) || max.is_some_and(|max|size > max)
+    }
+    /// if the file of an entry is within --skip/--limit, None without them
+    ///
+    /// Entries filtered by time don't count (and are left to be skipped when they're added).
+    pub fn in_window(&mut self,path:&Path) -> Option<bool>
+    {
+        let (skip,limit) = self.window?;
+        if (self.newer_than.is_some() || self.older_than.is_some()) && self.by_time(path.metadata().and_then(|m|m.modified()).ok()) {
+            return Some(true);
+        }
+        self.position += 1;
+        Some(self.position > skip && limit.is_none_or(|limit|self.position <= skip+limit))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn entries_are_counted_for_the_window()
+    {
+        let mut filters = Filters::new(&Options{window:Some((1,Some(2))),file_sizes:(Some(2),Some(5)),..Options::default()});
+        let inside:Vec<_> = (0..5).map(|_|filters.in_window(Path::new("missing")).unwrap()).collect();
+        assert_eq!(inside,[false,true,true,false,false]);
+        assert_eq!([1,2,5,6].map(|size|filters.by_size(size)),[true,false,false,true]);
+        assert!(Filters::new(&Options::default()).in_window(Path::new("missing")).is_none());
+    }
+}
@@ -0,0 +1,129 @@
+//! asking the HSM which files are online (--online-check) and if it's busy (--pause-when)
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use log::{debug, warn};
+use tokio::time::Instant;
+use crate::command::{self, ExtCommand};
+use crate::options::Options;
+
+/// the online check of the files, before they're added
+pub(crate) struct Online
+{
+    pub command:Option<Arc<ExtCommand>>,
+    /// the files queried at once
+    pub batch:usize,
+    /// what the command prints for files offline (one line per file), otherwise its exit status tells
+    offline_match:Option<String>,
+    /// skip the files offline, instead of checking them
+    pub skip:bool
+}
+
+impl Online
+{
+    pub fn new(options:&Options) -> Online
+    {
+        Online{
+            command:command::configured("online-check",options.online_check.as_deref(),&options.commands),
+            batch:if options.online_check.is_some() {options.online_check_batch.max(1)} else {1},
+            offline_match:options.offline_match.clone(),skip:options.skip_offline
+        }
+    }
+    /// query which of the paths are online, files we can't tell about count as online
+    pub async fn query(&self,paths:&[PathBuf]) -> Vec<bool>
+    {
+        let Some(online_check) = &self.command else {return vec![true;paths.len()]};
+        let output = match online_check.output("querying online status of",paths,&vec![]).await {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("failed running {online_check} for {}: {e}",command::describe(paths));
+                return vec![true;paths.len()]
+            }
+        };
+        let online = match &self.offline_match {
+            Some(offline) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let mut lines = stdout.lines();
+                paths.iter().map(|_|!lines.next().is_some_and(|l|l.contains(offline.as_str()))).collect()
+            }
+            None => vec![output.status.success();paths.len()]
+        };
+        for (path,online) in paths.iter().zip(&online) {
+            debug!("'{}' is {}",path.to_string_lossy(),if *online {"online"} else {"offline"});
+        }
+        online
+    }
+}
+
+/// the pause-when command, asked before adding checks
+pub(crate) struct Pause
+{
+    pub command:Option<Arc<ExtCommand>>,
+    /// how long its answer is valid
+    interval:Duration,
+    /// when it was run last, and if it said the system is busy
+    checked:Option<(Instant,bool)>
+}
+
+impl Pause
+{
+    pub fn new(options:&Options) -> Pause
+    {
+        Pause{command:command::configured("pause-when",options.pause_when.as_deref(),&options.commands),interval:options.pause_check_interval,checked:None}
+    }
+    /// if the pause-when command says the system is busy (cached for the interval)
+    pub async fn busy(&mut self) -> bool
+    {
+        let Some(pause_when) = &self.command else {return false};
+        if let Some((checked,busy)) = self.checked {
+            if checked.elapsed() < self.interval {return busy;}
+        }
+        let busy = match pause_when.output("checking if busy",&[],&vec![]).await {
+            Ok(output) => !output.status.success(),
+            Err(e) => {
+                warn!("failed running {pause_when}: {e}");
+                false
+            }
+        };
+        self.checked = Some((Instant::now(),busy));
+        busy
+    }
+    /// when the answer runs out
+    pub fn recheck(&self) -> Instant
+    {
+        self.checked.map(|(checked,_)|checked+self.interval).unwrap_or_else(Instant::now)
+    }
+}
+
+#[cfg(all(test,unix))]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn offline_files_are_told_by_line()
+    {
+        let commands = command::Options{shell:true,..Default::default()};
+        let options = Options{online_check:Some("printf 'a: online\\nb: offline\\n'".into()),offline_match:Some("offline".into()),commands,..Options::default()};
+        let paths = ["a","b","c"].map(PathBuf::from);
+        assert_eq!(Online::new(&options).query(&paths).await,[true,false,true]);
+        let failing = Options{online_check:Some("false".into()),..Options::default()};
+        assert_eq!(Online::new(&failing).query(&paths).await,[false;3]);
+        assert_eq!(Online::new(&Options::default()).query(&paths).await,[true;3]);
+    }
+
+    #[tokio::test]
+    async fn the_answer_is_kept_for_the_interval()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let runs = dir.path().join("runs");
+        let commands = command::Options{shell:true,..Default::default()};
+        let pause_when = format!("echo >> '{}'; false",runs.display());
+        let options = Options{pause_when:Some(pause_when),pause_check_interval:Duration::from_secs(60),commands,..Options::default()};
+        let mut pause = Pause::new(&options);
+        assert!(pause.busy().await && pause.busy().await);
+        assert_eq!(std::fs::read_to_string(&runs).unwrap().lines().count(),1);
+        assert!(pause.recheck() > Instant::now());
+        assert!(!Pause::new(&Options::default()).busy().await);
+    }
+}
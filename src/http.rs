@@ -0,0 +1,210 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use futures_util::TryStreamExt;
+use log::debug;
+use reqwest::header::{CONTENT_LENGTH, LAST_MODIFIED, RANGE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::Semaphore;
+use tokio_util::io::StreamReader;
+use crate::events::Events;
+use crate::retry::RetryPolicy;
+use crate::source::{Source, SourceFuture, SourceMetadata};
+use crate::Event;
+
+/// files served over HTTP(S), read with reqwest (TLS by rustls)
+///
+/// Redirects are followed, the size (and modification time) come from the headers of a HEAD request,
+/// so the server has to give Content-Length. Reads are resumed by Range requests.
+pub(crate) struct Http
+{
+    /// the client, or why it couldn't be set up (told for every request)
+    client:Result<Client,String>,
+    /// requests to make at once
+    requests:Arc<Semaphore>,
+    /// which errors are retried how often (transient ones are TimedOut)
+    policy:RetryPolicy,
+    events:Events
+}
+
+/// the error for a failed request (or reading its body), transient ones (connecting, timeouts, cut transfers) are TimedOut
+fn error(e:reqwest::Error) -> std::io::Error
+{
+    let kind = match e.status() {
+        Some(status) => status_kind(status),
+        None if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() => std::io::ErrorKind::TimedOut,
+        None => std::io::ErrorKind::Other
+    };
+    std::io::Error::new(kind,e)
+}
+
+/// what an HTTP error status is as an error
+fn status_kind(status:StatusCode) -> std::io::ErrorKind
+{
+    match status.as_u16() {
+        404|410 => std::io::ErrorKind::NotFound,
+        401|403 => std::io::ErrorKind::PermissionDenied,
+        408|429|500|502|503|504 => std::io::ErrorKind::TimedOut,
+        _ => std::io::ErrorKind::Other
+    }
+}
+
+/// the response, failing for error statuses
+fn checked(response:Response) -> std::io::Result<Response>
+{
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        return Err(std::io::Error::new(status_kind(status),format!("HTTP status {status} for {}",response.url())));
+    }
+    Ok(response)
+}
+
+impl Http
+{
+    /// insecure doesn't verify TLS certificates, at most requests are made at once
+    pub fn new(insecure:bool,requests:u32,policy:RetryPolicy,events:Events) -> Http
+    {
+        let client = Client::builder().user_agent(concat!("md5check/",env!("CARGO_PKG_VERSION")))
+            .tls_danger_accept_invalid_certs(insecure).build().map_err(|e|format!("can't set up HTTP: {e}"));
+        Http{client,requests:Arc::new(Semaphore::new(requests as usize)),policy,events}
+    }
+    fn request(&self,build:impl FnOnce(&Client)->RequestBuilder) -> std::io::Result<RequestBuilder>
+    {
+        self.client.as_ref().map(build).map_err(|e|std::io::Error::other(e.clone()))
+    }
+    /// size and modification time by a HEAD request
+    async fn head(&self,url:&Path) -> std::io::Result<SourceMetadata>
+    {
+        let request = self.request(|client|client.head(url.to_string_lossy().as_ref()))?;
+        debug!("HEAD {}",url.to_string_lossy());
+        let response = {
+            let _request = self.requests.acquire().await.expect("the requests are never closed");
+            checked(request.send().await.map_err(error)?)?
+        };
+        let headers = response.headers();
+        let size = headers.get(CONTENT_LENGTH).and_then(|size|size.to_str().ok()?.parse().ok()).ok_or_else(||{
+            std::io::Error::new(std::io::ErrorKind::InvalidData,"the server gives no Content-Length")
+        })?;
+        let modified = headers.get(LAST_MODIFIED).and_then(|time|chrono::DateTime::parse_from_rfc2822(time.to_str().ok()?).ok()).map(SystemTime::from);
+        Ok(SourceMetadata{size,modified})
+    }
+    /// start streaming the body from offset on (by a Range request), failing if there's no response
+    async fn download(&self,url:&Path,offset:u64) -> std::io::Result<Box<dyn AsyncRead+Unpin+Send>>
+    {
+        let mut request = self.request(|client|client.get(url.to_string_lossy().as_ref()))?;
+        if offset > 0 {request = request.header(RANGE,format!("bytes={offset}-"));}
+        let permit = self.requests.clone().acquire_owned().await.expect("the requests are never closed");
+        debug!("GET {} from offset {offset}",url.to_string_lossy());
+        let response = checked(request.send().await.map_err(error)?)?;
+        let status = response.status();
+        // the slot is taken until the body is read (or dropped)
+        let body = response.bytes_stream().map_err(error).inspect_ok(move |_|{let _ = &permit;});
+        let mut download = StreamReader::new(Box::pin(body));
+        match status {
+            StatusCode::PARTIAL_CONTENT if offset > 0 => {}
+            StatusCode::OK if offset > 0 => {
+                debug!("'{}' is sent whole, skipping the first {offset} bytes",url.to_string_lossy());
+                let skipped = tokio::io::copy(&mut (&mut download).take(offset),&mut tokio::io::sink()).await?;
+                if skipped < offset {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof,"the file got shorter"));
+                }
+            }
+            StatusCode::OK => {}
+            status => return Err(std::io::Error::other(format!("unexpected HTTP status {status}")))
+        }
+        Ok(Box::new(download))
+    }
+}
+
+impl Source for Http
+{
+    fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+    {
+        Box::pin(async move {
+            let mut attempt = 0;
+            loop {
+                match self.head(path).await {
+                    Err(err) if self.policy.should_retry(&err,attempt) => {
+                        let delay = self.policy.delay(attempt);
+                        debug!("looking up '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    result => return result
+                }
+            }
+        })
+    }
+    fn open<'a>(&'a self,path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+    {
+        Box::pin(async move {
+            self.events.emit(||Event::Opening{path:path.to_path_buf()});
+            let mut attempt = 0;
+            loop {
+                match self.download(path,offset).await {
+                    Ok(download) => return Ok(download),
+                    Err(err) if self.policy.should_retry(&err,attempt) => {
+                        let delay = self.policy.delay(attempt);
+                        debug!("downloading '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        self.events.emit(||Event::OpenRetried{path:path.to_path_buf(),attempt,error:err.kind()});
+                    }
+                    Err(err) => return Err(err)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::{BufRead, Write};
+    use super::*;
+
+    /// serve the body whole to each of the requests (ignoring ranges), answering HEAD with its length, the requests seen go back
+    fn serve(body:&'static str,requests:usize) -> (String,std::thread::JoinHandle<Vec<String>>)
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file",listener.local_addr().unwrap());
+        let server = std::thread::spawn(move ||{
+            (0..requests).map(|_|{
+                let (stream,_) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = std::io::BufReader::new(&stream);
+                while reader.read_line(&mut request).unwrap() > 2 && !request.ends_with("\r\n\r\n") {}
+                let head = request.starts_with("HEAD");
+                write!(&stream,"HTTP/1.1 200 OK\r\nContent-Length: {}\r\nLast-Modified: Tue, 15 Nov 1994 08:12:31 GMT\r\nConnection: close\r\n\r\n{}",
+                    body.len(),if head {""} else {body}).unwrap();
+                request
+            }).collect()
+        });
+        (url,server)
+    }
+
+    #[tokio::test]
+    async fn whole_responses_skip_to_the_offset()
+    {
+        let (url,server) = serve("hello world",2);
+        let http = Http::new(false,1,RetryPolicy{retryable:vec![],max_retries:Some(0)},Events::default());
+        let metadata = http.metadata(Path::new(&url)).await.unwrap();
+        assert_eq!(metadata.size,11);
+        assert!(metadata.modified.is_some());
+        let mut content = String::new();
+        http.open(Path::new(&url),6).await.unwrap().read_to_string(&mut content).await.unwrap();
+        assert_eq!(content,"world");
+        let requests = server.join().unwrap();
+        assert!(requests[1].to_ascii_lowercase().contains("range: bytes=6-"),"{}",requests[1]);
+    }
+
+    #[test]
+    fn statuses_are_error_kinds()
+    {
+        assert_eq!(status_kind(StatusCode::NOT_FOUND),std::io::ErrorKind::NotFound);
+        assert_eq!(status_kind(StatusCode::FORBIDDEN),std::io::ErrorKind::PermissionDenied);
+        assert_eq!(status_kind(StatusCode::SERVICE_UNAVAILABLE),std::io::ErrorKind::TimedOut);
+        assert_eq!(status_kind(StatusCode::IM_A_TEAPOT),std::io::ErrorKind::Other);
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// a record of checked files, so an interrupted run can be resumed
+///
+/// Every checked file is appended as one line `STATUS<tab>expected<tab>digest<tab>absolute path`
+/// (digest is `-` if the file could not be read). Files are matched by their absolute path and
+/// expected digest, so a changed manifest entry is checked again.
+///
+/// Checksum files done with --watch are appended as `MANIFEST<tab>size:mtime<tab>OK/FAIL/ERROR counts<tab>absolute path`.
+pub struct Journal
+{
+    file:std::fs::File,
+    /// (absolute path,expected digest) of the files that were verified OK
+    verified:HashSet<(String,String)>,
+    /// (absolute path,size:mtime) of the checksum files done
+    manifests:HashSet<(String,String)>
+}
+
+/// the size and modification time of a checksum file, so it's checked again when it changed
+fn fingerprint(manifest:&Path) -> String
+{
+    let metadata = manifest.metadata().ok();
+    let mtime = metadata.as_ref().and_then(|m|m.modified().ok()).and_then(|t|t.duration_since(std::time::UNIX_EPOCH).ok()).unwrap_or_default();
+    format!("{}:{}.{:09}",metadata.map_or(0,|m|m.len()),mtime.as_secs(),mtime.subsec_nanos())
+}
+
+fn key(path:&Path,expected:&str) -> (String,String)
+{
+    let path = std::path::absolute(path).unwrap_or_else(|_|path.to_path_buf());
+    (path.to_string_lossy().into_owned(),expected.to_owned())
+}
+
+impl Journal
+{
+    /// open (or create) the journal, with rescan the files recorded in it are not loaded
+    ///
+    /// A last line without line end was cut off by a crash (also if it has all fields, its path could be cut off),
+    /// it's ignored and removed, so the records appended start on a line of their own.
+    pub fn open(path:&Path,rescan:bool) -> std::io::Result<Journal>
+    {
+        let file = OpenOptions::new().create(true).append(true).read(true).open(path)?;
+        let (mut verified,mut manifests) = (HashSet::new(),HashSet::new());
+        let (mut reader,mut line,mut complete) = (BufReader::new(&file),vec![],0);
+        loop {
+            line.clear();
+            if reader.read_until(b'\n',&mut line)? == 0 || line.last() != Some(&b'\n') {break}
+            complete += line.len() as u64;
+            if rescan {continue}
+            let line = String::from_utf8_lossy(&line[..line.len()-1]);
+            let mut fields = line.splitn(4,'\t');
+            let (Some(status),Some(expected),Some(_),Some(path)) = (fields.next(),fields.next(),fields.next(),fields.next()) else {
+                continue
+            };
+            // later records replace earlier ones
+            let key = (path.to_owned(),expected.to_owned());
+            match status {
+                "MANIFEST" => {manifests.insert(key);}
+                "OK" => {verified.insert(key);}
+                _ => {verified.remove(&key);}
+            }
+        }
+        if file.metadata()?.len() > complete {file.set_len(complete)?;}
+        Ok(Journal{file,verified,manifests})
+    }
+    /// if the file was verified with this expected digest before
+    pub fn verified(&self,path:&Path,expected:&str) -> bool
+    {
+        self.verified.contains(&key(path,expected))
+    }
+    /// if the checksum file was done before, as it is now
+    pub fn manifest_done(&self,manifest:&Path) -> bool
+    {
+        self.manifests.contains(&key(manifest,&fingerprint(manifest)))
+    }
+    /// append that the checksum file is done, with the number of files OK, FAIL and ERROR
+    pub fn record_manifest(&mut self,manifest:&Path,counts:(usize,usize,usize)) -> std::io::Result<()>
+    {
+        let (path,fingerprint) = key(manifest,&fingerprint(manifest));
+        let (ok,fail,error) = counts;
+        let line = format!("MANIFEST\t{fingerprint}\t{ok}/{fail}/{error}\t{path}\n");
+        self.file.write_all(line.as_bytes())
+    }
+    /// append the result for a file (as one write, so it's either there completely or not at all)
+    pub fn record(&mut self,path:&Path,expected:&str,digest:Option<&str>,status:impl Display) -> std::io::Result<()>
+    {
+        let (path,expected) = key(path,expected);
+        let line = format!("{status}\t{expected}\t{}\t{path}\n",digest.unwrap_or("-"));
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const DIGEST:&str = "b1946ac92492d2347c6235b4d2611184";
+
+    #[test]
+    fn runs_resume_from_the_journal()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+        let (a,b,c) = (dir.path().join("a"),dir.path().join("b"),dir.path().join("c"));
+        {
+            let mut journal = Journal::open(&path,false).unwrap();
+            journal.record(&a,DIGEST,Some(DIGEST),"OK").unwrap();
+            journal.record(&b,DIGEST,Some(DIGEST),"OK").unwrap();
+            journal.record(&b,DIGEST,None,"ERROR").unwrap();
+            journal.record(&c,DIGEST,Some("00000000000000000000000000000000"),"FAIL").unwrap();
+        }
+        // cut off by a crash
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"OK\tb1946ac9").unwrap();
+        let journal = Journal::open(&path,false).unwrap();
+        assert!(journal.verified(&a,DIGEST));
+        // failed later, or failed verification
+        assert!(!journal.verified(&b,DIGEST) && !journal.verified(&c,DIGEST));
+        // the entry changed
+        assert!(!journal.verified(&a,"00000000000000000000000000000000"));
+        assert!(!Journal::open(&path,true).unwrap().verified(&a,DIGEST));
+    }
+
+    #[test]
+    fn cut_off_records_are_dropped()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+        let (a,b) = (dir.path().join("a"),dir.path().join("b"));
+        Journal::open(&path,false).unwrap().record(&a,DIGEST,Some(DIGEST),"OK").unwrap();
+        // all fields, but cut off in the middle of the path of another file
+        let line = format!("OK\t{DIGEST}\t{DIGEST}\t{}",b.to_string_lossy());
+        let cut = &line[..line.len()-1];
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(cut.as_bytes()).unwrap();
+        let mut journal = Journal::open(&path,false).unwrap();
+        assert!(journal.verified(&a,DIGEST));
+        assert!(!journal.verified(Path::new(cut.rsplit('\t').next().unwrap()),DIGEST) && !journal.verified(&b,DIGEST));
+        journal.record(&b,DIGEST,Some(DIGEST),"OK").unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(),2);
+        assert!(content.ends_with(&format!("{}\n",b.to_string_lossy())));
+        assert!(Journal::open(&path,false).unwrap().verified(&b,DIGEST));
+    }
+
+    #[test]
+    fn changed_checksum_files_are_not_done()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let (path,manifest) = (dir.path().join("journal"),dir.path().join("md5sum"));
+        std::fs::write(&manifest,format!("{DIGEST}  a\n")).unwrap();
+        Journal::open(&path,false).unwrap().record_manifest(&manifest,(1,0,0)).unwrap();
+        assert!(Journal::open(&path,false).unwrap().manifest_done(&manifest));
+        std::fs::write(&manifest,format!("{DIGEST}  a\n{DIGEST}  b\n")).unwrap();
+        assert!(!Journal::open(&path,false).unwrap().manifest_done(&manifest));
+    }
+}
@@ -0,0 +1,2300 @@
+//! Checking md5 checksums of files on systems with limited primary and slow secondary storage, like tape archives.
+//!
+//! A [`Checker`] reads as many files at a time as its budget of bytes allows, optionally recalling them
+//! with a stage command first and releasing them with a release command once they verified.
+//! The md5check command line tool ([`cli`]) checks the files listed in checksum files with it.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use tokio::task::JoinSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use buffers::BufferPool;
+use actions::Action;
+use command::ExtCommand;
+use events::Events;
+use journal::Journal;
+use options::{Accounting, Duplicates, HardLinks, Options, Order, Symlinks};
+
+pub use checker::{Algorithm, Canceller, CheckResult, Checker, CheckerBuilder};
+pub use config::Origin;
+pub use digest::{Digest, DigestFactory, DigestValue, Digests, Md5, Sha256};
+#[cfg(feature="openssl")]
+pub use digest::OpensslMd5;
+pub use error::Error;
+pub use events::Event;
+pub use retry::{RetryPolicy, Retryable};
+pub use source::{Source, SourceFuture, SourceMetadata};
+
+mod actions;
+mod adaptive;
+mod atime;
+mod bad_blocks;
+mod batch;
+#[cfg(all(unix,feature="daemon"))]
+mod budget;
+mod buffers;
+mod checker;
+mod chunks;
+pub mod cli;
+mod command;
+mod compare;
+mod config;
+mod confine;
+#[cfg(all(unix,feature="daemon"))]
+mod daemon;
+mod deadline;
+mod digest;
+mod error;
+mod events;
+mod extra;
+mod failed_script;
+mod filters;
+mod hsm;
+#[cfg(feature="http")]
+mod http;
+mod journal;
+mod lock;
+mod lookahead;
+mod manifest_list;
+mod merge;
+mod mount;
+mod options;
+mod path_compat;
+#[cfg(feature="sqlite")]
+mod ref_db;
+mod per_dir;
+mod physical;
+mod priority;
+mod retry;
+mod retry_pass;
+#[cfg(feature="s3")]
+mod s3;
+mod same_file;
+mod sample;
+#[cfg(feature="sftp")]
+mod sftp;
+mod signature;
+mod source;
+mod status;
+mod stream_digest;
+#[cfg(feature="tar")]
+mod tar_stream;
+mod tcman;
+mod thresholds;
+mod watcher;
+mod zip_member;
+
+struct File(tokio::fs::File);
+
+impl File
+{
+    /// open the file, retrying errors the policy considers retryable
+    async fn open<T>(path:T,policy:&RetryPolicy,events:&Events) -> std::io::Result<File> where T:AsRef<Path>
+    {
+        let path = path.as_ref();
+        let mut attempt = 0;
+        events.emit(||Event::Opening{path:path.to_path_buf()});
+        loop {
+            debug!("trying to open '{}'",path.to_string_lossy());
+            match atime::open(path).await {
+                Ok(file) => return Ok(File(file)),
+                Err(err) if policy.should_retry(&err,attempt) => {
+                    let delay = policy.delay(attempt);
+                    debug!("opening '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    events.emit(||Event::OpenRetried{path:path.to_path_buf(),attempt,error:err.kind()});
+                }
+                // kept as it is, so the check can tell the file system is gone
+                Err(err) if mount::lost(&err) => return Err(err),
+                Err(err) => {
+                    let desc=std::io::Error::other(format!("Failed to open {}: {err}",path.to_string_lossy()));
+                    return Err(std::io::Error::new(err.kind(),desc))
+                }
+            }
+        }
+    }
+}
+impl AsyncRead for File
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>>
+    {
+        match Pin::new(&mut self.get_mut().0).poll_read(cx,buf)
+        {
+            Poll::Ready(Ok(_)) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(e)) => {
+                if is_ebusy(&e) {
+                    debug!("Got EBUSY from OS, going to wait for a while...");
+                    let waker = cx.waker().clone();
+                    thread::spawn(move || {
+                        thread::sleep(SLEEP_DURATION);
+                        waker.wake();
+                    });
+                    Poll::Pending
+                }
+                else {Poll::Ready(Err(e)) }
+            },
+            Poll::Pending => Poll::Pending
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_ebusy(e:&std::io::Error) -> bool {e.raw_os_error() == Some(libc::EBUSY)}
+#[cfg(not(unix))]
+fn is_ebusy(_:&std::io::Error) -> bool {false}
+
+/// the path as the system has it (on Windows as UTF-8)
+#[cfg(unix)]
+fn path_bytes(path:&Path) -> std::borrow::Cow<'_,[u8]>
+{
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().into()
+}
+#[cfg(not(unix))]
+fn path_bytes(path:&Path) -> std::borrow::Cow<'_,[u8]>
+{
+    path.to_string_lossy().into_owned().into_bytes().into()
+}
+
+/// if the file has other hard links
+#[cfg(unix)]
+fn hardlinked(metadata:&std::fs::Metadata) -> bool
+{
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+#[cfg(not(unix))]
+fn hardlinked(_:&std::fs::Metadata) -> bool {false}
+
+/// identifies a file independent of the path it's reached by (device and inode, or the canonical path)
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+struct FileId(#[cfg(unix)] (u64,u64),#[cfg(not(unix))] PathBuf);
+
+#[cfg(unix)]
+fn file_id(_:&Path,metadata:&std::fs::Metadata) -> Option<FileId>
+{
+    use std::os::unix::fs::MetadataExt;
+    Some(FileId((metadata.dev(),metadata.ino())))
+}
+#[cfg(not(unix))]
+fn file_id(path:&Path,_:&std::fs::Metadata) -> Option<FileId>
+{
+    std::fs::canonicalize(path).ok().map(FileId)
+}
+
+/// the path of a file listed in a manifest in base
+///
+/// On Windows "/" is accepted as separator as well, so manifests written on Unix work there.
+fn manifest_path(base:&Path,name:&str) -> PathBuf
+{
+    #[cfg(windows)]
+    let name = name.replace('/',"\\");
+    base.join(name)
+}
+
+/// the path of a file listed in a manifest in base, and the archive and member name if it's an archive member
+fn manifest_member(base:&Path,name:&str) -> (PathBuf,Option<(PathBuf,Arc<str>)>)
+{
+    let member = zip_member::split(name).map(|(archive,member)|(manifest_path(base,archive),member.into()));
+    (manifest_path(base,name),member)
+}
+
+/// the directory the files listed in a manifest are relative to
+///
+/// That's the directory of the manifest, or the current directory if it has none (e.g. "md5sum" or "/").
+/// The current directory is given as empty path, so the paths of the files stay as they're listed.
+fn manifest_dir(manifest:&Path) -> &Path
+{
+    manifest.parent().unwrap_or(Path::new(""))
+}
+
+/// the [directory](manifest_dir) of a manifest about to be read, logging it
+fn manifest_base(manifest:&Path) -> &Path
+{
+    let base = manifest_dir(manifest);
+    let shown = if base.as_os_str().is_empty() {Path::new(".")} else {base};
+    debug!("files listed in '{}' are relative to '{}'",manifest.to_string_lossy(),shown.to_string_lossy());
+    base
+}
+
+/// split a manifest line into digest (hex digits of a known length) and file name
+///
+/// Blank lines and comments (starting with "#") give None.
+fn parse_line<'l>(line:&'l str,digests:&Digests) -> Result<Option<(&'l str,&'l str)>,&'static str>
+{
+    if line.trim().is_empty() || line.starts_with('#') {return Ok(None);}
+    let (digest,filename) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+    if !digest.chars().all(|c|c.is_ascii_hexdigit()) {return Err("not a valid checksum");}
+    if !digests.known_len(digest.len()) {return Err("no known checksum has this length");}
+    if filename.trim().is_empty() {return Err("no file name after the checksum");}
+    Ok(Some((digest,filename.trim())))
+}
+
+/// the totals a checksum file declares in comment lines "# files: <entries>" and "# bytes: <size of the files>"
+#[derive(Clone,Copy,Default)]
+struct Footer
+{
+    files:Option<u64>,
+    bytes:Option<u64>
+}
+
+impl Footer
+{
+    /// take the total of the line if it declares one
+    fn parse(&mut self,line:&str) -> bool
+    {
+        let Some((key,value)) = line.strip_prefix('#').and_then(|comment|comment.split_once(':')) else {return false};
+        let Ok(value) = value.trim().parse() else {return false};
+        match key.trim() {
+            "files" => self.files = Some(value),
+            "bytes" => self.bytes = Some(value),
+            _ => return false
+        }
+        true
+    }
+}
+
+/// why a file could not be checked
+#[derive(thiserror::Error,Debug)]
+#[non_exhaustive]
+pub enum CheckError
+{
+    #[error("pre-hook failed")]
+    PreHook,
+    #[error("stage command failed")]
+    Stage,
+    /// size or modification time differ after reading the file
+    #[error("file changed while being read")]
+    Changed,
+    /// the task checking it panicked
+    #[error("panicked: {0}")]
+    Panic(String),
+    /// e.g. an archive member compressed with an unsupported method
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    /// reading it failed at offset, and where --probe-bad-blocks found it unreadable (first offset and length)
+    #[error("reading failed at offset {offset}{}: {source}",
+        .bad.map(|(first,length)|format!(" (unreadable from offset {first} for {length} bytes)")).unwrap_or_default())]
+    Read{offset:u64,bad:Option<(u64,u64)>,source:std::io::Error},
+    /// opening or reading it failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error)
+}
+
+impl CheckError
+{
+    fn status(&self) -> Status
+    {
+        match self {
+            CheckError::PreHook => Status::HookSkipped,
+            CheckError::Stage => Status::Error(Some("stage")),
+            CheckError::Changed => Status::Changed,
+            CheckError::Panic(_) => Status::Error(Some("panic")),
+            CheckError::Unsupported(_) => Status::Unsupported,
+            CheckError::Read{..}|CheckError::Io(_) => Status::Error(None)
+        }
+    }
+}
+
+/// a file to be checked as listed in a manifest
+#[derive(Clone,Debug)]
+struct Entry
+{
+    path:PathBuf,
+    expected:String,
+    manifest:Arc<Path>,
+    /// size of the file when it was admitted
+    size:u64,
+    /// modification time of the file when it was admitted
+    mtime:Option<SystemTime>,
+    id:Option<FileId>,
+    /// the size and modification time listed in an extended checksum file (.tcman)
+    recorded:Option<tcman::Recorded>,
+    /// the zip archive and the name of the member in it, if the entry is an archive member (then path is "archive.zip//member")
+    member:Option<(PathBuf,Arc<str>)>,
+    /// entries of the same file with digests of other algorithms, computed while reading it
+    along:Vec<Entry>
+}
+
+impl Entry
+{
+    /// the entry and those read along with it
+    fn listings(mut self) -> impl Iterator<Item=Entry>
+    {
+        let along = std::mem::take(&mut self.along);
+        std::iter::once(self).chain(along)
+    }
+    /// the file to read (the archive for archive members)
+    fn file(&self) -> &Path
+    {
+        self.member.as_ref().map_or(&self.path,|(archive,_)|archive)
+    }
+    /// the path for checksum files (with "//member" for archive members)
+    fn listed_path(&self) -> PathBuf
+    {
+        let file = std::path::absolute(self.file()).unwrap_or_else(|_|self.file().to_path_buf());
+        match &self.member {
+            Some((_,member)) => format!("{}{}{member}",file.to_string_lossy(),zip_member::SEPARATOR).into(),
+            None => file
+        }
+    }
+    /// the environment for commands run for this entry
+    fn env(&self,status:Option<Status>,digest:Option<&str>) -> command::Env
+    {
+        let mut env:command::Env = vec![
+            ("TAPECHECK_PATH",std::path::absolute(self.file()).unwrap_or_else(|_|self.file().to_path_buf()).into()),
+            ("TAPECHECK_EXPECTED",self.expected.clone().into()),
+            ("TAPECHECK_SIZE",self.size.to_string().into()),
+            ("TAPECHECK_MANIFEST",std::path::absolute(&self.manifest).unwrap_or_else(|_|self.manifest.to_path_buf()).into()),
+        ];
+        if let Some(status) = status {env.push(("TAPECHECK_STATUS",status.to_string().into()));}
+        if let Some(digest) = digest {env.push(("TAPECHECK_DIGEST",digest.into()));}
+        if let Some((_,member)) = &self.member {env.push(("TAPECHECK_MEMBER",member.to_string().into()));}
+        env
+    }
+}
+
+/// how check_file works, shared by all checks
+struct CheckOptions
+{
+    pre_hook:Option<Arc<ExtCommand>>,
+    stage:Option<Arc<ExtCommand>>,
+    open_policy:RetryPolicy,
+    /// which read errors are resumed from how often
+    resume_policy:RetryPolicy,
+    /// look for chunk digests
+    chunks:bool,
+    /// the digest of all files in the order listed, None once it's finished
+    stream:Option<Arc<std::sync::Mutex<Option<md5::Context>>>>,
+    /// the algorithms for the digests listed, by their length
+    digests:Digests,
+    /// where the files are read from
+    source:Arc<dyn Source>,
+    /// the read buffers
+    buffers:Arc<BufferPool>,
+    /// set the access time of local files back after reading them
+    restore_atime:bool,
+    /// waiting for the file system of local files when it's gone
+    mount:Arc<mount::Mount>,
+    /// look for where local files are unreadable after read errors
+    probe_bad_blocks:bool,
+    events:Events
+}
+
+/// how far a file being checked is
+struct Progress
+{
+    size:u64,
+    /// the bytes read so far, updated by the check
+    read:Arc<AtomicU64>,
+    started:Instant,
+    /// when the last heartbeat was logged (or the check started), and the bytes read then
+    beat:(Instant,u64)
+}
+
+/// the result of reading a file
+struct Checked
+{
+    digest:String,
+    /// the digests of the other algorithms of the entries read along
+    others:Vec<String>,
+    /// how often reading was resumed after an error
+    resumes:u32,
+    /// the result for the chunks, if there were chunk digests for the file
+    chunks:Option<chunks::Checked>,
+    /// how long staging, opening and reading it took
+    duration:Duration
+}
+
+/// compute the digest of the entry (after running the pre-hook and staging it if configured)
+///
+/// If it is already being staged ahead, that is waited for instead of running the stage command.
+/// On read errors the resume policy considers retryable, the file is reopened and reading resumes where it failed.
+/// The bytes read so far are stored in progress.
+async fn check_file(entry:&Entry, options:Arc<CheckOptions>, staging:Option<JoinHandle<bool>>, progress:Arc<AtomicU64>) -> Result<Checked,CheckError>
+{
+    let path = &entry.file().to_path_buf();
+    let Some(digest) = options.digests.by_hex_len(entry.expected.len()) else {
+        return Err(CheckError::Unsupported(format!("no digest algorithm has {} hex digits",entry.expected.len())));
+    };
+    let mut digest = digest();
+    let mut others:Vec<(usize,Box<dyn Digest>)> = vec![];
+    for len in entry.along.iter().map(|e|e.expected.len()) {
+        if len == entry.expected.len() || others.iter().any(|(known,_)|*known == len) {continue}
+        if let Some(other) = options.digests.by_hex_len(len) {others.push((len,other()));}
+    }
+    if let Some(pre_hook) = &options.pre_hook {
+        if !pre_hook.run("running pre-hook for",std::slice::from_ref(path),&entry.env(None,None)).await {
+            return Err(CheckError::PreHook);
+        }
+    }
+    let start = Instant::now();
+    let staged = match (staging,&options.stage) {
+        (Some(staging),_) => staging.await.unwrap_or(false),
+        (None,Some(stage)) => stage.run("staging",std::slice::from_ref(path),&entry.env(None,None)).await,
+        (None,None) => true
+    };
+    if !staged {
+        return Err(CheckError::Stage);
+    }
+    let staged = Instant::now();
+    // restored when done, also if reading fails
+    let accessed = if options.restore_atime && options.source.local(path) {atime::accessed(path)} else {None};
+    let _atime = accessed.map(|before|atime::Restore{path,before});
+    if let Some((_,member)) = &entry.member {
+        if !options.source.local(path) {return Err(CheckError::Unsupported("archive members can only be read from local files".into()));}
+        let file = options.mount.retry(path,||File::open(path,&options.open_policy,&options.events)).await?;
+        debug!("reading '{}'",entry.path.to_string_lossy());
+        let archive = file.0.into_std().await;
+        let metadata = SourceMetadata::from(&archive.metadata()?);
+        let member = member.clone();
+        let mut buffer = options.buffers.take();
+        let computed = tokio::task::spawn_blocking(move||zip_member::digest(archive,&member,digest,&progress,&mut buffer)).await
+            .map_err(|e|CheckError::Panic(e.to_string()))??;
+        if changed(entry,&metadata) {return Err(CheckError::Changed);}
+        return Ok(Checked{digest:computed,others:vec![],resumes:0,chunks:None,duration:start.elapsed()});
+    }
+    // the local source tries opening until it gets the file, or a non-repeat-Error
+    let local = options.source.local(path);
+    let mut file = if local {options.mount.retry(path,||options.source.open(path,0)).await?} else {options.source.open(path,0).await?};
+    let opened = Instant::now();
+    debug!("reading '{}'",path.to_string_lossy());
+    let mut chunks = if options.chunks {chunks::Chunks::load(path).await} else {None};
+    let mut buffer = options.buffers.take();
+    let (mut offset,mut resumes) = (0,0);
+    // where reading was resumed after the file system was back, failing there again is the file's own error
+    let mut suspended = None;
+    loop {
+        match file.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => {
+                digest.update(&buffer[..read])?;
+                for (_,other) in &mut others {other.update(&buffer[..read])?;}
+                if let Some(chunks) = &mut chunks {chunks.consume(&buffer[..read]);}
+                if let Some(stream) = &options.stream {
+                    if let Some(context) = stream.lock().expect("stream digest lock").as_mut() {context.consume(&buffer[..read]);}
+                }
+                offset += read as u64;
+                progress.store(offset,Ordering::Relaxed);
+                options.events.emit(||Event::Progress{path:entry.path.clone(),bytes:offset});
+            }
+            Err(e) if local && mount::lost(&e) && suspended != Some(offset) => {
+                if !options.mount.suspend(path,&e).await {return Err(CheckError::Read{offset,bad:None,source:e})}
+                warn!("resuming '{}' at offset {offset}",path.to_string_lossy());
+                suspended = Some(offset);
+                file = options.mount.retry(path,||options.source.open(path,offset)).await?;
+            }
+            Err(e) if options.resume_policy.should_retry(&e,resumes) => {
+                warn!("reading '{}' failed at offset {offset}: {e}, resuming there",path.to_string_lossy());
+                tokio::time::sleep(options.resume_policy.delay(resumes)).await;
+                resumes += 1;
+                file = options.source.open(path,offset).await?;
+            }
+            Err(e) => {
+                let bad = if options.probe_bad_blocks && local {bad_blocks::locate(path,offset,entry.size).await} else {None};
+                return Err(CheckError::Read{offset,bad,source:e})
+            }
+        }
+    }
+    // done with it, sources may need what it holds (like a connection) to look the file up
+    drop(file);
+    let metadata = if local {options.mount.retry(path,||options.source.metadata(path)).await?} else {options.source.metadata(path).await?};
+    if changed(entry,&metadata) {
+        return Err(CheckError::Changed);
+    }
+    // sources may end early without an error (like a cut HTTP transfer without Content-Length)
+    if offset != entry.size {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof,format!("read {offset} of {} bytes",entry.size)).into());
+    }
+    let computed = digest.finalize()?.to_hex();
+    let others = others.into_iter().map(|(_,other)|other.finalize().map(|digest|digest.to_hex())).collect::<Result<_,_>>()?;
+    debug!("'{}' is done computed:'{computed}', reference:'{}'", path.to_string_lossy(),entry.expected);
+    debug!("'{}' took {:?} (stage {:?}, open {:?}, read {:?} at {:.1}M/s)",path.to_string_lossy(),
+        start.elapsed(),staged-start,opened-staged,opened.elapsed(),offset as f64/MEGABYTE as f64/opened.elapsed().as_secs_f64());
+    Ok(Checked{digest:computed,others,resumes,chunks:chunks.map(chunks::Chunks::finish),duration:start.elapsed()})
+}
+/// the number of files OK, FAIL and ERROR of a checksum file
+fn manifest_counts(files:&HashMap<PathBuf,Status>) -> (usize,usize,usize)
+{
+    let ok = files.values().filter(|s|**s == Status::Ok).count();
+    let fail = files.values().filter(|s|**s == Status::Fail).count();
+    (ok,fail,files.values().filter(|s|s.failed()).count()-fail)
+}
+/// entries of the same file with digests of different algorithms, as a md5sum and a sha256sum file of the same tree,
+/// are read once: the later ones are read along with the first
+fn group_algorithms(entries:Vec<Entry>) -> Vec<Entry>
+{
+    let mut grouped:Vec<Entry> = Vec::with_capacity(entries.len());
+    let mut first:HashMap<PathBuf,usize> = HashMap::new();
+    for entry in entries {
+        if let Some(&i) = first.get(&entry.path).filter(|_|entry.member.is_none()) {
+            if grouped[i].expected.len() != entry.expected.len() {
+                grouped[i].along.push(entry);
+                continue
+            }
+        }
+        first.entry(entry.path.clone()).or_insert(grouped.len());
+        grouped.push(entry);
+    }
+    grouped
+}
+/// if the size or modification time of the file differ from when the entry was admitted
+fn changed(entry:&Entry,metadata:&SourceMetadata) -> bool
+{
+    metadata.size != entry.size || metadata.modified != entry.mtime
+}
+
+/// the verdict for a file (or checksum file), as printed by the command line tool
+#[derive(Clone,Copy,PartialEq,Debug)]
+#[non_exhaustive]
+pub enum Status
+{
+    /// verified
+    Ok,
+    /// the digest differs
+    Fail,
+    /// it could not be checked, with the cause if it's not just failing to open or read it
+    Error(Option<&'static str>),
+    /// not online, and not checked
+    Offline,
+    /// the pre-hook failed, so it was not checked
+    HookSkipped,
+    /// verified before according to the journal
+    Cached,
+    /// it changed while being read
+    Changed,
+    /// a checksum file listed in a checksum file, not checked
+    SelfReference,
+    /// a symlink reported instead of checked
+    Symlink,
+    /// no regular file, not read
+    NotAFile,
+    /// it was being checked when the run was cancelled
+    Cancelled,
+    /// in the tar stream, but not listed
+    NotListed,
+    /// it could not be checked because it's stored in an unsupported way (e.g. an archive member's compression)
+    Unsupported,
+    /// a checksum file with a valid signature of this kind
+    Signed(&'static str),
+    /// a checksum file whose signature could not be verified
+    Rejected,
+    /// not listed in any checksum file
+    Extra,
+    /// outside the directory of its checksum file, not checked
+    Confinement,
+    /// the reference database has no digest for it, not checked
+    NoRef,
+    /// its size differs from the one listed in the extended checksum file, not read
+    SizeMismatch
+}
+
+impl Status
+{
+    /// if the file failed verification or could not be checked
+    fn failed(&self) -> bool {matches!(self,Status::Fail|Status::Error(_)|Status::Changed|Status::Unsupported|Status::SizeMismatch)}
+}
+
+impl std::fmt::Display for Status
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Ok => f.write_str("OK"),
+            Status::Fail => f.write_str("FAIL"),
+            Status::Error(None) => f.write_str("ERROR"),
+            Status::Error(Some(cause)) => write!(f,"ERROR({cause})"),
+            Status::Offline => f.write_str("OFFLINE"),
+            Status::HookSkipped => f.write_str("HOOK-SKIPPED"),
+            Status::Cached => f.write_str("CACHED"),
+            Status::Changed => f.write_str("CHANGED-DURING-READ"),
+            Status::SelfReference => f.write_str("SELF-REFERENCE"),
+            Status::Symlink => f.write_str("SYMLINK"),
+            Status::NotAFile => f.write_str("NOT-A-FILE"),
+            Status::Cancelled => f.write_str("CANCELLED"),
+            Status::NotListed => f.write_str("NOT-IN-MANIFEST"),
+            Status::Unsupported => f.write_str("UNSUPPORTED"),
+            Status::Signed(kind) => write!(f,"SIGNED({kind})"),
+            Status::Rejected => f.write_str("SIGNATURE-REJECTED"),
+            Status::Extra => f.write_str("EXTRA"),
+            Status::Confinement => f.write_str("CONFINEMENT"),
+            Status::NoRef => f.write_str("NOREF"),
+            Status::SizeMismatch => f.write_str("SIZE-MISMATCH")
+        }
+    }
+}
+
+#[derive(Default)]
+struct Summary
+{
+    ok:u64,fail:u64,error:u64,
+    /// files that changed while being read
+    changed:u64,
+    offline:u64,
+    /// manifest lines that could not be parsed
+    invalid_lines:u64,
+    /// checksum files not listing the number of entries or bytes of their footer
+    footer_mismatches:u64,
+    /// files the reference database has no digest for
+    noref:u64,
+    /// entries of the priority file that were checked ahead, and those not listed in any checksum file
+    pub(crate) expedited:u64,pub(crate) priority_unlisted:u64,
+    /// files whose size differs from the one their extended checksum file lists (they count as errors as well)
+    size_mismatches:u64,
+    /// files whose modification time differs from the one their extended checksum file lists (they're checked anyway)
+    mtime_mismatches:u64,
+    /// files bigger than max-size that were checked alone
+    oversize:u64,
+    /// checksum files listed in checksum files, that were skipped
+    self_references:u64,
+    /// entries outside the directory of their checksum file, that were not checked
+    confinement:u64,
+    /// entries that were symlinks, and not followed
+    symlinks:u64,
+    /// entries that are no regular files (directories, FIFOs, devices ...)
+    not_files:u64,
+    /// files skipped by --newer-than/--older-than
+    filtered:u64,
+    /// files skipped by --min-file-size/--max-file-size, and their size
+    size_filtered:u64,size_filtered_bytes:u64,
+    /// members of the tar stream not listed in the checksum files
+    not_listed:u64,
+    /// files modified after their checksum file, and how many of them failed
+    newer_than_manifest:u64,newer_failed:u64,
+    /// checksum files with a valid signature, and those rejected
+    signed:u64,rejected:u64,
+    /// checksum files that could not be opened
+    unreadable_manifests:u64,
+    /// files found by --check-extra that are not listed
+    extra:u64,
+    /// --shard, and how many of the entries listed were in it
+    shard:Option<(u64,u64)>,in_shard:u64,listed_for_shard:u64,
+    /// the entries not within --skip/--limit, and the window
+    outside_window:u64,window:Option<(u64,Option<u64>)>,
+    /// entries chosen by --sample, of how many, and the parameters
+    sample:Option<(u64,u64,String)>,
+    /// the seed of --shuffle
+    shuffle:Option<u64>,
+    /// entries for files already checked for another entry
+    duplicates:u64,
+    /// of those, files with several hard links
+    hardlinks:u64,
+    /// how often reading was resumed after an error, and for how many files
+    resumes:u64,resumed_files:u64,
+    /// chunks that didn't match, and in how many files
+    bad_chunks:u64,chunked_files:u64,
+    /// files skipped as they were verified according to the journal
+    cached:u64,
+    /// bytes of all files checked
+    bytes:u64,
+    /// time admission was paused by the pause-when command
+    paused:Duration,
+    /// how often the file system of the files was gone, and for how long in all
+    mount_losses:(u64,Duration),
+    /// with --adaptive the fewest and most files checked at once, and how many at the end
+    concurrency:Option<(usize,usize,usize)>,
+    release_failures:u64,
+    hook_skipped:u64,post_hook_failures:u64,
+    deleted:u64,delete_failures:u64,
+    /// checks cancelled by a second interrupt
+    cancelled:u64,
+    interrupted:bool,
+    /// entries that were not checked because the run stopped early
+    not_attempted:u64,
+    deadline_reached:bool,
+    /// --max-bytes, the bytes of the files started and of those not attempted
+    budget:Option<(u64,u64,u64)>,
+    /// why the run was stopped by --max-failures, --max-errors or --max-failure-rate
+    aborted:Option<String>
+}
+
+impl Summary
+{
+    fn checked(&self) -> u64 {self.ok+self.fail+self.error+self.changed}
+    /// add the counts of another one (of a --daemon job)
+    #[cfg(all(unix,feature="daemon"))]
+    fn add(&mut self,other:&Summary)
+    {
+        for (count,other) in [
+            (&mut self.ok,other.ok),(&mut self.fail,other.fail),(&mut self.error,other.error),(&mut self.changed,other.changed),
+            (&mut self.offline,other.offline),(&mut self.invalid_lines,other.invalid_lines),(&mut self.footer_mismatches,other.footer_mismatches),
+            (&mut self.noref,other.noref),(&mut self.size_mismatches,other.size_mismatches),(&mut self.mtime_mismatches,other.mtime_mismatches),
+            (&mut self.oversize,other.oversize),(&mut self.self_references,other.self_references),(&mut self.confinement,other.confinement),
+            (&mut self.symlinks,other.symlinks),(&mut self.not_files,other.not_files),(&mut self.filtered,other.filtered),
+            (&mut self.size_filtered,other.size_filtered),(&mut self.size_filtered_bytes,other.size_filtered_bytes),(&mut self.not_listed,other.not_listed),
+            (&mut self.newer_than_manifest,other.newer_than_manifest),(&mut self.newer_failed,other.newer_failed),
+            (&mut self.signed,other.signed),(&mut self.rejected,other.rejected),(&mut self.unreadable_manifests,other.unreadable_manifests),
+            (&mut self.in_shard,other.in_shard),(&mut self.listed_for_shard,other.listed_for_shard),(&mut self.outside_window,other.outside_window),
+            (&mut self.duplicates,other.duplicates),(&mut self.hardlinks,other.hardlinks),(&mut self.resumes,other.resumes),
+            (&mut self.resumed_files,other.resumed_files),(&mut self.bad_chunks,other.bad_chunks),(&mut self.chunked_files,other.chunked_files),
+            (&mut self.cached,other.cached),(&mut self.bytes,other.bytes),(&mut self.release_failures,other.release_failures),
+            (&mut self.hook_skipped,other.hook_skipped),(&mut self.post_hook_failures,other.post_hook_failures),
+            (&mut self.deleted,other.deleted),(&mut self.delete_failures,other.delete_failures),(&mut self.cancelled,other.cancelled)
+        ] {*count += other;}
+        self.paused += other.paused;
+    }
+    /// the environment for the finally command
+    fn env(&self,duration:Duration,aborted:bool) -> command::Env
+    {
+        vec![
+            ("TAPECHECK_FILES",self.checked().to_string().into()),
+            ("TAPECHECK_OK",self.ok.to_string().into()),
+            ("TAPECHECK_FAILURES",self.fail.to_string().into()),
+            ("TAPECHECK_ERRORS",self.error.to_string().into()),
+            ("TAPECHECK_CHANGED",self.changed.to_string().into()),
+            ("TAPECHECK_OFFLINE",self.offline.to_string().into()),
+            ("TAPECHECK_CACHED",self.cached.to_string().into()),
+            ("TAPECHECK_BYTES",self.bytes.to_string().into()),
+            ("TAPECHECK_RELEASE_FAILURES",self.release_failures.to_string().into()),
+            ("TAPECHECK_HOOK_SKIPPED",self.hook_skipped.to_string().into()),
+            ("TAPECHECK_DELETED",self.deleted.to_string().into()),
+            ("TAPECHECK_DURATION",duration.as_secs().to_string().into()),
+            ("TAPECHECK_PAUSED",self.paused.as_secs().to_string().into()),
+            ("TAPECHECK_NOT_ATTEMPTED",self.not_attempted.to_string().into()),
+            ("TAPECHECK_ABORTED",if aborted {"1"} else {"0"}.into()),
+        ]
+    }
+}
+
+impl std::fmt::Display for Summary
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{} files checked: {} OK, {} FAIL, {} ERROR",
+            self.checked(),self.ok,self.fail,self.error)?;
+        if let Some((i,n)) = self.shard {
+            write!(f,"\nshard {i}/{n}: {} of {} entries",self.in_shard,self.listed_for_shard)?;
+        }
+        if let Some((skip,limit)) = self.window {
+            let window = match limit {
+                Some(limit) => format!("{} to {}",skip+1,skip+limit),
+                None => format!("{} to the end",skip+1)
+            };
+            write!(f,"\nonly entries {window} were checked, {} entries were outside",self.outside_window)?;
+        }
+        if let Some((chosen,entries,sampling)) = &self.sample {
+            write!(f,"\na random sample of {chosen} of {entries} entries was checked ({sampling})")?;
+        }
+        if let Some(seed) = self.shuffle {
+            write!(f,"\nthe entries were checked in random order (--shuffle={seed})")?;
+        }
+        if self.unreadable_manifests > 0 {
+            write!(f,"\n{} checksum files could not be opened",self.unreadable_manifests)?;
+        }
+        if self.rejected > 0 {
+            write!(f,"\n{} checksum files were rejected as their signature could not be verified",self.rejected)?;
+        }
+        if self.signed > 0 {
+            write!(f,"\n{} checksum files had a valid signature",self.signed)?;
+        }
+        if self.changed > 0 {
+            write!(f,"\n{} files changed while being read",self.changed)?;
+        }
+        if self.newer_than_manifest > 0 {
+            write!(f,"\n{} files were modified after their checksum file ({} of them failed)",self.newer_than_manifest,self.newer_failed)?;
+        }
+        if self.bad_chunks > 0 {
+            write!(f,"\n{} chunks of {} files didn't match",self.bad_chunks,self.chunked_files)?;
+        }
+        if self.resumes > 0 {
+            write!(f,"\nreading was resumed {} times for {} files",self.resumes,self.resumed_files)?;
+        }
+        if self.duplicates > 0 {
+            write!(f,"\n{} files were listed more than once, and only checked once",self.duplicates)?;
+            if self.hardlinks > 0 {write!(f," ({} of them as hard links of a file read for another path)",self.hardlinks)?;}
+        }
+        if self.not_files > 0 {
+            write!(f,"\n{} entries were no regular files and not read",self.not_files)?;
+        }
+        if self.symlinks > 0 {
+            write!(f,"\n{} symlinks were not followed",self.symlinks)?;
+        }
+        if self.extra > 0 {
+            write!(f,"\n{} files are not listed in any checksum file",self.extra)?;
+        }
+        if self.not_listed > 0 {
+            write!(f,"\n{} files in the tar stream are not listed in the checksum files",self.not_listed)?;
+        }
+        if self.filtered > 0 {
+            write!(f,"\n{} files were skipped by --newer-than/--older-than",self.filtered)?;
+        }
+        if self.size_filtered > 0 {
+            write!(f,"\n{} files ({} bytes) were skipped by --min-file-size/--max-file-size",self.size_filtered,self.size_filtered_bytes)?;
+        }
+        if self.self_references > 0 {
+            write!(f,"\n{} checksum files listed in checksum files were skipped",self.self_references)?;
+        }
+        if self.confinement > 0 {
+            write!(f,"\n{} entries outside the directory of their checksum file were not checked",self.confinement)?;
+        }
+        if self.oversize > 0 {
+            write!(f,"\n{} files were bigger than the maximum buffer size and checked alone",self.oversize)?;
+        }
+        if self.invalid_lines > 0 {
+            write!(f,"\n{} lines in the checksum files could not be parsed",self.invalid_lines)?;
+        }
+        if self.footer_mismatches > 0 {
+            write!(f,"\n{} checksum files don't list what their footer declares, they may be truncated",self.footer_mismatches)?;
+        }
+        if self.noref > 0 {
+            write!(f,"\n{} files have no digest in the reference database",self.noref)?;
+        }
+        if self.expedited > 0 {
+            write!(f,"\n{} entries of the priority file were checked ahead of the others",self.expedited)?;
+        }
+        if self.priority_unlisted > 0 {
+            write!(f,"\n{} entries of the priority file are not listed in any checksum file",self.priority_unlisted)?;
+        }
+        if self.size_mismatches > 0 {
+            write!(f,"\n{} files differ in size from their extended checksum file, they were not read",self.size_mismatches)?;
+        }
+        if self.mtime_mismatches > 0 {
+            write!(f,"\n{} files were modified at another time than their extended checksum file lists",self.mtime_mismatches)?;
+        }
+        if self.offline > 0 {
+            write!(f,"\n{} offline files were skipped",self.offline)?;
+        }
+        if self.cached > 0 {
+            write!(f,"\n{} files were skipped as already verified according to the journal",self.cached)?;
+        }
+        if self.release_failures > 0 {
+            write!(f,"\nrelease/on-fail commands failed for {} files",self.release_failures)?;
+        }
+        if !self.paused.is_zero() {
+            write!(f,"\npaused for {} while the system was busy",humantime::format_duration(Duration::from_secs(self.paused.as_secs())))?;
+        }
+        if let Some((fewest,most,last)) = self.concurrency {
+            write!(f,"\nbetween {fewest} and {most} files were checked at once, {last} at the end")?;
+        }
+        if self.mount_losses.0 > 0 {
+            write!(f,"\nthe file system of the files was gone {} times, for {} in all",
+                self.mount_losses.0,humantime::format_duration(Duration::from_secs(self.mount_losses.1.as_secs())))?;
+        }
+        if self.hook_skipped > 0 {
+            write!(f,"\n{} files were skipped by the pre-hook",self.hook_skipped)?;
+        }
+        if self.post_hook_failures > 0 {
+            write!(f,"\nthe post-hook failed for {} files",self.post_hook_failures)?;
+        }
+        if self.deleted > 0 || self.delete_failures > 0 {
+            write!(f,"\n{} files deleted, {} could not be deleted",self.deleted,self.delete_failures)?;
+        }
+        let budget_spent = self.budget.is_some_and(|(max,attempted,_)|attempted >= max && self.not_attempted > 0);
+        if self.interrupted || self.deadline_reached || budget_spent || self.aborted.is_some() {
+            match &self.aborted {
+                _ if self.interrupted => write!(f,"\nthe run was interrupted")?,
+                Some(reason) => write!(f,"\nthe run was aborted as {reason}")?,
+                None if self.deadline_reached => write!(f,"\nthe run was stopped at the deadline")?,
+                None => write!(f,"\nthe run was stopped at --max-bytes")?
+            }
+            if self.cancelled > 0 {write!(f,", {} checks were cancelled",self.cancelled)?;}
+        }
+        if self.not_attempted > 0 {
+            write!(f,"\n{} files were not attempted",self.not_attempted)?;
+        }
+        if let Some((max,attempted,remaining)) = self.budget {
+            write!(f,"\n{:.2}G of --max-bytes {:.2}G attempted, {:.2}G remaining",
+                attempted as f64/GIGABYTE as f64,max as f64/GIGABYTE as f64,remaining as f64/GIGABYTE as f64)?;
+        }
+        Ok(())
+    }
+}
+
+struct Reader
+{
+    readers:JoinSet<(Entry,Result<Checked,CheckError>)>,
+    /// the commands for the files checked
+    actions:actions::Actions,
+    prescan:bool,
+    /// sort the entries by where their files are in the pre-scan
+    physical:bool,
+    /// check the totals declared by the footers of the checksum files
+    footers:bool,
+    /// the bytes declared by the footers of the checksum files and how many entries they list, for the pre-scan to check
+    declared_bytes:HashMap<Arc<Path>,(u64,u64)>,
+    events:Events,
+    online:hsm::Online,
+    pause:hsm::Pause,
+    fail_fast:bool,
+    batch:batch::Batch,
+    check:Arc<CheckOptions>,
+    allow_oversize:bool,
+    symlinks:Symlinks,
+    /// if entries have to be within the directory of their checksum file, and if symlinks are resolved for that
+    confine:Option<bool>,
+    /// where the files listed are, instead of the directory of their checksum file
+    source_url:Option<String>,
+    path_compat:Option<path_compat::PathCompat>,
+    allow_special:bool,
+    filters:filters::Filters,
+    print0:bool,
+    /// the results not taken yet
+    results:VecDeque<CheckResult>,
+    /// modification times of the checksum files, for newer_than_manifest()
+    manifest_mtimes:HashMap<Arc<Path>,Option<SystemTime>>,
+    /// files that failed or could not be checked with the digest they're listed with (for --failed-files0 and --failed-script)
+    failed_files:Vec<(PathBuf,Option<String>)>,
+    lookahead:lookahead::Lookahead,
+    /// entries being checked right now
+    in_flight:HashMap<PathBuf,Entry>,
+    /// the entries for the check tasks, to know which one panicked or was cancelled
+    tasks:HashMap<tokio::task::Id,PathBuf>,
+    accounting:Accounting,
+    /// how far the entries being checked are
+    progress:HashMap<PathBuf,Progress>,
+    /// the bytes read by the checks done, for --adaptive
+    read_done:u64,
+    /// how many files are checked at once with --adaptive
+    adaptive:Option<adaptive::Adaptive>,
+    heartbeat:Duration,
+    deadline:deadline::Deadline,
+    /// --max-bytes, and the bytes of the files started so far
+    pub(crate) max_bytes:Option<u64>,
+    pub(crate) admitted_bytes:u64,
+    /// entries not added because we're stopping
+    not_attempted:Vec<Entry>,
+    /// files of cancelled checks, for release_unverified()
+    cancelled:Vec<PathBuf>,
+    thresholds:thresholds::Thresholds,
+    same_file:same_file::SameFile,
+    release_hardlinks:HardLinks,
+    /// mark results of hard links using the digest of another one, with -v
+    pub(crate) mark_hardlinks:bool,
+    journal:Option<Journal>,
+    /// the signatures checksum files need to be trusted
+    signatures:Option<signature::Verifier>,
+    /// check only a random sample of all entries
+    sample:Option<sample::Sampling>,
+    /// check the entries in random order, with this seed
+    shuffle:Option<u64>,
+    /// the files listed and the checksum files (absolute), for --check-extra
+    known:Option<HashSet<PathBuf>>,
+    /// the last status of each file for each checksum file, with --summary-per-manifest
+    per_manifest:Option<HashMap<Arc<Path>,HashMap<PathBuf,Status>>>,
+    /// the files verified by checksum file with their digest, name as listed and size and modification time, for --write-tcman
+    pub(crate) verified:Option<HashMap<Arc<Path>,Vec<tcman::Verified>>>,
+    /// the entries to add before the others, with --priority-file
+    pub(crate) priorities:Option<priority::Priorities>,
+    /// the results by directory, with --aggregate-by-dir
+    per_dir:Option<per_dir::PerDir>,
+    retry_pass:retry_pass::RetryPass,
+    /// files that changed while being read, to check again when they're stable for requeue_changed
+    changed:Vec<Entry>,
+    requeue_changed:Option<Duration>,
+    stream_digest:stream_digest::StreamDigest,
+    /// the lengths of the digests added, the results tell their algorithm once there are several
+    digest_lens:HashSet<usize>,
+    /// number of interrupts received
+    signals:watch::Receiver<u32>,
+    /// with --daemon, the job whose results these are
+    #[cfg(all(unix,feature="daemon"))]
+    job:Option<daemon::Running>,
+    /// with --daemon, the part of its budget the job takes (shared by the jobs checked at once)
+    #[cfg(all(unix,feature="daemon"))]
+    budget:Option<budget::Share>,
+    summary:Summary,
+    cur_size:u64,max_size:u64
+}
+
+impl Reader
+{
+    /// the files are read from the source given, or the local file system
+    fn new(options:&Options,signals:watch::Receiver<u32>,events:Events,digests:Digests,source:Option<Arc<dyn Source>>)->Reader
+    {
+        let open_policy = options.open_policy.clone();
+        let source = source.unwrap_or_else(||Arc::new(source::LocalFs{open_policy:open_policy.clone(),events:events.clone()}));
+        // URLs are read over HTTP whatever the source
+        #[cfg(feature="http")]
+        let http:Arc<dyn Source> = Arc::new(http::Http::new(options.insecure,options.http_requests,open_policy.clone(),events.clone()));
+        #[cfg(not(feature="http"))]
+        let http:Arc<dyn Source> = Arc::new(source::Without("http"));
+        let source = Arc::new(source::Schemes{default:source,schemes:source::URL_SCHEMES.iter().map(|scheme|(*scheme,http.clone())).collect()});
+        let command = |what,cmd:&Option<String>|command::configured(what,cmd.as_deref(),&options.commands);
+        Reader{
+            readers:JoinSet::new(),actions:actions::Actions::new(options),
+            prescan:options.prescan || options.order == Order::Physical,physical:options.order == Order::Physical,footers:options.footers,
+            declared_bytes:HashMap::new(),events:events.clone(),
+            online:hsm::Online::new(options),
+            pause:hsm::Pause::new(options),
+            fail_fast:options.fail_fast,
+            batch:batch::Batch::new(options.release_batch,options.release_batch_wait),
+            check:Arc::new(CheckOptions{
+                pre_hook:command("pre-hook",&options.pre_hook),
+                stage:command("stage",&options.stage),
+                open_policy,
+                resume_policy:options.resume_policy.clone(),
+                chunks:options.chunks,
+                stream:options.stream_digest.then(||Arc::new(std::sync::Mutex::new(Some(md5::Context::new())))),
+                digests,source,
+                // the buffers of files using all of max-size at most
+                buffers:BufferPool::new(options.buffer_size as usize,(options.max_size/options.buffer_size.max(1)) as usize),
+                restore_atime:options.restore_atime,
+                mount:Arc::new(mount::Mount::new(options.mount_wait)),
+                probe_bad_blocks:options.probe_bad_blocks,
+                events
+            }),
+            allow_oversize:options.allow_oversize,symlinks:options.symlinks,allow_special:options.allow_special,
+            confine:options.confine,source_url:options.source_url.clone(),path_compat:options.path_compat.clone(),
+            filters:filters::Filters::new(options),
+            print0:options.print0,results:VecDeque::new(),manifest_mtimes:HashMap::new(),failed_files:vec![],
+            lookahead:lookahead::Lookahead::new(options),
+            in_flight:HashMap::new(),tasks:HashMap::new(),
+            accounting:options.accounting,progress:HashMap::new(),heartbeat:options.heartbeat,read_done:0,
+            adaptive:options.adaptive.map(|(min,max,window,freeze)|adaptive::Adaptive::new(min,max,window,freeze)),
+            deadline:deadline::Deadline::new(options),
+            max_bytes:options.max_bytes,admitted_bytes:0,
+            not_attempted:vec![],cancelled:vec![],
+            thresholds:thresholds::Thresholds::new(options),
+            // the stream digest needs every listing read
+            same_file:same_file::SameFile::new(options),
+            release_hardlinks:options.release_hardlinks,mark_hardlinks:options.mark_hardlinks,
+            journal:None,signatures:None,
+            sample:options.sample.clone(),
+            shuffle:options.shuffle,
+            per_manifest:options.per_manifest.then(HashMap::new),
+            verified:options.write_tcman.then(HashMap::new),priorities:None,
+            per_dir:options.per_dir.map(per_dir::PerDir::new),
+            known:options.known.then(HashSet::new),
+            retry_pass:retry_pass::RetryPass::new(options),
+            changed:vec![],requeue_changed:options.requeue_changed,
+            stream_digest:stream_digest::StreamDigest::default(),digest_lens:HashSet::new(),
+            signals,
+            #[cfg(all(unix,feature="daemon"))]
+            job:None,
+            #[cfg(all(unix,feature="daemon"))]
+            budget:None,
+            summary:Summary{window:options.window,shuffle:options.shuffle,shard:options.shard,..Summary::default()},
+            cur_size:0,max_size:options.max_size
+        }
+    }
+    /// add the files (after querying their online status if configured)
+    async fn add_all(&mut self,entries:Vec<Entry>) -> Result<(),Error>
+    {
+        self.notice_threshold();
+        if self.stopping() {
+            self.not_attempted.extend(entries);
+            return Ok(());
+        }
+        let (cached,entries):(Vec<Entry>,Vec<Entry>) = entries.into_iter()
+            .partition(|e|self.journal.as_ref().is_some_and(|j|j.verified(&e.path,&e.expected)));
+        for entry in cached {
+            self.report_entry(&entry,Status::Cached);
+            self.summary.cached += 1;
+        }
+        let entries = group_algorithms(entries);
+        self.digest_lens.extend(entries.iter().flat_map(|e|std::iter::once(e).chain(&e.along)).map(|e|e.expected.len()));
+        let paths:Vec<PathBuf> = entries.iter().map(|e|e.file().to_path_buf()).collect();
+        let online = self.online.query(&paths).await;
+        for (entry,online) in entries.into_iter().zip(online) {
+            if !online && self.online.skip {
+                self.report_entry(&entry,Status::Offline);
+                self.summary.offline += 1;
+            } else {
+                self.queue(entry).await?;
+            }
+        }
+        Ok(())
+    }
+    /// add the entry, or with lookahead queue it (and stage it ahead) and add the oldest queued one
+    async fn queue(&mut self,entry:Entry) -> Result<(),Error>
+    {
+        if self.lookahead.depth == 0 {return self.add(entry,None).await;}
+        self.lookahead.push(entry);
+        while self.lookahead.full() {
+            if !self.add_pending().await? {break;}
+        }
+        self.stage_ahead();
+        Ok(())
+    }
+    /// add the oldest queued entry, returns false if there is none (or we're stopping)
+    async fn add_pending(&mut self) -> Result<bool,Error>
+    {
+        if self.stopping() {return Ok(false);}
+        let Some((entry,staging)) = self.lookahead.pop() else {return Ok(false)};
+        self.add(entry,staging).await?;
+        self.stage_ahead();
+        Ok(true)
+    }
+    /// start staging the queued entries that aren't yet, unless we're stopping
+    fn stage_ahead(&mut self)
+    {
+        let Some(stage) = self.check.stage.clone() else {return};
+        if self.stopping() {return}
+        self.lookahead.stage(&stage);
+    }
+    /// wait (while still processing finished checks) until the system is not busy anymore
+    async fn wait_while_busy(&mut self) -> Result<(),Error>
+    {
+        if !self.pause.busy().await {return Ok(());}
+        info!("system is busy, pausing");
+        let paused = Instant::now();
+        while !self.stopping() && self.pause.busy().await {
+            let recheck = self.pause.recheck();
+            if self.readers.is_empty() {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(recheck) => {},
+                    _ = signalled(&mut self.signals) => self.on_signal()
+                }
+            } else {
+                // finished checks are processed in the meantime, we just don't add new ones
+                if let Ok(next) = tokio::time::timeout_at(recheck,self.next()).await {next?;}
+            }
+        }
+        self.summary.paused += paused.elapsed();
+        info!("system is not busy anymore, resuming after {}",humantime::format_duration(Duration::from_secs(paused.elapsed().as_secs())));
+        Ok(())
+    }
+    /// don't add checks while the file system of the files is gone, finished checks are processed in the meantime
+    async fn wait_while_lost(&mut self) -> Result<(),Error>
+    {
+        // the checks waiting for it tell when it's back
+        while !self.stopping() && !self.readers.is_empty() && self.check.mount.lost_since().is_some() {
+            if let Ok(next) = tokio::time::timeout(mount::PROBE_INTERVAL,self.next()).await {next?;}
+        }
+        Ok(())
+    }
+    /// wait for the file system of path to come back after looking it up failed with e, false if it doesn't (or we're stopping)
+    async fn suspend(&mut self,path:&Path,e:&std::io::Error) -> bool
+    {
+        let mount = self.check.mount.clone();
+        let suspend = mount.suspend(path,e);
+        tokio::pin!(suspend);
+        loop {
+            tokio::select! {
+                back = &mut suspend => return back,
+                _ = signalled(&mut self.signals) => {
+                    self.on_signal();
+                    if self.stopping() {return false}
+                }
+            }
+        }
+    }
+    /// the file of the entry for the dry run, if it's read once for all entries of it
+    fn plan_id(&self,entry:&Entry) -> Option<FileId>
+    {
+        let path = entry.file();
+        if self.same_file.mode != Duplicates::Coalesce || entry.member.is_some() || !self.check.source.local(path) {return None}
+        let metadata = if self.symlinks == Symlinks::Follow {path.metadata()} else {path.symlink_metadata()};
+        file_id(path,&metadata.ok()?)
+    }
+    /// the metadata of the local file of the entry, None if it's not to be read (and was reported)
+    async fn local_metadata(&mut self,entry:&Entry) -> Result<Option<std::fs::Metadata>,Error>
+    {
+        let path = entry.file();
+        if self.symlinks != Symlinks::Follow && path.symlink_metadata().is_ok_and(|m|m.is_symlink()) {
+            self.summary.symlinks += 1;
+            if self.symlinks == Symlinks::Report {
+                let target = std::fs::read_link(path).unwrap_or_default();
+                self.report(&entry.path,Status::Symlink,Some(&target));
+            }
+            return Ok(None);
+        }
+        let metadata = match path.metadata() {
+            Err(e) if mount::lost(&e) => {
+                if !self.suspend(path,&e).await && self.stopping() {
+                    self.not_attempted.push(entry.clone());
+                    return Ok(None);
+                }
+                path.metadata()
+            }
+            metadata => metadata
+        };
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(e) if path.symlink_metadata().is_ok_and(|m|m.is_symlink()) => {
+                let target = std::fs::read_link(path).unwrap_or_default();
+                let e = Error::DanglingSymlink{path:entry.path.clone(),target,source:e};
+                return self.error(entry,Status::Error(Some("dangling-symlink")),e).map(|_|None);
+            }
+            Err(e) => {
+                let e = Error::Check{path:entry.path.clone(),source:e.into()};
+                return self.error(entry,Status::Error(None),e).map(|_|None);
+            }
+        };
+        // reading a FIFO would block forever
+        if !metadata.is_file() && !self.allow_special {
+            self.report_entry(entry,Status::NotAFile);
+            self.summary.not_files += 1;
+            return Ok(None);
+        }
+        Ok(Some(metadata))
+    }
+    /// add the entry, and those to read along with it (those are added on their own if it's not read)
+    async fn add(&mut self,mut entry:Entry,staging:Option<JoinHandle<bool>>) -> Result<(),Error>
+    {
+        let mut along = std::mem::take(&mut entry.along);
+        self.admit(entry,staging,&mut along).await?;
+        for entry in along {self.admit(entry,None,&mut vec![]).await?;}
+        Ok(())
+    }
+    /// check the entry once the budget allows, taking those of along that are read with it (the others are left)
+    async fn admit(&mut self,mut entry:Entry,staging:Option<JoinHandle<bool>>,along:&mut Vec<Entry>) -> Result<(),Error>
+    {
+        self.wait_while_busy().await?;
+        self.wait_while_lost().await?;
+        self.notice_threshold();
+        if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+        if self.batch.deadline().is_some_and(|deadline|deadline <= Instant::now()) {
+            self.flush_batch();
+        }
+        let path = entry.file().to_path_buf();
+        let (metadata,id,hardlink) = if self.check.source.local(&path) {
+            let Some(metadata) = self.local_metadata(&entry).await? else {return Ok(())};
+            // members of the same archive are different files
+            let id = if entry.member.is_none() {file_id(&path,&metadata)} else {None};
+            (SourceMetadata::from(&metadata),id,hardlinked(&metadata))
+        } else {
+            match self.check.source.metadata(&path).await {
+                Ok(metadata) => (metadata,None,false),
+                Err(e) => {
+                    // like URLs without the http feature
+                    let status = if e.kind() == std::io::ErrorKind::Unsupported {Status::Unsupported} else {Status::Error(None)};
+                    let e = Error::Check{path:entry.path.clone(),source:e.into()};
+                    return self.error(&entry,status,e);
+                }
+            }
+        };
+        let (filesize,mtime) = (metadata.size,metadata.modified);
+        if self.filters.by_time(mtime) {
+            debug!("'{}' is skipped as its modification time is out of the range given",path.to_string_lossy());
+            self.summary.filtered += 1;
+            return Ok(());
+        }
+        if self.filters.by_size(filesize) {
+            self.skip_size(&path,filesize);
+            return Ok(());
+        }
+        // a truncated or replaced file is noticed without reading it
+        if let Some(recorded) = entry.recorded {
+            if recorded.size != filesize {
+                self.summary.size_mismatches += 1;
+                let e = Error::SizeMismatch{path:entry.path.clone(),size:filesize,recorded:recorded.size};
+                return self.error(&entry,Status::SizeMismatch,e);
+            }
+            if let Some(mtime) = mtime.filter(|mtime|*mtime != recorded.mtime) {
+                warn!("'{}' was modified at {}, its extended checksum file lists {}",path.to_string_lossy(),
+                    humantime::format_rfc3339_nanos(mtime),humantime::format_rfc3339_nanos(recorded.mtime));
+                self.summary.mtime_mismatches += 1;
+            }
+        }
+        entry.size = filesize;
+        entry.mtime = mtime;
+        entry.id = id;
+        if let Some(id) = entry.id.clone() {
+            match self.same_file.mode {
+                Duplicates::Coalesce => loop {
+                    let len = entry.expected.len();
+                    if let Some(digest) = self.same_file.digest(&id,len) {
+                        info!("'{}' is a file checked before, using that result",path.to_string_lossy());
+                        self.duplicate(&entry,&digest,hardlink);
+                        return Ok(());
+                    }
+                    match self.same_file.running(&id) {
+                        Some(lens) if lens.contains(&len) => {
+                            info!("'{}' is a file being checked right now, using that result",path.to_string_lossy());
+                            self.same_file.wait(id,entry,hardlink);
+                            return Ok(());
+                        }
+                        // it's read again for this one once done
+                        Some(_) => {
+                            if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+                            debug!("'{}' is being checked with another algorithm right now, waiting for that",path.to_string_lossy());
+                            self.next().await?;
+                        }
+                        None => break
+                    }
+                }
+                Duplicates::Check => while self.same_file.running(&id).is_some() {
+                    if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+                    debug!("'{}' is a file being checked right now, waiting for that",entry.path.to_string_lossy());
+                    self.next().await?;
+                }
+            }
+        }
+        let path = &entry.path;
+
+        if filesize > self.max_size {
+            if !self.allow_oversize {
+                let e = Error::Oversize{path:entry.path.clone(),size:filesize,budget:self.max_size};
+                return self.error(&entry,Status::Error(Some("oversize")),e);
+            }
+            // it's checked alone, the files after it wait for it as it exceeds the budget on its own
+            info!("'{}' is bigger than the maximum buffer size, checking it alone",path.to_string_lossy());
+            while !self.readers.is_empty() {
+                if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+                self.next().await?;
+            }
+        }
+
+        // the stream digest needs the files one after the other
+        if self.check.stream.is_some() {
+            while !self.readers.is_empty() {
+                if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+                self.next().await?;
+            }
+        }
+        // with --adaptive also until fewer files than allowed are checked, the limit may be raised meanwhile
+        while self.adaptive.as_ref().is_some_and(|adaptive|self.readers.len() >= adaptive.limit) {
+            if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+            if let Ok(next) = tokio::time::timeout(PROGRESS_INTERVAL,self.next()).await {next?;}
+        }
+        // wait for files to finish (or progress) until we're within our size allowance
+        while self.used() + filesize > self.max_size && !self.readers.is_empty()
+        {
+            if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+            debug!("{} is waiting for other checks to finish",path.to_string_lossy());
+            match self.accounting {
+                Accounting::WholeFile => {self.next().await?;}
+                Accounting::Progressive => {
+                    let recheck = Instant::now()+PROGRESS_INTERVAL;
+                    if let Ok(next) = tokio::time::timeout_at(recheck,self.next()).await {next?;}
+                }
+            }
+        }
+        // and for the other jobs of the daemon, unless it's the only file checked
+        #[cfg(all(unix,feature="daemon"))]
+        while self.budget.as_ref().is_some_and(|budget|budget.others() > 0 && budget.others()+self.used()+filesize > budget.max())
+        {
+            if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+            debug!("{} is waiting for the checks of other jobs to finish",path.to_string_lossy());
+            match &self.budget {
+                Some(budget) if self.readers.is_empty() => budget.freed(PROGRESS_INTERVAL).await,
+                _ => if let Ok(next) = tokio::time::timeout(PROGRESS_INTERVAL,self.next()).await {next?;}
+            }
+        }
+        // the deadline might have passed while waiting
+        if self.stopping() {self.not_attempted.push(entry);return Ok(());}
+        if filesize > self.max_size {self.summary.oversize += 1;}
+        for other in along.iter_mut() {(other.size,other.mtime,other.id) = (filesize,mtime,entry.id.clone());}
+        entry.along = std::mem::take(along);
+        self.in_flight.insert(entry.path.clone(),entry.clone());
+        if let Some(id) = &entry.id {self.same_file.start(id,&entry);}
+        let options = self.check.clone();
+        let progress = Arc::new(AtomicU64::new(0));
+        let now = Instant::now();
+        self.progress.insert(entry.path.clone(),Progress{size:filesize,read:progress.clone(),started:now,beat:(now,0)});
+        let path = entry.path.clone();
+        self.events.emit(||Event::Admitted{path:path.clone(),size:filesize});
+        let task = self.readers.spawn(async {
+            let result = check_file(&entry,options,staging,progress).await;
+            (entry,result)
+        });
+        self.tasks.insert(task.id(),path);
+        self.cur_size += filesize;
+        #[cfg(all(unix,feature="daemon"))]
+        if let Some(budget) = &mut self.budget {budget.take(filesize);}
+        self.admitted_bytes += filesize;
+        Ok(())
+    }
+    async fn next(&mut self) -> Result<Option<(PathBuf,bool)>,Error>
+    {
+        while let Some(failed) = self.actions.running.try_join_next() {self.count_failures(failed?)}
+        let (mut entry,result) = loop {
+            let timer = self.timer();
+            tokio::select! {
+                joined = self.readers.join_next_with_id() => match joined {
+                    None => return Ok(None),
+                    Some(Ok((id,joined))) => {
+                        self.tasks.remove(&id);
+                        break joined
+                    }
+                    Some(Err(e)) => {
+                        let entry = self.tasks.remove(&e.id()).and_then(|path|self.in_flight.get(&path).cloned());
+                        if e.is_cancelled() {
+                            self.summary.cancelled += 1;
+                            if let Some(entry) = entry {self.cancelled(entry);}
+                        } else {
+                            // we don't know what the task was doing, so it's fatal
+                            let Some(entry) = entry else {return Err(e.into())};
+                            let panic = e.into_panic();
+                            let message = panic.downcast_ref::<&str>().map(|m|m.to_string())
+                                .or_else(||panic.downcast_ref::<String>().cloned()).unwrap_or_default();
+                            break (entry,Err(CheckError::Panic(message)))
+                        }
+                    }
+                },
+                _ = sleep_until(timer) => self.on_timer(),
+                _ = signalled(&mut self.signals) => self.on_signal()
+            }
+        };
+        self.done(&entry);
+        let along = std::mem::take(&mut entry.along);
+        match result
+        {
+            Ok(Checked{digest:computed,others,resumes,chunks,duration}) =>
+                {
+                    if resumes > 0 {
+                        warn!("reading '{}' was resumed {resumes} times",entry.path.to_string_lossy());
+                        self.summary.resumes += resumes as u64;
+                        self.summary.resumed_files += 1;
+                    }
+                    // archive members are not part of the stream
+                    if entry.member.is_none() {self.stream_digest.streamed += 1;}
+                    let ok = computed == entry.expected;
+                    if let Some(chunks) = chunks {self.report_chunks(&entry,ok,chunks);}
+                    let status = if ok {Status::Ok} else {Status::Fail};
+                    if ok {self.summary.ok += 1} else {self.summary.fail += 1}
+                    self.summary.bytes += entry.size;
+                    self.report_verdict(&entry,status,&computed,Some(duration),false);
+                    let computed:Vec<String> = std::iter::once(computed).chain(others).collect();
+                    self.note_failing(&entry,&along,&computed);
+                    self.finish(&entry,status,Some(&computed[0]),true);
+                    self.resolve_duplicates(&entry,along,&computed)?;
+                    if !ok {self.retry_pass.mismatched(&entry,status);}
+                    Ok(Some((entry.path,ok)))
+                }
+            Err(CheckError::PreHook) => {
+                self.summary.hook_skipped += 1;
+                self.report_entry(&entry,Status::HookSkipped);
+                self.resolve_duplicates(&entry,along,&[])?;
+                Ok(Some((entry.path,false)))
+            }
+            Err(CheckError::Changed) => {
+                self.report_entry(&entry,Status::Changed);
+                if self.requeue_changed.is_some() {
+                    info!("'{}' changed while being read, will check it again later",entry.path.to_string_lossy());
+                    // those read along are checked again with it
+                    self.changed.push(Entry{along,..entry.clone()});
+                    self.resolve_duplicates(&entry,vec![],&[])?;
+                } else {
+                    warn!("'{}' changed while being read",entry.path.to_string_lossy());
+                    self.summary.changed += 1;
+                    self.finish(&entry,Status::Changed,None,true);
+                    self.resolve_duplicates(&entry,along,&[])?;
+                }
+                Ok(Some((entry.path,false)))
+            }
+            Err(e) => {
+                let status = e.status();
+                self.error(&entry,status,Error::Check{path:entry.path.clone(),source:e})?;
+                self.resolve_duplicates(&entry,along,&[])?;
+                Ok(Some((entry.path,false)))
+            }
+        }
+    }
+    /// the entry is not being checked anymore, so it doesn't count against max-size
+    fn done(&mut self,entry:&Entry)
+    {
+        self.cur_size -= entry.size;
+        #[cfg(all(unix,feature="daemon"))]
+        if let Some(budget) = &mut self.budget {budget.give(entry.size);}
+        self.in_flight.remove(&entry.path);
+        if let Some(progress) = self.progress.remove(&entry.path) {self.read_done += progress.read.load(Ordering::Relaxed);}
+        if let Some(id) = &entry.id {self.same_file.done(id);}
+    }
+    /// report a cancelled check, the entry counts as not attempted
+    fn cancelled(&mut self,entry:Entry)
+    {
+        self.done(&entry);
+        self.cancelled.push(entry.file().to_path_buf());
+        for entry in entry.listings() {
+            self.report_entry(&entry,Status::Cancelled);
+            self.not_attempted.push(entry);
+        }
+    }
+    /// report the result for a file that was not read (with the symlink target if given)
+    fn report(&mut self,path:&Path,status:Status,target:Option<&Path>)
+    {
+        self.emit(CheckResult{target:target.map(Path::to_path_buf),..CheckResult::new(path.to_path_buf(),status)});
+    }
+    /// report the result for an entry that was not verified
+    fn report_entry(&mut self,entry:&Entry,status:Status)
+    {
+        self.emit(CheckResult{expected:Some(entry.expected.clone()),..CheckResult::new(entry.path.clone(),status)});
+    }
+    /// the path of a file listed in the checksum file in base, and the archive and member name if it's an archive member,
+    /// as translated by --path-compat
+    fn manifest_member(&self,base:&Path,name:&str) -> (PathBuf,Option<(PathBuf,Arc<str>)>)
+    {
+        if source::is_url(Path::new(name)) {return (name.into(),None);}
+        if let Some(url) = &self.source_url {
+            let name = self.path_compat.as_ref().map_or(name.into(),|compat|compat.translate(name));
+            return (format!("{}/{}",url.trim_end_matches('/'),name.trim_start_matches("./")).into(),None);
+        }
+        let Some(compat) = &self.path_compat else {return manifest_member(base,name)};
+        match manifest_member(base,&compat.translate(name)) {
+            (path,None) => (compat.resolve(path),None),
+            (_,Some((archive,member))) => {
+                let archive = compat.resolve(archive);
+                (format!("{}{}{member}",archive.to_string_lossy(),zip_member::SEPARATOR).into(),Some((archive,member)))
+            }
+        }
+    }
+    /// if the file of an entry listed in the checksum file in base may be checked
+    fn confined(&self,base:&Path,file:&Path) -> bool
+    {
+        // absolute URLs have no directory to be confined to, but the URL given
+        if self.source_url.is_none() && source::is_url(file) {return true;}
+        let base = self.source_url.as_deref().map_or(base,Path::new);
+        self.confine.is_none_or(|strict|confine::confined(base,file,strict))
+    }
+    /// report the verdict for a checked file, marked if the file is newer than its checksum file
+    fn report_verdict(&mut self,entry:&Entry,status:Status,computed:&str,duration:Option<Duration>,hardlink:bool)
+    {
+        let newer = self.newer_than_manifest(entry);
+        if newer {
+            self.summary.newer_than_manifest += 1;
+            if status == Status::Fail {self.summary.newer_failed += 1;}
+        }
+        self.emit(CheckResult{
+            expected:Some(entry.expected.clone()),computed:Some(computed.into()),size:Some(entry.size),duration,
+            newer_than_manifest:newer,hardlink,algorithm:self.check.digests.name_of(computed.len()),..CheckResult::new(entry.path.clone(),status)
+        });
+    }
+    fn emit(&mut self,result:CheckResult)
+    {
+        if result.status.failed() {self.failed_files.push((result.path.clone(),result.expected.clone()));}
+        if let Some(per_dir) = &mut self.per_dir {per_dir.add(&result);}
+        self.events.emit(||Event::Completed(result.clone()));
+        self.results.push_back(result);
+    }
+    /// the next result, checking the files added until one is there, None once all are done
+    ///
+    /// Results are only produced (and the files done make room in the budget) while this is awaited,
+    /// or while adding files waits for room.
+    async fn next_result(&mut self) -> Result<Option<CheckResult>,Error>
+    {
+        loop {
+            if let Some(result) = self.results.pop_front() {return Ok(Some(result));}
+            if self.add_pending().await? {continue}
+            // cancelled checks are reported when the last one is done
+            if self.next().await?.is_none() {return Ok(self.results.pop_front());}
+        }
+    }
+    /// if the file was modified after the checksum file listing it (the checksum file's time is looked up once)
+    fn newer_than_manifest(&mut self,entry:&Entry) -> bool
+    {
+        let manifest = self.manifest_mtimes.entry(entry.manifest.clone())
+            .or_insert_with(||entry.manifest.metadata().and_then(|m|m.modified()).ok());
+        entry.mtime.zip(*manifest).is_some_and(|(mtime,manifest)|mtime > manifest)
+    }
+    /// if the checksum file's signature verifies (or none is needed), rejected ones are reported
+    async fn trusted(&mut self,manifest:&Path,content:&[u8]) -> bool
+    {
+        let Some(signatures) = &self.signatures else {return true};
+        match signatures.verify(manifest,content).await {
+            Ok(kind) => {
+                info!("signature of '{}' verified ({kind})",manifest.to_string_lossy());
+                self.report(manifest,Status::Signed(kind),None);
+                self.summary.signed += 1;
+                true
+            }
+            Err(reason) => {
+                error!("rejecting '{}': {reason}",manifest.to_string_lossy());
+                self.report(manifest,Status::Rejected,None);
+                self.summary.rejected += 1;
+                false
+            }
+        }
+    }
+    /// report and count a line of the checksum file that could not be parsed
+    fn invalid_line(&mut self,manifest:&Path,line:u64,kind:&'static str)
+    {
+        error!("{}",Error::ManifestParse{file:manifest.into(),line,kind});
+        self.summary.invalid_lines += 1;
+        self.events.emit(||Event::InvalidLine{manifest:manifest.into(),line,reason:kind});
+    }
+    /// the digests and names (as translated by --path-compat) of the entries of the checksum file, in the order listed
+    ///
+    /// Lines that can't be parsed are reported and counted, a checksum file whose signature is rejected lists none.
+    async fn entries(&mut self,manifest:&Path) -> Result<Vec<(String,String)>,Error>
+    {
+        let content = tokio::fs::read_to_string(manifest).await
+            .map_err(|source|Error::Io{action:"read",path:manifest.into(),source})?;
+        if !self.trusted(manifest,content.as_bytes()).await {return Ok(vec![]);}
+        let mut entries = vec![];
+        let mut format = tcman::Format::default();
+        for (nr,line) in content.lines().enumerate() {
+            let (digest,filename) = match format.parse(nr as u64+1,line,&self.check.digests) {
+                Ok(Some((digest,_,filename))) => (digest,filename),
+                Ok(None) => continue,
+                Err(kind) => {
+                    self.invalid_line(manifest,nr as u64+1,kind);
+                    continue
+                }
+            };
+            let filename = match &self.path_compat {
+                Some(compat) => compat.translate(filename).into_owned(),
+                None => filename.into()
+            };
+            entries.push((digest.into(),filename));
+        }
+        Ok(entries)
+    }
+    /// the entries are checked again, so they don't count as failed (yet)
+    fn forget_failed(&mut self,entries:&[Entry])
+    {
+        let paths:HashSet<&Path> = entries.iter().map(|e|e.path.as_path()).collect();
+        self.failed_files.retain(|(p,_)|!paths.contains(p.as_path()));
+    }
+    /// log which chunks of the file didn't match
+    fn report_chunks(&mut self,entry:&Entry,ok:bool,chunks:chunks::Checked)
+    {
+        let path = entry.path.to_string_lossy();
+        if chunks.mismatched.is_empty() {
+            if !ok {info!("all {} chunks of '{path}' match, the damage is outside of the chunks listed",chunks.count);}
+            return;
+        }
+        self.summary.bad_chunks += chunks.mismatched.len() as u64;
+        self.summary.chunked_files += 1;
+        let list:Vec<String> = chunks.mismatched.iter().map(|i|i.to_string()).collect();
+        let message = format!("chunks {} of '{path}' don't match (of {} chunks of {} bytes)",list.join(","),chunks.count,chunks.size);
+        // if the whole file matches, rather the chunk digests are wrong
+        if ok {warn!("{message}")} else {error!("{message}")}
+    }
+    /// report the entries read along with this one, and those waiting for its result (as they're the same file)
+    ///
+    /// Each gets the digest of its algorithm from those computed (none if it wasn't read).
+    fn resolve_duplicates(&mut self,entry:&Entry,along:Vec<Entry>,computed:&[String]) -> Result<(),Error>
+    {
+        let mut waiting = entry.id.as_ref().map(|id|self.same_file.take_waiting(id)).unwrap_or_default();
+        if let Some(id) = &entry.id {self.same_file.remember(id,computed);}
+        waiting.splice(0..0,along.into_iter().map(|entry|(entry,false)));
+        for (duplicate,hardlink) in waiting {
+            match computed.iter().find(|digest|digest.len() == duplicate.expected.len()) {
+                Some(digest) => self.duplicate(&duplicate,digest,hardlink),
+                None => {
+                    let e = Error::Duplicate{path:duplicate.path.clone(),original:entry.path.clone()};
+                    self.error(&duplicate,Status::Error(Some("duplicate")),e)?
+                }
+            }
+        }
+        Ok(())
+    }
+    /// remember the file of the entry as failing, if it, one read along or one waiting for it doesn't match the digests computed
+    ///
+    /// That's before its commands run, so a file listed in one checksum file with a digest that verifies, and in another with
+    /// one that doesn't, is neither released nor deleted.
+    fn note_failing(&mut self,entry:&Entry,along:&[Entry],computed:&[String])
+    {
+        let Some(id) = &entry.id else {return};
+        let failing = std::iter::once(entry).chain(along).chain(self.same_file.waiting(id)).any(|e|!computed.contains(&e.expected));
+        if failing {self.same_file.fail(id);}
+    }
+    /// report an entry for a file that was already checked with its digest
+    ///
+    /// The release and deletion was done (or not) when the file was checked, but post-hook and on-fail run for
+    /// entries failing. Unless it has several hard links and all commands are run for each path.
+    fn duplicate(&mut self,entry:&Entry,digest:&str,hardlink:bool)
+    {
+        let ok = digest == entry.expected;
+        let status = if ok {Status::Ok} else {Status::Fail};
+        if ok {self.summary.ok += 1} else {self.summary.fail += 1}
+        self.summary.duplicates += 1;
+        if hardlink {self.summary.hardlinks += 1;}
+        if !ok {
+            if let Some(id) = &entry.id {self.same_file.fail(id);}
+        }
+        self.report_verdict(entry,status,digest,None,hardlink);
+        if hardlink && self.release_hardlinks == HardLinks::Each {self.finish(entry,status,Some(digest),true)}
+        else if !ok {self.finish(entry,status,Some(digest),false)}
+        else {self.record(entry,status,Some(digest))}
+    }
+    /// count and report a file that could not be checked, that only ends the run with --fail-fast
+    fn error(&mut self,entry:&Entry,status:Status,e:Error) -> Result<(),Error>
+    {
+        self.summary.error += 1;
+        let mut result = CheckResult{expected:Some(entry.expected.clone()),..CheckResult::new(entry.path.clone(),status)};
+        if let Error::Check{source:CheckError::Read{offset,bad,..},..} = &e {(result.failed_at,result.bad_extent) = (Some(*offset),*bad);}
+        self.emit(result);
+        self.finish(entry,status,None,true);
+        self.retry_pass.errored(entry,status);
+        if self.fail_fast {return Err(e);}
+        error!("{e}");
+        Ok(())
+    }
+    /// spawn the commands configured for the given verdict, without release (and deletion) for a file released already
+    ///
+    /// They run in the background (at most max_release_procs at a time), join() waits for them.
+    fn finish(&mut self,entry:&Entry,status:Status,digest:Option<&str>,release:bool)
+    {
+        let path = entry.file();
+        self.record(entry,status,digest);
+        // the other members of the archive might still have to be checked
+        let keep = entry.member.is_some() || !release;
+        // verified for this entry, but not for another one of the same file
+        let listed_failing = entry.id.as_ref().is_some_and(|id|self.same_file.failing(id));
+        if status == Status::Ok && listed_failing && !keep && (self.actions.delete_after_verify || !self.actions.release.is_empty()) {
+            warn!("'{}' is listed elsewhere with a digest that failed, not releasing or deleting it",path.to_string_lossy());
+        }
+        let verified = status == Status::Ok && !listed_failing;
+        if self.actions.delete_after_verify && verified && !keep {self.delete(path);}
+        let mut commands = vec![];
+        if let Some(post_hook) = &self.actions.post_hook {
+            commands.push((Action::PostHook,post_hook.clone()));
+        }
+        if !self.actions.release.is_empty() && !keep {
+            if verified || self.actions.release_on_fail {
+                if self.batch.size > 1 {
+                    debug!("queueing '{}' (status {status}) for release",path.to_string_lossy());
+                    self.events.emit(||Event::Batched{path:path.to_path_buf()});
+                    if self.batch.push(path.to_path_buf()) {self.flush_batch();}
+                } else {
+                    debug!("releasing '{}' (status {status})",path.to_string_lossy());
+                    commands.extend(self.actions.release_chain());
+                }
+            } else {
+                debug!("not releasing '{}' as its status is {status}",path.to_string_lossy());
+            }
+        }
+        if let Some(on_fail) = self.actions.on_fail.as_ref().filter(|_|status != Status::Ok) {
+            debug!("running on-fail for '{}' (status {status})",path.to_string_lossy());
+            commands.push((Action::OnFail,on_fail.clone()));
+        }
+        if !commands.is_empty() {
+            self.actions.spawn(commands,vec![path.to_path_buf()],entry.env(Some(status),digest),&self.events);
+        }
+    }
+    /// delete the file verified, unless it's not a local file or a symlink
+    ///
+    /// Deleting a symlink (read with --symlinks follow) leaves the data verified, and its target may be listed elsewhere
+    /// or be outside the directory, so neither is deleted. A failed deletion doesn't change the verdict.
+    fn delete(&mut self,path:&Path)
+    {
+        if !self.check.source.local(path) {
+            warn!("not deleting '{}', it is not a local file",path.to_string_lossy());
+        } else if path.symlink_metadata().is_ok_and(|metadata|metadata.is_symlink()) {
+            warn!("not deleting '{}', it is a symlink",path.to_string_lossy());
+        } else {
+            match std::fs::remove_file(path) {
+                Ok(()) => {
+                    info!("deleted '{}'",path.to_string_lossy());
+                    self.summary.deleted += 1;
+                }
+                Err(e) => {
+                    error!("failed to delete '{}': {e}",path.to_string_lossy());
+                    self.summary.delete_failures += 1;
+                }
+            }
+        }
+    }
+    /// record the result in the journal
+    fn record(&mut self,entry:&Entry,status:Status,digest:Option<&str>)
+    {
+        if let Some(per_manifest) = &mut self.per_manifest {
+            per_manifest.entry(entry.manifest.clone()).or_default().insert(entry.path.clone(),status);
+        }
+        // archive members have the size and time of the archive, remote files aren't listed by path
+        if let (Some(verified),Status::Ok,Some(digest),Some(mtime),None) = (&mut self.verified,status,digest,entry.mtime,&entry.member) {
+            if self.check.source.local(&entry.path) {
+                let name = entry.path.strip_prefix(manifest_dir(&entry.manifest)).unwrap_or(&entry.path).to_string_lossy().into_owned();
+                verified.entry(entry.manifest.clone()).or_default().push((digest.into(),name,tcman::Recorded{size:entry.size,mtime}));
+            }
+        }
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.record(&entry.path,&entry.expected,digest,status) {
+                error!("failed to record '{}' in the journal: {e}",entry.path.to_string_lossy());
+            }
+        }
+    }
+    /// release all files queued for batch release
+    fn flush_batch(&mut self)
+    {
+        let paths = self.batch.take();
+        if !self.actions.release.is_empty() && !paths.is_empty() {
+            self.events.emit(||Event::BatchFlushed{files:paths.len()});
+            // per-file variables don't make sense for a batch
+            self.actions.spawn(self.actions.release_chain(),paths,vec![],&self.events);
+        }
+    }
+    fn count_failures(&mut self,(failed,hook_failed):(u64,u64))
+    {
+        self.summary.release_failures += failed;
+        self.summary.post_hook_failures += hook_failed;
+    }
+    /// the next time on_timer() has something to do
+    fn timer(&self) -> Option<Instant>
+    {
+        let deadline = self.deadline.pending();
+        let window = self.adaptive.as_ref().and_then(adaptive::Adaptive::window_end);
+        self.batch.deadline().into_iter().chain(deadline).chain(self.next_heartbeat()).chain(window).min()
+    }
+    fn on_timer(&mut self)
+    {
+        let now = Instant::now();
+        if self.next_heartbeat().is_some_and(|heartbeat|heartbeat <= now) {self.heartbeats(now);}
+        if self.adaptive.as_ref().and_then(adaptive::Adaptive::window_end).is_some_and(|end|end <= now) {
+            let read = self.read_done+self.progress.values().map(|progress|progress.read.load(Ordering::Relaxed)).sum::<u64>();
+            let running = self.readers.len();
+            if let Some(adaptive) = &mut self.adaptive {adaptive.adjust(now,read,running);}
+        }
+        if self.batch.deadline().is_some_and(|deadline|deadline <= now) {
+            debug!("release batch waited for {:?}, releasing it now",self.batch.wait.unwrap_or_default());
+            self.flush_batch()
+        }
+        if self.deadline.reached(now) {
+            self.events.emit(||Event::DeadlineReached{cancelling:self.deadline.cancel});
+            if self.deadline.cancel {self.cancel();}
+        }
+    }
+    /// wait until the file wasn't modified for requeue_changed
+    async fn wait_stable(&mut self,path:&Path)
+    {
+        let stable = self.requeue_changed.unwrap_or_default();
+        while !self.stopping() {
+            let age = path.metadata().and_then(|m|m.modified())
+                .map(|mtime|mtime.elapsed().unwrap_or_default()).unwrap_or(stable);
+            if age >= stable {return;}
+            debug!("waiting for '{}' to not change for {}",path.to_string_lossy(),humantime::format_duration(stable));
+            tokio::select! {
+                _ = tokio::time::sleep(stable-age) => {},
+                _ = signalled(&mut self.signals) => self.on_signal()
+            }
+        }
+    }
+    /// take the files to check again, they're not counted as failed anymore
+    fn take_failed(&mut self) -> Vec<Entry>
+    {
+        self.retry_pass.take().into_iter().map(|(entry,status)|{
+            if let Some(id) = &entry.id {self.same_file.forget(id);}
+            if status == Status::Fail {self.summary.fail -= 1} else {self.summary.error -= 1}
+            if status == Status::SizeMismatch {self.summary.size_mismatches -= 1;}
+            if status == Status::Fail && self.newer_than_manifest(&entry) {
+                self.summary.newer_than_manifest -= 1;
+                self.summary.newer_failed -= 1;
+            }
+            entry
+        }).collect()
+    }
+    /// if an interrupt was received, the deadline passed, --max-bytes was used up or a threshold was exceeded, no new files are added then
+    fn stopping(&self) -> bool {self.interrupted() || self.deadline_passed() || self.budget_spent() || self.threshold_exceeded().is_some()}
+    fn budget_spent(&self) -> bool {self.max_bytes.is_some_and(|max|self.admitted_bytes >= max)}
+    /// the bytes of the files of the entries, looked up for those not looked at yet (each file once)
+    async fn remaining_bytes(&self,entries:&[Entry]) -> u64
+    {
+        let mut files = HashSet::new();
+        let mut bytes = 0;
+        for entry in entries {
+            if !files.insert(entry.file()) {continue}
+            bytes += if entry.size > 0 {entry.size} else {
+                self.check.source.metadata(entry.file()).await.map_or(0,|metadata|metadata.size)
+            };
+        }
+        bytes
+    }
+    fn interrupted(&self) -> bool {*self.signals.borrow() > 0}
+    fn deadline_passed(&self) -> bool {self.deadline.passed()}
+    /// if the entry is in the shard, by the md5 of the path as listed (so it's the same on every host)
+    fn in_shard(&mut self,listed:&str) -> bool
+    {
+        let Some((i,n)) = self.summary.shard else {return true};
+        self.summary.listed_for_shard += 1;
+        let hash = md5::compute(listed.as_bytes());
+        let inside = u64::from_be_bytes(hash.0[..8].try_into().expect("8 bytes"))%n == i-1;
+        if inside {self.summary.in_shard += 1;}
+        inside
+    }
+    /// if the file of an entry is within --skip/--limit (counting those outside)
+    fn in_window(&mut self,path:&Path) -> bool
+    {
+        let inside = self.filters.in_window(path).unwrap_or(true);
+        if !inside {self.summary.outside_window += 1;}
+        inside
+    }
+    /// with --watch: the checksum file's files are all done, record it in the journal, returns its numbers of OK, FAIL and ERROR
+    fn manifest_done(&mut self,manifest:&Path) -> (usize,usize,usize)
+    {
+        let files = self.per_manifest.as_mut().and_then(|per_manifest|per_manifest.remove(manifest)).unwrap_or_default();
+        let counts = manifest_counts(&files);
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.record_manifest(manifest,counts) {error!("failed to write to the journal: {e}");}
+        }
+        counts
+    }
+    /// count a file skipped by its size
+    fn skip_size(&mut self,path:&Path,size:u64)
+    {
+        debug!("'{}' is skipped as its size {size} is out of the range given",path.to_string_lossy());
+        self.summary.size_filtered += 1;
+        self.summary.size_filtered_bytes += size;
+    }
+    /// finish the stream digest
+    fn finish_stream(&mut self)
+    {
+        let Some(stream) = &self.check.stream else {return};
+        let Some(context) = stream.lock().expect("stream digest lock").take() else {return};
+        self.stream_digest.finish(context);
+    }
+    /// which of max-failures, max-errors and max-failure-rate was exceeded (with the counts)
+    fn threshold_exceeded(&self) -> Option<String> {self.thresholds.exceeded(&self.summary)}
+    /// announce once that a threshold was exceeded
+    fn notice_threshold(&mut self)
+    {
+        if self.thresholds.noticed {return;}
+        if let Some(reason) = self.threshold_exceeded() {
+            self.events.emit(||Event::ThresholdExceeded{reason});
+            self.thresholds.noticed = true;
+        }
+    }
+    /// on the second interrupt the running checks (and staging ahead) are cancelled
+    fn on_signal(&mut self)
+    {
+        if *self.signals.borrow() > 1 {self.cancel();}
+    }
+    fn cancel(&mut self)
+    {
+        self.readers.abort_all();
+        self.lookahead.abort();
+    }
+    /// the entries that were not checked (after stopping): not added, pending or cancelled
+    fn not_attempted(&self) -> Vec<Entry>
+    {
+        self.not_attempted.iter().cloned()
+            .chain(self.lookahead.entries().cloned())
+            .chain(self.in_flight.values().cloned())
+            .flat_map(Entry::listings)
+            .collect()
+    }
+    /// with --daemon, count the results of the reader of a job done (or cancelled) for the daemon
+    ///
+    /// What's left of the job is only kept if the daemon was interrupted (for --remaining and --release-unverified),
+    /// the files staged ahead for it are not waited for otherwise.
+    #[cfg(all(unix,feature="daemon"))]
+    fn absorb(&mut self,mut job:Reader)
+    {
+        self.summary.add(&job.summary);
+        self.failed_files.append(&mut job.failed_files);
+        if let (Some(per_manifest),Some(done)) = (&mut self.per_manifest,job.per_manifest.take()) {per_manifest.extend(done);}
+        if let (Some(verified),Some(done)) = (&mut self.verified,job.verified.take()) {
+            for (manifest,mut files) in done {verified.entry(manifest).or_default().append(&mut files);}
+        }
+        if let (Some(per_dir),Some(done)) = (&mut self.per_dir,job.per_dir.take()) {per_dir.merge(done);}
+        if let (Some(priorities),Some(done)) = (&mut self.priorities,job.priorities.take()) {priorities.merge(done);}
+        if self.interrupted() {
+            self.not_attempted.append(&mut job.not_attempted);
+            self.lookahead.append(&mut job.lookahead);
+            self.in_flight.extend(job.in_flight.drain());
+            self.cancelled.append(&mut job.cancelled);
+        } else {
+            job.lookahead.abort();
+        }
+    }
+    /// forget the digests of the files checked, so files listed again later (rewritten meanwhile maybe) are read again
+    fn forget_digests(&mut self)
+    {
+        self.same_file.forget_all();
+    }
+    /// the part of max-size used by the files being checked
+    ///
+    /// With progressive accounting the bytes already read of them are not counted (files growing while being read don't count less than nothing).
+    fn used(&self) -> u64
+    {
+        if self.accounting != Accounting::Progressive {return self.cur_size}
+        let read:u64 = self.progress.values().map(|progress|progress.read.load(Ordering::Relaxed).min(progress.size)).sum();
+        self.cur_size - read
+    }
+    /// when the next heartbeat is due (for the file checked the longest since the last one)
+    fn next_heartbeat(&self) -> Option<Instant>
+    {
+        if self.heartbeat.is_zero() {return None}
+        self.progress.values().map(|progress|progress.beat.0+self.heartbeat).min()
+    }
+    /// log how far the files checked for longer than --heartbeat since the last heartbeat are
+    fn heartbeats(&mut self,now:Instant)
+    {
+        for (path,progress) in self.progress.iter_mut().filter(|(_,progress)|progress.beat.0+self.heartbeat <= now) {
+            let read = progress.read.load(Ordering::Relaxed);
+            let rate = read.saturating_sub(progress.beat.1) as f64/MEGABYTE as f64/(now-progress.beat.0).as_secs_f64();
+            let percent = if progress.size > 0 {read as f64*100.0/progress.size as f64} else {100.0};
+            info!("'{}' is being checked for {}: {:.2}G of {:.2}G read ({percent:.1}%), {rate:.1}M/s over the last {}",
+                path.to_string_lossy(),humantime::format_duration(Duration::from_secs((now-progress.started).as_secs())),
+                read as f64/GIGABYTE as f64,progress.size as f64/GIGABYTE as f64,
+                humantime::format_duration(Duration::from_secs((now-progress.beat.0).as_secs())));
+            progress.beat = (now,read);
+        }
+    }
+    /// compare the number of entries the checksum file lists with the one its footer declares, the bytes are left for the pre-scan
+    fn check_footer(&mut self,manifest:&Arc<Path>,footer:Footer,listed:u64)
+    {
+        if !self.footers {return}
+        if let Some(files) = footer.files.filter(|files|*files != listed) {
+            error!("'{}' lists {listed} entries, but its footer declares {files}, it may be truncated",manifest.to_string_lossy());
+            self.summary.footer_mismatches += 1;
+            return
+        }
+        match footer.bytes {
+            Some(bytes) if self.prescan => {self.declared_bytes.insert(manifest.clone(),(bytes,listed));}
+            Some(_) => debug!("the bytes declared by '{}' are only checked with --prescan",manifest.to_string_lossy()),
+            None => {}
+        }
+    }
+    /// stat the entries for the total to check, returns false if the run was interrupted meanwhile
+    ///
+    /// The size of files listed more than once counts once, files that can't be stat'ed count without size.
+    /// The bytes declared by the footers of the checksum files are checked then.
+    async fn prescan_entries(&mut self,entries:&mut [Entry]) -> bool
+    {
+        let (mut sizes,mut bytes) = (HashMap::new(),0);
+        let mut positions = HashMap::new();
+        // the entries, bytes and if all files could be stat'ed by checksum file, for the footers
+        let mut per_manifest:HashMap<&Arc<Path>,(u64,u64,bool)> = HashMap::new();
+        let mut seen_in_manifest = HashSet::new();
+        let mut next_progress = Instant::now()+PRESCAN_PROGRESS_INTERVAL;
+        for (done,entry) in entries.iter().enumerate() {
+            if self.interrupted() {
+                self.events.emit(||Event::PrescanInterrupted{entries:done as u64,of:entries.len() as u64});
+                return false;
+            }
+            let size = match sizes.get(entry.file()) {
+                Some(size) => *size,
+                None => {
+                    let size = self.check.source.metadata(entry.file()).await.ok().map(|meta|meta.size);
+                    bytes += size.unwrap_or_default();
+                    if self.physical && self.check.source.local(entry.file()) {
+                        positions.insert(entry.file().to_path_buf(),physical::position(entry.file()));
+                    }
+                    *sizes.entry(entry.file()).or_insert(size)
+                }
+            };
+            let listed = per_manifest.entry(&entry.manifest).or_insert((0,0,true));
+            listed.0 += 1;
+            if seen_in_manifest.insert((&entry.manifest,entry.file())) {
+                match size {Some(size) => listed.1 += size,None => listed.2 = false}
+            }
+            if Instant::now() >= next_progress {
+                self.events.emit(||Event::PrescanProgress{entries:done as u64,of:entries.len() as u64,bytes});
+                next_progress = Instant::now()+PRESCAN_PROGRESS_INTERVAL;
+            }
+        }
+        self.events.emit(||Event::Prescanned{files:entries.len() as u64,bytes});
+        for (manifest,(declared,listed)) in std::mem::take(&mut self.declared_bytes) {
+            match per_manifest.get(&manifest) {
+                Some((entries,bytes,true)) if *entries == listed && *bytes != declared => {
+                    error!("'{}' lists files of {bytes} bytes, but its footer declares {declared}, it may be truncated",manifest.to_string_lossy());
+                    self.summary.footer_mismatches += 1;
+                }
+                Some((entries,_,true)) if *entries == listed => {}
+                _ => debug!("not all files of '{}' are stat'ed, the bytes of its footer are not checked",manifest.to_string_lossy())
+            }
+        }
+        if self.physical {
+            let located = positions.values().filter(|position|position.is_some()).count();
+            self.events.emit(||Event::PhysicalOrder{located,files:sizes.len()});
+            // those not located come last, as listed
+            entries.sort_by_cached_key(|entry|{
+                let position = positions.get(entry.file()).copied().flatten();
+                (position.is_none(),position)
+            });
+        }
+        true
+    }
+    /// the configured commands
+    fn commands(&self) -> Vec<(&'static str,&ExtCommand)>
+    {
+        [
+            ("pre-hook",&self.check.pre_hook),("stage",&self.check.stage),
+            ("post-hook",&self.actions.post_hook),("on-fail",&self.actions.on_fail),
+            ("online-check",&self.online.command),("pause-when",&self.pause.command)
+        ].into_iter().filter_map(|(what,cmd)|Some((what,cmd.as_deref()?)))
+            .chain(self.actions.release.iter().map(|release|("release",release.as_ref()))).collect()
+    }
+    /// release the files that were staged ahead or being checked, but not verified (for an aborted run)
+    fn release_unverified(&mut self)
+    {
+        if self.actions.release.is_empty() {return}
+        // don't release files that are still being read
+        self.readers.abort_all();
+        let mut paths:Vec<PathBuf> = self.in_flight.drain().map(|(_,entry)|entry.file().to_path_buf()).collect();
+        paths.append(&mut self.cancelled);
+        paths.append(&mut self.lookahead.abort_staged());
+        // several members of the same archive
+        paths.sort();
+        paths.dedup();
+        if paths.is_empty() {return;}
+        info!("releasing {} unverified files",paths.len());
+        for batch in paths.chunks(self.batch.size) {
+            self.actions.spawn(self.actions.release_chain(),batch.to_vec(),vec![],&self.events);
+        }
+    }
+    async fn join(&mut self) -> Result<(),Error>
+    {
+        while self.add_pending().await? {}
+        // files not added because we're stopping stay pending for release_unverified
+        while self.next().await?.is_some() {}
+        self.join_releases().await
+    }
+    /// release the remaining batch and wait for all running release/on-fail commands
+    async fn join_releases(&mut self) -> Result<(),Error>
+    {
+        self.flush_batch();
+        loop {
+            let joined = match self.timer() {
+                Some(deadline) => match tokio::time::timeout_at(deadline,self.actions.running.join_next()).await {
+                    Ok(joined) => joined,
+                    Err(_) => {self.on_timer();continue}
+                },
+                None => self.actions.running.join_next().await
+            };
+            match joined {
+                Some(failed) => self.count_failures(failed?),
+                None => return Ok(())
+            }
+        }
+    }
+}
+
+/// wait until the deadline, or forever if there is none
+async fn sleep_until(deadline:Option<Instant>)
+{
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await
+    }
+}
+/// wait for the next interrupt
+async fn signalled(signals:&mut watch::Receiver<u32>)
+{
+    if signals.changed().await.is_err() {std::future::pending().await}
+}
+
+static GIGABYTE:u64 = 1<<30;
+static MEGABYTE:u64 = 1<<20;
+static SLEEP_DURATION:Duration = Duration::new(10,0);
+/// how often the pre-scan shows how far it got
+static PRESCAN_PROGRESS_INTERVAL:Duration = Duration::from_secs(10);
+/// how often waiting files look at the progress of the running ones with progressive accounting
+static PROGRESS_INTERVAL:Duration = Duration::from_secs(1);
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn reader(options:Options) -> Reader
+    {
+        CheckerBuilder::with(options,Digests::default(),None,Events::default()).build().unwrap().into_parts().0
+    }
+
+    #[test]
+    fn manifest_paths_are_in_the_base()
+    {
+        // on Windows the "/" of manifests written on Unix are separators as well
+        let path = manifest_path(Path::new("archive"),"run1/file.dat");
+        assert_eq!(path,Path::new("archive").join("run1").join("file.dat"));
+        assert_eq!(path.components().count(),3);
+        assert_eq!(manifest_path(Path::new(""),"file.dat"),Path::new("file.dat"));
+        let (path,member) = manifest_member(Path::new("archive"),"run1/data.zip//a.txt");
+        assert_eq!(path,Path::new("archive").join("run1").join("data.zip//a.txt"));
+        let (archive,name) = member.unwrap();
+        assert_eq!((archive,&*name),(Path::new("archive").join("run1").join("data.zip"),"a.txt"));
+    }
+
+    #[test]
+    fn manifests_without_parent_are_in_the_current_directory()
+    {
+        assert_eq!(manifest_base(Path::new("md5sum")),Path::new(""));
+        assert_eq!(manifest_base(Path::new("/")),Path::new(""));
+        assert_eq!(manifest_base(Path::new("..")),Path::new(""));
+        assert_eq!(manifest_base(Path::new("/md5sum")),Path::new("/"));
+        assert_eq!(manifest_base(Path::new("run1/md5sum")),Path::new("run1"));
+        assert_eq!(manifest_dir(Path::new("md5sum")),manifest_base(Path::new("md5sum")));
+        // the files listed stay as they are
+        assert_eq!(manifest_path(manifest_base(Path::new("md5sum")),"file.dat"),Path::new("file.dat"));
+    }
+
+    #[test]
+    fn short_lines_are_errors()
+    {
+        let digests = Digests::default();
+        let digest = "b1946ac92492d2347c6235b4d2611184";
+        assert_eq!(parse_line(&format!("{digest}  hello"),&digests),Ok(Some((digest,"hello"))));
+        assert_eq!(parse_line("  ",&digests),Ok(None));
+        assert_eq!(parse_line("# files: 2",&digests),Ok(None));
+        assert!(parse_line("b1946ac9",&digests).is_err());
+        assert!(parse_line("b1946ac9 hello",&digests).is_err());
+        assert!(parse_line("é",&digests).is_err());
+        assert_eq!(parse_line(digest,&digests),Err("no file name after the checksum"));
+        assert_eq!(parse_line(&format!("{digest}  "),&digests),Err("no file name after the checksum"));
+    }
+
+    #[test]
+    fn progressive_budget_is_never_negative()
+    {
+        let mut reader = reader(Options{accounting:Accounting::Progressive,..Options::default()});
+        let now = Instant::now();
+        let mut admit = |path:&str,size:u64,read:u64|{
+            let read = Arc::new(AtomicU64::new(read));
+            reader.progress.insert(path.into(),Progress{size,read,started:now,beat:(now,0)});
+            reader.cur_size += size;
+        };
+        admit("a",100,40);
+        // grown while being read
+        admit("b",50,80);
+        assert_eq!(reader.used(),60);
+        reader.accounting = Accounting::WholeFile;
+        assert_eq!(reader.used(),150);
+    }
+
+    #[tokio::test]
+    async fn retries_count_once_and_skip_oversize_files()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let mut reader = reader(Options{retry_failed:1,max_size:4,..Options::default()});
+        let entry = |name:&str,recorded|{
+            std::fs::write(dir.path().join(name),"hello\n").unwrap();
+            Entry{
+                path:dir.path().join(name),expected:"b1946ac92492d2347c6235b4d2611184".into(),manifest:Path::new("").into(),
+                size:0,mtime:None,id:None,recorded,member:None,along:vec![]
+            }
+        };
+        let truncated = entry("truncated",Some(tcman::Recorded{size:3,mtime:SystemTime::UNIX_EPOCH}));
+        reader.add_all(vec![entry("big",None),truncated.clone()]).await.unwrap();
+        while reader.next_result().await.unwrap().is_some() {}
+        assert_eq!((reader.summary.error,reader.summary.size_mismatches),(2,1));
+        let failed = reader.take_failed();
+        assert_eq!(failed.iter().map(|entry|&entry.path).collect::<Vec<_>>(),[&truncated.path]);
+        assert_eq!((reader.summary.error,reader.summary.size_mismatches),(1,0));
+    }
+
+    /// the local files, as if they were remote
+    struct Remote;
+
+    impl Source for Remote
+    {
+        fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+        {
+            Box::pin(async move {tokio::fs::metadata(path).await.map(|metadata|SourceMetadata::from(&metadata))})
+        }
+        fn open<'a>(&'a self,path:&'a Path,_offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+        {
+            Box::pin(async move {Ok(Box::new(tokio::fs::File::open(path).await?) as Box<dyn AsyncRead+Unpin+Send>)})
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn only_local_files_are_deleted()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = |name:&str|Entry{
+            path:dir.path().join(name),expected:"b1946ac92492d2347c6235b4d2611184".into(),manifest:Path::new("").into(),
+            size:0,mtime:None,id:None,recorded:None,member:None,along:vec![]
+        };
+        for name in ["file","target","remote"] {std::fs::write(dir.path().join(name),"hello\n").unwrap();}
+        std::os::unix::fs::symlink("target",dir.path().join("link")).unwrap();
+        let options = Options{delete_after_verify:true,symlinks:Symlinks::Follow,..Options::default()};
+        let mut reader = reader(options.clone());
+        reader.add_all(vec![entry("file"),entry("link")]).await.unwrap();
+        while reader.next_result().await.unwrap().is_some() {}
+        let mut remote = CheckerBuilder::with(options,Digests::default(),Some(Arc::new(Remote)),Events::default()).build().unwrap().into_parts().0;
+        remote.add_all(vec![entry("remote")]).await.unwrap();
+        while remote.next_result().await.unwrap().is_some() {}
+        assert_eq!((reader.summary.ok,reader.summary.deleted,remote.summary.ok,remote.summary.deleted),(2,1,1,0));
+        assert!(!dir.path().join("file").exists());
+        assert!(["link","target","remote"].iter().all(|name|dir.path().join(name).exists()));
+    }
+
+    #[test]
+    fn path_bytes_are_as_listed()
+    {
+        assert_eq!(&*path_bytes(Path::new("run1/file.dat")),b"run1/file.dat");
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::debug;
+use tokio::time::Instant;
+use crate::error::Error;
+
+static POLL_INTERVAL:Duration = Duration::from_secs(1);
+
+/// an advisory lock (flock on unix) on a lock file, released when dropped (or the process exits)
+///
+/// The lock file contains the pid and start time of the run holding it, for the message of others.
+pub struct Lock
+{
+    _file:File
+}
+
+/// the lock file for a checksum file (or list, or watched directory)
+///
+/// That's next to it. If its directory isn't writable (like that of a read-only archive) it's in $XDG_RUNTIME_DIR,
+/// or else in a directory of our own in the temp directory, named by the path of the checksum file.
+pub fn manifest_lock(manifest:&Path) -> Result<PathBuf,Error>
+{
+    // the same file by another path must be the same lock
+    let manifest = std::fs::canonicalize(manifest).unwrap_or_else(|_|manifest.to_path_buf());
+    let next_to = journal_lock(&manifest);
+    if next_to.parent().is_some_and(writable) {return Ok(next_to)}
+    let hash = md5::compute(manifest.to_string_lossy().as_bytes());
+    let name = format!("{}-{hash:x}.lock",env!("CARGO_PKG_NAME"));
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir|!dir.is_empty()) {
+        Some(dir) => Ok(Path::new(&dir).join(name)),
+        None => Ok(private_temp()?.join(name))
+    }
+}
+
+/// the lock file for a journal, next to it
+pub fn journal_lock(journal:&Path) -> PathBuf
+{
+    let mut lock = journal.as_os_str().to_owned();
+    lock.push(".lock");
+    lock.into()
+}
+
+#[cfg(unix)]
+fn writable(dir:&Path) -> bool
+{
+    use std::os::unix::ffi::OsStrExt;
+    let dir = if dir.as_os_str().is_empty() {Path::new(".")} else {dir};
+    let Ok(dir) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {return false};
+    unsafe {libc::access(dir.as_ptr(),libc::W_OK) == 0}
+}
+#[cfg(not(unix))]
+fn writable(dir:&Path) -> bool
+{
+    let dir = if dir.as_os_str().is_empty() {Path::new(".")} else {dir};
+    dir.metadata().is_ok_and(|metadata|!metadata.permissions().readonly())
+}
+
+/// a directory in the temp directory only we can write to (made if it isn't there)
+#[cfg(unix)]
+fn private_temp() -> Result<PathBuf,Error>
+{
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    let uid = unsafe {libc::geteuid()};
+    let dir = std::env::temp_dir().join(format!("{}-{uid}",env!("CARGO_PKG_NAME")));
+    let failed = |source|Error::Io{action:"make lock directory",path:dir.clone(),source};
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(failed(e)),
+        _ => {}
+    }
+    // made by someone else to have our lock files elsewhere, or to read them
+    let metadata = dir.symlink_metadata().map_err(failed)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(failed(std::io::Error::other("it's not a directory of ours only we can write to")));
+    }
+    Ok(dir)
+}
+#[cfg(not(unix))]
+fn private_temp() -> Result<PathBuf,Error>
+{
+    Ok(std::env::temp_dir())
+}
+
+impl Lock
+{
+    /// take the lock, if it's held by someone else wait for it up to wait
+    pub async fn acquire(path:&Path,wait:Option<Duration>) -> Result<Lock,Error>
+    {
+        let mut options = OpenOptions::new();
+        options.create(true).truncate(false).read(true).write(true);
+        // a symlink put in place of the lock file doesn't make us overwrite its target
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options,libc::O_NOFOLLOW);
+        let mut file = options.open(path)
+            .map_err(|source|Error::Io{action:"open lock file",path:path.to_path_buf(),source})?;
+        let give_up = wait.map(|wait|Instant::now()+wait);
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    if give_up.is_none_or(|give_up|Instant::now() >= give_up) {
+                        let mut holder = String::new();
+                        file.read_to_string(&mut holder).ok();
+                        let holder = if holder.trim().is_empty() {"unknown".into()} else {holder.trim().to_string()};
+                        return Err(Error::Locked{lock:path.to_path_buf(),holder});
+                    }
+                    debug!("waiting for lock '{}'",path.to_string_lossy());
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                Err(TryLockError::Error(source)) => return Err(Error::Io{action:"lock",path:path.to_path_buf(),source})
+            }
+        }
+        // only now it's ours to overwrite
+        let since = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        file.set_len(0).and_then(|_|file.rewind()).and_then(|_|writeln!(file,"pid {} since {since}",std::process::id()))
+            .map_err(|source|Error::Io{action:"write lock file",path:path.to_path_buf(),source})?;
+        debug!("locked '{}'",path.to_string_lossy());
+        Ok(Lock{_file:file})
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn second_run_is_told_who_holds_the_lock()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("md5sum");
+        std::fs::write(&manifest,"").unwrap();
+        let lock = manifest_lock(&manifest).unwrap();
+        assert_eq!(lock,std::fs::canonicalize(dir.path()).unwrap().join("md5sum.lock"));
+        let _held = Lock::acquire(&lock,None).await.unwrap();
+        match Lock::acquire(&lock,Some(Duration::ZERO)).await {
+            Err(Error::Locked{holder,..}) => assert!(holder.starts_with(&format!("pid {} since ",std::process::id())),"{holder}"),
+            other => panic!("locked twice: {:?}",other.map(|_|()))
+        }
+    }
+
+    #[tokio::test]
+    async fn lock_is_free_once_dropped()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = journal_lock(&dir.path().join("journal"));
+        drop(Lock::acquire(&lock,None).await.unwrap());
+        Lock::acquire(&lock,None).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_are_not_followed()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        std::fs::write(&target,"keep").unwrap();
+        let lock = dir.path().join("md5sum.lock");
+        std::os::unix::fs::symlink(&target,&lock).unwrap();
+        assert!(Lock::acquire(&lock,None).await.is_err());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(),"keep");
+    }
+}
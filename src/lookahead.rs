@@ -0,0 +1,123 @@
+//! --lookahead: the next entries are staged ahead while the files before them are checked
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use log::{debug, warn};
+use tokio::task::JoinHandle;
+use crate::Entry;
+use crate::command::ExtCommand;
+use crate::options::Options;
+
+/// an entry waiting to be checked, maybe being staged ahead already
+struct Pending
+{
+    entry:Entry,
+    staging:Option<JoinHandle<bool>>
+}
+
+/// the entries read from the manifest but not added yet, the first ones are staged ahead
+pub(crate) struct Lookahead
+{
+    queue:VecDeque<Pending>,
+    /// how many entries are queued at most, none without --stage
+    pub depth:usize,
+    /// size of the entries being staged ahead, and how big it gets at most
+    staged:u64,size:u64
+}
+
+impl Lookahead
+{
+    pub fn new(options:&Options) -> Lookahead
+    {
+        let depth = if options.stage.is_some() {options.lookahead} else {
+            if options.lookahead > 0 {warn!("--lookahead has no effect without --stage");}
+            0
+        };
+        Lookahead{queue:VecDeque::new(),depth,staged:0,size:options.lookahead_size}
+    }
+    pub fn push(&mut self,entry:Entry) {self.queue.push_back(Pending{entry,staging:None});}
+    /// if more entries are queued than the lookahead
+    pub fn full(&self) -> bool {self.queue.len() > self.depth}
+    /// the oldest entry queued, and its staging if it was staged ahead
+    pub fn pop(&mut self) -> Option<(Entry,Option<JoinHandle<bool>>)>
+    {
+        let Pending{entry,staging} = self.queue.pop_front()?;
+        if staging.is_some() {self.staged -= entry.size;}
+        Some((entry,staging))
+    }
+    /// start staging the queued entries that aren't yet, as long as they fit into the lookahead size
+    pub fn stage(&mut self,stage:&Arc<ExtCommand>)
+    {
+        for pending in self.queue.iter_mut().filter(|p|p.staging.is_none()) {
+            // problems with the file are reported when it's added
+            let Ok(metadata) = pending.entry.file().metadata() else {continue};
+            if self.staged + metadata.len() > self.size {
+                debug!("not staging '{}' ahead, it doesn't fit into the lookahead size",pending.entry.path.to_string_lossy());
+                break;
+            }
+            pending.entry.size = metadata.len();
+            self.staged += metadata.len();
+            debug!("staging '{}' ahead",pending.entry.path.to_string_lossy());
+            let (stage,path,env) = (stage.clone(),pending.entry.file().to_path_buf(),pending.entry.env(None,None));
+            pending.staging = Some(tokio::spawn(async move {
+                stage.run("staging ahead",&[path],&env).await
+            }));
+        }
+    }
+    pub fn entries(&self) -> impl Iterator<Item=&Entry> {self.queue.iter().map(|p|&p.entry)}
+    /// take the entries of another one (of a --daemon job)
+    #[cfg(all(unix,feature="daemon"))]
+    pub fn append(&mut self,other:&mut Lookahead)
+    {
+        self.staged += std::mem::take(&mut other.staged);
+        self.queue.append(&mut other.queue);
+    }
+    /// stop staging ahead
+    pub fn abort(&self)
+    {
+        for staging in self.queue.iter().filter_map(|p|p.staging.as_ref()) {staging.abort();}
+    }
+    /// stop staging ahead and forget the entries, giving the files staged (maybe partly)
+    pub fn abort_staged(&mut self) -> Vec<PathBuf>
+    {
+        self.staged = 0;
+        self.queue.drain(..).filter_map(|pending|{
+            pending.staging?.abort();
+            Some(pending.entry.file().to_path_buf())
+        }).collect()
+    }
+}
+
+#[cfg(all(test,unix))]
+mod tests
+{
+    use std::path::Path;
+    use crate::command;
+    use super::*;
+
+    #[tokio::test]
+    async fn only_what_fits_is_staged_ahead()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = |name:&str|{
+            std::fs::write(dir.path().join(name),"hello\n").unwrap();
+            Entry{
+                path:dir.path().join(name),expected:"b1946ac92492d2347c6235b4d2611184".into(),manifest:Path::new("").into(),
+                size:0,mtime:None,id:None,recorded:None,member:None,along:vec![]
+            }
+        };
+        let options = Options{stage:Some("true".into()),lookahead:2,lookahead_size:10,..Options::default()};
+        let stage = command::configured("stage",options.stage.as_deref(),&options.commands).unwrap();
+        let mut lookahead = Lookahead::new(&options);
+        for name in ["a","b","c"] {lookahead.push(entry(name));}
+        assert!(lookahead.full());
+        lookahead.stage(&stage);
+        let (first,staging) = lookahead.pop().unwrap();
+        assert!(first.path.ends_with("a") && staging.unwrap().await.unwrap());
+        assert!(!lookahead.full());
+        // the room of the first is free again
+        lookahead.stage(&stage);
+        assert_eq!(lookahead.abort_staged(),[dir.path().join("b")]);
+        assert_eq!(Lookahead::new(&Options{lookahead:2,..Options::default()}).depth,0);
+    }
+}
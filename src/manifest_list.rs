@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use log::info;
+use crate::error::Error;
+use crate::watcher::Watcher;
+
+/// the names of checksum files looked for in a directory, in this order
+static CONVENTIONAL:[&str;5] = ["md5sum","MD5SUMS","md5sums.txt","SHA256SUMS","CHECKSUMS"];
+
+/// the names of checksum files looked for in a directory
+pub fn conventional() -> Vec<String>
+{
+    CONVENTIONAL.iter().map(|name|name.to_string()).collect()
+}
+
+/// the checksum file of a directory, the one with a conventional name in it
+///
+/// It's an error if there is none, or several (the one to check has to be given then).
+pub fn discover(dir:&Path) -> Result<PathBuf,Error>
+{
+    info!("looking for checksum files in '{}': {}",dir.to_string_lossy(),CONVENTIONAL.join(", "));
+    let found:Vec<PathBuf> = CONVENTIONAL.iter().map(|name|dir.join(name)).filter(|file|file.is_file()).collect();
+    let reason = |reason:String|Error::Discovery{dir:dir.to_path_buf(),reason};
+    match found.as_slice() {
+        [] => Err(reason(format!("none of {} found",CONVENTIONAL.join(", ")))),
+        [file] => {
+            info!("checking '{}' found in '{}'",file.to_string_lossy(),dir.to_string_lossy());
+            Ok(file.clone())
+        }
+        found => {
+            let found:Vec<String> = found.iter().map(|file|format!("'{}'",file.to_string_lossy())).collect();
+            Err(reason(format!("found {}, give the one to check",found.join(", "))))
+        }
+    }
+}
+
+/// the checksum files of a run: those given as arguments, then those of --manifest-list (read as they're needed),
+/// then those showing up in the directory watched
+pub struct Manifests
+{
+    given:Vec<PathBuf>,
+    next:usize,
+    /// the list, its reader and the separator of its paths
+    list:Option<(PathBuf,Box<dyn AsyncBufRead+Unpin+Send>,u8)>,
+    watcher:Option<Watcher>
+}
+
+#[cfg(unix)]
+fn path(bytes:Vec<u8>) -> PathBuf
+{
+    use std::os::unix::ffi::OsStringExt;
+    std::ffi::OsString::from_vec(bytes).into()
+}
+#[cfg(not(unix))]
+fn path(bytes:Vec<u8>) -> PathBuf
+{
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+impl Manifests
+{
+    /// the list ("-" for stdin) has a path per line, or separated by NUL
+    pub async fn open(given:Vec<PathBuf>,list:Option<&Path>,separator:u8) -> Result<Manifests,Error>
+    {
+        let list = match list {
+            None => None,
+            Some(list) if list == Path::new("-") => Some((list.to_path_buf(),Box::new(BufReader::new(tokio::io::stdin())) as Box<dyn AsyncBufRead+Unpin+Send>,separator)),
+            Some(list) => {
+                let file = tokio::fs::File::open(list).await
+                    .map_err(|source|Error::Io{action:"open manifest list",path:list.to_path_buf(),source})?;
+                Some((list.to_path_buf(),Box::new(BufReader::new(file)) as Box<dyn AsyncBufRead+Unpin+Send>,separator))
+            }
+        };
+        Ok(Manifests{given,next:0,list,watcher:None})
+    }
+    /// the checksum files given as arguments
+    pub fn given(&self) -> &[PathBuf]
+    {
+        &self.given
+    }
+    /// after the others, give the checksum files showing up in the directory watched
+    pub fn watch(&mut self,watcher:Watcher)
+    {
+        self.watcher = Some(watcher);
+    }
+    /// if there may be more than one checksum file (besides those showing up in the directory watched)
+    pub fn several(&self) -> bool
+    {
+        self.given.len() > 1 || self.list.is_some()
+    }
+    pub fn watching(&self) -> bool
+    {
+        self.watcher.is_some()
+    }
+    /// with a directory watched, wait until there could be new checksum files in it
+    pub async fn changed(&mut self)
+    {
+        match &mut self.watcher {
+            Some(watcher) => watcher.changed().await,
+            None => std::future::pending().await
+        }
+    }
+    /// the next checksum file, empty lines of the list are skipped
+    ///
+    /// When watching there's none if no checksum file is ready now, more can be there after [`Manifests::changed`].
+    pub async fn next(&mut self) -> Result<Option<PathBuf>,Error>
+    {
+        if let Some(given) = self.given.get(self.next) {
+            self.next += 1;
+            return Ok(Some(given.clone()));
+        }
+        if let Some((list,reader,separator)) = &mut self.list {
+            if let Some(listed) = Self::listed(list,reader,*separator).await? {return Ok(Some(listed));}
+        }
+        Ok(self.watcher.as_mut().and_then(|watcher|watcher.ready()))
+    }
+    /// the next path in the list
+    async fn listed(list:&Path,reader:&mut Box<dyn AsyncBufRead+Unpin+Send>,separator:u8) -> Result<Option<PathBuf>,Error>
+    {
+        loop {
+            let mut line = vec![];
+            let read = reader.read_until(separator,&mut line).await
+                .map_err(|source|Error::Io{action:"read manifest list",path:list.to_path_buf(),source})?;
+            if read == 0 {return Ok(None);}
+            if line.last() == Some(&separator) {line.pop();}
+            if separator == b'\n' && line.last() == Some(&b'\r') {line.pop();}
+            if !line.is_empty() {return Ok(Some(path(line)));}
+        }
+    }
+    /// all the remaining checksum files
+    pub async fn collect(&mut self) -> Result<Vec<PathBuf>,Error>
+    {
+        let mut all = vec![];
+        while let Some(manifest) = self.next().await? {all.push(manifest);}
+        Ok(all)
+    }
+}
@@ -0,0 +1,88 @@
+//! the merge subcommand: checksum files combined into one, sorted by path and with the paths relative to a common root
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use log::{error, warn};
+use crate::error::Error;
+use crate::{manifest_base, manifest_dir, manifest_path, zip_member, Reader};
+
+/// the path without "." and with ".." taken out with the directory before it (without looking at symlinks)
+fn normalize(path:&Path) -> PathBuf
+{
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(),Some(Component::Normal(_))) => {normalized.pop();}
+            component => normalized.push(component)
+        }
+    }
+    normalized
+}
+
+/// the name for the merged checksum file, relative to root if the file is below it (absolute otherwise)
+fn rebase(path:&Path,root:&Path) -> String
+{
+    let path = normalize(&std::path::absolute(path).unwrap_or_else(|_|path.to_path_buf()));
+    let name = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().into_owned();
+    // the merged checksum file works on other systems too
+    if std::path::MAIN_SEPARATOR == '\\' {name.replace('\\',"/")} else {name}
+}
+
+/// write the entries of the checksum files into output (or stdout for "-"), each once and sorted by path
+///
+/// The names are rebased from the directory of their checksum file onto root (the directory of output by default).
+/// Digests listed for the same path that differ are conflicts, nothing is written then unless keep_conflicts.
+pub(crate) async fn merge(manifests:&[PathBuf],output:&Path,root:Option<&Path>,keep_conflicts:bool,reader:&mut Reader) -> Result<ExitCode,Error>
+{
+    let stdout = output == Path::new("-");
+    let root = match root {
+        Some(root) => root,
+        None if stdout => Path::new("."),
+        None => Some(manifest_dir(output)).filter(|base|!base.as_os_str().is_empty()).unwrap_or(Path::new("."))
+    };
+    let root = normalize(&std::path::absolute(root).map_err(|source|Error::Io{action:"resolve",path:root.into(),source})?);
+    // the digests by path, and where each was listed first
+    let mut merged:BTreeMap<String,BTreeMap<String,PathBuf>> = BTreeMap::new();
+    let (mut listed,mut duplicates,mut conflicts) = (0,0,0);
+    for manifest in manifests {
+        let base = manifest_base(manifest);
+        for (digest,filename) in reader.entries(manifest).await? {
+            listed += 1;
+            // the member name of an archive is kept as it is
+            let name = match zip_member::split(&filename) {
+                Some((archive,member)) => format!("{}{}{member}",rebase(&manifest_path(base,archive),&root),zip_member::SEPARATOR),
+                None => rebase(&manifest_path(base,&filename),&root)
+            };
+            let digests = merged.entry(name.clone()).or_default();
+            let digest = digest.to_ascii_lowercase();
+            if digests.contains_key(&digest) {
+                duplicates += 1;
+                continue
+            }
+            // digests of another kind are no conflict, the file can be checked with both
+            if let Some((other,first)) = digests.iter().find(|(other,_)|other.len() == digest.len()) {
+                error!("'{name}' is listed with {digest} in '{}' but with {other} in '{}'",manifest.to_string_lossy(),first.to_string_lossy());
+                conflicts += 1;
+            }
+            digests.insert(digest,manifest.clone());
+        }
+    }
+    if conflicts > 0 && !keep_conflicts {return Err(Error::Conflicts(conflicts));}
+    if conflicts > 0 {warn!("keeping all digests of the {conflicts} conflicting entries");}
+    let mut merged_manifest = Vec::new();
+    let entries = merged.values().map(BTreeMap::len).sum::<usize>();
+    for (name,digests) in &merged {
+        for digest in digests.keys() {writeln!(merged_manifest,"{digest}  {name}").expect("writing to memory");}
+    }
+    // the sizes are unknown without looking at the files
+    writeln!(merged_manifest,"# files: {entries}").expect("writing to memory");
+    let written = if stdout {std::io::stdout().write_all(&merged_manifest)} else {std::fs::write(output,&merged_manifest)};
+    written.map_err(|source|Error::Io{action:"write",path:output.into(),source})?;
+    eprintln!("{listed} entries of {} checksum files merged into {entries} entries: {duplicates} duplicates dropped, {conflicts} conflicts",
+        manifests.len());
+    if reader.summary.rejected > 0 {return Err(Error::SignatureRejected(reader.summary.rejected));}
+    if reader.summary.invalid_lines > 0 {return Err(Error::InvalidLines(reader.summary.invalid_lines));}
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,90 @@
+//! waiting for the file system of the files to come back when it's gone for a while (like an NFS or GPFS mount failing over)
+use std::future::Future;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use log::{error, info, warn};
+use tokio::time::Instant;
+
+/// how often the directory of a file is looked at while its file system is gone
+pub(crate) static PROBE_INTERVAL:Duration = Duration::from_secs(5);
+
+/// if the error tells the file system is gone (ESTALE, ENODEV, ENOTCONN), rather than something being wrong with the file
+#[cfg(unix)]
+pub fn lost(e:&std::io::Error) -> bool
+{
+    matches!(e.raw_os_error(),Some(libc::ESTALE|libc::ENODEV|libc::ENOTCONN))
+}
+
+#[cfg(not(unix))]
+pub fn lost(e:&std::io::Error) -> bool
+{
+    matches!(e.kind(),std::io::ErrorKind::StaleNetworkFileHandle|std::io::ErrorKind::NotConnected)
+}
+
+/// if the file system is gone, shared by the checks and the reader (which doesn't start new files meanwhile)
+pub(crate) struct Mount
+{
+    /// how long to wait for it to come back, None to not wait
+    wait:Option<Duration>,
+    /// since when it's gone
+    lost:Mutex<Option<Instant>>,
+    /// how often it was gone, and for how long in all
+    losses:Mutex<(u64,Duration)>
+}
+
+impl Mount
+{
+    pub fn new(wait:Duration) -> Mount
+    {
+        Mount{wait:Some(wait).filter(|wait|!wait.is_zero()),lost:Mutex::new(None),losses:Mutex::new((0,Duration::ZERO))}
+    }
+    /// since when the file system is gone, if it is
+    pub fn lost_since(&self) -> Option<Instant> {*self.lost.lock().expect("mount lock")}
+    /// how often the file system was gone, and for how long in all
+    pub fn losses(&self) -> (u64,Duration) {*self.losses.lock().expect("mount lock")}
+    /// wait until the directory of the file can be looked at again after failing with e, false if it can't within the time to wait
+    ///
+    /// Every check (and the reader) failing waits here, the first one tells the file system is gone, the one seeing it back
+    /// tells how long it was gone.
+    pub async fn suspend(&self,path:&Path,e:&std::io::Error) -> bool
+    {
+        let Some(wait) = self.wait else {return false};
+        let dir = path.parent().filter(|dir|!dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let since = *self.lost.lock().expect("mount lock").get_or_insert_with(||{
+            warn!("the file system of '{}' is gone ({e}), pausing until '{}' is back (at most {})",
+                path.to_string_lossy(),dir.to_string_lossy(),humantime::format_duration(wait));
+            self.losses.lock().expect("mount lock").0 += 1;
+            Instant::now()
+        });
+        loop {
+            // looking at a stale mount can hang as well
+            if let Ok(Ok(_)) = tokio::time::timeout(PROBE_INTERVAL,tokio::fs::metadata(dir)).await {
+                self.end(|lost|info!("'{}' is back after {lost}, resuming",dir.to_string_lossy()));
+                return true
+            }
+            if since.elapsed() >= wait {
+                self.end(|lost|error!("'{}' is still gone after {lost}, giving up on the files waiting for it",dir.to_string_lossy()));
+                return false
+            }
+            tokio::time::sleep(PROBE_INTERVAL.min(wait.saturating_sub(since.elapsed()))).await;
+        }
+    }
+    /// open (or look up) the file, waiting for its file system to come back when it's gone
+    ///
+    /// Once it's back, failing the same way again is the file's own error, and returned.
+    pub async fn retry<T,F>(&self,path:&Path,mut open:impl FnMut()->F) -> std::io::Result<T> where F:Future<Output=std::io::Result<T>>
+    {
+        match open().await {
+            Err(e) if lost(&e) && self.suspend(path,&e).await => open().await,
+            opened => opened
+        }
+    }
+    /// the file system is back (or given up on), tell how long it was gone if it wasn't told already
+    fn end(&self,tell:impl FnOnce(String))
+    {
+        let Some(since) = self.lost.lock().expect("mount lock").take() else {return};
+        self.losses.lock().expect("mount lock").1 += since.elapsed();
+        tell(humantime::format_duration(Duration::from_secs(since.elapsed().as_secs())).to_string());
+    }
+}
@@ -0,0 +1,198 @@
+//! the options a reader checks with, set by the [`CheckerBuilder`](crate::CheckerBuilder) or taken from the command line
+use std::time::{Duration, SystemTime};
+use crate::command;
+use crate::path_compat::PathCompat;
+use crate::retry::{RetryPolicy, Retryable};
+use crate::sample::Sampling;
+use crate::GIGABYTE;
+
+/// the order entries are checked in
+#[derive(Clone,Copy,PartialEq,clap::ValueEnum)]
+pub(crate) enum Order
+{
+    /// as listed in the checksum files
+    Listed,
+    /// by where the files are on their disk (the first extent, or the inode number), to avoid seeking (Linux only)
+    Physical
+}
+
+/// how the size of files being checked counts against max-size
+#[derive(Clone,Copy,PartialEq,clap::ValueEnum)]
+pub(crate) enum Accounting
+{
+    /// the whole file until it's done (for storage that frees space only then)
+    WholeFile,
+    /// only what hasn't been read yet
+    Progressive
+}
+
+/// how files listed more than once are handled
+#[derive(Clone,Copy,PartialEq,clap::ValueEnum)]
+pub(crate) enum Duplicates
+{
+    /// check them once, and report the result for every listing
+    Coalesce,
+    /// check them again for every listing (but not at the same time)
+    Check
+}
+
+/// for which paths of files with several hard links the commands are run
+#[derive(Clone,Copy,PartialEq,clap::ValueEnum)]
+pub(crate) enum HardLinks
+{
+    /// once, for the path that was read
+    Once,
+    /// for each path listed, after the file was read for one of them
+    Each
+}
+
+/// how entries that are symlinks are handled
+#[derive(Clone,Copy,PartialEq,clap::ValueEnum)]
+pub(crate) enum Symlinks
+{
+    /// check the file they point to
+    Follow,
+    /// report them with their target, without checking
+    Report,
+    /// skip them (only counting them)
+    Skip
+}
+
+/// how a reader checks the files; the defaults are those of the library, the command line tool gives its own
+#[derive(Clone)]
+pub(crate) struct Options
+{
+    /// the most bytes of files being checked at a time, and if bigger files are checked alone (instead of failing)
+    pub max_size:u64,
+    pub allow_oversize:bool,
+    pub accounting:Accounting,
+    /// how much of a file is read at once
+    pub buffer_size:u64,
+    /// the fewest and most files checked at once when tuning for the most throughput, how long it's measured
+    /// and if the best number stays once found
+    pub adaptive:Option<(usize,usize,Duration,bool)>,
+    pub pre_hook:Option<String>,
+    pub stage:Option<String>,
+    pub post_hook:Option<String>,
+    pub release:Vec<String>,
+    pub on_fail:Option<String>,
+    pub online_check:Option<String>,
+    pub pause_when:Option<String>,
+    /// how the commands are run
+    pub commands:command::Options,
+    /// files queried with one call of the online check, the text of its output for offline files, and if those are skipped
+    pub online_check_batch:usize,
+    pub offline_match:Option<String>,
+    pub skip_offline:bool,
+    /// how long the result of the pause-when command is valid
+    pub pause_check_interval:Duration,
+    pub release_on_fail:bool,
+    pub release_retries:u32,
+    /// files released with one call, and how long a batch not full waits
+    pub release_batch:usize,
+    pub release_batch_wait:Option<Duration>,
+    /// release and on-fail commands running at once
+    pub release_procs:usize,
+    pub release_hardlinks:HardLinks,
+    pub delete_after_verify:bool,
+    pub fail_fast:bool,
+    /// files staged ahead, and the most bytes of them
+    pub lookahead:usize,
+    pub lookahead_size:u64,
+    /// which errors opening a file are retried how often, and which read errors are resumed from
+    pub open_policy:RetryPolicy,
+    pub resume_policy:RetryPolicy,
+    pub chunks:bool,
+    /// compute the digest of all files in the order listed
+    pub stream_digest:bool,
+    pub restore_atime:bool,
+    /// how long to wait for the file system of the files when it's gone
+    pub mount_wait:Duration,
+    pub probe_bad_blocks:bool,
+    pub prescan:bool,
+    pub order:Order,
+    /// check the totals of the footers of checksum files
+    pub footers:bool,
+    pub symlinks:Symlinks,
+    pub allow_special:bool,
+    /// keep the entries within the directory of their checksum file, resolving symlinks if true
+    pub confine:Option<bool>,
+    pub source_url:Option<String>,
+    pub path_compat:Option<PathCompat>,
+    /// don't verify TLS certificates, and HTTP requests made at once
+    #[cfg(feature="http")]
+    pub insecure:bool,
+    #[cfg(feature="http")]
+    pub http_requests:u32,
+    /// the range of modification times and sizes of the files checked
+    pub newer_than:Option<SystemTime>,
+    pub older_than:Option<SystemTime>,
+    pub file_sizes:(Option<u64>,Option<u64>),
+    /// the entries to skip and to check at most, and the shard checked
+    pub window:Option<(u64,Option<u64>)>,
+    pub shard:Option<(u64,u64)>,
+    pub print0:bool,
+    /// how often a file being checked logs how far it is (zero for never)
+    pub heartbeat:Duration,
+    /// how long until no new files are started, and if the running ones are cancelled then
+    pub deadline:Option<Duration>,
+    pub deadline_cancel:bool,
+    pub max_bytes:Option<u64>,
+    pub max_failures:Option<u64>,
+    pub max_errors:Option<u64>,
+    /// the failure rate, and the files to check before it applies
+    pub max_failure_rate:Option<(f64,u64)>,
+    pub duplicates:Duplicates,
+    /// mark results of hard links using the digest of another one
+    pub mark_hardlinks:bool,
+    pub sample:Option<Sampling>,
+    /// the seed of the random order
+    pub shuffle:Option<u64>,
+    /// keep the results per checksum file, the files verified for extended checksum files, and the results by directory at a depth
+    pub per_manifest:bool,
+    pub write_tcman:bool,
+    pub per_dir:Option<usize>,
+    /// keep the files listed, to find those not listed
+    pub known:bool,
+    pub retry_failed:u32,
+    pub retry_mismatch:bool,
+    pub requeue_changed:Option<Duration>
+}
+
+impl Default for Options
+{
+    fn default() -> Options
+    {
+        #[cfg(unix)]
+        let resumed = vec![Retryable::Errno(libc::EIO),Retryable::Kind(std::io::ErrorKind::TimedOut),Retryable::Kind(std::io::ErrorKind::Interrupted)];
+        #[cfg(not(unix))]
+        let resumed = vec![Retryable::Kind(std::io::ErrorKind::TimedOut),Retryable::Kind(std::io::ErrorKind::Interrupted)];
+        Options{
+            max_size:1024*GIGABYTE,allow_oversize:false,accounting:Accounting::WholeFile,buffer_size:1<<20,adaptive:None,
+            pre_hook:None,stage:None,post_hook:None,release:vec![],on_fail:None,online_check:None,pause_when:None,
+            commands:command::Options::default(),
+            online_check_batch:1,offline_match:None,skip_offline:false,pause_check_interval:Duration::from_secs(60),
+            release_on_fail:false,release_retries:0,release_batch:1,release_batch_wait:None,release_procs:2,
+            release_hardlinks:HardLinks::Once,delete_after_verify:false,fail_fast:false,
+            lookahead:0,lookahead_size:1024*GIGABYTE,
+            open_policy:RetryPolicy{
+                retryable:vec![Retryable::Kind(std::io::ErrorKind::TimedOut),Retryable::Kind(std::io::ErrorKind::Interrupted)],
+                max_retries:None
+            },
+            resume_policy:RetryPolicy{retryable:resumed,max_retries:Some(3)},
+            chunks:false,stream_digest:false,restore_atime:false,mount_wait:Duration::from_secs(600),probe_bad_blocks:false,
+            prescan:false,order:Order::Listed,footers:true,symlinks:Symlinks::Follow,allow_special:false,confine:Some(false),
+            source_url:None,path_compat:None,
+            #[cfg(feature="http")]
+            insecure:false,
+            #[cfg(feature="http")]
+            http_requests:4,
+            newer_than:None,older_than:None,file_sizes:(None,None),window:None,shard:None,print0:false,
+            heartbeat:Duration::from_secs(1800),deadline:None,deadline_cancel:false,
+            max_bytes:None,max_failures:None,max_errors:None,max_failure_rate:None,
+            duplicates:Duplicates::Coalesce,mark_hardlinks:false,sample:None,shuffle:None,
+            per_manifest:false,write_tcman:false,per_dir:None,known:false,
+            retry_failed:0,retry_mismatch:false,requeue_changed:None
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use log::debug;
+use unicode_normalization::UnicodeNormalization;
+
+/// reading checksum files written on Windows or macOS (--path-compat)
+#[derive(Clone)]
+pub struct PathCompat
+{
+    /// the directories drive letters are mapped to (upper case), others are stripped
+    pub drives:Vec<(char,PathBuf)>,
+    /// look for files missing under their listed name in the other Unicode normalization (NFC/NFD)
+    pub unicode:bool
+}
+
+impl PathCompat
+{
+    /// the name with backslashes as "/", and the drive prefix ("C:") stripped or replaced by the directory it's mapped to
+    pub fn translate<'n>(&self,name:&'n str) -> Cow<'n,str>
+    {
+        let mut translated = Cow::Borrowed(name);
+        if translated.contains('\\') {
+            translated = Cow::Owned(translated.replace('\\',"/"));
+        }
+        let bytes = translated.as_bytes();
+        if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            let drive = (bytes[0] as char).to_ascii_uppercase();
+            let rest = translated[2..].trim_start_matches('/');
+            translated = Cow::Owned(match self.drives.iter().find(|(letter,_)|*letter == drive) {
+                Some((_,dir)) => dir.join(rest).to_string_lossy().into_owned(),
+                None => rest.to_string()
+            });
+        }
+        if translated != name {debug!("'{name}' is taken as '{translated}'");}
+        translated
+    }
+    /// the path, or the one with the name in the other Unicode normalization if only that exists
+    pub fn resolve(&self,path:PathBuf) -> PathBuf
+    {
+        if !self.unicode || path.symlink_metadata().is_ok() {return path;}
+        let Some(name) = path.to_str() else {return path};
+        let variants:[String;2] = [name.nfc().collect(),name.nfd().collect()];
+        match variants.into_iter().filter(|v|v != name).find(|v|Path::new(v).symlink_metadata().is_ok()) {
+            Some(variant) => {
+                debug!("'{name}' does not exist, using '{variant}' (other Unicode normalization)");
+                variant.into()
+            }
+            None => path
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn windows_names_are_translated()
+    {
+        let compat = PathCompat{drives:vec![('D',"/mnt/d".into())],unicode:false};
+        assert_eq!(compat.translate("run1\\file.dat"),"run1/file.dat");
+        assert_eq!(compat.translate("C:\\run1\\file.dat"),"run1/file.dat");
+        assert_eq!(compat.translate("d:\\run1\\file.dat"),"/mnt/d/run1/file.dat");
+        assert!(matches!(compat.translate("run1/file.dat"),Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn names_are_found_in_the_other_normalization()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        // stored decomposed (as by macOS), listed composed
+        let (composed,decomposed) = ("caf\u{e9}","cafe\u{301}");
+        std::fs::write(dir.path().join(decomposed),"").unwrap();
+        let compat = PathCompat{drives:vec![],unicode:true};
+        assert_eq!(compat.resolve(dir.path().join(composed)),dir.path().join(decomposed));
+        assert_eq!(compat.resolve(dir.path().join("missing")),dir.path().join("missing"));
+        let strict = PathCompat{drives:vec![],unicode:false};
+        assert_eq!(strict.resolve(dir.path().join(composed)),dir.path().join(composed));
+    }
+}
@@ -0,0 +1,93 @@
+//! --aggregate-by-dir: the results of the files summed up by the directory they're in, at a depth (like one per tape)
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use crate::{CheckResult, Status, GIGABYTE, MEGABYTE};
+
+/// status, size and reading time (None if it wasn't read) of the files by path, the last result of a file counts
+type Files = HashMap<PathBuf,(Status,u64,Option<Duration>)>;
+
+/// the results of the files by directory
+pub(crate) struct PerDir
+{
+    depth:usize,
+    dirs:HashMap<PathBuf,Files>
+}
+
+/// the first depth directories of the directory of the file (or its directory, if that's not as deep)
+fn dir(path:&Path,depth:usize) -> PathBuf
+{
+    let mut dir = PathBuf::new();
+    let mut levels = 0;
+    for component in path.parent().unwrap_or(Path::new("")).components() {
+        if levels == depth {break}
+        if matches!(component,Component::Normal(_)) {levels += 1;}
+        dir.push(component);
+    }
+    if dir.as_os_str().is_empty() {".".into()} else {dir}
+}
+
+/// the totals of a directory
+#[derive(Default)]
+struct Totals
+{
+    files:u64,bytes:u64,fail:u64,error:u64,
+    /// the lowest throughput of the files read (in bytes/s)
+    slowest:Option<f64>
+}
+
+impl PerDir
+{
+    pub(crate) fn new(depth:usize) -> PerDir
+    {
+        PerDir{depth,dirs:HashMap::new()}
+    }
+    /// add the results of another one (of a --daemon job), they replace those of the same files
+    #[cfg(all(unix,feature="daemon"))]
+    pub(crate) fn merge(&mut self,other:PerDir)
+    {
+        for (dir,files) in other.dirs {self.dirs.entry(dir).or_default().extend(files);}
+    }
+    /// count the result, if it's a verdict for a file
+    pub(crate) fn add(&mut self,result:&CheckResult)
+    {
+        if result.status != Status::Ok && !result.status.failed() {return}
+        let files = self.dirs.entry(dir(&result.path,self.depth)).or_default();
+        files.insert(result.path.clone(),(result.status,result.size.unwrap_or_default(),result.duration));
+    }
+    fn totals(files:&Files) -> Totals
+    {
+        let mut totals = Totals::default();
+        for (status,size,duration) in files.values() {
+            totals.files += 1;
+            match status {
+                Status::Ok => {}
+                Status::Fail => totals.fail += 1,
+                _ => totals.error += 1
+            }
+            // only the files read, not those checked for another entry
+            let Some(duration) = duration.filter(|_|matches!(status,Status::Ok|Status::Fail)) else {continue};
+            totals.bytes += size;
+            let throughput = *size as f64/duration.as_secs_f64().max(1e-6);
+            if totals.slowest.is_none_or(|slowest|throughput < slowest) {totals.slowest = Some(throughput);}
+        }
+        totals
+    }
+}
+
+impl std::fmt::Display for PerDir
+{
+    /// a line for each directory, those with failures first
+    fn fmt(&self,f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let mut dirs:Vec<(&PathBuf,Totals)> = self.dirs.iter().map(|(dir,files)|(dir,PerDir::totals(files))).collect();
+        dirs.sort_by_key(|(dir,totals)|(totals.fail+totals.error == 0,dir.to_path_buf()));
+        for (dir,totals) in dirs {
+            write!(f,"{}: {} files, {:.1}G, {} FAIL, {} ERROR",dir.to_string_lossy(),totals.files,
+                totals.bytes as f64/GIGABYTE as f64,totals.fail,totals.error)?;
+            if let Some(slowest) = totals.slowest {write!(f,", slowest {:.1}M/s",slowest/MEGABYTE as f64)?;}
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+/// where the data of a local file is on its device, for --order physical
+///
+/// The device, and the physical offset of the first extent (by FIEMAP) or the inode number if the file
+/// system can't tell that. Files without data are at 0. Only Linux tells, elsewhere it's None.
+#[cfg(target_os="linux")]
+pub fn position(path:&Path) -> Option<(u64,u64)>
+{
+    use std::os::unix::fs::MetadataExt;
+    let file = std::fs::File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    Some((metadata.dev(),fiemap::first_extent(&file).unwrap_or(metadata.ino())))
+}
+
+#[cfg(not(target_os="linux"))]
+pub fn position(_path:&Path) -> Option<(u64,u64)>
+{
+    None
+}
+
+#[cfg(target_os="linux")]
+mod fiemap
+{
+    use std::os::fd::AsRawFd;
+
+    /// _IOWR('f', 11, struct fiemap)
+    const FS_IOC_FIEMAP:libc::c_ulong = 0xc020660b;
+
+    /// struct fiemap with room for one struct fiemap_extent
+    #[repr(C)]
+    #[derive(Default)]
+    struct Fiemap
+    {
+        start:u64,
+        length:u64,
+        flags:u32,
+        mapped_extents:u32,
+        extent_count:u32,
+        reserved:u32,
+        extent:Extent
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Extent
+    {
+        logical:u64,
+        physical:u64,
+        length:u64,
+        reserved64:[u64;2],
+        flags:u32,
+        reserved:[u32;3]
+    }
+
+    /// the physical offset of the first extent of the file, None if the file system doesn't support FIEMAP
+    pub fn first_extent(file:&std::fs::File) -> Option<u64>
+    {
+        let mut map = Fiemap{length:u64::MAX,extent_count:1,..Fiemap::default()};
+        if unsafe {libc::ioctl(file.as_raw_fd(),FS_IOC_FIEMAP as _,&mut map)} < 0 {return None}
+        Some(if map.mapped_extents > 0 {map.extent.physical} else {0})
+    }
+}
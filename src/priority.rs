@@ -0,0 +1,102 @@
+//! --priority-file: entries to check ahead of the others, e.g. for a restore request coming in during a campaign
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use log::{debug, error, info};
+use crate::Entry;
+
+/// the paths (absolute) of the priority file, and which of them were found among the entries
+pub(crate) struct Priorities
+{
+    file:PathBuf,
+    separator:u8,
+    paths:HashSet<PathBuf>,
+    found:HashSet<PathBuf>,
+    /// counted up on SIGHUP (or by the daemon) to read the file again before the next entries are added,
+    /// shared by the readers of the jobs of --daemon
+    reload:Arc<AtomicU64>,
+    /// the count when the file was read last
+    read_at:u64,
+    /// if all entries are collected first, so those of all checksum files can be put first (not with --watch or --daemon)
+    pub collect:bool
+}
+
+impl Priorities
+{
+    /// read the paths of the file, separated by separator
+    pub fn open(file:&Path,separator:u8,collect:bool) -> std::io::Result<Priorities>
+    {
+        let mut priorities = Priorities{
+            file:file.into(),separator,paths:HashSet::new(),found:HashSet::new(),reload:Arc::new(AtomicU64::new(0)),read_at:0,collect
+        };
+        priorities.read()?;
+        Ok(priorities)
+    }
+    fn read(&mut self) -> std::io::Result<()>
+    {
+        let content = std::fs::read(&self.file)?;
+        self.paths = content.split(|b|*b == self.separator)
+            .map(|path|String::from_utf8_lossy(path))
+            .filter(|path|!path.trim().is_empty())
+            .map(|path|std::path::absolute(path.trim_end_matches('\r')))
+            .collect::<std::io::Result<_>>()?;
+        info!("{} entries in the priority file '{}'",self.paths.len(),self.file.to_string_lossy());
+        Ok(())
+    }
+    /// the count to count up for reading the file again
+    #[cfg(unix)]
+    pub fn reloader(&self) -> Arc<AtomicU64> {self.reload.clone()}
+    /// the same paths for the reader of a --daemon job, read again on its first entries if that was asked for meanwhile
+    #[cfg(all(unix,feature="daemon"))]
+    pub fn again(&self) -> Priorities
+    {
+        Priorities{
+            file:self.file.clone(),separator:self.separator,paths:self.paths.clone(),found:HashSet::new(),
+            reload:self.reload.clone(),read_at:self.read_at,collect:self.collect
+        }
+    }
+    /// count the entries found by the reader of a --daemon job as found
+    #[cfg(all(unix,feature="daemon"))]
+    pub fn merge(&mut self,job:Priorities) {self.found.extend(job.found);}
+    /// move the entries of the priority file to the front, keeping the order otherwise
+    ///
+    /// Unless they're new, that's only done if the file was read again (if that fails the paths read before stay).
+    pub fn order(&mut self,entries:&mut VecDeque<Entry>,new:bool)
+    {
+        let count = self.reload.load(Ordering::Relaxed);
+        let reload = count != self.read_at;
+        self.read_at = count;
+        if reload {
+            if let Err(e) = self.read() {error!("failed to read the priority file '{}' again: {e}",self.file.to_string_lossy());}
+        }
+        if !(new || reload) || self.paths.is_empty() {return}
+        let (first,rest):(VecDeque<Entry>,VecDeque<Entry>) = entries.drain(..).partition(|entry|self.paths.contains(&entry.listed_path()));
+        for entry in &first {
+            if self.found.insert(entry.listed_path()) {debug!("'{}' is checked ahead",entry.path.to_string_lossy());}
+        }
+        *entries = first;
+        entries.extend(rest);
+    }
+    /// how many entries of the priority file were found, and put first
+    pub fn expedited(&self) -> u64 {self.found.len() as u64}
+    /// the paths of the priority file that are not among the entries (sorted)
+    pub fn unlisted(&self) -> Vec<PathBuf>
+    {
+        let mut unlisted:Vec<PathBuf> = self.paths.difference(&self.found).cloned().collect();
+        unlisted.sort();
+        unlisted
+    }
+}
+
+/// count up to read the priority file again on every SIGHUP
+#[cfg(unix)]
+pub(crate) async fn reload_on_hangup(reload:Arc<AtomicU64>) -> std::io::Result<()>
+{
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        info!("reading the priority file again before adding the next entries");
+        reload.fetch_add(1,Ordering::Relaxed);
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use log::{debug, error};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use rusqlite::config::DbConfig;
+use rusqlite::types::ValueRef;
+use crate::error::Error;
+use crate::{cli, extra, Entry, Event, Reader, Status};
+
+/// a SQLite database with the digests of files by their path, for --ref-db
+///
+/// The digest for a path is looked up by a statement prepared once, so the path column should be indexed
+/// (or the primary key) for millions of rows.
+pub(crate) struct RefDb
+{
+    path:PathBuf,
+    db:Connection,
+    lookup:String
+}
+
+/// the name quoted as SQL identifier
+fn quoted(name:&str) -> String
+{
+    format!("\"{}\"",name.replace('"',"\"\""))
+}
+
+impl RefDb
+{
+    /// open the database read-only and prepare looking up the digest column of the table by the path column
+    pub fn open(path:&Path,table:&str,path_column:&str,digest_column:&str) -> Result<RefDb,Error>
+    {
+        let failed = |e:rusqlite::Error|Error::RefDb{path:path.into(),reason:e.to_string()};
+        let db = Connection::open_with_flags(path,OpenFlags::SQLITE_OPEN_READ_ONLY|OpenFlags::SQLITE_OPEN_NO_MUTEX).map_err(failed)?;
+        // else a column that isn't there is taken as a string, the digest of every file
+        db.set_db_config(DbConfig::SQLITE_DBCONFIG_DQS_DML,false).map_err(failed)?;
+        let lookup = format!("SELECT {} FROM {} WHERE {} = ?1",quoted(digest_column),quoted(table),quoted(path_column));
+        debug!("looking up digests in '{}' by {lookup}",path.to_string_lossy());
+        // a missing table or column is told now
+        db.prepare_cached(&lookup).map_err(failed)?;
+        Ok(RefDb{path:path.into(),db,lookup})
+    }
+    /// the digest the database has for the path, if it has a row for it
+    pub fn digest(&mut self,key:&str) -> Result<Option<String>,Error>
+    {
+        let failed = |e:rusqlite::Error|Error::RefDb{path:self.path.clone(),reason:e.to_string()};
+        let mut lookup = self.db.prepare_cached(&self.lookup).map_err(failed)?;
+        let digest = lookup.query_row([key],|row|Ok(match row.get_ref(0)? {
+            // a NULL digest is no digest
+            ValueRef::Null => None,
+            ValueRef::Text(text)|ValueRef::Blob(text) => Some(String::from_utf8_lossy(text).trim().to_string()),
+            ValueRef::Integer(number) => Some(number.to_string()),
+            ValueRef::Real(number) => Some(number.to_string())
+        })).optional().map_err(failed)?;
+        Ok(digest.flatten())
+    }
+}
+
+/// the path as looked up: absolute, with the prefix replaced by remap
+fn key(path:&Path,remap:Option<&(PathBuf,String)>) -> String
+{
+    match remap.and_then(|(local,catalog)|Some((path.strip_prefix(local).ok()?,catalog))) {
+        Some((below,catalog)) if below.as_os_str().is_empty() => catalog.clone(),
+        Some((below,catalog)) => format!("{}/{}",catalog.trim_end_matches('/'),below.to_string_lossy()),
+        None => path.to_string_lossy().into_owned()
+    }
+}
+
+/// check the files given, and those below the directories given, against the digests the database has by their path
+///
+/// Files it has no row for are reported as NOREF. Symlinks below the directories are not followed.
+pub(crate) async fn check(paths:&[PathBuf],db:&mut RefDb,remap:Option<&(PathBuf,String)>,reader:&mut Reader) -> Result<(),Error>
+{
+    let manifest:Arc<Path> = db.path.as_path().into();
+    let collect = cli::collects(reader);
+    let mut all = vec![];
+    for given in paths {
+        if reader.interrupted() {break}
+        let files = if given.is_dir() {
+            let dir = given.clone();
+            tokio::task::spawn_blocking(move||extra::walk(&dir,&HashSet::new(),true)).await?
+                .map_err(|source|Error::Io{action:"look for files in",path:given.clone(),source})?
+        } else {
+            vec![std::path::absolute(given).map_err(|source|Error::Io{action:"look up",path:given.clone(),source})?]
+        };
+        let mut entries = vec![];
+        for path in files {
+            let key = key(&path,remap);
+            if !reader.in_shard(&key) || !reader.in_window(&path) {continue}
+            reader.stream_digest.listed += 1;
+            reader.events.emit(||Event::Listed{path:path.clone()});
+            let Some(digest) = db.digest(&key)? else {
+                reader.report(&path,Status::NoRef,None);
+                reader.summary.noref += 1;
+                continue
+            };
+            if !reader.check.digests.known_len(digest.len()) || !digest.chars().all(|c|c.is_ascii_hexdigit()) {
+                error!("'{digest}' the reference database has for '{key}' is no digest");
+                reader.report(&path,Status::Error(Some("invalid-reference")),None);
+                reader.summary.error += 1;
+                continue
+            }
+            debug!("adding '{}' with reference '{digest}'",path.to_string_lossy());
+            entries.push(Entry{path,expected:digest.to_ascii_lowercase(),manifest:manifest.clone(),size:0,mtime:None,id:None,recorded:None,member:None,along:vec![]});
+        }
+        if collect {all.append(&mut entries)} else {reader.add_batches(entries).await?}
+    }
+    cli::check_collected(all,reader).await
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn database(dir:&tempfile::TempDir) -> PathBuf
+    {
+        let path = dir.path().join("ref.db");
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch("CREATE TABLE digests (path TEXT PRIMARY KEY, md5 TEXT);
+            INSERT INTO digests VALUES ('/archive/a','b026324c6904b2a9cb4b88d6d61c81d1 '),('/archive/null',NULL);").unwrap();
+        path
+    }
+
+    #[test]
+    fn lookup()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = RefDb::open(&database(&dir),"digests","path","md5").unwrap();
+        // the statement is reused
+        for _ in 0..2 {
+            assert_eq!(db.digest("/archive/a").unwrap().as_deref(),Some("b026324c6904b2a9cb4b88d6d61c81d1"));
+            assert_eq!(db.digest("/archive/null").unwrap(),None);
+            assert_eq!(db.digest("/archive/missing").unwrap(),None);
+        }
+    }
+
+    #[test]
+    fn missing_column()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(RefDb::open(&database(&dir),"digests","path","sha256"),Err(Error::RefDb{..})));
+        assert!(matches!(RefDb::open(&dir.path().join("none.db"),"digests","path","md5"),Err(Error::RefDb{..})));
+    }
+
+    #[test]
+    fn remapped_key()
+    {
+        let remap = (PathBuf::from("/mnt/archive"),"/archive/".to_string());
+        assert_eq!(key(Path::new("/mnt/archive/run1/a"),Some(&remap)),"/archive/run1/a");
+        assert_eq!(key(Path::new("/mnt/archive"),Some(&remap)),"/archive/");
+        assert_eq!(key(Path::new("/elsewhere/a"),Some(&remap)),"/elsewhere/a");
+        assert_eq!(key(Path::new("/mnt/archive/a"),None),"/mnt/archive/a");
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::ErrorKind;
+use std::str::FromStr;
+use std::time::Duration;
+
+static FIRST_DELAY:Duration = Duration::from_millis(100);
+static MAX_DELAY:Duration = Duration::new(60,0);
+
+#[cfg(unix)]
+static ERRNO_NAMES:&[(&str,i32)] = &[
+    ("EAGAIN",libc::EAGAIN),("EWOULDBLOCK",libc::EWOULDBLOCK),("EBUSY",libc::EBUSY),
+    ("EINTR",libc::EINTR),("EIO",libc::EIO),("ENODEV",libc::ENODEV),("ENXIO",libc::ENXIO),
+    ("ESTALE",libc::ESTALE),("ENOTCONN",libc::ENOTCONN),("ETIMEDOUT",libc::ETIMEDOUT),
+    ("ECONNRESET",libc::ECONNRESET),("EHOSTDOWN",libc::EHOSTDOWN),("EHOSTUNREACH",libc::EHOSTUNREACH),
+    ("ENOLCK",libc::ENOLCK),("ENOENT",libc::ENOENT),("EACCES",libc::EACCES),("EPERM",libc::EPERM),
+    #[cfg(target_os = "linux")]
+    ("ENOMEDIUM",libc::ENOMEDIUM),
+    #[cfg(target_os = "linux")]
+    ("EREMOTEIO",libc::EREMOTEIO),
+];
+#[cfg(not(unix))]
+static ERRNO_NAMES:&[(&str,i32)] = &[];
+
+static KIND_NAMES:&[(&str,ErrorKind)] = &[
+    ("TimedOut",ErrorKind::TimedOut),("Interrupted",ErrorKind::Interrupted),("WouldBlock",ErrorKind::WouldBlock),
+    ("NotFound",ErrorKind::NotFound),("PermissionDenied",ErrorKind::PermissionDenied),
+    ("ResourceBusy",ErrorKind::ResourceBusy),("StaleNetworkFileHandle",ErrorKind::StaleNetworkFileHandle),
+    ("NotConnected",ErrorKind::NotConnected),("ConnectionReset",ErrorKind::ConnectionReset),
+    ("ConnectionAborted",ErrorKind::ConnectionAborted),("HostUnreachable",ErrorKind::HostUnreachable),
+    ("NetworkUnreachable",ErrorKind::NetworkUnreachable),("NetworkDown",ErrorKind::NetworkDown),
+    ("BrokenPipe",ErrorKind::BrokenPipe),("UnexpectedEof",ErrorKind::UnexpectedEof),
+];
+
+/// an error condition worth retrying
+///
+/// Given as errno name (`EAGAIN`), errno number (`11`) or std::io::ErrorKind name (`TimedOut`).
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Retryable
+{
+    Errno(i32),
+    Kind(ErrorKind)
+}
+
+impl Retryable
+{
+    pub fn matches(&self,err:&std::io::Error) -> bool
+    {
+        match self {
+            Retryable::Errno(errno) => err.raw_os_error() == Some(*errno),
+            Retryable::Kind(kind) => err.kind() == *kind
+        }
+    }
+}
+
+impl FromStr for Retryable
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(errno) = s.parse() {
+            return Ok(Retryable::Errno(errno));
+        }
+        if let Some((_,errno)) = ERRNO_NAMES.iter().find(|(name,_)|name.eq_ignore_ascii_case(s)) {
+            return Ok(Retryable::Errno(*errno));
+        }
+        if let Some((_,kind)) = KIND_NAMES.iter().find(|(name,_)|name.eq_ignore_ascii_case(s)) {
+            return Ok(Retryable::Kind(*kind));
+        }
+        let known:Vec<&str> = ERRNO_NAMES.iter().map(|(n,_)|*n).chain(KIND_NAMES.iter().map(|(n,_)|*n)).collect();
+        Err(format!("unknown error '{s}', known are: {}",known.join(", ")))
+    }
+}
+
+/// which errors are retried how often
+#[derive(Clone,Debug)]
+pub struct RetryPolicy
+{
+    pub retryable:Vec<Retryable>,
+    /// retry forever if None
+    pub max_retries:Option<u32>
+}
+
+impl RetryPolicy
+{
+    pub fn should_retry(&self,err:&std::io::Error,attempt:u32) -> bool
+    {
+        self.max_retries.is_none_or(|max|attempt < max) && self.retryable.iter().any(|r|r.matches(err))
+    }
+    /// how long to wait before the given retry, doubling each time
+    pub fn delay(&self,attempt:u32) -> Duration
+    {
+        FIRST_DELAY.saturating_mul(1 << attempt.min(16)).min(MAX_DELAY)
+    }
+}
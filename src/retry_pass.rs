@@ -0,0 +1,60 @@
+//! --retry-failed: the files that failed are checked again at the end of the run
+use crate::{Entry, Status};
+use crate::options::Options;
+
+/// the files to check again, and how they ended
+pub(crate) struct RetryPass
+{
+    /// how often they're checked again at most
+    pub passes:u32,
+    /// check the files with a wrong digest again as well, not only those that couldn't be read
+    mismatch:bool,
+    entries:Vec<(Entry,Status)>
+}
+
+impl RetryPass
+{
+    pub fn new(options:&Options) -> RetryPass
+    {
+        RetryPass{passes:options.retry_failed,mismatch:options.retry_mismatch,entries:vec![]}
+    }
+    /// a file that couldn't be checked
+    pub fn errored(&mut self,entry:&Entry,status:Status)
+    {
+        // a file too big for the budget doesn't fit in another pass either
+        if self.passes > 0 && status != Status::Error(Some("oversize")) {self.entries.push((entry.clone(),status));}
+    }
+    /// a file with a wrong digest
+    pub fn mismatched(&mut self,entry:&Entry,status:Status)
+    {
+        if self.mismatch {self.entries.push((entry.clone(),status));}
+    }
+    pub fn is_empty(&self) -> bool {self.entries.is_empty()}
+    pub fn take(&mut self) -> Vec<(Entry,Status)> {std::mem::take(&mut self.entries)}
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn oversize_files_and_mismatches_are_not_retried_by_default()
+    {
+        let entry = Entry{
+            path:"file".into(),expected:"b1946ac92492d2347c6235b4d2611184".into(),manifest:Path::new("").into(),
+            size:0,mtime:None,id:None,recorded:None,member:None,along:vec![]
+        };
+        let mut retries = RetryPass::new(&Options{retry_failed:1,..Options::default()});
+        retries.errored(&entry,Status::Error(Some("oversize")));
+        retries.mismatched(&entry,Status::Fail);
+        assert!(retries.is_empty());
+        retries.errored(&entry,Status::Error(None));
+        assert_eq!(retries.take().len(),1);
+        assert!(retries.is_empty());
+        let mut none = RetryPass::new(&Options::default());
+        none.errored(&entry,Status::Error(None));
+        assert!(none.is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::Path;
+use std::time::SystemTime;
+use aws_config::meta::region::RegionProviderChain;
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::Client;
+use log::debug;
+use tokio::io::AsyncRead;
+use tokio::sync::OnceCell;
+use tokio_util::io::StreamReader;
+use crate::events::Events;
+use crate::retry::RetryPolicy;
+use crate::source::{Source, SourceFuture, SourceMetadata};
+use crate::Event;
+
+/// the prefix of paths of objects in S3
+pub static SCHEME:&str = "s3://";
+
+/// error codes of S3 worth retrying (besides HTTP 5xx)
+static TRANSIENT:[&str;5] = ["InternalError","ServiceUnavailable","SlowDown","Throttling","RequestTimeout"];
+
+/// objects in S3 (or compatible storage like Ceph RGW) as "s3://bucket/key", read with the AWS SDK
+///
+/// Credentials and region come from the usual AWS environment variables, config files and profiles.
+/// Objects are streamed and hashed like local files, their ETags are not used.
+pub(crate) struct S3
+{
+    /// for storage other than AWS (addressed by path then)
+    endpoint:Option<String>,
+    /// set up on first use, as the configuration is loaded asynchronously
+    client:OnceCell<Client>,
+    /// which errors are retried how often (transient ones are TimedOut), on top of the SDK's own retries
+    policy:RetryPolicy,
+    events:Events
+}
+
+/// the bucket and key of an "s3://bucket/key" path
+fn split(path:&Path) -> std::io::Result<(&str,&str)>
+{
+    path.to_str().and_then(|path|path.strip_prefix(SCHEME)).and_then(|path|path.split_once('/'))
+        .filter(|(bucket,key)|!bucket.is_empty() && !key.is_empty())
+        .ok_or_else(||std::io::Error::new(std::io::ErrorKind::InvalidInput,format!("'{}' is no s3://bucket/key",path.to_string_lossy())))
+}
+
+/// the error for a failed request, transient ones (timeouts, connections, 5xx, throttling) are TimedOut
+fn error<E>(e:SdkError<E,aws_sdk_s3::config::http::HttpResponse>) -> std::io::Error where E:ProvideErrorMetadata+std::error::Error+Send+Sync+'static
+{
+    let status = e.raw_response().map(|response|response.status().as_u16());
+    let code = e.as_service_error().and_then(|e|e.code()).unwrap_or_default();
+    let kind = match (&e,status) {
+        (SdkError::TimeoutError(_)|SdkError::DispatchFailure(_)|SdkError::ResponseError(_),_) => std::io::ErrorKind::TimedOut,
+        (_,Some(404)) => std::io::ErrorKind::NotFound,
+        (_,Some(403)) => std::io::ErrorKind::PermissionDenied,
+        (_,Some(408|429|500..=599)) => std::io::ErrorKind::TimedOut,
+        _ if TRANSIENT.contains(&code) => std::io::ErrorKind::TimedOut,
+        _ if code == "NoSuchKey" => std::io::ErrorKind::NotFound,
+        _ if code == "AccessDenied" => std::io::ErrorKind::PermissionDenied,
+        _ => std::io::ErrorKind::Other
+    };
+    std::io::Error::new(kind,format!("S3: {}",DisplayErrorContext(e)))
+}
+
+impl S3
+{
+    pub fn new(endpoint:Option<String>,policy:RetryPolicy,events:Events) -> S3
+    {
+        S3{endpoint,client:OnceCell::new(),policy,events}
+    }
+    async fn client(&self) -> &Client
+    {
+        self.client.get_or_init(||async {
+            // other storage often doesn't care for the region, but signing needs one
+            let region = RegionProviderChain::default_provider().or_else("us-east-1");
+            let shared = aws_config::defaults(BehaviorVersion::latest()).region(region).load().await;
+            let mut config = aws_sdk_s3::config::Builder::from(&shared);
+            if let Some(endpoint) = &self.endpoint {config = config.endpoint_url(endpoint).force_path_style(true);}
+            Client::from_conf(config.build())
+        }).await
+    }
+    /// size and modification time by HeadObject
+    async fn head(&self,bucket:&str,key:&str) -> std::io::Result<SourceMetadata>
+    {
+        debug!("HeadObject {bucket} {key}");
+        let head = self.client().await.head_object().bucket(bucket).key(key).send().await.map_err(error)?;
+        let size = head.content_length().and_then(|size|u64::try_from(size).ok()).ok_or_else(||{
+            std::io::Error::new(std::io::ErrorKind::InvalidData,"S3 gives no ContentLength")
+        })?;
+        let modified = head.last_modified().and_then(|time|SystemTime::try_from(*time).ok());
+        Ok(SourceMetadata{size,modified})
+    }
+    /// start streaming the object from offset on (by a ranged GetObject)
+    async fn download(&self,bucket:&str,key:&str,offset:u64) -> std::io::Result<Box<dyn AsyncRead+Unpin+Send>>
+    {
+        debug!("GetObject {bucket} {key} from offset {offset}");
+        let mut request = self.client().await.get_object().bucket(bucket).key(key);
+        if offset > 0 {request = request.range(format!("bytes={offset}-"));}
+        let body = request.send().await.map_err(error)?.body;
+        // a transfer cut short is worth retrying
+        let chunks = futures_util::stream::unfold(body,|mut body|async {
+            let chunk = body.next().await?.map_err(|e|std::io::Error::new(std::io::ErrorKind::TimedOut,e));
+            Some((chunk,body))
+        });
+        Ok(Box::new(StreamReader::new(Box::pin(chunks))))
+    }
+}
+
+impl Source for S3
+{
+    fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+    {
+        Box::pin(async move {
+            let (bucket,key) = split(path)?;
+            let mut attempt = 0;
+            loop {
+                match self.head(bucket,key).await {
+                    Err(err) if self.policy.should_retry(&err,attempt) => {
+                        let delay = self.policy.delay(attempt);
+                        debug!("looking up '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    result => return result
+                }
+            }
+        })
+    }
+    fn open<'a>(&'a self,path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+    {
+        Box::pin(async move {
+            let (bucket,key) = split(path)?;
+            self.events.emit(||Event::Opening{path:path.to_path_buf()});
+            let mut attempt = 0;
+            loop {
+                match self.download(bucket,key,offset).await {
+                    Ok(download) => return Ok(download),
+                    Err(err) if self.policy.should_retry(&err,attempt) => {
+                        let delay = self.policy.delay(attempt);
+                        debug!("downloading '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        self.events.emit(||Event::OpenRetried{path:path.to_path_buf(),attempt,error:err.kind()});
+                    }
+                    Err(err) => return Err(err)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::{BufRead, Write};
+    use tokio::io::AsyncReadExt;
+    use super::*;
+
+    #[test]
+    fn paths_are_bucket_and_key()
+    {
+        assert_eq!(split(Path::new("s3://archive/run1/file")).unwrap(),("archive","run1/file"));
+        assert!(split(Path::new("s3://archive/")).is_err());
+        assert!(split(Path::new("archive/file")).is_err());
+    }
+
+    #[tokio::test]
+    async fn objects_are_read_by_range()
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}",listener.local_addr().unwrap());
+        let server = std::thread::spawn(move ||{
+            (0..2).map(|_|{
+                let (stream,_) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = std::io::BufReader::new(&stream);
+                while reader.read_line(&mut request).unwrap() > 2 && !request.ends_with("\r\n\r\n") {}
+                let (status,body) = if request.starts_with("HEAD") {("200 OK","")} else {("206 Partial Content","world")};
+                write!(&stream,"HTTP/1.1 {status}\r\nContent-Length: {}\r\nLast-Modified: Tue, 15 Nov 1994 08:12:31 GMT\r\nConnection: close\r\n\r\n{body}",
+                    if body.is_empty() {11} else {body.len()}).unwrap();
+                request
+            }).collect::<Vec<_>>()
+        });
+        // the credentials only sign (and no other test talks to AWS)
+        std::env::set_var("AWS_ACCESS_KEY_ID","test");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY","test");
+        std::env::set_var("AWS_REGION","us-east-1");
+        let s3 = S3::new(Some(endpoint),RetryPolicy{retryable:vec![],max_retries:Some(0)},Events::default());
+        let path = Path::new("s3://archive/run1/file");
+        let metadata = s3.metadata(path).await.unwrap();
+        assert_eq!(metadata.size,11);
+        assert!(metadata.modified.is_some());
+        let mut content = String::new();
+        s3.open(path,6).await.unwrap().read_to_string(&mut content).await.unwrap();
+        assert_eq!(content,"world");
+        let requests = server.join().unwrap();
+        assert!(requests[1].starts_with("GET /archive/run1/file"),"{}",requests[1]);
+        assert!(requests[1].to_ascii_lowercase().contains("range: bytes=6-"),"{}",requests[1]);
+    }
+}
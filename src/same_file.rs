@@ -0,0 +1,96 @@
+//! --duplicates: files listed more than once (or hard links of each other) are checked once
+use std::collections::{HashMap, HashSet};
+use crate::{Entry, FileId};
+use crate::options::{Duplicates, Options};
+
+/// the files checked and being checked, by their id
+pub(crate) struct SameFile
+{
+    pub mode:Duplicates,
+    /// files being checked right now, and the algorithms (by their hex length) computed for them
+    running:HashMap<FileId,Vec<usize>>,
+    /// digests of the files checked by algorithm (with coalesced duplicates)
+    digests:HashMap<(FileId,usize),String>,
+    /// entries waiting for the result of the same file being checked right now, and if it has several hard links
+    waiting:HashMap<FileId,Vec<(Entry,bool)>>,
+    /// files listed with a digest that failed (with coalesced duplicates), they're not released or deleted for the listings that verified
+    failed:HashSet<FileId>
+}
+
+impl SameFile
+{
+    pub fn new(options:&Options) -> SameFile
+    {
+        // the stream digest needs each entry read
+        let mode = if options.stream_digest {Duplicates::Check} else {options.duplicates};
+        SameFile{mode,running:HashMap::new(),digests:HashMap::new(),waiting:HashMap::new(),failed:HashSet::new()}
+    }
+    /// the digest of the file computed before for the algorithm with the length
+    pub fn digest(&self,id:&FileId,len:usize) -> Option<String> {self.digests.get(&(id.clone(),len)).cloned()}
+    /// the algorithms (by their hex length) the file is being checked for right now
+    pub fn running(&self,id:&FileId) -> Option<&[usize]> {self.running.get(id).map(Vec::as_slice)}
+    /// the file of the entry (and those read along) is being checked
+    pub fn start(&mut self,id:&FileId,entry:&Entry)
+    {
+        let mut lens:Vec<usize> = std::iter::once(entry).chain(&entry.along).map(|e|e.expected.len()).collect();
+        lens.sort_unstable();
+        lens.dedup();
+        self.running.insert(id.clone(),lens);
+    }
+    pub fn done(&mut self,id:&FileId) {self.running.remove(id);}
+    /// an entry waits for the result of the file being checked
+    pub fn wait(&mut self,id:FileId,entry:Entry,hardlink:bool) {self.waiting.entry(id).or_default().push((entry,hardlink));}
+    pub fn take_waiting(&mut self,id:&FileId) -> Vec<(Entry,bool)> {self.waiting.remove(id).unwrap_or_default()}
+    pub fn waiting(&self,id:&FileId) -> impl Iterator<Item=&Entry> {self.waiting.get(id).into_iter().flatten().map(|(e,_)|e)}
+    /// keep the digests computed for the file, when coalescing
+    pub fn remember(&mut self,id:&FileId,computed:&[String])
+    {
+        if self.mode != Duplicates::Coalesce {return}
+        for digest in computed {self.digests.insert((id.clone(),digest.len()),digest.clone());}
+    }
+    pub fn fail(&mut self,id:&FileId) {self.failed.insert(id.clone());}
+    pub fn failing(&self,id:&FileId) -> bool {self.failed.contains(id)}
+    /// forget the digests of the file, to read it again
+    pub fn forget(&mut self,id:&FileId) {self.digests.retain(|(checked,_),_|checked != id);}
+    /// forget the digests of all files checked
+    pub fn forget_all(&mut self)
+    {
+        self.digests.clear();
+        self.failed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn digests_are_kept_by_algorithm()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path,"hello\n").unwrap();
+        let id = crate::file_id(&path,&path.metadata().unwrap()).unwrap();
+        let entry = Entry{
+            path,expected:"b1946ac92492d2347c6235b4d2611184".into(),manifest:Path::new("").into(),
+            size:0,mtime:None,id:Some(id.clone()),recorded:None,member:None,along:vec![]
+        };
+        let mut same = SameFile::new(&Options::default());
+        same.start(&id,&entry);
+        assert_eq!(same.running(&id),Some([32].as_slice()));
+        same.wait(id.clone(),entry.clone(),true);
+        assert_eq!(same.waiting(&id).count(),1);
+        same.remember(&id,std::slice::from_ref(&entry.expected));
+        same.done(&id);
+        assert!(same.running(&id).is_none() && same.take_waiting(&id).len() == 1);
+        assert_eq!(same.digest(&id,32).as_deref(),Some("b1946ac92492d2347c6235b4d2611184"));
+        assert!(same.digest(&id,64).is_none());
+        same.forget(&id);
+        assert!(same.digest(&id,32).is_none());
+        let mut checked = SameFile::new(&Options{stream_digest:true,..Options::default()});
+        checked.remember(&id,&[entry.expected]);
+        assert!(checked.mode == Duplicates::Check && checked.digest(&id,32).is_none());
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt::{Display, Formatter};
+use std::time::SystemTime;
+
+/// a small generator (SplitMix64), so a seed gives the same choice everywhere
+pub struct Rng(u64);
+
+impl Rng
+{
+    pub fn new(seed:u64) -> Rng {Rng(seed)}
+    pub fn next_u64(&mut self) -> u64
+    {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    /// uniform in (0,1]
+    pub fn next_f64(&mut self) -> f64
+    {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+    /// uniform in 0..n
+    pub fn below(&mut self,n:usize) -> usize
+    {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// a seed from the clock and the pid, for when none is given
+pub fn random_seed() -> u64
+{
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    Rng::new(nanos ^ ((std::process::id() as u64) << 32)).next_u64()
+}
+
+/// how many entries are chosen
+#[derive(Clone,Copy)]
+pub enum Size
+{
+    /// this fraction of the entries (rounded)
+    Fraction(f64),
+    Count(usize)
+}
+
+impl Size
+{
+    fn of(&self,entries:usize) -> usize
+    {
+        match *self {
+            Size::Fraction(fraction) => (fraction*entries as f64).round() as usize,
+            Size::Count(count) => count.min(entries)
+        }
+    }
+}
+
+/// the parameters of --sample, to choose a random subset of the entries
+#[derive(Clone)]
+pub struct Sampling
+{
+    pub size:Size,
+    pub seed:u64,
+    /// weight the entries by their size
+    pub by_bytes:bool
+}
+
+impl Sampling
+{
+    /// the indices (ascending) of the entries chosen, weights are only used with by_bytes
+    ///
+    /// Weighted entries are chosen without replacement by the keys u^(1/weight) (Efraimidis-Spirakis).
+    pub fn choose(&self,weights:&[u64]) -> Vec<usize>
+    {
+        let count = self.size.of(weights.len());
+        let mut rng = Rng::new(self.seed);
+        let mut chosen:Vec<usize> = if self.by_bytes {
+            // compare ln(u)/weight instead, the same order without underflowing
+            let mut keys:Vec<(f64,usize)> = weights.iter().enumerate()
+                .map(|(i,&weight)|(rng.next_f64().ln()/weight.max(1) as f64,i)).collect();
+            keys.sort_by(|a,b|b.0.total_cmp(&a.0));
+            keys.into_iter().take(count).map(|(_,i)|i).collect()
+        } else {
+            // the first count of a partial Fisher-Yates shuffle
+            let mut indices:Vec<usize> = (0..weights.len()).collect();
+            for i in 0..count {
+                let j = i+rng.below(indices.len()-i);
+                indices.swap(i,j);
+            }
+            indices.truncate(count);
+            indices
+        };
+        chosen.sort_unstable();
+        chosen
+    }
+}
+
+impl Display for Sampling
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.size {
+            Size::Fraction(fraction) => write!(f,"{}%",fraction*100.0)?,
+            Size::Count(count) => write!(f,"{count} entries")?
+        }
+        if self.by_bytes {f.write_str(" weighted by size")?;}
+        write!(f,", seed {}",self.seed)
+    }
+}
+
+/// put the items in random order (Fisher-Yates)
+pub fn shuffle<T>(items:&mut [T],seed:u64)
+{
+    let mut rng = Rng::new(seed);
+    for i in (1..items.len()).rev() {
+        items.swap(i,rng.below(i+1));
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use log::debug;
+use russh::client::{Config, Handle, Handler};
+use russh::keys::agent::client::AgentClient;
+use russh::keys::{PrivateKeyWithHashAlg, PublicKeyOrCertificate};
+use russh_sftp::client::SftpSession;
+use russh_sftp::protocol::StatusCode;
+use tokio::io::{AsyncRead, AsyncSeekExt, ReadBuf};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use crate::events::Events;
+use crate::retry::RetryPolicy;
+use crate::source::{Source, SourceFuture, SourceMetadata};
+use crate::Event;
+
+/// the prefix of paths of remote files
+pub static SCHEME:&str = "sftp://";
+
+/// the keys tried without --ssh-key (if they have no passphrase), like ssh does
+static DEFAULT_KEYS:[&str;3] = ["id_ed25519","id_ecdsa","id_rsa"];
+
+/// files on an SFTP server as "sftp://[user@]host[:port]/path", read with russh
+///
+/// All reads of a host share one connection and SFTP session, each file read is a handle of it.
+/// Keys come from the ssh agent (or the key file given, or the default ones in ~/.ssh), the host key has to be in ~/.ssh/known_hosts.
+pub(crate) struct Sftp
+{
+    /// the key file to use besides the ones of the agent
+    key:Option<PathBuf>,
+    /// files to read at once
+    channels:Arc<Semaphore>,
+    /// which errors are retried how often (connection failures are TimedOut)
+    policy:RetryPolicy,
+    events:Events,
+    /// the sessions by user, host and port, connected on first use (and again once the connection is lost)
+    sessions:Mutex<HashMap<Remote,Arc<Session>>>
+}
+
+/// whom to connect to
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+struct Remote
+{
+    user:String,
+    host:String,
+    port:u16
+}
+
+/// an SFTP session and the connection it runs in
+struct Session
+{
+    sftp:SftpSession,
+    ssh:Handle<KnownHosts>
+}
+
+/// accepts the host keys in ~/.ssh/known_hosts only
+struct KnownHosts
+{
+    host:String,
+    port:u16
+}
+
+impl Handler for KnownHosts
+{
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self,key:&PublicKeyOrCertificate) -> Result<bool,Self::Error>
+    {
+        let PublicKeyOrCertificate::PublicKey{key,..} = key else {return Ok(false)};
+        Ok(russh::keys::check_known_hosts(&self.host,self.port,key)?)
+    }
+}
+
+/// the remote and path of an "sftp://[user@]host[:port]/path" path, the user is the local one if not given
+fn split(path:&Path) -> std::io::Result<(Remote,&str)>
+{
+    let invalid = ||std::io::Error::new(std::io::ErrorKind::InvalidInput,format!("'{}' is no sftp://host/path",path.to_string_lossy()));
+    let (destination,remote) = path.to_str().and_then(|path|path.strip_prefix(SCHEME))
+        .and_then(|path|path.find('/').map(|slash|path.split_at(slash)))
+        .filter(|(destination,remote)|!destination.is_empty() && remote.len() > 1)
+        .ok_or_else(invalid)?;
+    let (user,host) = destination.rsplit_once('@').map_or((None,destination),|(user,host)|(Some(user),host));
+    // IPv6 addresses are in brackets, as they have colons
+    let (host,port) = match host.strip_prefix('[') {
+        Some(address) => address.split_once(']').map(|(address,rest)|(address,rest.strip_prefix(':'))).ok_or_else(invalid)?,
+        None => host.split_once(':').map_or((host,None),|(host,port)|(host,Some(port)))
+    };
+    let port = port.map_or(Ok(22),|port|port.parse().map_err(|_|invalid()))?;
+    let user = user.map(str::to_string).or_else(||std::env::var("USER").or_else(|_|std::env::var("USERNAME")).ok()).ok_or_else(||{
+        std::io::Error::new(std::io::ErrorKind::InvalidInput,format!("no user given in '{}', and no USER set",path.to_string_lossy()))
+    })?;
+    Ok((Remote{user,host:host.to_string(),port},remote))
+}
+
+/// the error for failing to connect or log in, only connection failures are TimedOut
+fn ssh_error(remote:&Remote,e:russh::Error) -> std::io::Error
+{
+    let kind = match &e {
+        russh::Error::UnknownKey|russh::Error::KeyChanged{..}|russh::Error::Keys(russh::keys::Error::KeyChanged{..}) => std::io::ErrorKind::PermissionDenied,
+        _ => std::io::ErrorKind::TimedOut
+    };
+    let hint = if matches!(e,russh::Error::UnknownKey) {" (it has to be in ~/.ssh/known_hosts)"} else {""};
+    std::io::Error::new(kind,format!("ssh {}@{}:{}: {e}{hint}",remote.user,remote.host,remote.port))
+}
+
+/// the error for a failed SFTP request
+fn sftp_error(e:russh_sftp::client::error::Error) -> std::io::Error
+{
+    use russh_sftp::client::error::Error;
+    let kind = match &e {
+        Error::Status(status) => match status.status_code {
+            StatusCode::NoSuchFile => std::io::ErrorKind::NotFound,
+            StatusCode::PermissionDenied => std::io::ErrorKind::PermissionDenied,
+            StatusCode::NoConnection|StatusCode::ConnectionLost => std::io::ErrorKind::TimedOut,
+            _ => std::io::ErrorKind::Other
+        }
+        Error::IO(_)|Error::Timeout => std::io::ErrorKind::TimedOut,
+        _ => std::io::ErrorKind::Other
+    };
+    std::io::Error::new(kind,format!("sftp: {e}"))
+}
+
+/// the content of a remote file, taking a channel until it's dropped
+struct Download
+{
+    file:russh_sftp::client::fs::File,
+    session:Arc<Session>,
+    _channel:OwnedSemaphorePermit
+}
+
+impl AsyncRead for Download
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>>
+    {
+        let download = self.get_mut();
+        match Pin::new(&mut download.file).poll_read(cx,buf) {
+            // reads failing as the connection is gone are resumed (on a new one)
+            Poll::Ready(Err(e)) if download.session.ssh.is_closed() => Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::TimedOut,e))),
+            polled => polled
+        }
+    }
+}
+
+impl Sftp
+{
+    /// key is tried besides the keys of the agent, channels is how many files are read at once
+    pub fn new(key:Option<PathBuf>,channels:u32,policy:RetryPolicy,events:Events) -> Sftp
+    {
+        Sftp{key,channels:Arc::new(Semaphore::new(channels as usize)),policy,events,sessions:Mutex::new(HashMap::new())}
+    }
+    /// a channel to read in, waiting for one to be free
+    async fn channel(&self) -> OwnedSemaphorePermit
+    {
+        self.channels.clone().acquire_owned().await.expect("the channels are never closed")
+    }
+    /// the session to the remote, connecting (again) if there's none (or its connection is gone)
+    async fn session(&self,remote:&Remote) -> std::io::Result<Arc<Session>>
+    {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get(remote).filter(|session|!session.ssh.is_closed()) {return Ok(session.clone());}
+        let session = Arc::new(self.connect(remote).await?);
+        sessions.insert(remote.clone(),session.clone());
+        Ok(session)
+    }
+    async fn connect(&self,remote:&Remote) -> std::io::Result<Session>
+    {
+        debug!("connecting to {}@{}:{}",remote.user,remote.host,remote.port);
+        let config = Arc::new(Config{keepalive_interval:Some(Duration::from_secs(30)),..Config::default()});
+        let known = KnownHosts{host:remote.host.clone(),port:remote.port};
+        let mut ssh = russh::client::connect(config,(remote.host.as_str(),remote.port),known).await.map_err(|e|ssh_error(remote,e))?;
+        if !self.authenticate(&mut ssh,remote).await? {
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied,format!(
+                "ssh {}@{}:{}: none of the keys (of the agent, or the key file) is accepted",remote.user,remote.host,remote.port
+            )));
+        }
+        let channel = ssh.channel_open_session().await.map_err(|e|ssh_error(remote,e))?;
+        channel.request_subsystem(true,"sftp").await.map_err(|e|ssh_error(remote,e))?;
+        let sftp = SftpSession::new(channel.into_stream()).await.map_err(sftp_error)?;
+        Ok(Session{sftp,ssh})
+    }
+    /// log in with the keys of the agent, then the key file (or the default ones), true once one is accepted
+    async fn authenticate(&self,ssh:&mut Handle<KnownHosts>,remote:&Remote) -> std::io::Result<bool>
+    {
+        let rsa_hash = ssh.best_supported_rsa_hash().await.ok().flatten().flatten();
+        if let Ok(mut agent) = AgentClient::connect_env().await {
+            for identity in agent.request_identities().await.unwrap_or_default() {
+                let key = identity.public_key().into_owned();
+                match ssh.authenticate_publickey_with(&remote.user,key,rsa_hash,&mut agent).await {
+                    Ok(result) if result.success() => return Ok(true),
+                    Ok(_) => {}
+                    Err(e) => debug!("the ssh agent failed signing with '{}': {e}",identity.comment())
+                }
+            }
+        }
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let keys:Vec<PathBuf> = match &self.key {
+            Some(key) => vec![key.clone()],
+            None => home.map(|home|DEFAULT_KEYS.iter().map(|key|home.join(".ssh").join(key)).collect()).unwrap_or_default()
+        };
+        for path in keys {
+            let key = match russh::keys::load_secret_key(&path,None) {
+                Ok(key) => key,
+                // a default key that isn't there, or has a passphrase, is just not tried
+                Err(e) if self.key.is_none() => {debug!("not using '{}': {e}",path.to_string_lossy());continue}
+                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,format!("can't load the ssh key '{}': {e}",path.to_string_lossy())))
+            };
+            let key = PrivateKeyWithHashAlg::new(Arc::new(key),rsa_hash);
+            if ssh.authenticate_publickey(&remote.user,key).await.map_err(|e|ssh_error(remote,e))?.success() {return Ok(true)}
+        }
+        Ok(false)
+    }
+    /// size and modification time (following symlinks)
+    async fn stat(&self,path:&Path) -> std::io::Result<SourceMetadata>
+    {
+        let (remote,file) = split(path)?;
+        let _channel = self.channel().await;
+        let metadata = self.session(&remote).await?.sftp.metadata(file).await.map_err(sftp_error)?;
+        let size = metadata.size.ok_or_else(||std::io::Error::new(std::io::ErrorKind::InvalidData,"the server gives no size"))?;
+        Ok(SourceMetadata{size,modified:metadata.mtime.map(|mtime|SystemTime::UNIX_EPOCH+Duration::from_secs(mtime.into()))})
+    }
+    /// open the file at offset, failing if there's no connection
+    async fn download(&self,path:&Path,offset:u64) -> std::io::Result<Download>
+    {
+        let (remote,file) = split(path)?;
+        let channel = self.channel().await;
+        debug!("opening '{file}' on {} at offset {offset}",remote.host);
+        let session = self.session(&remote).await?;
+        let mut file = session.sftp.open(file).await.map_err(sftp_error)?;
+        if offset > 0 {file.seek(SeekFrom::Start(offset)).await?;}
+        Ok(Download{file,session,_channel:channel})
+    }
+}
+
+impl Source for Sftp
+{
+    fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+    {
+        Box::pin(async move {
+            let mut attempt = 0;
+            loop {
+                match self.stat(path).await {
+                    Err(err) if self.policy.should_retry(&err,attempt) => {
+                        let delay = self.policy.delay(attempt);
+                        debug!("looking up '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    result => return result
+                }
+            }
+        })
+    }
+    fn open<'a>(&'a self,path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+    {
+        Box::pin(async move {
+            split(path)?;
+            self.events.emit(||Event::Opening{path:path.to_path_buf()});
+            let mut attempt = 0;
+            loop {
+                match self.download(path,offset).await {
+                    Ok(download) => return Ok(Box::new(download) as Box<dyn AsyncRead+Unpin+Send>),
+                    Err(err) if self.policy.should_retry(&err,attempt) => {
+                        let delay = self.policy.delay(attempt);
+                        debug!("reading '{}' failed with '{err}', retrying in {delay:?}",path.to_string_lossy());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        self.events.emit(||Event::OpenRetried{path:path.to_path_buf(),attempt,error:err.kind()});
+                    }
+                    Err(err) => return Err(err)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn remote(user:&str,host:&str,port:u16) -> Remote {Remote{user:user.into(),host:host.into(),port}}
+
+    #[test]
+    fn paths_are_remote_and_file()
+    {
+        assert_eq!(split(Path::new("sftp://archive@tape1/data/run1")).unwrap(),(remote("archive","tape1",22),"/data/run1"));
+        assert_eq!(split(Path::new("sftp://archive@tape1:2222/f")).unwrap(),(remote("archive","tape1",2222),"/f"));
+        assert_eq!(split(Path::new("sftp://archive@[::1]:2222/f")).unwrap(),(remote("archive","::1",2222),"/f"));
+        assert!(split(Path::new("sftp://archive@tape1:ssh/f")).is_err());
+        assert!(split(Path::new("sftp://tape1/")).is_err());
+    }
+
+    #[test]
+    fn unknown_hosts_are_denied()
+    {
+        let remote = remote("archive","tape1",22);
+        let denied = ssh_error(&remote,russh::Error::UnknownKey);
+        assert_eq!(denied.kind(),std::io::ErrorKind::PermissionDenied);
+        assert!(denied.to_string().contains("known_hosts"));
+        assert_eq!(ssh_error(&remote,russh::Error::ConnectionTimeout).kind(),std::io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+#[cfg(feature="minisign")]
+use minisign_verify::{PublicKey, Signature};
+use crate::command::{self, ExtCommand};
+use crate::error::Error;
+
+/// gets the signature as argument and the content on stdin, tells the signatures verified on stdout
+static GPG:&str = "gpg --batch --no-tty --status-fd 1 --verify {} -";
+
+/// verifies the detached signature of checksum files before their entries are trusted
+///
+/// A checksum file needs a signature `<file>.minisig` that verifies with the minisign public key,
+/// or `<file>.asc` that gpg verifies as made by one of the keys given (by fingerprint).
+pub struct Verifier
+{
+    #[cfg(feature="minisign")]
+    minisign:Option<PublicKey>,
+    gpg:Option<Gpg>
+}
+
+/// gpg, and the fingerprints of the keys whose signatures are accepted
+struct Gpg
+{
+    command:ExtCommand,
+    fingerprints:Vec<String>,
+    /// GNUPGHOME, if a home directory (with its keyring) is given
+    env:command::Env
+}
+
+/// the fingerprint in upper case without spaces, if it is one (40 or 64 hex digits)
+fn fingerprint(key:&str) -> Result<String,Error>
+{
+    let fingerprint:String = key.trim_start_matches("0x").chars().filter(|c|!c.is_whitespace()).collect::<String>().to_ascii_uppercase();
+    match [40,64].contains(&fingerprint.len()) && fingerprint.chars().all(|c|c.is_ascii_hexdigit()) {
+        true => Ok(fingerprint),
+        false => Err(Error::PublicKey{key:key.into(),reason:"is no fingerprint of 40 or 64 hex digits".into()})
+    }
+}
+
+/// the key of a VALIDSIG line of gpg's status that is one of the fingerprints (the signing key or its primary key)
+///
+/// gpg tells VALIDSIG for good signatures regardless of how the key is trusted, the fingerprints given are the trust.
+fn signed_by<'a>(status:&[u8],fingerprints:&'a [String]) -> Option<&'a str>
+{
+    String::from_utf8_lossy(status).lines().filter_map(|line|line.strip_prefix("[GNUPG:] VALIDSIG ")).find_map(|validsig|{
+        let fields:Vec<&str> = validsig.split_whitespace().collect();
+        // the primary key is the 10th field after the signing key, if there is one
+        let signer = [fields.first(),fields.get(9)].into_iter().flatten()
+            .find_map(|key|fingerprints.iter().find(|fingerprint|fingerprint.eq_ignore_ascii_case(key)));
+        signer
+    }).map(String::as_str)
+}
+
+/// the file next to the checksum file with the extension appended
+fn sidecar(manifest:&Path,extension:&str) -> PathBuf
+{
+    let mut sidecar = manifest.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(extension);
+    sidecar.into()
+}
+
+impl Verifier
+{
+    /// the key is read from the file if there is one, otherwise it's taken as the base64 encoded key itself
+    /// (it's ignored without the minisign feature)
+    ///
+    /// gpg signatures are accepted from the keys of the fingerprints given (none for no gpg signatures), with the keys
+    /// of the keyring in gpg_home (or the default one).
+    #[cfg_attr(not(feature="minisign"),allow(unused_variables))]
+    pub fn new(key:Option<&str>,gpg:&[String],gpg_home:Option<&Path>,timeout:Option<std::time::Duration>) -> Result<Verifier,Error>
+    {
+        #[cfg(feature="minisign")]
+        let minisign = key.map(|key|match Path::new(key).is_file() {
+            true => PublicKey::from_file(key),
+            false => PublicKey::from_base64(key)
+        }.map_err(|e|Error::PublicKey{key:key.into(),reason:e.to_string()})).transpose()?;
+        let fingerprints = gpg.iter().map(|key|fingerprint(key)).collect::<Result<Vec<_>,_>>()?;
+        let gpg = (!fingerprints.is_empty()).then(||Gpg{
+            command:ExtCommand::new(GPG,command::Options{timeout,..Default::default()}),
+            fingerprints,
+            env:gpg_home.map(|home|("GNUPGHOME",home.as_os_str().to_owned())).into_iter().collect()
+        });
+        if let Some(gpg) = &gpg {
+            gpg.command.validate().map_err(|reason|Error::InvalidCommand{what:"gpg",reason})?;
+        }
+        Ok(Verifier{#[cfg(feature="minisign")] minisign,gpg})
+    }
+    /// check the content of the checksum file, returns which signature was accepted or why it was rejected
+    ///
+    /// Both check the content given (gpg gets it on stdin), so it's what was read that is verified, not the file on disk.
+    #[cfg_attr(not(feature="minisign"),allow(unused_variables))]
+    pub async fn verify(&self,manifest:&Path,content:&[u8]) -> Result<&'static str,String>
+    {
+        let minisig = sidecar(manifest,"minisig");
+        #[cfg(feature="minisign")]
+        if let Some(key) = self.minisign.as_ref().filter(|_|minisig.exists()) {
+            let signature = std::fs::read_to_string(&minisig)
+                .map_err(|e|format!("failed to read '{}': {e}",minisig.to_string_lossy()))?;
+            let signature = Signature::decode(&signature)
+                .map_err(|e|format!("invalid signature '{}': {e}",minisig.to_string_lossy()))?;
+            return key.verify(content,&signature,false).map(|_|"minisign").map_err(|e|e.to_string());
+        }
+        let asc = sidecar(manifest,"asc");
+        if let Some(gpg) = self.gpg.as_ref().filter(|_|asc.exists()) {
+            let command = &gpg.command;
+            let output = command.output_with_input("verifying signature of",&[asc],&gpg.env,content).await
+                .map_err(|e|format!("failed running {command}: {e}"))?;
+            if !output.status.success() {
+                return Err(format!("{command} failed ({}): {}",output.status,String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            return match signed_by(&output.stdout,&gpg.fingerprints) {
+                Some(key) => {
+                    log::debug!("'{}' is signed by {key}",manifest.to_string_lossy());
+                    Ok("gpg")
+                }
+                None => Err("the gpg signature is not made by any of the keys given".into())
+            };
+        }
+        #[cfg(not(feature="minisign"))]
+        let minisign:Option<()> = None;
+        #[cfg(feature="minisign")]
+        let minisign = self.minisign.as_ref();
+        let expected:Vec<String> = [minisign.map(|_|minisig),self.gpg.as_ref().map(|_|asc)]
+            .into_iter().flatten().map(|p|format!("'{}'",p.to_string_lossy())).collect();
+        Err(format!("no signature {}",expected.join(" or ")))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const KEY:&str = "0123456789ABCDEF0123456789ABCDEF01234567";
+
+    #[test]
+    fn signatures_are_accepted_from_the_keys_given()
+    {
+        let fingerprints = vec![fingerprint(&KEY.to_lowercase()).unwrap()];
+        let status = |keys:&str|format!("[GNUPG:] GOODSIG 89ABCDEF01234567 test\n[GNUPG:] VALIDSIG {keys}\n[GNUPG:] TRUST_UNDEFINED 0 pgp\n");
+        let other = "F".repeat(40);
+        assert_eq!(signed_by(status(&format!("{KEY} 2026-10-14 1791936000 0 4 0 22 10 00 {KEY}")).as_bytes(),&fingerprints),Some(KEY));
+        // made by a subkey of the key given
+        assert_eq!(signed_by(status(&format!("{other} 2026-10-14 1791936000 0 4 0 22 10 00 {KEY}")).as_bytes(),&fingerprints),Some(KEY));
+        assert_eq!(signed_by(status(&format!("{other} 2026-10-14 1791936000 0 4 0 22 10 00 {other}")).as_bytes(),&fingerprints),None);
+        assert_eq!(signed_by(b"[GNUPG:] GOODSIG 89ABCDEF01234567 test\n",&fingerprints),None);
+        assert_eq!(fingerprint("0123 4567 89AB CDEF 0123  4567 89AB CDEF 0123 4567").unwrap(),KEY);
+        assert!(fingerprint("89ABCDEF01234567").is_err());
+    }
+
+    /// run gpg with the home directory, its stdout
+    #[cfg(unix)]
+    fn gpg(home:&Path,args:&[&str]) -> String
+    {
+        let output = std::process::Command::new("gpg").arg("--batch").arg("--homedir").arg(home).args(args).output().unwrap();
+        assert!(output.status.success(),"gpg {args:?}: {}",String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn gpg_verifies_the_content_read()
+    {
+        if ExtCommand::new("gpg",Default::default()).resolve().is_none() {return eprintln!("no gpg to test with");}
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("gnupg");
+        std::fs::create_dir(&home).unwrap();
+        std::fs::set_permissions(&home,std::os::unix::fs::PermissionsExt::from_mode(0o700)).unwrap();
+        gpg(&home,&["--passphrase","","--quick-gen-key","test <test@example.org>","ed25519","sign","never"]);
+        let key = gpg(&home,&["--with-colons","--list-keys"]).lines().find_map(|line|line.strip_prefix("fpr:::::::::"))
+            .map(|fpr|fpr.trim_end_matches(':').to_owned()).unwrap();
+        let manifest = dir.path().join("md5sum");
+        let content = b"b1946ac92492d2347c6235b4d2611184  hello\n";
+        std::fs::write(&manifest,content).unwrap();
+        gpg(&home,&["--pinentry-mode","loopback","--passphrase","","--armor","--detach-sign",manifest.to_str().unwrap()]);
+        let verifier = Verifier::new(None,std::slice::from_ref(&key),Some(&home),None).unwrap();
+        assert_eq!(verifier.verify(&manifest,content).await,Ok("gpg"));
+        // the file on disk is signed, but not what was read
+        assert!(verifier.verify(&manifest,b"00000000000000000000000000000000  hello\n").await.is_err());
+        let stranger = Verifier::new(None,&["F".repeat(40)],Some(&home),None).unwrap();
+        assert!(stranger.verify(&manifest,content).await.unwrap_err().contains("not made by any of the keys"));
+        let _ = std::process::Command::new("gpgconf").arg("--homedir").arg(&home).args(["--kill","gpg-agent"]).status();
+    }
+}
@@ -0,0 +1,184 @@
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncSeekExt};
+use crate::events::Events;
+use crate::retry::RetryPolicy;
+use crate::File;
+
+/// the prefixes of URLs, which are read over HTTP
+pub(crate) static URL_SCHEMES:[&str;2] = ["http://","https://"];
+
+/// if the path is an http(s):// URL
+pub(crate) fn is_url(path:&Path) -> bool
+{
+    path.to_str().is_some_and(|path|URL_SCHEMES.iter().any(|scheme|path.starts_with(scheme)))
+}
+
+/// what a [`Source`] gives back, eventually
+pub type SourceFuture<'a,T> = Pin<Box<dyn Future<Output=std::io::Result<T>>+Send+'a>>;
+
+/// the size and modification time of an object read from a [`Source`]
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct SourceMetadata
+{
+    pub size:u64,
+    pub modified:Option<SystemTime>
+}
+
+impl From<&std::fs::Metadata> for SourceMetadata
+{
+    fn from(metadata:&std::fs::Metadata) -> Self
+    {
+        SourceMetadata{size:metadata.len(),modified:metadata.modified().ok()}
+    }
+}
+
+/// where the files to check are read from, the local file system by default
+///
+/// The paths listed (or submitted) are given as they are, for other sources they can stand for
+/// anything the source understands (like object keys).
+pub trait Source:Send+Sync
+{
+    fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata>;
+    /// the content from offset on (the read is resumed there after errors)
+    fn open<'a>(&'a self,path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>;
+    /// if the path is a local file, so symlinks, special files, hard links and zip members are handled
+    fn local(&self,_path:&Path) -> bool {false}
+}
+
+/// the local file system, opening is retried by the policy
+pub(crate) struct LocalFs
+{
+    pub open_policy:RetryPolicy,
+    pub events:Events
+}
+
+impl Source for LocalFs
+{
+    fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+    {
+        Box::pin(async move {tokio::fs::metadata(path).await.map(|metadata|SourceMetadata::from(&metadata))})
+    }
+    fn open<'a>(&'a self,path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+    {
+        Box::pin(async move {
+            let mut file = File::open(path,&self.open_policy,&self.events).await?;
+            if offset > 0 {file.0.seek(std::io::SeekFrom::Start(offset)).await?;}
+            Ok(Box::new(file) as Box<dyn AsyncRead+Unpin+Send>)
+        })
+    }
+    fn local(&self,_path:&Path) -> bool {true}
+}
+
+/// what can't be read as md5check was built without the feature needed (like URLs without "http")
+#[cfg(not(feature="http"))]
+pub(crate) struct Without(pub &'static str);
+
+#[cfg(not(feature="http"))]
+impl Without
+{
+    fn error(&self) -> std::io::Error
+    {
+        std::io::Error::new(std::io::ErrorKind::Unsupported,format!("md5check was built without the \"{}\" feature",self.0))
+    }
+}
+
+#[cfg(not(feature="http"))]
+impl Source for Without
+{
+    fn metadata<'a>(&'a self,_path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+    {
+        let error = self.error();
+        Box::pin(async move {Err(error)})
+    }
+    fn open<'a>(&'a self,_path:&'a Path,_offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+    {
+        let error = self.error();
+        Box::pin(async move {Err(error)})
+    }
+}
+
+/// paths starting with one of the schemes are read from its source, all others from the default one
+pub(crate) struct Schemes
+{
+    pub default:Arc<dyn Source>,
+    pub schemes:Vec<(&'static str,Arc<dyn Source>)>
+}
+
+impl Schemes
+{
+    fn of(&self,path:&Path) -> &dyn Source
+    {
+        let path = path.to_string_lossy();
+        self.schemes.iter().find(|(scheme,_)|path.starts_with(scheme)).map_or(&*self.default,|(_,source)|&**source)
+    }
+}
+
+impl Source for Schemes
+{
+    fn metadata<'a>(&'a self,path:&'a Path) -> SourceFuture<'a,SourceMetadata> {self.of(path).metadata(path)}
+    fn open<'a>(&'a self,path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>> {self.of(path).open(path,offset)}
+    fn local(&self,path:&Path) -> bool {self.of(path).local(path)}
+}
+
+#[cfg(test)]
+mod tests
+{
+    use tokio::io::AsyncReadExt;
+    use super::*;
+
+    fn local() -> LocalFs
+    {
+        LocalFs{open_policy:RetryPolicy{retryable:vec![],max_retries:Some(0)},events:Events::default()}
+    }
+
+    #[tokio::test]
+    async fn local_files_are_read_from_the_offset()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path,"hello world").unwrap();
+        let local = local();
+        let metadata = local.metadata(&path).await.unwrap();
+        assert_eq!(metadata.size,11);
+        assert!(metadata.modified.is_some());
+        let mut content = String::new();
+        local.open(&path,6).await.unwrap().read_to_string(&mut content).await.unwrap();
+        assert_eq!(content,"world");
+        let missing = local.open(&dir.path().join("missing"),0).await.err().unwrap();
+        assert_eq!(missing.kind(),std::io::ErrorKind::NotFound);
+    }
+
+    /// the same content for every path
+    struct Memory(&'static [u8]);
+
+    impl Source for Memory
+    {
+        fn metadata<'a>(&'a self,_path:&'a Path) -> SourceFuture<'a,SourceMetadata>
+        {
+            Box::pin(async {Ok(SourceMetadata{size:self.0.len() as u64,modified:None})})
+        }
+        fn open<'a>(&'a self,_path:&'a Path,offset:u64) -> SourceFuture<'a,Box<dyn AsyncRead+Unpin+Send>>
+        {
+            Box::pin(async move {Ok(Box::new(&self.0[offset as usize..]) as Box<dyn AsyncRead+Unpin+Send>)})
+        }
+    }
+
+    #[tokio::test]
+    async fn schemes_pick_their_source()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let schemes = Schemes{default:Arc::new(local()),schemes:vec![("mem://",Arc::new(Memory(b"hello world")) as Arc<dyn Source>)]};
+        let path = Path::new("mem://run1/file");
+        assert!(schemes.local(dir.path()) && !schemes.local(path));
+        assert_eq!(schemes.metadata(path).await.unwrap().size,11);
+        let mut content = String::new();
+        schemes.open(path,6).await.unwrap().read_to_string(&mut content).await.unwrap();
+        assert_eq!(content,"world");
+        assert!(schemes.metadata(&dir.path().join("missing")).await.is_err());
+        assert!(is_url(Path::new("https://example.org/file")) && !is_url(path));
+    }
+}
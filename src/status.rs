@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::oneshot;
+use tokio::time::{Instant, MissedTickBehavior};
+use crate::{Event, Status, GIGABYTE};
+
+/// the status line of --status-interval, kept up to date by the events of the checks
+pub struct StatusLine
+{
+    /// the bytes read count against the budget only with progressive accounting
+    progressive:bool,
+    max_size:u64,
+    /// if checking started
+    checking:bool,
+    /// size and bytes read of the entries being checked
+    in_flight:HashMap<PathBuf,(u64,u64)>,
+    files_done:u64,bytes_done:u64,
+    /// command sets waiting for a slot and running, and files waiting for a batch release
+    queued:usize,running:usize,batched:usize,
+    /// the files and bytes to check found by the pre-scan, and when checking them started
+    total:Option<(u64,u64,Instant)>
+}
+
+impl StatusLine
+{
+    pub fn new(progressive:bool,max_size:u64) -> StatusLine
+    {
+        StatusLine{
+            progressive,max_size,checking:false,in_flight:HashMap::new(),files_done:0,bytes_done:0,
+            queued:0,running:0,batched:0,total:None
+        }
+    }
+    fn update(&mut self,event:Event)
+    {
+        match event {
+            Event::Admitted{path,size} => {
+                self.checking = true;
+                self.in_flight.insert(path,(size,0));
+            }
+            Event::Progress{path,bytes} => {
+                if let Some((_,read)) = self.in_flight.get_mut(&path) {*read = bytes;}
+            }
+            Event::Completed(result) => {
+                self.checking = true;
+                self.in_flight.remove(&result.path);
+                if matches!(result.status,Status::Ok|Status::Fail|Status::Error(_)) {self.files_done += 1;}
+                // files listed again were not read again
+                if let (Some(size),Some(_)) = (result.size,result.duration) {self.bytes_done += size;}
+            }
+            Event::Batched{..} => self.batched += 1,
+            Event::BatchFlushed{files} => self.batched = self.batched.saturating_sub(files),
+            Event::CommandsQueued{..} => self.queued += 1,
+            Event::CommandsStarted{..} => {
+                self.queued = self.queued.saturating_sub(1);
+                self.running += 1;
+            }
+            Event::CommandsDone{..} => self.running = self.running.saturating_sub(1),
+            Event::Prescanned{files,bytes} => self.total = Some((files,bytes,Instant::now())),
+            _ => {}
+        }
+    }
+    /// bytes read of the entries being checked (files growing while being read don't count more than their size)
+    fn reading(&self) -> u64
+    {
+        self.in_flight.values().map(|(size,read)|*read.min(size)).sum()
+    }
+}
+
+impl Display for StatusLine
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let files = self.files_done;
+        let bytes = self.bytes_done+self.reading();
+        let done = match self.total {
+            Some((total_files,total_bytes,started)) => {
+                let left = match bytes {
+                    0 => "no estimate yet".to_string(),
+                    _ => {
+                        let left = started.elapsed().mul_f64(total_bytes.saturating_sub(bytes) as f64/bytes as f64);
+                        format!("{} left",humantime::format_duration(Duration::from_secs(left.as_secs())))
+                    }
+                };
+                format!("{files} of {total_files} files done, {:.1}G of {:.1}G ({:.0}%, {left})",
+                    bytes as f64/GIGABYTE as f64,total_bytes as f64/GIGABYTE as f64,100.0*bytes as f64/total_bytes.max(1) as f64)
+            }
+            None => format!("{files} files done, {:.1}G (counts only, no total without --prescan)",bytes as f64/GIGABYTE as f64)
+        };
+        let size:u64 = self.in_flight.values().map(|(size,_)|size).sum();
+        let used = if self.progressive {size-self.reading()} else {size};
+        write!(f,"status: {done}, {} in flight ({:.1}G of {}G), releases: {} running, {} queued",
+            self.in_flight.len(),used as f64/GIGABYTE as f64,self.max_size/GIGABYTE,
+            self.running,self.queued+self.batched
+        )
+    }
+}
+
+/// what is told of an event, like reaching the deadline
+fn notice(event:&Event) -> Option<String>
+{
+    let gigabytes = |bytes:u64|bytes as f64/GIGABYTE as f64;
+    Some(match event {
+        Event::PrescanProgress{entries,of,bytes} => format!("pre-scan: {entries} of {of} entries, {:.1}G so far",gigabytes(*bytes)),
+        Event::PrescanInterrupted{entries,of} => format!("pre-scan interrupted after {entries} of {of} entries"),
+        Event::Prescanned{files,bytes} => format!("pre-scan: {files} entries, {:.1}G to check",gigabytes(*bytes)),
+        Event::PhysicalOrder{located,files} => format!("checking the entries in physical order ({located} of {files} files located)"),
+        Event::DeadlineReached{cancelling:true} => "deadline reached, cancelling the running checks".into(),
+        Event::DeadlineReached{cancelling:false} => "deadline reached, waiting for the running checks to finish".into(),
+        Event::ThresholdExceeded{reason} => format!("{reason}, not starting new files"),
+        _ => return None
+    })
+}
+
+/// tell the notices of the events, and with --status-interval print the status line every interval once checking started, until done
+pub async fn show(mut events:UnboundedReceiver<Event>,mut status:Option<(Duration,StatusLine)>,mut done:oneshot::Receiver<()>)
+{
+    let interval = status.as_ref().map_or(Duration::from_secs(3600),|(interval,_)|*interval);
+    let mut ticks = tokio::time::interval_at(Instant::now()+interval,interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            // the events so far first, so the line is up to date (and none are left once done)
+            biased;
+            event = events.recv() => match event {
+                Some(event) => {
+                    if let Some(notice) = notice(&event) {eprintln!("{notice}");}
+                    if let Some((_,status)) = &mut status {status.update(event);}
+                }
+                None => return
+            },
+            _ = &mut done => return,
+            _ = ticks.tick(), if status.is_some() => if let Some((_,status)) = status.as_ref().filter(|(_,status)|status.checking) {eprintln!("{status}")}
+        }
+    }
+}
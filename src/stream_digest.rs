@@ -0,0 +1,41 @@
+//! --stream-digest: one digest of all files read in the order listed
+/// how far the stream digest got
+#[derive(Default)]
+pub(crate) struct StreamDigest
+{
+    /// entries listed in the checksum files, and files read completely for it
+    pub listed:u64,pub streamed:u64,
+    /// the digest, or how many files were not read completely for it
+    pub result:Option<Result<String,u64>>
+}
+
+impl StreamDigest
+{
+    /// the files listed that were not read completely
+    pub fn missing(&self) -> u64 {self.listed-self.streamed}
+    /// finish it, it's only complete if all files listed were read completely
+    pub fn finish(&mut self,context:md5::Context)
+    {
+        let missing = self.missing();
+        self.result = Some(if missing == 0 {Ok(format!("{:x}",context.compute()))} else {Err(missing)});
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn it_is_only_complete_with_all_files()
+    {
+        let mut context = md5::Context::new();
+        context.consume("hello\n");
+        let mut digest = StreamDigest{listed:2,streamed:2,..StreamDigest::default()};
+        digest.finish(context.clone());
+        assert_eq!(digest.result,Some(Ok("b1946ac92492d2347c6235b4d2611184".into())));
+        let mut incomplete = StreamDigest{listed:3,streamed:1,..StreamDigest::default()};
+        incomplete.finish(context);
+        assert_eq!(incomplete.result,Some(Err(2)));
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::mpsc::Sender;
+use crate::{Digest, DigestFactory};
+
+/// a regular file of the stream: its path, size and digest (or why it could not be read)
+pub type Member = (PathBuf,u64,std::io::Result<String>);
+
+/// the path as key for matching members with entries (without "./")
+pub fn key(path:&Path) -> PathBuf
+{
+    path.components().filter(|c|*c != Component::CurDir).collect()
+}
+
+/// read a tar archive from source ("-" for stdin) strictly sequentially, and send each regular file in it
+/// with its digest by the algorithm given, reading buffer_size bytes at once
+///
+/// This blocks, so it's run in its own thread. It stops when the receiver is gone,
+/// an error reading the archive itself is sent last.
+pub fn digest_members(source:&Path,algorithm:DigestFactory,buffer_size:usize,members:Sender<std::io::Result<Member>>)
+{
+    if let Err(e) = read(source,&algorithm,buffer_size,&members) {
+        members.blocking_send(Err(e)).ok();
+    }
+}
+
+fn read(source:&Path,algorithm:&DigestFactory,buffer_size:usize,members:&Sender<std::io::Result<Member>>) -> std::io::Result<()>
+{
+    let input:Box<dyn Read> = if source == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::fs::File::open(source)?)
+    };
+    let mut archive = tar::Archive::new(std::io::BufReader::with_capacity(buffer_size,input));
+    let mut buffer = vec![0;buffer_size];
+    for member in archive.entries()? {
+        let mut member = member?;
+        if !member.header().entry_type().is_file() {continue}
+        let path = key(&member.path()?);
+        let size = member.size();
+        let digest = digest(&mut member,algorithm(),&mut buffer);
+        if members.blocking_send(Ok((path,size,digest))).is_err() {break}
+    }
+    Ok(())
+}
+
+fn digest(member:&mut impl Read,mut digest:Box<dyn Digest>,buffer:&mut [u8]) -> std::io::Result<String>
+{
+    loop {
+        let read = member.read(buffer)?;
+        if read == 0 {break}
+        digest.update(&buffer[..read])?;
+    }
+    Ok(digest.finalize()?.to_hex())
+}
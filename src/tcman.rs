@@ -0,0 +1,149 @@
+//! extended checksum files (".tcman"), listing the size and modification time of each file with its digest
+//!
+//! They start with the header "# tcman <version>", followed by lines "<digest>  <size>  <mtime>  <path>"
+//! with the size in bytes and the modification time as RFC 3339 timestamp (UTC, with nanoseconds).
+//! Other comment lines (and footers) are as in classic checksum files, which are kept separately for md5sum.
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::{parse_line, Digests};
+
+/// the version written, and the only one read
+pub(crate) const VERSION:&str = "1";
+/// the extension of the extended checksum file written next to a classic one
+pub(crate) const EXTENSION:&str = "tcman";
+
+/// the size and modification time a file had when it was recorded
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub(crate) struct Recorded
+{
+    pub size:u64,
+    pub mtime:SystemTime
+}
+
+/// a parsed line: the digest, what's recorded (tcman only) and the file name
+pub(crate) type Line<'l> = (&'l str,Option<Recorded>,&'l str);
+/// a file verified, to be written: its digest, the name as listed and what's recorded
+pub(crate) type Verified = (String,String,Recorded);
+
+/// the format of a checksum file, as declared by its first line
+#[derive(Clone,Copy,Default,PartialEq)]
+pub(crate) enum Format
+{
+    /// lines "<digest>  <path>" like md5sum writes them
+    #[default]
+    Classic,
+    Tcman,
+    /// a tcman version not known, no entries are taken from it
+    Unsupported
+}
+
+impl Format
+{
+    /// split the line nr (from 1) into digest, what's recorded (tcman only) and file name
+    ///
+    /// The format is taken from the first line. Blank lines and comments give None.
+    pub fn parse<'l>(&mut self,nr:u64,line:&'l str,digests:&Digests) -> Result<Option<Line<'l>>,&'static str>
+    {
+        if nr == 1 {
+            if let Some(version) = line.strip_prefix("# tcman ").and_then(|rest|rest.split_whitespace().next()) {
+                *self = if version == VERSION {Format::Tcman} else {Format::Unsupported};
+                return if version == VERSION {Ok(None)} else {Err("a tcman version not supported")};
+            }
+        }
+        if *self == Format::Unsupported {return Ok(None);}
+        let Some((digest,rest)) = parse_line(line,digests)? else {return Ok(None)};
+        if *self == Format::Classic {return Ok(Some((digest,None,rest)));}
+        let (size,rest) = field(rest);
+        let (mtime,filename) = field(rest);
+        let size = size.parse().map_err(|_|"no valid size after the checksum")?;
+        let mtime = chrono::DateTime::parse_from_rfc3339(mtime).map_err(|_|"no valid modification time after the size")?;
+        if filename.is_empty() {return Err("no file name after the modification time");}
+        Ok(Some((digest,Some(Recorded{size,mtime:mtime.into()}),filename)))
+    }
+}
+
+/// the first whitespace separated field and the rest after it
+fn field(s:&str) -> (&str,&str)
+{
+    let (field,rest) = s.split_at(s.find(char::is_whitespace).unwrap_or(s.len()));
+    (field,rest.trim_start())
+}
+
+/// the extended checksum file written for a checksum file with --write-tcman
+pub(crate) fn path_for(manifest:&Path) -> PathBuf
+{
+    let mut name = manifest.as_os_str().to_owned();
+    name.push(".");
+    name.push(EXTENSION);
+    name.into()
+}
+
+/// write the entries (digest, name and what was recorded) sorted by name, with footers for the files and bytes listed
+pub(crate) fn write(path:&Path,mut entries:Vec<Verified>) -> std::io::Result<()>
+{
+    entries.sort_by(|a,b|(&a.1,&a.0).cmp(&(&b.1,&b.0)));
+    entries.dedup_by(|a,b|a.0 == b.0 && a.1 == b.1);
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(file,"# tcman {VERSION}")?;
+    writeln!(file,"# <digest>  <size in bytes>  <modification time>  <path>")?;
+    let mut names = HashSet::new();
+    let mut bytes = 0;
+    for (digest,name,recorded) in &entries {
+        writeln!(file,"{digest}  {}  {}  {name}",recorded.size,humantime::format_rfc3339_nanos(recorded.mtime))?;
+        // each file counts once, also if listed with several digests
+        if names.insert(name) {bytes += recorded.size;}
+    }
+    writeln!(file,"# files: {}",entries.len())?;
+    writeln!(file,"# bytes: {bytes}")?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::Duration;
+    use super::*;
+    use crate::Footer;
+
+    #[test]
+    fn written_files_are_read_back()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path_for(&dir.path().join("md5sum"));
+        assert_eq!(path,dir.path().join("md5sum.tcman"));
+        let mtime = SystemTime::UNIX_EPOCH+Duration::new(1700000000,123456789);
+        let digest = "b1946ac92492d2347c6235b4d2611184".to_string();
+        let entries = vec![
+            (digest.clone(),"run1/with  spaces".to_string(),Recorded{size:6,mtime}),
+            (digest.clone(),"a".to_string(),Recorded{size:6,mtime}),
+            // listed twice
+            (digest.clone(),"a".to_string(),Recorded{size:6,mtime})
+        ];
+        write(&path,entries).unwrap();
+        let (digests,mut format,mut footer) = (Digests::default(),Format::default(),Footer::default());
+        let mut read = vec![];
+        for (nr,line) in std::fs::read_to_string(&path).unwrap().lines().enumerate() {
+            if footer.parse(line) {continue}
+            if let Some((digest,recorded,name)) = format.parse(nr as u64+1,line,&digests).unwrap() {
+                read.push((digest.to_string(),name.to_string(),recorded.unwrap()));
+            }
+        }
+        assert_eq!(read,[(digest.clone(),"a".into(),Recorded{size:6,mtime}),(digest,"run1/with  spaces".into(),Recorded{size:6,mtime})]);
+        assert_eq!((footer.files,footer.bytes),(Some(2),Some(12)));
+    }
+
+    #[test]
+    fn other_versions_are_not_read()
+    {
+        let digests = Digests::default();
+        let mut format = Format::default();
+        assert!(format.parse(1,"# tcman 2",&digests).is_err());
+        assert_eq!(format.parse(2,"b1946ac92492d2347c6235b4d2611184  6  2023-11-14T22:13:20Z  a",&digests),Ok(None));
+        let mut format = Format::default();
+        assert_eq!(format.parse(1,"b1946ac92492d2347c6235b4d2611184  a",&digests),Ok(Some(("b1946ac92492d2347c6235b4d2611184",None,"a"))));
+        let mut format = Format::Tcman;
+        assert!(format.parse(2,"b1946ac92492d2347c6235b4d2611184  6  a",&digests).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! --max-failures, --max-errors and --max-failure-rate: no new files are started once too many failed
+use crate::Summary;
+use crate::options::Options;
+
+/// the thresholds of failures of a run
+pub(crate) struct Thresholds
+{
+    max_failures:Option<u64>,max_errors:Option<u64>,
+    /// the maximum failure rate, and the files to check before it applies
+    max_failure_rate:Option<(f64,u64)>,
+    /// if exceeding a threshold was announced already
+    pub noticed:bool
+}
+
+impl Thresholds
+{
+    pub fn new(options:&Options) -> Thresholds
+    {
+        Thresholds{max_failures:options.max_failures,max_errors:options.max_errors,max_failure_rate:options.max_failure_rate,noticed:false}
+    }
+    /// which of them the counts exceed (with the counts)
+    pub fn exceeded(&self,summary:&Summary) -> Option<String>
+    {
+        if let Some(max) = self.max_failures.filter(|max|summary.fail > *max) {
+            return Some(format!("{} files failed verification (more than --max-failures {max})",summary.fail));
+        }
+        if let Some(max) = self.max_errors.filter(|max|summary.error > *max) {
+            return Some(format!("{} files could not be checked (more than --max-errors {max})",summary.error));
+        }
+        let (rate,min_files) = self.max_failure_rate?;
+        let failed = summary.fail+summary.error+summary.changed;
+        let checked = summary.checked();
+        (checked >= min_files && failed as f64 > rate*checked as f64).then(||
+            format!("{failed} of {checked} files failed or could not be checked (more than --max-failure-rate {}%)",rate*100.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn the_rate_applies_after_the_files_given()
+    {
+        let thresholds = Thresholds::new(&Options{max_errors:Some(1),max_failure_rate:Some((0.5,4)),..Options::default()});
+        let summary = |ok,fail,error|Summary{ok,fail,error,..Summary::default()};
+        assert!(thresholds.exceeded(&summary(0,2,1)).is_none());
+        assert!(thresholds.exceeded(&summary(0,0,2)).unwrap().contains("--max-errors 1"));
+        assert!(thresholds.exceeded(&summary(1,3,0)).unwrap().contains("--max-failure-rate 50%"));
+        assert!(thresholds.exceeded(&summary(2,2,0)).is_none());
+    }
+}
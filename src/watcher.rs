@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use log::{debug, info, warn};
+use tokio::sync::mpsc;
+
+/// how often the directory is scanned for checksum files, also when notified about changes
+static POLL_INTERVAL:Duration = Duration::from_secs(10);
+
+/// if the name matches the pattern, with `*` for any characters and `?` for one
+pub fn glob(pattern:&str,name:&str) -> bool
+{
+    let (pattern,name):(Vec<char>,Vec<char>) = (pattern.chars().collect(),name.chars().collect());
+    // the position of the last * in the pattern, and how much of the name it matches so far
+    let (mut p,mut n,mut star) = (0,0,None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {star = Some((p,n));p += 1;}
+            Some(c) if *c == '?' || *c == name[n] => {p += 1;n += 1;}
+            _ => match star {
+                // the * takes one more character
+                Some((at,matched)) => {star = Some((at,matched+1));p = at+1;n = matched+1;}
+                None => return false
+            }
+        }
+    }
+    pattern[p..].iter().all(|c|*c == '*')
+}
+
+/// the checksum files showing up in a directory (and below), for --watch
+///
+/// The directory is scanned every few seconds, and on Linux also when inotify tells about new files in it.
+/// A checksum file is given once it wasn't changed for the time it has to be stable, and only once
+/// (unless it changes again).
+pub struct Watcher
+{
+    dir:PathBuf,
+    names:Vec<String>,
+    stable:Duration,
+    /// size and modification time of the checksum files found, and if they were given already
+    found:HashMap<PathBuf,(u64,SystemTime,bool)>,
+    /// wakes up the scanning when something changed
+    changes:Option<(inotify::Inotify,mpsc::Receiver<()>)>
+}
+
+impl Watcher
+{
+    /// watch the directory for files with a name matching one of the patterns
+    pub fn new(dir:&Path,names:Vec<String>,stable:Duration) -> Watcher
+    {
+        let changes = inotify::Inotify::new().inspect_err(|e|info!("not notified about changes in '{}' ({e}), just scanning it",dir.to_string_lossy())).ok();
+        info!("watching '{}' for {}",dir.to_string_lossy(),names.join(", "));
+        Watcher{dir:dir.into(),names,stable,found:HashMap::new(),changes}
+    }
+    /// the next checksum file that is stable now, if any
+    pub fn ready(&mut self) -> Option<PathBuf>
+    {
+        let mut found = HashSet::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            if let Some((inotify,_)) = &self.changes {inotify.watch(&dir);}
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {warn!("failed to scan '{}': {e}",dir.to_string_lossy());continue}
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                match entry.file_type() {
+                    Ok(kind) if kind.is_dir() => dirs.push(path),
+                    Ok(kind) if kind.is_file() && self.names.iter().any(|name|glob(name,&entry.file_name().to_string_lossy())) => {
+                        found.insert(path);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // those gone could show up again
+        self.found.retain(|path,_|found.contains(path));
+        let mut ready = None;
+        for path in found {
+            let Ok(metadata) = path.metadata() else {continue};
+            let (size,mtime) = (metadata.len(),metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+            let known = self.found.entry(path.clone()).or_insert((size,mtime,false));
+            if (known.0,known.1) != (size,mtime) {
+                debug!("'{}' changed",path.to_string_lossy());
+                *known = (size,mtime,false);
+            }
+            let age = mtime.elapsed().unwrap_or_default();
+            if !known.2 && age >= self.stable && ready.as_ref().is_none_or(|ready|path < *ready) {ready = Some(path);}
+        }
+        if let Some(path) = &ready {
+            if let Some(known) = self.found.get_mut(path) {known.2 = true;}
+        }
+        ready
+    }
+    /// wait until it's worth looking for stable checksum files again
+    pub async fn changed(&mut self)
+    {
+        let unstable = self.found.values().filter(|(_,_,given)|!given)
+            .map(|(_,mtime,_)|self.stable.saturating_sub(mtime.elapsed().unwrap_or_default())).min();
+        let wait = unstable.map_or(POLL_INTERVAL,|unstable|unstable.clamp(Duration::from_millis(100),POLL_INTERVAL));
+        match &mut self.changes {
+            Some((_,changes)) => tokio::select! {
+                _ = tokio::time::sleep(wait) => {},
+                _ = changes.recv() => {}
+            },
+            None => tokio::time::sleep(wait).await
+        }
+    }
+}
+
+#[cfg(target_os="linux")]
+mod inotify
+{
+    use std::ffi::CString;
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::sync::Arc;
+    use log::debug;
+    use tokio::sync::mpsc;
+
+    /// an inotify instance, with a thread reading its events
+    pub struct Inotify(Arc<std::fs::File>);
+
+    impl Inotify
+    {
+        pub fn new() -> std::io::Result<(Inotify,mpsc::Receiver<()>)>
+        {
+            let fd = unsafe {libc::inotify_init1(libc::IN_CLOEXEC)};
+            if fd < 0 {return Err(std::io::Error::last_os_error());}
+            let file = Arc::new(unsafe {std::fs::File::from_raw_fd(fd)});
+            let (sender,receiver) = mpsc::channel(1);
+            let events = file.clone();
+            std::thread::spawn(move||{
+                let mut buffer = [0;4096];
+                // the events don't matter, only that there are some
+                while (&*events).read(&mut buffer).is_ok_and(|read|read > 0) {
+                    if sender.is_closed() {break}
+                    sender.try_send(()).ok();
+                }
+            });
+            Ok((Inotify(file),receiver))
+        }
+        /// be notified about files created or moved into the directory (watching it again is harmless)
+        pub fn watch(&self,dir:&Path)
+        {
+            let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {return};
+            let mask = libc::IN_CREATE|libc::IN_MOVED_TO|libc::IN_CLOSE_WRITE;
+            if unsafe {libc::inotify_add_watch(self.0.as_raw_fd(),path.as_ptr(),mask)} < 0 {
+                debug!("failed to watch '{}': {}",dir.to_string_lossy(),std::io::Error::last_os_error());
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os="linux"))]
+mod inotify
+{
+    use std::path::Path;
+    use tokio::sync::mpsc;
+
+    /// there's no inotify, the directory is just scanned
+    pub struct Inotify;
+
+    impl Inotify
+    {
+        pub fn new() -> std::io::Result<(Inotify,mpsc::Receiver<()>)>
+        {
+            Err(std::io::Error::new(std::io::ErrorKind::Unsupported,"only on Linux"))
+        }
+        pub fn watch(&self,_dir:&Path) {}
+    }
+}
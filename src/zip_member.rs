@@ -0,0 +1,56 @@
+#[cfg(feature="zip")]
+use std::io::Read;
+#[cfg(feature="zip")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature="zip")]
+use zip::result::ZipError;
+use crate::{CheckError, Digest};
+
+/// the separator between the archive and the member in the paths of checksum files ("archive.zip//member")
+pub static SEPARATOR:&str = "//";
+
+/// split a name listed in a checksum file into archive and member, if it is addressing a zip member
+pub fn split(name:&str) -> Option<(&str,&str)>
+{
+    let (archive,member) = name.split_once(SEPARATOR)?;
+    (archive.to_lowercase().ends_with(".zip") && !member.is_empty()).then_some((archive,member))
+}
+
+/// the digest of the (decompressed) member of the archive, the bytes read so far are stored in progress
+///
+/// This blocks, members are decompressed while reading so memory use doesn't depend on their size.
+#[cfg(feature="zip")]
+pub fn digest(archive:std::fs::File,member:&str,mut digest:Box<dyn Digest>,progress:&AtomicU64,buffer:&mut [u8]) -> Result<String,CheckError>
+{
+    let mut archive = zip::ZipArchive::new(archive).map_err(error)?;
+    let mut member = archive.by_name(member).map_err(error)?;
+    let mut offset = 0;
+    loop {
+        // also fails if the CRC of the member doesn't match
+        let read = member.read(buffer)?;
+        if read == 0 {break}
+        digest.update(&buffer[..read])?;
+        offset += read as u64;
+        progress.store(offset,Ordering::Relaxed);
+    }
+    Ok(digest.finalize()?.to_hex())
+}
+
+#[cfg(feature="zip")]
+fn error(e:ZipError) -> CheckError
+{
+    match e {
+        ZipError::Io(e) => CheckError::Io(e),
+        ZipError::FileNotFound => CheckError::Io(std::io::Error::new(std::io::ErrorKind::NotFound,"not in the archive")),
+        ZipError::UnsupportedArchive(why) => CheckError::Unsupported(why.into()),
+        ZipError::CompressionMethodNotSupported(method) => CheckError::Unsupported(format!("compression method {method}")),
+        e => CheckError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData,e))
+    }
+}
+
+/// members can't be read without the zip feature
+#[cfg(not(feature="zip"))]
+pub fn digest(_archive:std::fs::File,_member:&str,_digest:Box<dyn Digest>,_progress:&std::sync::atomic::AtomicU64,_buffer:&mut [u8]) -> Result<String,CheckError>
+{
+    Err(CheckError::Unsupported("md5check was built without the \"zip\" feature".into()))
+}